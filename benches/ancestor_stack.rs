@@ -0,0 +1,55 @@
+//! Compares the loop check of `SmallHandleVec` with a plain `Vec<Handle>`.
+//!
+//! Run with `cargo bench --bench ancestor_stack`. Both stacks hold the same
+//! open directories; each iteration checks an identity that is not on the
+//! stack, which is the common case of a walk without loops.
+
+use std::fs::File;
+use std::hint::black_box;
+use std::time::Instant;
+
+use cross_file_id::{FileId, Handle, SmallHandleVec};
+
+const ITERATIONS: usize = 100_000;
+
+/// Run `f` repeatedly, reporting the mean time per call.
+fn bench(name: &str, mut f: impl FnMut()) {
+    f();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let elapsed = start.elapsed();
+    println!("{:<32} {:>10.0?}/iter", name, elapsed / ITERATIONS as u32);
+}
+
+fn main() {
+    let root = std::env::temp_dir()
+        .join(format!("cross-file-id-bench-{}", std::process::id()));
+    let mut dirs = vec![root.clone()];
+    for depth in 0..64 {
+        dirs.push(dirs[depth].join(format!("d{}", depth)));
+    }
+    std::fs::create_dir_all(dirs.last().unwrap()).unwrap();
+    let outside = std::env::temp_dir();
+    let missing = FileId::from_path(&outside).unwrap();
+
+    for depth in [4, 16, 64] {
+        let mut small = SmallHandleVec::<File>::new();
+        let mut naive = Vec::new();
+        for dir in &dirs[..depth] {
+            small.push(Handle::from_path(dir).unwrap()).unwrap();
+            naive.push(Handle::from_path(dir).unwrap());
+        }
+        let candidate = Handle::from_path(&outside).unwrap();
+
+        bench(&format!("SmallHandleVec (depth {})", depth), || {
+            black_box(small.contains(black_box(&missing)));
+        });
+        bench(&format!("Vec<Handle> (depth {})", depth), || {
+            black_box(naive.iter().any(|h| *h == *black_box(&candidate)));
+        });
+    }
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,77 @@
+//! Measures the time and the heap allocations of comparing files by path.
+//!
+//! Run with `cargo bench --bench is_same_file`. Comparing short paths is
+//! expected to make no allocations at all.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs::File;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use cross_file_id::{is_same_file, is_same_file_path};
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const ITERATIONS: usize = 10_000;
+
+/// Run `f` repeatedly, reporting the mean time and allocations per call.
+fn bench(name: &str, mut f: impl FnMut()) {
+    f();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    println!(
+        "{:<24} {:>10.0?}/iter {:>6} allocations/iter",
+        name,
+        elapsed / ITERATIONS as u32,
+        allocations / ITERATIONS,
+    );
+}
+
+fn main() {
+    let dir = std::env::temp_dir()
+        .join(format!("cross-file-id-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let a = dir.join("a");
+    let b = dir.join("b");
+    File::create(&a).unwrap();
+    File::create(&b).unwrap();
+
+    let long = dir.join("x".repeat(200)).join("y".repeat(200));
+    std::fs::create_dir_all(long.parent().unwrap()).unwrap();
+    File::create(&long).unwrap();
+
+    let (file_a, file_b) = (File::open(&a).unwrap(), File::open(&b).unwrap());
+    bench("is_same_file", || {
+        black_box(is_same_file(&file_a, &file_b).unwrap());
+    });
+    bench("is_same_file_path", || {
+        black_box(is_same_file_path(&a, &b).unwrap());
+    });
+    bench("is_same_file_path (long)", || {
+        black_box(is_same_file_path(&long, &long).unwrap());
+    });
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
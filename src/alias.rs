@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::{DeviceClass, DirHandle, Error, FileId, Handle};
+
+/// A directory that was reached through more than one path.
+///
+/// Returned by [`find_aliases`]. The `original` path is the first path under
+/// which the directory was encountered during the scan, and `alias` is a
+/// later path that resolved to the same directory identity.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirAlias {
+    /// The path under which the directory was first seen.
+    pub original: PathBuf,
+    /// A different path that refers to the same directory.
+    pub alias: PathBuf,
+}
+
+/// Returns true if the two directory paths refer to the same directory.
+///
+/// Unlike a lexical comparison, this detects directories that are reachable
+/// through different paths, such as bind mounts, symlinks, junctions and
+/// `subst` drives.
+///
+/// # Errors
+/// This function will return an [`io::Error`] if either path cannot be
+/// opened, or if either path does not refer to a directory.
+///
+/// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use cross_file_id::dirs_alias;
+///
+/// assert!(dirs_alias("/mnt/bind", "/srv/data")?);
+/// # Ok::<_, std::io::Error>(())
+/// ```
+pub fn dirs_alias<P, Q>(a: P, b: Q) -> io::Result<bool>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    Ok(DirHandle::open(a)? == DirHandle::open(b)?)
+}
+
+/// Returns true if `ancestor` is a directory that contains `descendant`,
+/// or is the same file as `descendant`, by identity.
+///
+/// The parent chain of `descendant` is walked up to the root of the
+/// filesystem, appending `..` to its path, and the identity of every
+/// directory on the way is compared with the identity of `ancestor`. Unlike
+/// a lexical [`Path::starts_with`] check, this cannot be fooled by
+/// symbolic links, `..` components, bind mounts or case-insensitive names.
+/// On Unix, `..` is resolved by the kernel, so the chain is that of the
+/// directory the symbolic links in `descendant` lead to. Windows resolves
+/// `..` lexically, before following any link.
+///
+/// If `descendant` is not a directory, the walk starts from the directory
+/// the file is in once every symbolic link in its path is followed, as
+/// reported for the open file by [`Handle::current_path`], or by
+/// [`fs::canonicalize`] where that is not supported. `ancestor` stays open
+/// during the walk, so its identity cannot be reused.
+///
+/// # Errors
+/// This function will return an [`io::Error`] if either path, or a
+/// directory on the parent chain, cannot be opened, or if the resolved path
+/// of `descendant` names another file by the time it is checked, in which
+/// case it wraps [`Error::Replaced`].
+///
+/// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use cross_file_id::is_ancestor_of;
+///
+/// // Refuse paths that escape the sandbox, whichever way they are spelled.
+/// assert!(!is_ancestor_of("/srv/sandbox", "/srv/sandbox/link-to-etc/passwd")?);
+/// # Ok::<_, std::io::Error>(())
+/// ```
+pub fn is_ancestor_of<P, Q>(ancestor: P, descendant: Q) -> io::Result<bool>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let ancestor = Handle::from_path(ancestor)?;
+    let descendant = descendant.as_ref();
+    let file = Handle::from_path(descendant)?;
+    let mut id = Handle::id(&file);
+    if id == Handle::id(&ancestor) {
+        return Ok(true);
+    }
+    let is_dir = Handle::device_class(&file)? == DeviceClass::Directory;
+    let mut dir = if is_dir {
+        descendant.to_path_buf()
+    } else {
+        // The last component may be a symbolic link to a file elsewhere, so
+        // the walk starts from the directory the file really is in.
+        let mut real = match file.current_path() {
+            Ok(path) => path,
+            Err(_) => fs::canonicalize(descendant)
+                .map_err(|err| Error::open_failed(descendant, err))?,
+        };
+        let actual = FileId::from_path(&real)?;
+        if actual != id {
+            let err =
+                Error::Replaced { expected: id, actual, path: Some(real) };
+            return Err(err.into());
+        }
+        real.pop();
+        real
+    };
+    if !is_dir {
+        id = FileId::from_path(&dir)?;
+        if id == Handle::id(&ancestor) {
+            return Ok(true);
+        }
+    }
+    loop {
+        dir.push("..");
+        let parent = FileId::from_path(&dir)?;
+        if parent == Handle::id(&ancestor) {
+            return Ok(true);
+        }
+        // The root of the filesystem is its own parent.
+        if parent == id {
+            return Ok(false);
+        }
+        id = parent;
+    }
+}
+
+/// Returns true if the two paths name the same file lexically, without
+/// touching the filesystem.
+///
+/// Paths are compared component by component, so redundant separators and
+/// `.` components are ignored. On macOS and iOS, names are also compared
+/// after Unicode canonical decomposition (NFD), as HFS+ and APFS treat
+/// differently normalized names as the same name: `café` spelled with a
+/// precomposed `é` and with `e` followed by a combining accent is one file.
+///
+/// This is a fast path: a true result means the paths refer to the same file
+/// if they exist, but a false result does not mean they are distinct, as
+/// links, mounts, `..` components and case-insensitive filesystems can still
+/// make them alias. Use [`is_same_file_path`](crate::is_same_file_path) to
+/// find out.
+///
+/// # Example
+///
+/// ```rust
+/// use cross_file_id::paths_lexically_equal;
+///
+/// assert!(paths_lexically_equal("./foo//bar", "foo/./bar"));
+/// assert!(!paths_lexically_equal("foo/bar", "foo/baz"));
+/// ```
+pub fn paths_lexically_equal<P, Q>(a: P, b: Q) -> bool
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let (mut a, mut b) = (components(a.as_ref()), components(b.as_ref()));
+    loop {
+        match (a.next(), b.next()) {
+            (None, None) => return true,
+            (Some(Component::Normal(x)), Some(Component::Normal(y))) => {
+                if !names_equal(x, y) {
+                    return false;
+                }
+            }
+            (x, y) => {
+                if x != y {
+                    return false;
+                }
+            }
+        }
+    }
+}
+
+/// The components of a path, without the `.` components that
+/// [`Path::components`] keeps at its start.
+fn components(path: &Path) -> impl Iterator<Item = Component<'_>> {
+    path.components().filter(|c| *c != Component::CurDir)
+}
+
+/// Compare two file names the way the filesystems of the platform do.
+#[cfg(target_vendor = "apple")]
+fn names_equal(a: &OsStr, b: &OsStr) -> bool {
+    use unicode_normalization::UnicodeNormalization;
+
+    if a == b {
+        return true;
+    }
+    match (a.to_str(), b.to_str()) {
+        (Some(a), Some(b)) => a.nfd().eq(b.nfd()),
+        _ => false,
+    }
+}
+
+/// Compare two file names the way the filesystems of the platform do.
+#[cfg(not(target_vendor = "apple"))]
+fn names_equal(a: &OsStr, b: &OsStr) -> bool {
+    a == b
+}
+
+/// Scans the directory tree under `root` for directories that alias each
+/// other.
+///
+/// Symbolic links to directories are followed, and every directory that is
+/// reached a second time under a different path is reported as a
+/// [`DirAlias`]. Aliased directories are not descended into, so the scan
+/// terminates even in the presence of directory cycles.
+///
+/// Every directory visited is kept open for the duration of the scan, which
+/// guarantees that the identities being compared remain valid.
+///
+/// # Errors
+/// This function will return an [`io::Error`] if `root` is not a directory,
+/// or if any directory in the tree cannot be opened or read.
+///
+/// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+pub fn find_aliases<P: AsRef<Path>>(root: P) -> io::Result<Vec<DirAlias>> {
+    let mut seen: HashMap<FileId, DirHandle> = HashMap::new();
+    let mut aliases = Vec::new();
+    let mut stack = vec![DirHandle::open(root)?];
+
+    while let Some(dir) = stack.pop() {
+        let id = dir.id();
+        if let Some(original) = seen.get(&id) {
+            aliases.push(DirAlias {
+                original: original.path().to_path_buf(),
+                alias: dir.path().to_path_buf(),
+            });
+            continue;
+        }
+        let mut children = Vec::new();
+        for entry in dir.read()? {
+            let child = entry?.path();
+            // `Path::is_dir` follows symbolic links.
+            if child.is_dir() {
+                children.push(child);
+            }
+        }
+        children.sort();
+        seen.insert(id, dir);
+        for child in children.into_iter().rev() {
+            stack.push(DirHandle::open(child)?);
+        }
+    }
+    Ok(aliases)
+}
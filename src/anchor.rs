@@ -0,0 +1,161 @@
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::{AsRawFilelike, DirHandle, Error, FileId, Handle, imp};
+
+/// A pinned root directory that relative paths are resolved under.
+///
+/// Build tools and language servers address files relative to a project
+/// root. An `Anchor` keeps that root open, and only opens files that are
+/// inside it: absolute paths and paths that climb out with `..` are
+/// rejected before anything is opened, and paths are then resolved relative
+/// to the open root rather than its path, so that a symbolic link that
+/// leads out of the root is refused before anything outside is opened.
+///
+/// On Linux 5.6 and later, the kernel resolves the path beneath the root
+/// with `openat2`. Elsewhere on Unix, the path is walked one component at a
+/// time, following symbolic links by hand. On Windows, symbolic links and
+/// junctions below the root are refused as if they led outside, and on
+/// WASI, the runtime keeps every path beneath the root. Anchors cannot open
+/// files on Hermit.
+///
+/// The root itself may be renamed or replaced while the anchor exists.
+/// Every open checks that the root path still leads to the pinned
+/// directory, and [`verify_root`](Anchor::verify_root) does so on demand.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use cross_file_id::Anchor;
+///
+/// let project = Anchor::new("/src/project")?;
+/// let manifest = project.open("Cargo.toml")?;
+/// assert!(project.open("../elsewhere/Cargo.toml").is_err());
+/// # Ok::<_, std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Anchor {
+    root: DirHandle,
+}
+
+impl Anchor {
+    /// Pin the directory at `root`.
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] if the path cannot be
+    /// opened, or does not refer to a directory.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn new<P: AsRef<Path>>(root: P) -> io::Result<Anchor> {
+        Ok(Anchor { root: DirHandle::open(root)? })
+    }
+
+    /// The pinned root directory.
+    pub fn root(&self) -> &DirHandle {
+        &self.root
+    }
+
+    /// Check that the root path still leads to the pinned directory.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] of kind
+    /// [`io::ErrorKind::NotFound`] if the directory was renamed, removed or
+    /// replaced, or any error met while taking the identity of the path.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn verify_root(&self) -> io::Result<()> {
+        if FileId::from_path(self.root.path())? != self.root.id() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "{} is no longer the anchored directory",
+                    self.root.path().display()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Join a relative path onto the root, without touching the filesystem.
+    ///
+    /// `.` components are dropped and `..` components are applied, so the
+    /// result is the root path followed by normal components only.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] of kind
+    /// [`io::ErrorKind::InvalidInput`] if the path is absolute, or climbs
+    /// above the root.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn resolve<P: AsRef<Path>>(&self, relative: P) -> io::Result<PathBuf> {
+        let mut path = self.root.path().to_path_buf();
+        path.extend(normalize(relative.as_ref())?);
+        Ok(path)
+    }
+
+    /// Open the file at a path relative to the root.
+    ///
+    /// The path is resolved relative to the open root, not to its path, so
+    /// it is not affected by the root being renamed between the check of
+    /// the root and the open.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the root was replaced,
+    /// if the path cannot be resolved under the root, if the file cannot be
+    /// opened, or with [`io::ErrorKind::PermissionDenied`] if the path leads
+    /// outside the root.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn open<P: AsRef<Path>>(
+        &self,
+        relative: P,
+    ) -> io::Result<Handle<File>> {
+        let relative = relative.as_ref();
+        let parts = normalize(relative)?;
+        self.verify_root()?;
+        let path = if parts.is_empty() {
+            PathBuf::from(".")
+        } else {
+            parts.iter().collect()
+        };
+        fail_point!(Open);
+        let root = self.root.handle().as_raw_filelike();
+        let file = imp::open_beneath(root, &path)
+            .map_err(|err| Error::open_failed(relative, err))?;
+        let Some(file) = file else {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "{} leads outside of {}",
+                    relative.display(),
+                    self.root.path().display()
+                ),
+            ));
+        };
+        Handle::from_file_like(file)
+    }
+}
+
+/// Drop `.` components and apply `..` components, refusing absolute paths
+/// and paths that climb above their start.
+fn normalize(relative: &Path) -> io::Result<Vec<&OsStr>> {
+    let mut parts = Vec::new();
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) => parts.push(part),
+            Component::CurDir => {}
+            Component::ParentDir if parts.pop().is_some() => {}
+            _ => return Err(escape(relative)),
+        }
+    }
+    Ok(parts)
+}
+
+fn escape(relative: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{} is not a path below the anchor", relative.display()),
+    )
+}
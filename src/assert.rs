@@ -0,0 +1,375 @@
+//! Support code for the `assert_same_file!`, `assert_different_file!` and
+//! `assert_replaced_atomically!` macros.
+//!
+//! The assertions are only available with the `testing` feature.
+
+use std::ffi::{OsStr, OsString};
+use std::fmt::Write;
+use std::fs::{File, Metadata};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use crate::{AsRawFilelike, FileId, Handle, metadata_of};
+
+/// Asserts that two paths or handles refer to the same file.
+///
+/// Each operand may be a path (anything path-like, such as `&str`, `Path` or
+/// `PathBuf`), a [`File`](std::fs::File), or a [`Handle`]. On failure, the
+/// panic message includes the identity and a summary of the metadata of both
+/// operands, rather than just reporting that a boolean was false.
+///
+/// An optional format string and arguments can be given to add context to
+/// the failure message, as with [`assert!`].
+///
+/// This macro is only available with the `testing` feature.
+///
+/// # Panics
+///
+/// Panics if the operands refer to different files, or if the identity of
+/// either operand cannot be obtained.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # #[cfg(feature = "testing")]
+/// # fn main() {
+/// use cross_file_id::assert_same_file;
+///
+/// assert_same_file!("./output.txt", "./link-to-output.txt");
+/// # }
+/// # #[cfg(not(feature = "testing"))]
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! assert_same_file {
+    ($left:expr, $right:expr $(,)?) => {
+        $crate::__assert_file_identity(
+            &$left,
+            &$right,
+            true,
+            ::core::option::Option::None,
+        )
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        $crate::__assert_file_identity(
+            &$left,
+            &$right,
+            true,
+            ::core::option::Option::Some(::std::format!($($arg)+)),
+        )
+    };
+}
+
+/// Asserts that two paths or handles refer to different files.
+///
+/// Accepts the same operands as [`assert_same_file!`], and is only
+/// available with the `testing` feature.
+///
+/// # Panics
+///
+/// Panics if the operands refer to the same file, or if the identity of
+/// either operand cannot be obtained.
+#[macro_export]
+macro_rules! assert_different_file {
+    ($left:expr, $right:expr $(,)?) => {
+        $crate::__assert_file_identity(
+            &$left,
+            &$right,
+            false,
+            ::core::option::Option::None,
+        )
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        $crate::__assert_file_identity(
+            &$left,
+            &$right,
+            false,
+            ::core::option::Option::Some(::std::format!($($arg)+)),
+        )
+    };
+}
+
+/// Asserts that an operation replaces the file at a path atomically.
+///
+/// The first argument is the path, and the second is an expression that
+/// performs the replacement; the value of the expression is returned. While
+/// the expression runs, an observer thread repeatedly opens the path and
+/// reads its contents. The assertion checks that:
+///
+/// * the identity of the file at the path changed,
+/// * every observation saw either the original file with its original
+///   contents, or the final file with its final contents,
+/// * the path never went missing (unless it was missing to begin with), and
+/// * once the final file was observed, the original was never observed
+///   again.
+///
+/// Since the observer samples the path, a non-atomic replacement may go
+/// unnoticed if the window is small. A failure, however, always indicates a
+/// real problem.
+///
+/// This macro is only available with the `testing` feature.
+///
+/// # Panics
+///
+/// Panics if any of the conditions above is violated, or if the final file
+/// cannot be read.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # #[cfg(feature = "testing")]
+/// # fn main() {
+/// use cross_file_id::assert_replaced_atomically;
+///
+/// assert_replaced_atomically!("config.toml", {
+///     std::fs::write("config.toml.tmp", "new = true\n").unwrap();
+///     std::fs::rename("config.toml.tmp", "config.toml").unwrap();
+/// });
+/// # }
+/// # #[cfg(not(feature = "testing"))]
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! assert_replaced_atomically {
+    ($path:expr, $op:expr $(,)?) => {
+        $crate::__assert_replaced_atomically($path, || $op)
+    };
+}
+
+/// A description of one side of a file identity assertion.
+#[doc(hidden)]
+pub struct Operand {
+    label: String,
+    id: io::Result<FileId>,
+    metadata: Option<Metadata>,
+}
+
+impl Operand {
+    fn from_path(path: &Path) -> Operand {
+        let label = format!("path {:?}", path);
+        match Handle::from_path(path) {
+            Ok(handle) => Operand {
+                label,
+                id: Ok(Handle::id(&handle)),
+                metadata: handle.metadata().ok(),
+            },
+            Err(err) => Operand { label, id: Err(err), metadata: None },
+        }
+    }
+
+    fn from_file_like<F: AsRawFilelike>(label: String, file: &F) -> Operand {
+        let metadata = metadata_of(file).ok();
+        Operand { label, id: FileId::from_file_like(file), metadata }
+    }
+}
+
+/// Types that can be used as operands of the file identity assertions.
+#[doc(hidden)]
+pub trait FileOperand {
+    fn describe(&self) -> Operand;
+}
+
+impl<T: FileOperand + ?Sized> FileOperand for &T {
+    fn describe(&self) -> Operand {
+        (**self).describe()
+    }
+}
+
+macro_rules! path_operand {
+    ($($ty:ty),*) => {
+        $(
+            impl FileOperand for $ty {
+                fn describe(&self) -> Operand {
+                    Operand::from_path(self.as_ref())
+                }
+            }
+        )*
+    };
+}
+
+path_operand!(Path, PathBuf, str, String, OsStr, OsString);
+#[cfg(feature = "camino")]
+path_operand!(camino::Utf8Path, camino::Utf8PathBuf);
+
+impl FileOperand for File {
+    fn describe(&self) -> Operand {
+        Operand::from_file_like("file".to_string(), self)
+    }
+}
+
+impl<F: AsRawFilelike> FileOperand for Handle<F> {
+    fn describe(&self) -> Operand {
+        let mut operand =
+            Operand::from_file_like("handle".to_string(), &self.handle);
+        operand.id = Ok(Handle::id(self));
+        operand
+    }
+}
+
+fn summarize(metadata: &Metadata) -> String {
+    let kind = if metadata.is_dir() {
+        "directory"
+    } else if metadata.is_file() {
+        "regular file"
+    } else if metadata.is_symlink() {
+        "symlink"
+    } else {
+        "other"
+    };
+    #[allow(unused_mut)]
+    let mut summary = format!("{}, {} bytes", kind, metadata.len());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        summary.push_str(&format!(", {} links", metadata.nlink()));
+    }
+    summary
+}
+
+fn write_operand(out: &mut String, name: &str, operand: &Operand) {
+    let _ = writeln!(out, "{:>6}: {}", name, operand.label);
+    match operand.id {
+        Ok(ref id) => {
+            let _ = writeln!(out, "{:>6}  id: {:?}", "", id);
+        }
+        Err(ref err) => {
+            let _ = writeln!(out, "{:>6}  error: {}", "", err);
+        }
+    }
+    if let Some(ref metadata) = operand.metadata {
+        let _ = writeln!(out, "{:>6}  metadata: {}", "", summarize(metadata));
+    }
+}
+
+#[doc(hidden)]
+#[track_caller]
+pub fn __assert_file_identity<L, R>(
+    left: &L,
+    right: &R,
+    expect_same: bool,
+    context: Option<String>,
+) where
+    L: FileOperand + ?Sized,
+    R: FileOperand + ?Sized,
+{
+    let left = left.describe();
+    let right = right.describe();
+    let outcome = match (&left.id, &right.id) {
+        (Ok(l), Ok(r)) if (l == r) == expect_same => return,
+        (Ok(_), Ok(_)) if expect_same => "refer to different files",
+        (Ok(_), Ok(_)) => "refer to the same file",
+        _ => "could not be identified",
+    };
+    let mut message =
+        format!("assertion failed: `left` and `right` {}", outcome);
+    if let Some(context) = context {
+        let _ = write!(message, ": {}", context);
+    }
+    message.push('\n');
+    write_operand(&mut message, "left", &left);
+    write_operand(&mut message, "right", &right);
+    panic!("{}", message.trim_end());
+}
+
+/// A snapshot of the file at a path, taken by the replacement observer.
+#[derive(Debug, PartialEq, Eq)]
+enum Snapshot {
+    Missing,
+    Present { id: FileId, contents: Vec<u8> },
+}
+
+fn snapshot(path: &Path) -> io::Result<Snapshot> {
+    let mut handle = match Handle::from_path(path) {
+        Ok(handle) => handle,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Ok(Snapshot::Missing);
+        }
+        Err(err) => return Err(err),
+    };
+    let mut contents = Vec::new();
+    handle.read_to_end(&mut contents)?;
+    Ok(Snapshot::Present { id: Handle::id(&handle), contents })
+}
+
+#[doc(hidden)]
+#[track_caller]
+pub fn __assert_replaced_atomically<P, T>(path: P, op: impl FnOnce() -> T) -> T
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let initial = snapshot(path).unwrap_or_else(|err| {
+        panic!("failed to observe {:?} before replacement: {}", path, err)
+    });
+    let done = AtomicBool::new(false);
+    let (result, observed) = thread::scope(|scope| {
+        let observer = scope.spawn(|| {
+            let mut observed = Vec::new();
+            while !done.load(Ordering::Acquire) {
+                // Errors other than a missing file (e.g. sharing violations
+                // on Windows) say nothing about atomicity, so skip them.
+                if let Ok(snap) = snapshot(path)
+                    && observed.last() != Some(&snap)
+                {
+                    observed.push(snap);
+                }
+                thread::yield_now();
+            }
+            observed
+        });
+        let result = op();
+        done.store(true, Ordering::Release);
+        (result, observer.join().expect("observer thread panicked"))
+    });
+    let last = snapshot(path).unwrap_or_else(|err| {
+        panic!("failed to observe {:?} after replacement: {}", path, err)
+    });
+
+    let fail = |reason: &str| -> ! {
+        panic!(
+            "assertion failed: {:?} was not replaced atomically: {}\n\
+             initial: {:?}\n  final: {:?}",
+            path,
+            reason,
+            summarize_snapshot(&initial),
+            summarize_snapshot(&last),
+        )
+    };
+    match (&initial, &last) {
+        (_, Snapshot::Missing) => fail("the file is missing afterwards"),
+        (
+            Snapshot::Present { id: before, .. },
+            Snapshot::Present { id: after, .. },
+        ) if before == after => fail("the file identity did not change"),
+        _ => {}
+    }
+    let mut seen_final = false;
+    for snap in &observed {
+        if *snap == last {
+            seen_final = true;
+        } else if *snap == initial {
+            if seen_final {
+                fail("the original file reappeared after the replacement");
+            }
+        } else if *snap == Snapshot::Missing {
+            fail("the path was missing during the replacement");
+        } else {
+            fail(&format!(
+                "an intermediate file was observed: {:?}",
+                summarize_snapshot(snap)
+            ));
+        }
+    }
+    result
+}
+
+fn summarize_snapshot(snap: &Snapshot) -> String {
+    match *snap {
+        Snapshot::Missing => "missing".to_string(),
+        Snapshot::Present { ref id, ref contents } => {
+            format!("{:?} with {} bytes", id, contents.len())
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Runtime-agnostic async constructors.
+//!
+//! These run the blocking work of opening files and taking identities on the
+//! thread pool of the `blocking` crate, which works with any executor, such
+//! as those of `async-std` and `smol`. Applications using `tokio` should
+//! prefer the constructors of the `tokio` feature, which use its own pool.
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use crate::{FileId, Handle};
+
+impl FileId {
+    /// Extract the identity of the file at a path without blocking the
+    /// executor.
+    ///
+    /// This is the async equivalent of [`from_path`](FileId::from_path), and
+    /// shares its caveats.
+    ///
+    /// # Errors
+    /// This function fails as [`from_path`](FileId::from_path) does.
+    pub async fn from_path_unblocking<P: AsRef<Path>>(
+        path: P,
+    ) -> io::Result<FileId> {
+        let path = path.as_ref().to_path_buf();
+        blocking::unblock(move || FileId::from_path(path)).await
+    }
+}
+
+impl Handle<File> {
+    /// Open the file at a path and take its identity without blocking the
+    /// executor.
+    ///
+    /// This is the async equivalent of [`from_path`](Handle::from_path).
+    ///
+    /// # Errors
+    /// This function fails as [`from_path`](Handle::from_path) does.
+    pub async fn from_path_unblocking<P: AsRef<Path>>(
+        path: P,
+    ) -> io::Result<Handle<File>> {
+        let path = path.as_ref().to_path_buf();
+        blocking::unblock(move || Handle::from_path(path)).await
+    }
+
+    /// Take the identity of an open file without blocking the executor.
+    ///
+    /// This is the async equivalent of [`from_file`](Handle::from_file).
+    ///
+    /// # Errors
+    /// This function fails as [`from_file`](Handle::from_file) does.
+    pub async fn from_file_unblocking(file: File) -> io::Result<Handle<File>> {
+        blocking::unblock(move || Handle::from_file(file)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+
+    use crate::testing::TempDir;
+    use crate::{FileId, Handle};
+
+    #[test]
+    fn unblocking_constructors() {
+        let tdir = TempDir::new().unwrap();
+        let dir = tdir.path();
+        File::create(dir.join("a")).unwrap();
+        fs::hard_link(dir.join("a"), dir.join("alink")).unwrap();
+
+        futures_lite::future::block_on(async {
+            let a = Handle::from_path_unblocking(dir.join("a")).await.unwrap();
+            let file = File::open(dir.join("alink")).unwrap();
+            let alink = Handle::from_file_unblocking(file).await.unwrap();
+            assert_eq!(a, alink);
+            assert_eq!(
+                FileId::from_path_unblocking(dir.join("a")).await.unwrap(),
+                Handle::id(&a)
+            );
+            assert!(
+                Handle::from_path_unblocking(dir.join("missing"))
+                    .await
+                    .is_err()
+            );
+        });
+    }
+}
@@ -0,0 +1,166 @@
+//! `cfi`, a command line front end to `cross-file-id`.
+//!
+//! This is built with the `cli` feature, and doubles as a set of worked
+//! examples of the crate's APIs:
+//!
+//! * `cfi same A B` exits with status 0 if `A` and `B` are the same file,
+//!   and 1 otherwise.
+//! * `cfi id PATH` prints the identity of the file at `PATH`.
+//! * `cfi dups DIR` prints the groups of paths under `DIR` that name the
+//!   same file, through hard or symbolic links.
+//! * `cfi hardlinks DIR TARGET` prints the paths under `DIR` that are hard
+//!   links to `TARGET`.
+//! * `cfi doctor PATH` prints how reliably the file at `PATH` can be
+//!   identified.
+//!
+//! Errors are reported on stderr, with exit status 2. The scans of `dups`
+//! and `hardlinks` take the identities of the paths in parallel, report the
+//! paths that cannot be identified, such as dangling symbolic links, and go
+//! on with the others; they then exit with status 1.
+
+use std::env;
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use cross_file_id::{
+    DirHandle, FileId, FileIdMap, Handle, ThreadSpawner, doctor,
+    ids_from_paths, ids_from_paths_no_follow, is_same_file_path,
+};
+
+const USAGE: &str = "\
+usage: cfi same A B
+       cfi id PATH
+       cfi dups DIR
+       cfi hardlinks DIR TARGET
+       cfi doctor PATH";
+
+fn main() -> ExitCode {
+    let args: Vec<OsString> = env::args_os().skip(1).collect();
+    let Some((command, args)) = args.split_first() else {
+        eprintln!("{}", USAGE);
+        return ExitCode::from(2);
+    };
+    let result = match (command.to_str(), args) {
+        (Some("same"), [a, b]) => same(a.as_ref(), b.as_ref()),
+        (Some("id"), [path]) => id(path.as_ref()),
+        (Some("dups"), [dir]) => dups(dir.as_ref()),
+        (Some("hardlinks"), [dir, target]) => {
+            hardlinks(dir.as_ref(), target.as_ref())
+        }
+        (Some("doctor"), [path]) => print_doctor(path.as_ref()),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match result {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("cfi: {}", err);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn same(a: &Path, b: &Path) -> io::Result<ExitCode> {
+    Ok(if is_same_file_path(a, b)? {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn id(path: &Path) -> io::Result<ExitCode> {
+    println!("{}", FileId::from_path(path)?);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn dups(dir: &Path) -> io::Result<ExitCode> {
+    // The files are not kept open, as a large tree would exhaust the
+    // descriptors of the process, so a file replaced during the scan can
+    // show up under its old identity.
+    let paths = walk(dir)?;
+    let ids = ids_from_paths(&ThreadSpawner::default(), &paths);
+    let mut groups: FileIdMap<Vec<PathBuf>> = FileIdMap::new();
+    let mut order = Vec::new();
+    let mut status = ExitCode::SUCCESS;
+    for (path, id) in paths.into_iter().zip(ids) {
+        let Some(id) = identified(id, &mut status) else {
+            continue;
+        };
+        if let Some(paths) = groups.get_mut(&id) {
+            paths.push(path);
+        } else {
+            order.push(id.clone());
+            groups.insert(id, vec![path]);
+        }
+    }
+    for id in order {
+        let paths = groups.get(&id).unwrap();
+        if paths.len() > 1 {
+            println!("{}", id);
+            for path in paths {
+                println!("  {}", path.display());
+            }
+        }
+    }
+    Ok(status)
+}
+
+fn hardlinks(dir: &Path, target: &Path) -> io::Result<ExitCode> {
+    // Keeping the target open pins its identity for the whole scan.
+    let target = Handle::from_path(target)?;
+    let paths = walk(dir)?;
+    let ids = ids_from_paths_no_follow(&ThreadSpawner::default(), &paths);
+    let mut status = ExitCode::SUCCESS;
+    for (path, id) in paths.iter().zip(ids) {
+        if identified(id, &mut status)
+            .is_some_and(|id| id == Handle::id(&target))
+        {
+            println!("{}", path.display());
+        }
+    }
+    Ok(status)
+}
+
+/// Return the identity of a path from a scan, or report why it has none
+/// and fail the scan. The errors of the crate name the path.
+fn identified(
+    id: io::Result<FileId>,
+    status: &mut ExitCode,
+) -> Option<FileId> {
+    match id {
+        Ok(id) => Some(id),
+        Err(err) => {
+            eprintln!("cfi: {}", err);
+            *status = ExitCode::FAILURE;
+            None
+        }
+    }
+}
+
+fn print_doctor(path: &Path) -> io::Result<ExitCode> {
+    println!("{}", doctor(path)?);
+    Ok(ExitCode::SUCCESS)
+}
+
+/// List the paths of everything but directories under `root`, in sorted
+/// order. Symbolic links to directories are listed rather than followed.
+fn walk(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut stack = vec![DirHandle::open(root)?];
+    while let Some(dir) = stack.pop() {
+        for entry in dir.read()? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                stack.push(entry.pin_dir()?);
+            } else {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
@@ -0,0 +1,69 @@
+use std::io;
+
+use io_lifetimes::BorrowedFilelike;
+use io_lifetimes::raw::AsRawFilelike;
+
+use crate::Handle;
+
+/// A [`Handle`] to a file owned by another part of the program.
+///
+/// The handle borrows a [`BorrowedFd`] or [`BorrowedHandle`], so the borrow
+/// checker guarantees that the file stays open, and the identity valid, for
+/// as long as the handle is used. It compares equal to any other handle to
+/// the same file, owned or borrowed. Borrowing an existing handle with
+/// [`Handle::as_borrowed`] does not query the identity again.
+///
+/// # Examples
+///
+/// Passing a handle to code that should not take ownership of it:
+///
+/// ```rust
+/// use std::fs::File;
+///
+/// use cross_file_id::{BorrowedHandle, Handle};
+///
+/// fn is_config(file: BorrowedHandle<'_>, config: &Handle<File>) -> bool {
+///     file == *config
+/// }
+///
+/// let config = Handle::from_path(".")?;
+/// assert!(is_config(Handle::as_borrowed(&config), &config));
+/// # Ok::<_, std::io::Error>(())
+/// ```
+///
+/// [`BorrowedFd`]: https://doc.rust-lang.org/std/os/fd/struct.BorrowedFd.html
+/// [`BorrowedHandle`]: https://doc.rust-lang.org/std/os/windows/io/struct.BorrowedHandle.html
+pub type BorrowedHandle<'a> = Handle<BorrowedFilelike<'a>>;
+
+impl<'a> Handle<BorrowedFilelike<'a>> {
+    /// Construct a handle from a borrowed descriptor or handle.
+    ///
+    /// The identity is valid for as long as the borrow.
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] if the identity of the
+    /// file cannot be obtained.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn from_borrowed(
+        file: BorrowedFilelike<'a>,
+    ) -> io::Result<BorrowedHandle<'a>> {
+        Handle::from_file_like(file)
+    }
+}
+
+impl<F: AsRawFilelike> Handle<F> {
+    /// Borrow this handle as a [`BorrowedHandle`], reusing its identity.
+    ///
+    /// This is provided as an associated function instead of a method
+    /// to ensure that operations that rely on the value being accessible via
+    /// dereference aren't accidentally masked.
+    pub fn as_borrowed(this: &Self) -> BorrowedHandle<'_> {
+        // SAFETY: `this` keeps the underlying file open for the lifetime of
+        // the borrow.
+        let file = unsafe {
+            BorrowedFilelike::borrow_raw(this.handle.as_raw_filelike())
+        };
+        Handle::new(file, this.identity.clone(), this.kind, this.mode)
+    }
+}
@@ -0,0 +1,740 @@
+//! A small protocol for brokering file access by identity.
+//!
+//! This module is only available with the `broker` feature. It supports the
+//! architecture where a sandboxed child cannot open files itself, and instead
+//! asks a privileged parent for them:
+//!
+//! 1. The child sends a [`FileRequest`] naming a path, or a file it was
+//!    previously granted, together with an [`AccessMode`].
+//! 2. The parent's [`Broker`] checks the request against its policy, opens
+//!    the file, and answers with a [`FileGrant`] carrying a [`SignedId`] for
+//!    the opened file. The descriptor itself is passed alongside the grant.
+//! 3. The child checks that the descriptor it received has the identity
+//!    claimed by the grant with [`FileGrant::accept`].
+//!
+//! When a file is requested again by identity, the broker verifies the
+//! signature on the token and checks that the file it reopens is still the
+//! one it granted before, so a child can never be handed a file that was
+//! swapped in behind the broker's back.
+//!
+//! Requested paths are resolved before the policy sees them, so that a
+//! symbolic link cannot lead the broker to a file the policy would refuse.
+//! Whatever the policy allows, only regular files and directories are
+//! granted: the [`DeviceClass`] of every opened file is checked, so that a
+//! FIFO, device or socket swapped in for a file is refused.
+//!
+//! The messages implement `serde`'s traits so that any encoding can be used.
+//! On Unix, the [`unix`] submodule provides a ready-made transport over a
+//! `UnixStream`, passing descriptors with `SCM_RIGHTS`.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::signed::SignedId;
+use crate::{AsRawFilelike, DeviceClass, FileId, Handle};
+
+/// The kind of access requested for a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AccessMode {
+    /// Open the file for reading.
+    Read,
+    /// Open the file for writing.
+    Write,
+    /// Open the file for reading and writing.
+    ReadWrite,
+}
+
+impl AccessMode {
+    /// Open a file with this access, refusing anything other than a regular
+    /// file or a directory.
+    ///
+    /// On Unix, the file is opened with `O_NONBLOCK` so that a FIFO swapped
+    /// in for the file cannot block the broker, and with `O_NOCTTY` so that
+    /// a terminal does not become its controlling terminal. `O_NONBLOCK` is
+    /// cleared once the file is known to be regular.
+    ///
+    /// Only regular files and directories are granted, by their
+    /// [`DeviceClass`], which comes from the same query as the identity.
+    fn open(self, path: &Path) -> io::Result<Handle<File>> {
+        let mut options = OpenOptions::new();
+        match self {
+            AccessMode::Read => options.read(true),
+            AccessMode::Write => options.write(true),
+            AccessMode::ReadWrite => options.read(true).write(true),
+        };
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+
+            options.custom_flags(libc::O_NONBLOCK | libc::O_NOCTTY);
+        }
+        let handle = Handle::from_file_like(options.open(path)?)?;
+        let class = Handle::device_class(&handle)?;
+        if !matches!(class, DeviceClass::RegularFile | DeviceClass::Directory)
+        {
+            return Err(denied(&format!("a {} cannot be granted", class)));
+        }
+        #[cfg(unix)]
+        crate::imp::set_blocking(handle.as_raw_filelike())?;
+        Ok(handle)
+    }
+
+    fn scope(self) -> &'static [u8] {
+        match self {
+            AccessMode::Read => b"read",
+            AccessMode::Write => b"write",
+            AccessMode::ReadWrite => b"read-write",
+        }
+    }
+
+    fn from_scope(scope: &[u8]) -> Option<AccessMode> {
+        match scope {
+            b"read" => Some(AccessMode::Read),
+            b"write" => Some(AccessMode::Write),
+            b"read-write" => Some(AccessMode::ReadWrite),
+            _ => None,
+        }
+    }
+
+    /// Returns true if a grant with this access allows `other`.
+    fn covers(self, other: AccessMode) -> bool {
+        self == other || self == AccessMode::ReadWrite
+    }
+}
+
+/// The file a [`FileRequest`] refers to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RequestTarget {
+    /// A file by path.
+    Path(PathBuf),
+    /// A file that was previously granted, identified by the token of its
+    /// grant.
+    Granted(Vec<u8>),
+}
+
+/// A request from a child for access to a file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileRequest {
+    /// The file being requested.
+    pub target: RequestTarget,
+    /// The access being requested.
+    pub mode: AccessMode,
+}
+
+/// The answer to a [`FileRequest`].
+///
+/// The descriptor or handle for the file is not part of this value, and
+/// has to be passed alongside it by the transport.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileGrant {
+    /// The encoded [`SignedId`] of the granted file.
+    pub token: Vec<u8>,
+    /// The access that was granted.
+    pub mode: AccessMode,
+}
+
+impl FileGrant {
+    /// Accept a granted file on the receiving side, checking that it has the
+    /// identity claimed by the grant.
+    ///
+    /// The receiving side does not hold the broker's key, so this checks the
+    /// consistency of the grant and the file, not who issued the grant.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] with
+    /// [`io::ErrorKind::InvalidData`] if the token is malformed or does not
+    /// match the file, or if the identity of the file cannot be obtained.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn accept<F: AsRawFilelike>(&self, file: F) -> io::Result<Handle<F>> {
+        let token = SignedId::from_bytes(&self.token)?;
+        let handle = Handle::from_file_like(file)?;
+        if !token.claims(&fail_identity!(Handle::id(&handle))) {
+            log_warn!(
+                "granted file {:?} does not match the grant",
+                Handle::id(&handle)
+            );
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "granted file does not match the grant",
+            ));
+        }
+        Ok(handle)
+    }
+
+    /// Build a request for this file again, with the access of this grant or
+    /// less of it.
+    pub fn request(&self, mode: AccessMode) -> FileRequest {
+        FileRequest {
+            target: RequestTarget::Granted(self.token.clone()),
+            mode,
+        }
+    }
+}
+
+type Policy = Box<dyn Fn(&Path, AccessMode) -> bool + Send + Sync>;
+
+/// The number of grants a broker keeps by default, see
+/// [`Broker::with_max_grants`].
+pub const DEFAULT_MAX_GRANTS: usize = 1024;
+
+/// The privileged side of the protocol, which answers [`FileRequest`]s.
+///
+/// Each granted file is kept open by the broker until its grant is
+/// [revoked](Broker::revoke), so that its identity cannot be reused by
+/// another file while the grant is outstanding. The number of outstanding
+/// grants is bounded, so that a child cannot exhaust the open files of the
+/// broker.
+pub struct Broker {
+    key: Vec<u8>,
+    policy: Policy,
+    granted: HashMap<[u8; 32], (PathBuf, File)>,
+    max_grants: usize,
+}
+
+impl std::fmt::Debug for Broker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Broker").field("granted", &self.granted).finish()
+    }
+}
+
+fn denied(message: &str) -> io::Error {
+    log_warn!("broker denied request: {}", message);
+    io::Error::new(io::ErrorKind::PermissionDenied, message)
+}
+
+impl Broker {
+    /// Create a broker that signs grants with `key`, and allows access to a
+    /// path in a given mode only if `policy` returns true.
+    ///
+    /// The policy is given the path with symbolic links resolved, as by
+    /// [`fs::canonicalize`], and never the path as requested. The file is
+    /// opened through the resolved path, which is checked again once the
+    /// file is open. A child that can change the directories along the path
+    /// may still race the broker by replacing one of them with a symbolic
+    /// link and back while the file is opened, so the directories the
+    /// policy allows should not be writable by the child.
+    ///
+    /// The broker keeps up to [`DEFAULT_MAX_GRANTS`] grants.
+    ///
+    /// [`fs::canonicalize`]: https://doc.rust-lang.org/std/fs/fn.canonicalize.html
+    pub fn new<P>(key: &[u8], policy: P) -> Broker
+    where
+        P: Fn(&Path, AccessMode) -> bool + Send + Sync + 'static,
+    {
+        Broker {
+            key: key.to_vec(),
+            policy: Box::new(policy),
+            granted: HashMap::new(),
+            max_grants: DEFAULT_MAX_GRANTS,
+        }
+    }
+
+    /// Keep up to `max` grants, each of which holds a file open. Requests
+    /// for files that were not granted yet are refused once the limit is
+    /// reached, until grants are [revoked](Broker::revoke).
+    pub fn with_max_grants(mut self, max: usize) -> Broker {
+        self.max_grants = max;
+        self
+    }
+
+    /// Answer a request, returning the opened file and the grant to send
+    /// along with it.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] with
+    /// [`io::ErrorKind::PermissionDenied`] if the policy refuses the request,
+    /// or if a token is invalid, was not issued by this broker, was revoked
+    /// or was issued for less access than requested, if the limit of
+    /// outstanding grants is reached, if the path changed while the file was
+    /// opened, or if it is not a regular file or a directory. It returns an
+    /// error with [`io::ErrorKind::InvalidData`] if a previously granted file
+    /// no longer has the identity it was granted with, and otherwise fails if
+    /// the path cannot be resolved or the file cannot be opened.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn handle(
+        &mut self,
+        request: &FileRequest,
+    ) -> io::Result<(Handle<File>, FileGrant)> {
+        let (path, expected) = match request.target {
+            RequestTarget::Path(ref path) => (fs::canonicalize(path)?, None),
+            RequestTarget::Granted(ref token) => {
+                let token = SignedId::from_bytes(token)
+                    .map_err(|_| denied("malformed grant token"))?;
+                if !token.verify(&self.key) {
+                    return Err(denied("grant token failed verification"));
+                }
+                // A grant never allows more access than it was issued for,
+                // which has to be requested by path again.
+                let granted = AccessMode::from_scope(token.scope());
+                if !granted.is_some_and(|mode| mode.covers(request.mode)) {
+                    return Err(denied("grant token does not cover the mode"));
+                }
+                let path = self
+                    .granted
+                    .get(token.packed())
+                    .map(|(path, _)| path.clone())
+                    .ok_or_else(|| denied("grant token is unknown"))?;
+                (path, Some(token))
+            }
+        };
+        if !(self.policy)(&path, request.mode) {
+            return Err(denied("request refused by policy"));
+        }
+        fail_point!(Open);
+        let handle = request.mode.open(&path)?;
+        let id = Handle::id(&handle);
+        match expected {
+            Some(token) if !token.claims(&fail_identity!(id.clone())) => {
+                log_warn!("granted file at {:?} was replaced", path);
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "granted file was replaced",
+                ));
+            }
+            // A directory along the path may have been replaced by a
+            // symbolic link since the path was resolved.
+            None if fs::canonicalize(&path)? != path => {
+                return Err(denied("path changed while it was opened"));
+            }
+            _ => {}
+        }
+        let packed = id.to_packed();
+        if self.granted.len() >= self.max_grants
+            && !self.granted.contains_key(&packed)
+        {
+            return Err(denied("too many outstanding grants"));
+        }
+        log_debug!("broker granted {:?} for {:?}", path, request.mode);
+        let token = SignedId::sign(&self.key, &id, request.mode.scope());
+        let pin = handle.try_clone()?;
+        self.granted.insert(packed, (path, pin));
+        Ok((handle, FileGrant { token: token.to_bytes(), mode: request.mode }))
+    }
+
+    /// Returns true if this broker has granted access to the identity.
+    pub fn has_granted(&self, id: &FileId) -> bool {
+        self.granted.contains_key(&id.to_packed())
+    }
+
+    /// Revoke the grants of a file, closing the broker's copy of it.
+    ///
+    /// The tokens of the file are then refused, and the file has to be
+    /// requested by path again. Files already passed to the child stay
+    /// open. Returns true if the file was granted.
+    pub fn revoke(&mut self, id: &FileId) -> bool {
+        self.granted.remove(&id.to_packed()).is_some()
+    }
+}
+
+/// A transport for the broker protocol over Unix domain sockets.
+///
+/// Messages are JSON documents prefixed by their length as a 32-bit
+/// big-endian integer, and are at most [`MAX_FRAME`](unix::MAX_FRAME)
+/// bytes long. Descriptors are passed with `SCM_RIGHTS` on the message
+/// carrying the grant, and are received close-on-exec.
+#[cfg(unix)]
+pub mod unix {
+    use std::fs::File;
+    use std::io::{self, Read, Write};
+    use std::mem;
+    use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+    use std::os::unix::net::UnixStream;
+    use std::ptr;
+
+    use serde::Serialize;
+    use serde::de::DeserializeOwned;
+
+    use super::{FileGrant, FileRequest};
+    use crate::Handle;
+
+    /// The largest message body that is sent or received, in bytes.
+    ///
+    /// Larger messages are refused before anything is allocated for them, so
+    /// that a peer cannot make the other side allocate arbitrary amounts of
+    /// memory.
+    pub const MAX_FRAME: u32 = 64 * 1024;
+
+    fn frame<T: Serialize>(message: &T) -> io::Result<Vec<u8>> {
+        let body = serde_json::to_vec(message)?;
+        let len = u32::try_from(body.len())
+            .ok()
+            .filter(|&len| len <= MAX_FRAME)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "message too large",
+                )
+            })?;
+        let mut frame = len.to_be_bytes().to_vec();
+        frame.extend_from_slice(&body);
+        Ok(frame)
+    }
+
+    fn read_body<T: DeserializeOwned>(
+        stream: &mut UnixStream,
+        header: [u8; 4],
+    ) -> io::Result<T> {
+        let len = u32::from_be_bytes(header);
+        if len > MAX_FRAME {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "message too large",
+            ));
+        }
+        let mut body = vec![0; len as usize];
+        stream.read_exact(&mut body)?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// Send a request to the broker.
+    pub fn send_request(
+        stream: &mut UnixStream,
+        request: &FileRequest,
+    ) -> io::Result<()> {
+        stream.write_all(&frame(request)?)
+    }
+
+    /// Receive a request from a child.
+    pub fn recv_request(stream: &mut UnixStream) -> io::Result<FileRequest> {
+        let mut header = [0; 4];
+        stream.read_exact(&mut header)?;
+        read_body(stream, header)
+    }
+
+    /// Send a grant along with the descriptor of the granted file.
+    pub fn send_grant(
+        stream: &mut UnixStream,
+        grant: &FileGrant,
+        file: &Handle<File>,
+    ) -> io::Result<()> {
+        let frame = frame(grant)?;
+        send_with_fd(stream.as_raw_fd(), &frame[..4], file.as_raw_fd())?;
+        stream.write_all(&frame[4..])
+    }
+
+    /// Receive a grant and its descriptor, and check that they match.
+    pub fn recv_grant(
+        stream: &mut UnixStream,
+    ) -> io::Result<(Handle<File>, FileGrant)> {
+        let mut header = [0; 4];
+        let file = File::from(recv_with_fd(stream.as_raw_fd(), &mut header)?);
+        let grant: FileGrant = read_body(stream, header)?;
+        let handle = grant.accept(file)?;
+        Ok((handle, grant))
+    }
+
+    const FD_SIZE: u32 = mem::size_of::<RawFd>() as u32;
+
+    fn send_with_fd(socket: RawFd, data: &[u8], fd: RawFd) -> io::Result<()> {
+        // SAFETY: The message header points at buffers that outlive the
+        // call, and the control buffer is large and aligned enough for a
+        // single descriptor.
+        unsafe {
+            let space = libc::CMSG_SPACE(FD_SIZE) as usize;
+            let mut control = vec![0u64; space.div_ceil(8)];
+            let mut iov = libc::iovec {
+                iov_base: data.as_ptr() as *mut _,
+                iov_len: data.len(),
+            };
+            let mut msg: libc::msghdr = mem::zeroed();
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            msg.msg_control = control.as_mut_ptr().cast();
+            msg.msg_controllen = space as _;
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(FD_SIZE) as _;
+            ptr::write_unaligned(libc::CMSG_DATA(cmsg).cast::<RawFd>(), fd);
+            if libc::sendmsg(socket, &msg, 0) != data.len() as isize {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// Flags that make received descriptors close-on-exec as they are
+    /// created, where `recvmsg` supports it.
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd"
+    ))]
+    const RECV_FLAGS: libc::c_int = libc::MSG_CMSG_CLOEXEC;
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd"
+    )))]
+    const RECV_FLAGS: libc::c_int = 0;
+
+    fn recv_with_fd(socket: RawFd, data: &mut [u8]) -> io::Result<OwnedFd> {
+        let invalid =
+            |message| io::Error::new(io::ErrorKind::InvalidData, message);
+        // Every descriptor received is owned before anything is checked, so
+        // that it is closed on every error.
+        let mut fds = Vec::new();
+        // SAFETY: As in `send_with_fd`. Any descriptor found in the control
+        // message is newly created for this process.
+        let (received, flags) = unsafe {
+            let space = libc::CMSG_SPACE(FD_SIZE) as usize;
+            let mut control = vec![0u64; space.div_ceil(8)];
+            let mut iov = libc::iovec {
+                iov_base: data.as_mut_ptr().cast(),
+                iov_len: data.len(),
+            };
+            let mut msg: libc::msghdr = mem::zeroed();
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            msg.msg_control = control.as_mut_ptr().cast();
+            msg.msg_controllen = space as _;
+            let received = libc::recvmsg(
+                socket,
+                &mut msg,
+                libc::MSG_WAITALL | RECV_FLAGS,
+            );
+            if received < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_SOCKET
+                    && (*cmsg).cmsg_type == libc::SCM_RIGHTS
+                {
+                    let data = libc::CMSG_DATA(cmsg).cast::<RawFd>();
+                    let len = (*cmsg).cmsg_len as usize
+                        - (data as usize - cmsg as usize);
+                    for i in 0..len / FD_SIZE as usize {
+                        let fd = ptr::read_unaligned(data.add(i));
+                        fds.push(OwnedFd::from_raw_fd(fd));
+                    }
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+            (received as usize, msg.msg_flags)
+        };
+        if flags & libc::MSG_CTRUNC != 0 {
+            return Err(invalid("descriptors of the grant were truncated"));
+        }
+        if received != data.len() || fds.len() != 1 {
+            return Err(invalid("grant was not accompanied by a descriptor"));
+        }
+        let fd = fds.pop().unwrap();
+        if RECV_FLAGS == 0 {
+            crate::imp::set_inheritable(fd.as_raw_fd(), false)?;
+        }
+        Ok(fd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{AccessMode, Broker, FileRequest, RequestTarget};
+    use crate::testing::TempDir;
+
+    #[test]
+    fn grant_and_regrant() {
+        let tdir = TempDir::new().unwrap();
+        let dir = fs::canonicalize(tdir.path()).unwrap();
+        fs::write(dir.join("allowed"), "data").unwrap();
+        fs::write(dir.join("other"), "data").unwrap();
+
+        let allowed = dir.join("allowed");
+        let mut broker = Broker::new(b"key", move |path, mode| {
+            path == allowed || mode == AccessMode::Read
+        });
+        let request = FileRequest {
+            target: RequestTarget::Path(dir.join("allowed")),
+            mode: AccessMode::ReadWrite,
+        };
+        let (file, grant) = broker.handle(&request).unwrap();
+        assert!(grant.accept(file).is_ok());
+
+        // Requesting a granted file by identity, with less access.
+        let (file, read) =
+            broker.handle(&grant.request(AccessMode::Read)).unwrap();
+        assert!(read.accept(file).is_ok());
+
+        // A grant cannot be upgraded, even where the policy would allow it.
+        let err = broker.handle(&read.request(AccessMode::Write)).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+
+        // The policy is applied to requests by path.
+        let err = broker
+            .handle(&FileRequest {
+                target: RequestTarget::Path(dir.join("other")),
+                mode: AccessMode::ReadWrite,
+            })
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+
+        // A replaced file is detected.
+        fs::remove_file(dir.join("allowed")).unwrap();
+        fs::write(dir.join("allowed"), "new").unwrap();
+        let err = broker.handle(&grant.request(AccessMode::Read)).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        // A forged token is refused.
+        let mut forged = grant.clone();
+        forged.token[0] ^= 1;
+        let err =
+            broker.handle(&forged.request(AccessMode::Read)).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn revoke_and_limit() {
+        let tdir = TempDir::new().unwrap();
+        fs::write(tdir.path().join("a"), "data").unwrap();
+        fs::write(tdir.path().join("b"), "data").unwrap();
+        let mut broker = Broker::new(b"key", |_, _| true).with_max_grants(1);
+        let request = |name| FileRequest {
+            target: RequestTarget::Path(tdir.path().join(name)),
+            mode: AccessMode::Read,
+        };
+
+        let (a, grant) = broker.handle(&request("a")).unwrap();
+        let id = crate::Handle::id(&a);
+        // Granting the same file again does not count against the limit.
+        assert!(broker.handle(&request("a")).is_ok());
+        let err = broker.handle(&request("b")).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+
+        assert!(broker.revoke(&id));
+        assert!(!broker.revoke(&id));
+        assert!(!broker.has_granted(&id));
+        let err = broker.handle(&grant.request(AccessMode::Read)).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+        assert!(broker.handle(&request("b")).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn policy_sees_resolved_path() {
+        let tdir = TempDir::new().unwrap();
+        let dir = fs::canonicalize(tdir.path()).unwrap();
+        fs::create_dir(dir.join("public")).unwrap();
+        fs::write(dir.join("secret"), "data").unwrap();
+        std::os::unix::fs::symlink(
+            dir.join("secret"),
+            dir.join("public/link"),
+        )
+        .unwrap();
+
+        let public = dir.join("public");
+        let mut broker =
+            Broker::new(b"key", move |path, _| path.starts_with(&public));
+        let err = broker
+            .handle(&FileRequest {
+                target: RequestTarget::Path(dir.join("public/link")),
+                mode: AccessMode::Read,
+            })
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn policy_applies_to_grants() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let tdir = TempDir::new().unwrap();
+        fs::write(tdir.path().join("a"), "data").unwrap();
+        let allow = Arc::new(AtomicBool::new(true));
+        let policy = Arc::clone(&allow);
+        let mut broker =
+            Broker::new(b"key", move |_, _| policy.load(Ordering::SeqCst));
+        let (_, grant) = broker
+            .handle(&FileRequest {
+                target: RequestTarget::Path(tdir.path().join("a")),
+                mode: AccessMode::Read,
+            })
+            .unwrap();
+
+        allow.store(false, Ordering::SeqCst);
+        let err = broker.handle(&grant.request(AccessMode::Read)).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_fifo() {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let tdir = TempDir::new().unwrap();
+        let fifo = tdir.path().join("fifo");
+        let path = CString::new(fifo.as_os_str().as_bytes()).unwrap();
+        // SAFETY: the path is a valid C string.
+        assert_eq!(unsafe { libc::mkfifo(path.as_ptr(), 0o600) }, 0);
+
+        // Opening the FIFO for reading would block without a writer.
+        let mut broker = Broker::new(b"key", |_, _| true);
+        let err = broker
+            .handle(&FileRequest {
+                target: RequestTarget::Path(fifo),
+                mode: AccessMode::Read,
+            })
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+        assert_eq!(err.to_string(), "a pipe cannot be granted");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_frame_limit() {
+        use std::io::Write;
+        use std::os::unix::net::UnixStream;
+
+        use super::unix::{MAX_FRAME, recv_request};
+
+        let (mut child, mut parent) = UnixStream::pair().unwrap();
+        child.write_all(&(MAX_FRAME + 1).to_be_bytes()).unwrap();
+        let err = recv_request(&mut parent).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_transport() {
+        use std::os::unix::net::UnixStream;
+
+        use super::unix::{
+            recv_grant, recv_request, send_grant, send_request,
+        };
+
+        let tdir = TempDir::new().unwrap();
+        fs::write(tdir.path().join("a"), "data").unwrap();
+        let mut broker = Broker::new(b"key", |_, _| true);
+        let (mut child, mut parent) = UnixStream::pair().unwrap();
+
+        send_request(
+            &mut child,
+            &FileRequest {
+                target: RequestTarget::Path(tdir.path().join("a")),
+                mode: AccessMode::Read,
+            },
+        )
+        .unwrap();
+        let request = recv_request(&mut parent).unwrap();
+        let (file, grant) = broker.handle(&request).unwrap();
+        send_grant(&mut parent, &grant, &file).unwrap();
+        let (received, received_grant) = recv_grant(&mut child).unwrap();
+        assert_eq!(received, file);
+        assert_eq!(received_grant, grant);
+
+        // The received descriptor is not inherited by child processes.
+        let fd = std::os::unix::io::AsRawFd::as_raw_fd(&received);
+        // SAFETY: F_GETFD only reads the flags of the descriptor.
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+        assert_ne!(flags & libc::FD_CLOEXEC, 0);
+    }
+}
@@ -0,0 +1,37 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+use crate::Handle;
+
+impl Handle<File> {
+    /// Wrap the file of this handle in a [`BufReader`], keeping its
+    /// identity.
+    ///
+    /// The identity is not queried again, as the reader keeps the same file
+    /// open.
+    ///
+    /// This is provided as an associated function instead of a method
+    /// to ensure that operations that rely on the value being accessible via
+    /// dereference aren't accidentally masked.
+    ///
+    /// [`BufReader`]: https://doc.rust-lang.org/std/io/struct.BufReader.html
+    pub fn buffered_reader(this: Self) -> Handle<BufReader<File>> {
+        Handle::map_inner(this, BufReader::new)
+    }
+
+    /// Wrap the file of this handle in a [`BufWriter`], keeping its
+    /// identity.
+    ///
+    /// The identity is not queried again, as the writer keeps the same file
+    /// open. As with any [`BufWriter`], buffered data is flushed when the
+    /// handle is dropped, and errors doing so are ignored.
+    ///
+    /// This is provided as an associated function instead of a method
+    /// to ensure that operations that rely on the value being accessible via
+    /// dereference aren't accidentally masked.
+    ///
+    /// [`BufWriter`]: https://doc.rust-lang.org/std/io/struct.BufWriter.html
+    pub fn buffered_writer(this: Self) -> Handle<BufWriter<File>> {
+        Handle::map_inner(this, BufWriter::new)
+    }
+}
@@ -0,0 +1,195 @@
+use std::fs::File;
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+
+use crate::{FileId, Handle, HandleSet};
+
+/// A task run by a [`Spawner`].
+pub type Task<'a> = Box<dyn FnOnce() + Send + 'a>;
+
+/// Runs the tasks of the bulk identity APIs, such as [`ids_from_paths`].
+///
+/// Implementations decide how the tasks are scheduled: [`Sequential`] runs
+/// them on the calling thread, [`ThreadSpawner`] on scoped standard threads,
+/// and, with the `rayon` feature, `RayonSpawner` on the rayon thread pool.
+/// Applications can implement this trait to run the tasks on their own
+/// thread pool.
+pub trait Spawner {
+    /// Run every task, possibly in parallel, and return once all of them have
+    /// completed.
+    fn run<'a>(&self, tasks: Vec<Task<'a>>);
+}
+
+impl<S: Spawner + ?Sized> Spawner for &S {
+    fn run<'a>(&self, tasks: Vec<Task<'a>>) {
+        (**self).run(tasks)
+    }
+}
+
+/// A [`Spawner`] that runs the tasks one after the other on the calling
+/// thread.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sequential;
+
+impl Spawner for Sequential {
+    fn run<'a>(&self, tasks: Vec<Task<'a>>) {
+        for task in tasks {
+            task();
+        }
+    }
+}
+
+/// A [`Spawner`] that runs the tasks on a fixed number of scoped standard
+/// threads, started for every call.
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadSpawner {
+    threads: NonZeroUsize,
+}
+
+impl ThreadSpawner {
+    /// Create a spawner that uses the given number of threads.
+    pub fn new(threads: NonZeroUsize) -> ThreadSpawner {
+        ThreadSpawner { threads }
+    }
+}
+
+impl Default for ThreadSpawner {
+    /// Use as many threads as [`thread::available_parallelism`] reports, or
+    /// a single thread if it is unknown.
+    fn default() -> ThreadSpawner {
+        ThreadSpawner::new(
+            thread::available_parallelism().unwrap_or(NonZeroUsize::MIN),
+        )
+    }
+}
+
+impl Spawner for ThreadSpawner {
+    fn run<'a>(&self, tasks: Vec<Task<'a>>) {
+        let threads = self.threads.get().min(tasks.len());
+        let queue = Mutex::new(tasks.into_iter());
+        let next = || queue.lock().unwrap().next();
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| {
+                    while let Some(task) = next() {
+                        task();
+                    }
+                });
+            }
+        });
+    }
+}
+
+/// A [`Spawner`] that runs the tasks on the current rayon thread pool.
+#[cfg(feature = "rayon")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RayonSpawner;
+
+#[cfg(feature = "rayon")]
+impl Spawner for RayonSpawner {
+    fn run<'a>(&self, tasks: Vec<Task<'a>>) {
+        rayon::scope(|scope| {
+            for task in tasks {
+                scope.spawn(move |_| task());
+            }
+        });
+    }
+}
+
+/// Apply `f` to every item with the spawner, returning the results in the
+/// order of the items.
+pub(crate) fn map_with<S, T, R, F>(spawner: &S, items: &[T], f: F) -> Vec<R>
+where
+    S: Spawner + ?Sized,
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let mut results: Vec<Option<R>> = items.iter().map(|_| None).collect();
+    let f = &f;
+    let tasks = items
+        .iter()
+        .zip(results.iter_mut())
+        .map(|(item, slot)| -> Task<'_> {
+            Box::new(move || *slot = Some(f(item)))
+        })
+        .collect();
+    spawner.run(tasks);
+    results
+        .into_iter()
+        .map(|result| result.expect("spawner did not run every task"))
+        .collect()
+}
+
+/// Take the identity of every path, running the work with the given
+/// spawner.
+///
+/// The results are in the order of the paths. As with
+/// [`FileId::from_path`], the files are not kept open.
+///
+/// # Panics
+///
+/// Panics if the spawner returns without running every task.
+pub fn ids_from_paths<S, P>(
+    spawner: &S,
+    paths: &[P],
+) -> Vec<io::Result<FileId>>
+where
+    S: Spawner + ?Sized,
+    P: AsRef<Path> + Sync,
+{
+    map_with(spawner, paths, |path| FileId::from_path(path))
+}
+
+/// Take the identity of every path, without following a final symbolic
+/// link, running the work with the given spawner.
+///
+/// This is [`ids_from_paths`] with [`FileId::from_path_no_follow`].
+///
+/// # Panics
+///
+/// Panics if the spawner returns without running every task.
+pub fn ids_from_paths_no_follow<S, P>(
+    spawner: &S,
+    paths: &[P],
+) -> Vec<io::Result<FileId>>
+where
+    S: Spawner + ?Sized,
+    P: AsRef<Path> + Sync,
+{
+    map_with(spawner, paths, |path| FileId::from_path_no_follow(path))
+}
+
+impl HandleSet<File> {
+    /// Build a set by opening every path, running the work with the given
+    /// spawner.
+    ///
+    /// Files are inserted in the order of the paths, so a set created this
+    /// way is the same as one created by
+    /// [`from_paths`](HandleSet::from_paths).
+    ///
+    /// # Errors
+    /// This function will return the [`io::Error`] of the first path, in
+    /// order, that could not be opened.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    ///
+    /// # Panics
+    ///
+    /// Panics if the spawner returns without running every task.
+    pub fn from_paths_with<S, P>(
+        spawner: &S,
+        paths: &[P],
+    ) -> io::Result<HandleSet<File>>
+    where
+        S: Spawner + ?Sized,
+        P: AsRef<Path> + Sync,
+    {
+        map_with(spawner, paths, |path| Handle::from_path(path))
+            .into_iter()
+            .collect()
+    }
+}
@@ -0,0 +1,41 @@
+//! Integration with the UTF-8 paths of `camino`.
+//!
+//! Every path-based constructor of this crate takes `impl AsRef<Path>`, which
+//! `Utf8Path` and `Utf8PathBuf` implement, so they can be passed as is. This
+//! module adds the methods that return paths in their UTF-8 form.
+
+use std::io;
+
+use camino::Utf8PathBuf;
+
+use crate::{AsRawFilelike, DirHandle, Handle};
+
+impl<F> Handle<F>
+where
+    F: AsRawFilelike,
+{
+    /// Returns the current path of the file this handle refers to, as a
+    /// UTF-8 path.
+    ///
+    /// This is the same as [`current_path`](Handle::current_path), with the
+    /// same caveats.
+    ///
+    /// # Errors
+    /// This method fails as [`current_path`](Handle::current_path) does, and
+    /// with [`io::ErrorKind::InvalidData`] if the path is not valid UTF-8.
+    pub fn current_utf8_path(&self) -> io::Result<Utf8PathBuf> {
+        Utf8PathBuf::from_path_buf(self.current_path()?).map_err(|path| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("path is not valid UTF-8: {}", path.display()),
+            )
+        })
+    }
+}
+
+impl DirHandle {
+    /// The path this directory was opened with, if it is valid UTF-8.
+    pub fn utf8_path(&self) -> Option<&camino::Utf8Path> {
+        camino::Utf8Path::from_path(self.path())
+    }
+}
@@ -0,0 +1,63 @@
+use crate::imp;
+
+/// What this crate can do on the current platform.
+///
+/// Returned by [`capabilities`]. Each field is a fact about the running
+/// system rather than about the target it was compiled for, so programs can
+/// branch on it instead of guessing from `cfg!(windows)`. New fields may be
+/// added as the crate learns to do more.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// Files have identities at all. When false, every operation that takes
+    /// an identity fails.
+    pub identity: bool,
+    /// Identities are a best guess rather than read from the filesystem, so
+    /// two distinct files may share one. This is the case on platforms that
+    /// have no file index, where identities are derived from metadata.
+    pub heuristic: bool,
+    /// The identity of an open file cannot be reused by another file while
+    /// a [`Handle`](crate::Handle) keeps it open. When false, identities are
+    /// derived from metadata and two distinct files may share one.
+    pub pins_while_open: bool,
+    /// Identities of files that still exist stay the same across reboots
+    /// and remounts, so they can be persisted.
+    pub stable_across_reboots: bool,
+    /// Identities taken with
+    /// [`FileId::from_file_like_mount_aware`](crate::FileId::from_file_like_mount_aware)
+    /// tell apart different mounts of the same filesystem, such as bind
+    /// mounts. Other identities never do.
+    pub mount_ids: bool,
+    /// Files that share their data through reflinks can be detected.
+    pub reflink_detection: bool,
+    /// [`Handle::current_path`](crate::Handle::current_path) can recover
+    /// the path of an open file.
+    pub current_path: bool,
+    /// Symbolic links can be opened rather than followed, as with
+    /// [`Handle::from_path_no_follow`](crate::Handle::from_path_no_follow).
+    pub open_no_follow: bool,
+    /// [`HandleOptions::minimal_access`](crate::HandleOptions::minimal_access)
+    /// opens files without asking for read access.
+    pub minimal_access: bool,
+    /// Mounted volumes can be listed and watched, with the `mount-watch`
+    /// feature enabled.
+    pub mount_watch: bool,
+}
+
+/// Report what this crate can do on the current platform.
+///
+/// # Examples
+///
+/// Only persisting identities where they survive a reboot:
+///
+/// ```rust
+/// let caps = cross_file_id::capabilities();
+/// if caps.stable_across_reboots {
+///     // Store identities in the on-disk index.
+/// } else {
+///     // Store paths, and take identities again at startup.
+/// }
+/// ```
+pub fn capabilities() -> Capabilities {
+    imp::capabilities()
+}
@@ -0,0 +1,145 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::FileId;
+
+/// What happened to the file at a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IdChangeKind {
+    /// A file appeared where there was none.
+    Created,
+    /// The file disappeared, and nothing took its place.
+    Deleted,
+    /// A different file took the place of the file.
+    Replaced,
+    /// The file was found at a different path.
+    Moved,
+}
+
+impl fmt::Display for IdChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            IdChangeKind::Created => "created",
+            IdChangeKind::Deleted => "deleted",
+            IdChangeKind::Replaced => "replaced",
+            IdChangeKind::Moved => "moved",
+        })
+    }
+}
+
+/// A change of identity observed at a path.
+///
+/// This is a common record for the different ways of noticing that a file
+/// was replaced, moved or deleted, so that applications can log, persist
+/// and replay such events in one format. With the `serde` feature, it
+/// implements `Serialize` and `Deserialize`. Like any [`FileId`], the
+/// identities it holds are only meaningful while the files exist.
+///
+/// # Examples
+///
+/// Checking whether a configuration file was swapped out:
+///
+/// ```rust,no_run
+/// use cross_file_id::{FileId, IdChange};
+///
+/// let id = FileId::from_path("app.toml")?;
+/// // ...
+/// if let Some(change) = IdChange::observe("app.toml", Some(&id))? {
+///     eprintln!("{}", change);
+/// }
+/// # Ok::<_, std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IdChange {
+    /// What happened.
+    pub kind: IdChangeKind,
+    /// The identity before the change, if there was a file.
+    pub old_id: Option<FileId>,
+    /// The identity after the change, if there is a file.
+    pub new_id: Option<FileId>,
+    /// The path the change was observed at. For a move, this is the new
+    /// path.
+    pub path: PathBuf,
+    /// When the change was observed.
+    pub observed_at: SystemTime,
+}
+
+impl IdChange {
+    /// Classify the difference between the identities of the file at
+    /// `path` before and after, as observed now.
+    ///
+    /// Returns `None` if nothing changed.
+    pub fn between<P: AsRef<Path>>(
+        path: P,
+        old_id: Option<FileId>,
+        new_id: Option<FileId>,
+    ) -> Option<IdChange> {
+        let kind = match (&old_id, &new_id) {
+            (None, None) => return None,
+            (Some(old), Some(new)) if old == new => return None,
+            (None, Some(_)) => IdChangeKind::Created,
+            (Some(_), None) => IdChangeKind::Deleted,
+            (Some(_), Some(_)) => IdChangeKind::Replaced,
+        };
+        Some(IdChange {
+            kind,
+            old_id,
+            new_id,
+            path: path.as_ref().to_path_buf(),
+            observed_at: SystemTime::now(),
+        })
+    }
+
+    /// Compare the identity of the file currently at `path` with the one
+    /// that was expected there, if any.
+    ///
+    /// A missing file counts as no identity. Returns `None` if nothing
+    /// changed.
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] if the identity of the
+    /// file at `path` cannot be obtained for any reason other than the file
+    /// not existing.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn observe<P: AsRef<Path>>(
+        path: P,
+        expected: Option<&FileId>,
+    ) -> io::Result<Option<IdChange>> {
+        let path = path.as_ref();
+        let current = match FileId::from_path(path) {
+            Ok(id) => Some(id),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+        Ok(IdChange::between(path, expected.cloned(), current))
+    }
+
+    /// Record that the file with identity `id` was found at `new_path`.
+    pub fn moved<P: AsRef<Path>>(id: FileId, new_path: P) -> IdChange {
+        IdChange {
+            kind: IdChangeKind::Moved,
+            old_id: Some(id.clone()),
+            new_id: Some(id),
+            path: new_path.as_ref().to_path_buf(),
+            observed_at: SystemTime::now(),
+        }
+    }
+}
+
+impl fmt::Display for IdChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.path.display(), self.kind)?;
+        match (&self.old_id, &self.new_id) {
+            (Some(old), Some(new)) if old != new => {
+                write!(f, " ({} -> {})", old, new)
+            }
+            (Some(id), _) | (None, Some(id)) => write!(f, " ({})", id),
+            (None, None) => Ok(()),
+        }
+    }
+}
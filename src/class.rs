@@ -0,0 +1,156 @@
+use std::fmt;
+use std::io;
+
+use crate::{AsRawFilelike, Error, FileId, Handle, RawFilelike, imp};
+
+/// The kind of object an open file refers to.
+///
+/// Identities are only meaningful for objects that live on a filesystem.
+/// Pipes, sockets and consoles may have an identity on Unix, which is that
+/// of an anonymous inode. On Windows, pipes with a single instance have an
+/// identity derived from their name, shared by both of their ends, and
+/// consoles one that is only meaningful within the process, while other
+/// pipes, sockets and other character devices have none. Classifying a file
+/// makes it possible to refuse or special-case such objects deliberately,
+/// rather than through the error of a failed identity query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceClass {
+    /// A block device, such as a disk.
+    BlockDevice,
+    /// A character device other than a console, such as `/dev/null` or
+    /// `NUL`.
+    CharDevice,
+    /// A terminal or console.
+    Console,
+    /// A pipe or FIFO. On Windows, this includes sockets.
+    Pipe,
+    /// A socket.
+    Socket,
+    /// A regular file.
+    RegularFile,
+    /// A directory.
+    Directory,
+    /// A symbolic link, opened without following it.
+    Symlink,
+    /// Anything else.
+    Other,
+}
+
+impl DeviceClass {
+    /// Classify an open file.
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] if the file cannot be
+    /// queried.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn of<F: AsRawFilelike>(file: &F) -> io::Result<DeviceClass> {
+        DeviceClass::of_raw(file.as_raw_filelike())
+    }
+
+    /// Classify an open file from its raw OS file descriptor or handle.
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] if the file cannot be
+    /// queried.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn of_raw(os_file: RawFilelike) -> io::Result<DeviceClass> {
+        fail_point!(Stat);
+        imp::device_class(os_file).map_err(Error::metadata_failed)
+    }
+
+    /// Returns true for regular files, directories and symbolic links, the
+    /// objects that live on a filesystem.
+    pub fn is_filesystem_object(&self) -> bool {
+        matches!(
+            self,
+            DeviceClass::RegularFile
+                | DeviceClass::Directory
+                | DeviceClass::Symlink
+        )
+    }
+}
+
+impl fmt::Display for DeviceClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DeviceClass::BlockDevice => "block device",
+            DeviceClass::CharDevice => "character device",
+            DeviceClass::Console => "console",
+            DeviceClass::Pipe => "pipe",
+            DeviceClass::Socket => "socket",
+            DeviceClass::RegularFile => "regular file",
+            DeviceClass::Directory => "directory",
+            DeviceClass::Symlink => "symbolic link",
+            DeviceClass::Other => "special file",
+        })
+    }
+}
+
+impl FileId {
+    /// Extract the identity of an open file together with its
+    /// [`DeviceClass`].
+    ///
+    /// On Unix and WASI, both come from a single `fstat` call. On Windows,
+    /// a socket or a character device other than a console has no identity,
+    /// and fails with an error that names its class.
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] if the file cannot be
+    /// queried, or if it has no identity on this platform.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn from_file_like_classified<F: AsRawFilelike>(
+        file: &F,
+    ) -> io::Result<(FileId, DeviceClass)> {
+        fail_point!(Stat);
+        let (id, class) =
+            imp::FileId::from_filelike_classified(file.as_raw_filelike())
+                .map_err(Error::metadata_failed)?;
+        Ok((FileId(id), class))
+    }
+}
+
+impl<F> Handle<F> {
+    /// The kind of object this handle refers to, as recorded when the
+    /// handle was built.
+    ///
+    /// The class comes from the same query as the identity where the
+    /// platform allows it, so this never touches the file. It is `None`
+    /// only for handles built with [`from_parts`](Handle::from_parts), or
+    /// when the file could not be classified; [`device_class`] then asks
+    /// the OS.
+    ///
+    /// This is provided as an associated function instead of a method
+    /// to ensure that operations that rely on the value being accessible via
+    /// dereference aren't accidentally masked.
+    ///
+    /// [`device_class`]: Handle::device_class
+    pub fn kind(this: &Self) -> Option<DeviceClass> {
+        this.kind
+    }
+}
+
+impl<F: AsRawFilelike> Handle<F> {
+    /// Classify the file this handle refers to.
+    ///
+    /// The class recorded when the handle was built is returned if there is
+    /// one, and the file is queried otherwise.
+    ///
+    /// This is provided as an associated function instead of a method
+    /// to ensure that operations that rely on the value being accessible via
+    /// dereference aren't accidentally masked.
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] if the file cannot be
+    /// queried.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn device_class(this: &Self) -> io::Result<DeviceClass> {
+        match this.kind {
+            Some(kind) => Ok(kind),
+            None => DeviceClass::of(&this.handle),
+        }
+    }
+}
@@ -0,0 +1,817 @@
+use std::collections::hash_map::{self, HashMap};
+use std::fs::File;
+use std::hash::Hash;
+use std::io;
+use std::path::Path;
+use std::{slice, vec};
+
+use crate::{FileId, FileIdentitySource, Handle, OsIdentity};
+
+/// A set of open handles, deduplicated by file identity.
+///
+/// Since every handle in the set keeps its file open, the identities used
+/// for deduplication remain valid for as long as the handles are in the set.
+///
+/// By default, the set is iterated in arbitrary order. A set created with
+/// [`preserving_order`](HandleSet::preserving_order) is instead iterated in
+/// the order in which files were first inserted, which gives deterministic
+/// output when deduplicating a list of paths.
+#[derive(Debug)]
+pub struct HandleSet<F> {
+    handles: HashMap<FileId, Handle<F>>,
+    // The identities in first-seen order, if the set preserves it.
+    order: Option<Vec<FileId>>,
+}
+
+impl<F> HandleSet<F> {
+    /// Create an empty set, iterated in arbitrary order.
+    pub fn new() -> HandleSet<F> {
+        HandleSet { handles: HashMap::new(), order: None }
+    }
+
+    /// Create an empty set, iterated in the order in which files were first
+    /// inserted.
+    ///
+    /// Removing a handle from such a set takes time proportional to the size
+    /// of the set.
+    pub fn preserving_order() -> HandleSet<F> {
+        HandleSet { handles: HashMap::new(), order: Some(Vec::new()) }
+    }
+
+    /// Insert a handle into the set.
+    ///
+    /// Returns true if the handle's file was not already in the set. If it
+    /// was, the set is left unchanged and the given handle is dropped.
+    pub fn insert(&mut self, handle: Handle<F>) -> bool {
+        match self.handles.entry(Handle::id(&handle)) {
+            hash_map::Entry::Occupied(_) => false,
+            hash_map::Entry::Vacant(entry) => {
+                if let Some(ref mut order) = self.order {
+                    order.push(entry.key().clone());
+                }
+                entry.insert(handle);
+                true
+            }
+        }
+    }
+
+    /// Returns true if the set contains a handle with the given identity.
+    pub fn contains(&self, id: &FileId) -> bool {
+        self.handles.contains_key(id)
+    }
+
+    /// Returns the handle with the given identity, if any.
+    pub fn get(&self, id: &FileId) -> Option<&Handle<F>> {
+        self.handles.get(id)
+    }
+
+    /// Remove and return the handle with the given identity, if any.
+    pub fn remove(&mut self, id: &FileId) -> Option<Handle<F>> {
+        let handle = self.handles.remove(id)?;
+        if let Some(ref mut order) = self.order {
+            order.retain(|other| other != id);
+        }
+        Some(handle)
+    }
+
+    /// The number of handles in the set.
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    /// Returns true if the set contains no handles.
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty()
+    }
+
+    /// An iterator over the handles in the set.
+    ///
+    /// The order is the order of first insertion if the set was created with
+    /// [`preserving_order`](HandleSet::preserving_order), and arbitrary
+    /// otherwise.
+    pub fn iter(&self) -> HandleSetIter<'_, F> {
+        HandleSetIter(match self.order {
+            Some(ref order) => IterInner::Ordered(order.iter(), &self.handles),
+            None => IterInner::Hashed(self.handles.values()),
+        })
+    }
+
+    /// Remove every handle whose file resides on the given volume, returning
+    /// the number of handles removed.
+    ///
+    /// This is meant for when a removable volume goes away, and closes all
+    /// the files that were kept open on it.
+    pub fn evict_volume(&mut self, volume: u64) -> usize {
+        let before = self.handles.len();
+        self.handles.retain(|id, _| id.volume() != volume);
+        if let Some(ref mut order) = self.order {
+            order.retain(|id| id.volume() != volume);
+        }
+        before - self.handles.len()
+    }
+}
+
+impl HandleSet<File> {
+    /// Build a set by opening every path in the iterator.
+    ///
+    /// # Errors
+    /// This function will return the first [`io::Error`] encountered while
+    /// opening the paths.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn from_paths<I>(paths: I) -> io::Result<HandleSet<File>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<Path>,
+    {
+        let mut set = HandleSet::new();
+        set.try_extend_paths(paths)?;
+        Ok(set)
+    }
+
+    /// Open every path in the iterator and add it to the set.
+    ///
+    /// # Errors
+    /// This method will return the first [`io::Error`] encountered while
+    /// opening the paths. Paths before the failing one remain in the set.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn try_extend_paths<I>(&mut self, paths: I) -> io::Result<()>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<Path>,
+    {
+        for path in paths {
+            self.insert(Handle::from_path(path)?);
+        }
+        Ok(())
+    }
+}
+
+impl<F> Default for HandleSet<F> {
+    fn default() -> HandleSet<F> {
+        HandleSet::new()
+    }
+}
+
+impl<F> FromIterator<Handle<F>> for HandleSet<F> {
+    fn from_iter<I: IntoIterator<Item = Handle<F>>>(iter: I) -> Self {
+        let mut set = HandleSet::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<F> Extend<Handle<F>> for HandleSet<F> {
+    fn extend<I: IntoIterator<Item = Handle<F>>>(&mut self, iter: I) {
+        for handle in iter {
+            self.insert(handle);
+        }
+    }
+}
+
+impl<F> IntoIterator for HandleSet<F> {
+    type Item = Handle<F>;
+    type IntoIter = HandleSetIntoIter<F>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        HandleSetIntoIter(match self.order {
+            Some(order) => {
+                IntoIterInner::Ordered(order.into_iter(), self.handles)
+            }
+            None => IntoIterInner::Hashed(self.handles.into_values()),
+        })
+    }
+}
+
+impl<'a, F> IntoIterator for &'a HandleSet<F> {
+    type Item = &'a Handle<F>;
+    type IntoIter = HandleSetIter<'a, F>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the handles of a [`HandleSet`].
+///
+/// Returned by [`HandleSet::iter`].
+#[derive(Debug)]
+pub struct HandleSetIter<'a, F>(IterInner<'a, F>);
+
+#[derive(Debug)]
+enum IterInner<'a, F> {
+    Hashed(hash_map::Values<'a, FileId, Handle<F>>),
+    Ordered(slice::Iter<'a, FileId>, &'a HashMap<FileId, Handle<F>>),
+}
+
+impl<'a, F> Iterator for HandleSetIter<'a, F> {
+    type Item = &'a Handle<F>;
+
+    fn next(&mut self) -> Option<&'a Handle<F>> {
+        match self.0 {
+            IterInner::Hashed(ref mut values) => values.next(),
+            IterInner::Ordered(ref mut order, handles) => {
+                order.next().map(|id| &handles[id])
+            }
+        }
+    }
+}
+
+/// An owning iterator over the handles of a [`HandleSet`].
+#[derive(Debug)]
+pub struct HandleSetIntoIter<F>(IntoIterInner<F>);
+
+#[derive(Debug)]
+enum IntoIterInner<F> {
+    Hashed(hash_map::IntoValues<FileId, Handle<F>>),
+    Ordered(vec::IntoIter<FileId>, HashMap<FileId, Handle<F>>),
+}
+
+impl<F> Iterator for HandleSetIntoIter<F> {
+    type Item = Handle<F>;
+
+    fn next(&mut self) -> Option<Handle<F>> {
+        match self.0 {
+            IntoIterInner::Hashed(ref mut values) => values.next(),
+            IntoIterInner::Ordered(ref mut order, ref mut handles) => {
+                order.next().and_then(|id| handles.remove(&id))
+            }
+        }
+    }
+}
+
+/// A map keyed by file identity.
+///
+/// This does not keep any files open. As with [`FileId`] itself, the keys are
+/// only guaranteed to be meaningful while the files they were taken from are
+/// kept open by the caller.
+#[derive(Debug, Clone)]
+pub struct FileIdMap<V> {
+    map: HashMap<FileId, V>,
+}
+
+impl<V> FileIdMap<V> {
+    /// Create an empty map.
+    pub fn new() -> FileIdMap<V> {
+        FileIdMap { map: HashMap::new() }
+    }
+
+    /// Insert a value, returning the previous value for the identity.
+    pub fn insert(&mut self, id: FileId, value: V) -> Option<V> {
+        self.map.insert(id, value)
+    }
+
+    /// Returns the value for the given identity, if any.
+    pub fn get(&self, id: &FileId) -> Option<&V> {
+        self.map.get(id)
+    }
+
+    /// Returns a mutable reference to the value for the given identity.
+    pub fn get_mut(&mut self, id: &FileId) -> Option<&mut V> {
+        self.map.get_mut(id)
+    }
+
+    /// Returns the value for the file of a handle, if any.
+    ///
+    /// The identity of the handle is reused rather than queried again.
+    pub fn get_by_handle<F>(&self, handle: &Handle<F>) -> Option<&V> {
+        self.map.get(&handle.identity)
+    }
+
+    /// Returns a mutable reference to the value for the file of a handle.
+    pub fn get_mut_by_handle<F>(
+        &mut self,
+        handle: &Handle<F>,
+    ) -> Option<&mut V> {
+        self.map.get_mut(&handle.identity)
+    }
+
+    /// The entry for the given identity, for in-place manipulation.
+    pub fn entry(&mut self, id: FileId) -> hash_map::Entry<'_, FileId, V> {
+        self.map.entry(id)
+    }
+
+    /// The entry for the file at a path, for in-place manipulation.
+    ///
+    /// The identity of the path is taken without keeping the file open.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the path cannot be opened
+    /// or its identity cannot be obtained.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    ///
+    /// # Examples
+    ///
+    /// Counting the paths that name each file:
+    ///
+    /// ```rust,no_run
+    /// use cross_file_id::FileIdMap;
+    ///
+    /// let mut counts = FileIdMap::new();
+    /// for path in ["./a", "./b", "./link-to-a"] {
+    ///     *counts.entry_for_path(path)?.or_insert(0) += 1;
+    /// }
+    /// # Ok::<_, std::io::Error>(())
+    /// ```
+    pub fn entry_for_path<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> io::Result<hash_map::Entry<'_, FileId, V>> {
+        self.entry_for_path_via(OsIdentity, path)
+    }
+
+    /// The entry for the file at a path, with its identity taken from
+    /// another source.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the source has no
+    /// identity for the path.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn entry_for_path_via<S, P>(
+        &mut self,
+        source: S,
+        path: P,
+    ) -> io::Result<hash_map::Entry<'_, FileId, V>>
+    where
+        S: FileIdentitySource,
+        P: AsRef<Path>,
+    {
+        Ok(self.map.entry(source.id_from_path(path.as_ref())?))
+    }
+
+    /// Remove and return the value for the given identity, if any.
+    pub fn remove(&mut self, id: &FileId) -> Option<V> {
+        self.map.remove(id)
+    }
+
+    /// Returns true if the map contains the given identity.
+    pub fn contains_key(&self, id: &FileId) -> bool {
+        self.map.contains_key(id)
+    }
+
+    /// The number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns true if the map contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// An iterator over the entries in the map, in arbitrary order.
+    pub fn iter(&self) -> hash_map::Iter<'_, FileId, V> {
+        self.map.iter()
+    }
+
+    /// Remove every entry whose identity is on the given volume, returning
+    /// the number of entries removed.
+    ///
+    /// Once a volume is unmounted, its identities may be reused by a
+    /// different volume, so entries for it should not outlive the mount.
+    pub fn evict_volume(&mut self, volume: u64) -> usize {
+        let before = self.map.len();
+        self.map.retain(|id, _| id.volume() != volume);
+        before - self.map.len()
+    }
+
+    /// Build a map by taking the identity of every path in the iterator.
+    ///
+    /// If several paths refer to the same file, the value of the last one
+    /// wins.
+    ///
+    /// # Errors
+    /// This function will return the first [`io::Error`] encountered while
+    /// opening the paths.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn from_paths<I, P>(entries: I) -> io::Result<FileIdMap<V>>
+    where
+        I: IntoIterator<Item = (P, V)>,
+        P: AsRef<Path>,
+    {
+        entries
+            .into_iter()
+            .map(|(path, value)| {
+                Ok((Handle::id(&Handle::from_path(path)?), value))
+            })
+            .collect()
+    }
+}
+
+impl<V> Default for FileIdMap<V> {
+    fn default() -> FileIdMap<V> {
+        FileIdMap::new()
+    }
+}
+
+impl<V> FromIterator<(FileId, V)> for FileIdMap<V> {
+    fn from_iter<I: IntoIterator<Item = (FileId, V)>>(iter: I) -> Self {
+        FileIdMap { map: HashMap::from_iter(iter) }
+    }
+}
+
+impl<V> Extend<(FileId, V)> for FileIdMap<V> {
+    fn extend<I: IntoIterator<Item = (FileId, V)>>(&mut self, iter: I) {
+        self.map.extend(iter);
+    }
+}
+
+impl<V> IntoIterator for FileIdMap<V> {
+    type Item = (FileId, V);
+    type IntoIter = hash_map::IntoIter<FileId, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.into_iter()
+    }
+}
+
+impl<'a, V> IntoIterator for &'a FileIdMap<V> {
+    type Item = (&'a FileId, &'a V);
+    type IntoIter = hash_map::Iter<'a, FileId, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.iter()
+    }
+}
+
+/// A set of [`FileIdMap`]s, one per scope.
+///
+/// This is meant for long-running services shared between several users or
+/// sessions, which need to keep their identity caches isolated: entries
+/// cached on behalf of one scope are never visible from another, and all the
+/// entries of a scope can be dropped at once, e.g. when a session ends.
+#[derive(Debug, Clone)]
+pub struct ScopedFileIdMap<S, V> {
+    scopes: HashMap<S, FileIdMap<V>>,
+}
+
+impl<S, V> ScopedFileIdMap<S, V>
+where
+    S: Eq + Hash,
+{
+    /// Create an empty map.
+    pub fn new() -> ScopedFileIdMap<S, V> {
+        ScopedFileIdMap { scopes: HashMap::new() }
+    }
+
+    /// Returns the map for the given scope, if the scope exists.
+    ///
+    /// A scope exists from the first call to
+    /// [`scope_mut`](ScopedFileIdMap::scope_mut) for it, even if nothing is
+    /// inserted into its map, until it is removed. The returned map may then
+    /// be empty.
+    pub fn scope(&self, scope: &S) -> Option<&FileIdMap<V>> {
+        self.scopes.get(scope)
+    }
+
+    /// Returns the map for the given scope, creating it if needed.
+    pub fn scope_mut(&mut self, scope: S) -> &mut FileIdMap<V> {
+        self.scopes.entry(scope).or_default()
+    }
+
+    /// Remove and return the map for the given scope, if any.
+    pub fn remove_scope(&mut self, scope: &S) -> Option<FileIdMap<V>> {
+        self.scopes.remove(scope)
+    }
+
+    /// Remove every entry whose identity is on the given volume, in every
+    /// scope, returning the number of entries removed.
+    ///
+    /// Scopes left without entries are removed.
+    pub fn evict_volume(&mut self, volume: u64) -> usize {
+        let mut evicted = 0;
+        self.scopes.retain(|_, map| {
+            evicted += map.evict_volume(volume);
+            !map.is_empty()
+        });
+        evicted
+    }
+
+    /// The number of scopes in the map.
+    pub fn len(&self) -> usize {
+        self.scopes.len()
+    }
+
+    /// Returns true if the map contains no scopes.
+    pub fn is_empty(&self) -> bool {
+        self.scopes.is_empty()
+    }
+
+    /// An iterator over the scopes and their maps, in arbitrary order.
+    pub fn iter(&self) -> hash_map::Iter<'_, S, FileIdMap<V>> {
+        self.scopes.iter()
+    }
+}
+
+impl<S, V> Default for ScopedFileIdMap<S, V>
+where
+    S: Eq + Hash,
+{
+    fn default() -> ScopedFileIdMap<S, V> {
+        ScopedFileIdMap::new()
+    }
+}
+
+/// A frozen set of identities, optimized for membership tests.
+///
+/// This is meant for the hot check "is this file one of my protected
+/// files?", as done by [`FileId::is_any_of`]. The set is built once from an
+/// iterator and cannot be modified afterwards. The identities are stored as
+/// their [sortable bytes](FileId::as_sortable_bytes) in a sorted array, so a
+/// lookup is a binary search over fixed-size keys that neither allocates nor
+/// hashes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct IdSet {
+    keys: Box<[[u8; FileId::BYTES_LEN]]>,
+}
+
+impl IdSet {
+    /// Returns true if the set contains the given identity.
+    pub fn contains(&self, id: &FileId) -> bool {
+        self.keys.binary_search(&id.as_sortable_bytes()).is_ok()
+    }
+
+    /// Returns the number of identities in the set.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns true if the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Build a set from the identities of the files at the given paths,
+    /// following symbolic links.
+    ///
+    /// # Errors
+    /// This function will return the first [`io::Error`] encountered while
+    /// taking the identities of the paths.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn from_paths<I>(paths: I) -> io::Result<IdSet>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<Path>,
+    {
+        IdSet::from_paths_via(OsIdentity, paths)
+    }
+
+    /// Build a set from the identities of the files at the given paths, as
+    /// taken by another source.
+    ///
+    /// # Errors
+    /// This function will return the first [`io::Error`] encountered while
+    /// taking the identities of the paths.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn from_paths_via<S, I>(source: S, paths: I) -> io::Result<IdSet>
+    where
+        S: FileIdentitySource,
+        I: IntoIterator,
+        I::Item: AsRef<Path>,
+    {
+        paths
+            .into_iter()
+            .map(|path| source.id_from_path(path.as_ref()))
+            .collect()
+    }
+
+    /// Iterate over the identities in the set, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = FileId> + '_ {
+        self.keys.iter().map(|key| {
+            FileId::from_packed(key).expect("keys come from identities")
+        })
+    }
+}
+
+impl FromIterator<FileId> for IdSet {
+    fn from_iter<I: IntoIterator<Item = FileId>>(iter: I) -> IdSet {
+        let mut keys: Vec<_> =
+            iter.into_iter().map(|id| id.as_sortable_bytes()).collect();
+        keys.sort_unstable();
+        keys.dedup();
+        IdSet { keys: keys.into_boxed_slice() }
+    }
+}
+
+impl<'a> FromIterator<&'a FileId> for IdSet {
+    fn from_iter<I: IntoIterator<Item = &'a FileId>>(iter: I) -> IdSet {
+        iter.into_iter().cloned().collect()
+    }
+}
+
+impl FileId {
+    /// Returns true if this identity is in the given set.
+    ///
+    /// # Examples
+    ///
+    /// Refusing to overwrite any of a few protected files:
+    ///
+    /// ```rust,no_run
+    /// use cross_file_id::{FileId, IdSet};
+    ///
+    /// let protected: IdSet = ["/etc/passwd", "/etc/shadow"]
+    ///     .iter()
+    ///     .map(FileId::from_path)
+    ///     .collect::<Result<_, _>>()?;
+    /// let target = FileId::from_path("output.txt")?;
+    /// assert!(!target.is_any_of(&protected));
+    /// # Ok::<_, std::io::Error>(())
+    /// ```
+    pub fn is_any_of(&self, set: &IdSet) -> bool {
+        set.contains(self)
+    }
+}
+
+/// The number of identities a [`SmallHandleVec`] stores inline.
+const INLINE_DEPTH: usize = 8;
+
+/// The packed form of an identity, as words.
+type Key = [u64; 4];
+
+// Comparing arrays calls `memcmp`, which costs more than scanning a short
+// stack does.
+fn same(a: &Key, b: &Key) -> bool {
+    (a[0] ^ b[0]) | (a[1] ^ b[1]) | (a[2] ^ b[2]) | (a[3] ^ b[3]) == 0
+}
+
+fn key(id: &FileId) -> Key {
+    let packed = id.as_sortable_bytes();
+    let mut key = [0; 4];
+    for (word, bytes) in key.iter_mut().zip(packed.chunks_exact(8)) {
+        *word = u64::from_ne_bytes(bytes.try_into().unwrap());
+    }
+    key
+}
+
+/// A stack of open directories for walkers, which detects loops.
+///
+/// A recursive walk that follows symbolic links keeps the directories it is
+/// inside of open, so that their identities stay valid, and refuses to enter
+/// a directory that is already on the stack. The identities are kept apart
+/// from the handles, as [packed bytes](FileId::as_sortable_bytes) stored
+/// inline for the first levels, so the loop check on every push scans a few
+/// contiguous cache lines and neither allocates nor follows pointers.
+/// Popping is constant time.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use cross_file_id::{Handle, SmallHandleVec};
+///
+/// let mut stack = SmallHandleVec::new();
+/// stack.push(Handle::from_path("/src")?)?;
+/// if stack.push(Handle::from_path("/src/link-to-src")?).is_err() {
+///     // Do not descend into the link.
+/// }
+/// # Ok::<_, std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct SmallHandleVec<F = File> {
+    inline: [Key; INLINE_DEPTH],
+    // Once deeper than `INLINE_DEPTH`, all identities, including a copy of
+    // the inline ones.
+    spilled: Vec<Key>,
+    handles: Vec<Handle<F>>,
+}
+
+impl<F> SmallHandleVec<F> {
+    /// Create an empty stack.
+    pub fn new() -> SmallHandleVec<F> {
+        SmallHandleVec {
+            inline: [[0; 4]; INLINE_DEPTH],
+            spilled: Vec::new(),
+            handles: Vec::new(),
+        }
+    }
+
+    fn keys(&self) -> &[Key] {
+        let len = self.handles.len();
+        if len <= INLINE_DEPTH { &self.inline[..len] } else { &self.spilled }
+    }
+
+    /// Push a directory onto the stack.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] of kind
+    /// [`io::ErrorKind::InvalidData`], wrapping
+    /// [`Error::FilesystemLoop`](crate::Error::FilesystemLoop), if a
+    /// directory with the same identity is already on the stack. The stack
+    /// is then left unchanged, and the given handle is dropped.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn push(&mut self, handle: Handle<F>) -> io::Result<()> {
+        let key = key(&Handle::id(&handle));
+        if let Some(depth) = self.keys().iter().position(|k| same(k, &key)) {
+            let id = Handle::id(&handle);
+            return Err(crate::Error::FilesystemLoop { id, depth }.into());
+        }
+        let len = self.handles.len();
+        if len < INLINE_DEPTH {
+            self.inline[len] = key;
+        } else {
+            if len == INLINE_DEPTH {
+                self.spilled.clear();
+                self.spilled.extend_from_slice(&self.inline);
+            }
+            self.spilled.push(key);
+        }
+        self.handles.push(handle);
+        Ok(())
+    }
+
+    /// Pop the innermost directory off the stack.
+    pub fn pop(&mut self) -> Option<Handle<F>> {
+        if self.handles.len() > INLINE_DEPTH {
+            self.spilled.pop();
+        }
+        self.handles.pop()
+    }
+
+    /// Pop the innermost directory off the stack if it has the given
+    /// identity, which checks that a walker leaves the directory it entered
+    /// last.
+    pub fn pop_if(&mut self, id: &FileId) -> Option<Handle<F>> {
+        if !same(self.keys().last()?, &key(id)) {
+            return None;
+        }
+        self.pop()
+    }
+
+    /// The innermost directory on the stack.
+    pub fn last(&self) -> Option<&Handle<F>> {
+        self.handles.last()
+    }
+
+    /// Returns true if a directory with the given identity is on the stack.
+    pub fn contains(&self, id: &FileId) -> bool {
+        let key = key(id);
+        self.keys().iter().any(|k| same(k, &key))
+    }
+
+    /// Returns true if the directory of a handle is on the stack.
+    pub fn contains_handle<G>(&self, handle: &Handle<G>) -> bool {
+        self.contains(&handle.identity)
+    }
+
+    /// Returns true if the directory at a path is on the stack, which tells
+    /// a walker that following a symbolic link would enter a loop.
+    ///
+    /// The identity of the path is taken following symbolic links, without
+    /// keeping it open.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the path cannot be opened
+    /// or its identity cannot be obtained.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn contains_path<P: AsRef<Path>>(&self, path: P) -> io::Result<bool> {
+        Ok(self.contains(&FileId::from_path(path)?))
+    }
+
+    /// The number of directories on the stack.
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    /// Returns true if the stack is empty.
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty()
+    }
+
+    /// Iterate over the directories on the stack, outermost first.
+    pub fn iter(&self) -> slice::Iter<'_, Handle<F>> {
+        self.handles.iter()
+    }
+}
+
+impl<F> Default for SmallHandleVec<F> {
+    fn default() -> SmallHandleVec<F> {
+        SmallHandleVec::new()
+    }
+}
+
+/// The stack of the directories a recursive walk is inside of, which
+/// detects symbolic link loops the way `walkdir` does.
+///
+/// This is another name for [`SmallHandleVec`], for walkers that think of it
+/// as the ancestors of the current directory.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use cross_file_id::{AncestorStack, Handle};
+///
+/// let mut ancestors = AncestorStack::new();
+/// ancestors.push(Handle::from_path("/src")?)?;
+/// if ancestors.contains_path("/src/link-to-src")? {
+///     // Do not follow the link.
+/// }
+/// # Ok::<_, std::io::Error>(())
+/// ```
+pub type AncestorStack<F = File> = SmallHandleVec<F>;
@@ -0,0 +1,322 @@
+use std::collections::VecDeque;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::{Condvar, Mutex, MutexGuard, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::bulk::map_with;
+use crate::{Error, FileId, Spawner, is_same_file_path};
+
+/// The number of worker threads that operations with a timeout may use at
+/// once, counting the ones left behind by operations that timed out.
+const MAX_WORKERS: usize = 32;
+
+/// How long an idle worker thread waits for more work before exiting.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The workers that run operations with a timeout.
+static POOL: WorkerPool = WorkerPool::new(MAX_WORKERS);
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A bounded pool of worker threads.
+///
+/// A worker that is stuck on a dead network mount stays counted until the
+/// call returns, so a mount that never answers can hold up to `max`
+/// threads, and no more. Once they are all busy, new operations fail right
+/// away rather than pile up more threads.
+struct WorkerPool {
+    state: Mutex<PoolState>,
+    ready: Condvar,
+    max: usize,
+}
+
+struct PoolState {
+    jobs: VecDeque<Job>,
+    workers: usize,
+    idle: usize,
+}
+
+impl WorkerPool {
+    const fn new(max: usize) -> WorkerPool {
+        WorkerPool {
+            state: Mutex::new(PoolState {
+                jobs: VecDeque::new(),
+                workers: 0,
+                idle: 0,
+            }),
+            ready: Condvar::new(),
+            max,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Queue `job` for an idle worker, or start a new one if there is room.
+    fn submit(
+        &'static self,
+        operation: &'static str,
+        job: Job,
+    ) -> io::Result<()> {
+        let mut state = self.lock();
+        if state.jobs.len() < state.idle {
+            state.jobs.push_back(job);
+            self.ready.notify_one();
+            return Ok(());
+        }
+        if state.workers == self.max {
+            return Err(
+                Error::WorkersBusy { operation, workers: self.max }.into()
+            );
+        }
+        thread::Builder::new()
+            .name("cross-file-id-timeout".into())
+            .spawn(move || self.work())?;
+        state.workers += 1;
+        state.jobs.push_back(job);
+        Ok(())
+    }
+
+    fn work(&self) {
+        let mut state = self.lock();
+        loop {
+            if let Some(job) = state.jobs.pop_front() {
+                drop(state);
+                // A panic drops the sender of the job, which the caller
+                // reports, and leaves this worker usable.
+                let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                state = self.lock();
+                continue;
+            }
+            state.idle += 1;
+            let (guard, wait) = self
+                .ready
+                .wait_timeout(state, IDLE_TIMEOUT)
+                .unwrap_or_else(|err| err.into_inner());
+            state = guard;
+            state.idle -= 1;
+            if wait.timed_out() && state.jobs.is_empty() {
+                state.workers -= 1;
+                return;
+            }
+        }
+    }
+
+    /// Run `f` on a worker thread, giving up on it after `timeout`.
+    fn run<T, F>(
+        &'static self,
+        operation: &'static str,
+        timeout: Duration,
+        f: F,
+    ) -> io::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> io::Result<T> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        self.submit(
+            operation,
+            Box::new(move || {
+                // The receiver is gone if the operation timed out.
+                let _ = sender.send(f());
+            }),
+        )?;
+        match receiver.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                Err(Error::TimedOut { operation, timeout }.into())
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                Err(io::Error::other(format!(
+                    "the worker thread for {} panicked",
+                    operation
+                )))
+            }
+        }
+    }
+}
+
+/// Run `f` on a worker thread, giving up on it after `timeout`.
+///
+/// The operating systems offer no way to bound a blocking `open` or `stat`
+/// on a dead network mount, so the call is moved to a worker thread that is
+/// left behind when it takes too long. Whatever it eventually returns, such
+/// as an open file, is then dropped on that thread. The workers come from a
+/// pool of at most [`MAX_WORKERS`] threads; when they are all busy, the
+/// operation fails with [`Error::WorkersBusy`] without being attempted.
+pub(crate) fn run_with_timeout<T, F>(
+    operation: &'static str,
+    timeout: Duration,
+    f: F,
+) -> io::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+{
+    POOL.run(operation, timeout, f)
+}
+
+impl FileId {
+    /// Extract the identity of the file at a path, giving up after
+    /// `timeout`.
+    ///
+    /// This is [`from_path`](FileId::from_path), run on a worker thread so
+    /// that a path on a network filesystem that stopped responding cannot
+    /// hang the caller. When the timeout elapses, the worker thread is left
+    /// to finish the blocked call on its own, and is not available to other
+    /// calls until it does.
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] of kind
+    /// [`io::ErrorKind::TimedOut`], wrapping [`Error::TimedOut`], if the
+    /// identity could not be obtained in time, and one of kind
+    /// [`io::ErrorKind::ResourceBusy`], wrapping [`Error::WorkersBusy`], if
+    /// too many earlier calls are still blocked. Otherwise it fails as
+    /// [`from_path`](FileId::from_path) does.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn from_path_timeout<P: AsRef<Path>>(
+        path: P,
+        timeout: Duration,
+    ) -> io::Result<FileId> {
+        let path = path.as_ref().to_path_buf();
+        run_with_timeout("taking the identity of a path", timeout, move || {
+            FileId::from_path(path)
+        })
+    }
+}
+
+/// Returns true if the two file paths may correspond to the same file,
+/// giving up after `timeout`.
+///
+/// This is [`is_same_file_path`], run on a worker thread. When the timeout
+/// elapses, the worker thread is left to finish the blocked call on its own,
+/// and closes the files it opened.
+///
+/// # Errors
+/// This function will return an [`io::Error`] of kind
+/// [`io::ErrorKind::TimedOut`], wrapping [`Error::TimedOut`], if the
+/// comparison could not be made in time, and one of kind
+/// [`io::ErrorKind::ResourceBusy`], wrapping [`Error::WorkersBusy`], if too
+/// many earlier calls are still blocked. Otherwise it fails as
+/// [`is_same_file_path`] does.
+///
+/// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+pub fn is_same_file_path_timeout<P, Q>(
+    path1: P,
+    path2: Q,
+    timeout: Duration,
+) -> io::Result<bool>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let path1 = path1.as_ref().to_path_buf();
+    let path2 = path2.as_ref().to_path_buf();
+    run_with_timeout("comparing two paths", timeout, move || {
+        is_same_file_path(path1, path2)
+    })
+}
+
+/// Take the identity of every path, running the work with the given
+/// spawner, and giving up on the paths that are not done by `deadline`.
+///
+/// This is [`ids_from_paths`](crate::ids_from_paths), with every path
+/// given the time left until the deadline, as with
+/// [`FileId::from_path_timeout`]. Paths on a dead network mount then time
+/// out rather than hang the whole scan, while the other paths still get
+/// their identity. The worker threads are shared with the other operations
+/// that take a timeout and are bounded in number, so once enough paths are
+/// blocked, the remaining ones fail with [`Error::WorkersBusy`] right away
+/// rather than wait for the deadline.
+///
+/// # Panics
+///
+/// Panics if the spawner returns without running every task.
+pub fn ids_from_paths_until<S, P>(
+    spawner: &S,
+    paths: &[P],
+    deadline: Instant,
+) -> Vec<io::Result<FileId>>
+where
+    S: Spawner + ?Sized,
+    P: AsRef<Path> + Sync,
+{
+    map_with(spawner, paths, |path| {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        FileId::from_path_timeout(path, timeout)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::WorkerPool;
+    use crate::Error;
+
+    #[test]
+    fn busy_pool_fails_fast() {
+        let pool: &'static WorkerPool =
+            Box::leak(Box::new(WorkerPool::new(1)));
+        let (unblock, blocked) = mpsc::channel::<()>();
+        let err = pool
+            .run("blocking", Duration::from_millis(10), move || {
+                blocked.recv().map_err(io::Error::other)
+            })
+            .unwrap_err();
+        assert!(matches!(Error::of(&err), Some(Error::TimedOut { .. })));
+
+        // The only worker is still blocked.
+        let start = Instant::now();
+        let err = pool
+            .run("waiting", Duration::from_secs(60), || Ok(()))
+            .unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(60));
+        assert_eq!(err.kind(), io::ErrorKind::ResourceBusy);
+        assert!(matches!(
+            Error::of(&err),
+            Some(Error::WorkersBusy { operation: "waiting", workers: 1 })
+        ));
+
+        // Once unblocked, the worker takes new operations.
+        unblock.send(()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            match pool.run("waiting", Duration::from_secs(60), || Ok(1)) {
+                Ok(value) => break assert_eq!(value, 1),
+                Err(err) if Instant::now() < deadline => {
+                    assert_eq!(err.kind(), io::ErrorKind::ResourceBusy);
+                    thread::sleep(Duration::from_millis(10));
+                }
+                Err(err) => panic!("worker was not freed: {}", err),
+            }
+        }
+        assert_eq!(pool.lock().workers, 1);
+    }
+
+    #[test]
+    fn panic_keeps_worker() {
+        let pool: &'static WorkerPool =
+            Box::leak(Box::new(WorkerPool::new(1)));
+        let err = pool
+            .run::<(), _>("panicking", Duration::from_secs(60), || {
+                panic!("boom")
+            })
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while pool.run("waiting", Duration::from_secs(60), || Ok(())).is_err()
+        {
+            assert!(Instant::now() < deadline, "worker was lost");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
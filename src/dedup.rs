@@ -0,0 +1,135 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use crate::{FileId, FileIdentitySource, Handle, HandleSet, OsIdentity};
+
+/// Tracks the files seen so far, to process each of them only once.
+///
+/// A [`FileId`] is only guaranteed to be meaningful while its file is kept
+/// open: once a file is deleted, a new file may be given the same identity,
+/// and would then wrongly be reported as already seen. A deduplicator keeps
+/// the files it sees open, up to a budget of open descriptors or handles,
+/// so that their identities stay valid for as long as it lives. Files seen
+/// once the budget is spent are still tracked, by identity alone.
+///
+/// The files are identified following symbolic links.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use cross_file_id::Deduplicator;
+///
+/// let mut seen = Deduplicator::with_fd_budget(512);
+/// for path in ["./a", "./b", "./link-to-a"] {
+///     if seen.first_seen(path)? {
+///         println!("processing {}", path);
+///     }
+/// }
+/// # Ok::<_, std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Deduplicator {
+    pinned: HandleSet<File>,
+    // The identities seen while the budget was spent.
+    unpinned: HashSet<FileId>,
+    budget: usize,
+}
+
+impl Deduplicator {
+    /// Create a deduplicator that does not keep any file open.
+    pub fn new() -> Deduplicator {
+        Deduplicator::with_fd_budget(0)
+    }
+
+    /// Create a deduplicator that keeps up to `budget` files open.
+    pub fn with_fd_budget(budget: usize) -> Deduplicator {
+        Deduplicator {
+            pinned: HandleSet::new(),
+            unpinned: HashSet::new(),
+            budget,
+        }
+    }
+
+    /// Returns true if the file at `path` was not seen before, and records
+    /// it as seen.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the path cannot be opened
+    /// or its identity cannot be obtained. The file is then not recorded.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn first_seen<P: AsRef<Path>>(&mut self, path: P) -> io::Result<bool> {
+        if self.pinned.len() < self.budget {
+            Ok(self.first_seen_handle(Handle::from_path(path)?))
+        } else {
+            self.first_seen_via(OsIdentity, path)
+        }
+    }
+
+    /// Returns true if the file at `path` was not seen before, taking its
+    /// identity from another source, and records it as seen.
+    ///
+    /// The file is never kept open, whatever the budget.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the source has no
+    /// identity for the path. The file is then not recorded.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn first_seen_via<S, P>(
+        &mut self,
+        source: S,
+        path: P,
+    ) -> io::Result<bool>
+    where
+        S: FileIdentitySource,
+        P: AsRef<Path>,
+    {
+        let id = source.id_from_path(path.as_ref())?;
+        Ok(!self.pinned.contains(&id) && self.unpinned.insert(id))
+    }
+
+    /// Returns true if the file of a handle was not seen before, and records
+    /// it as seen.
+    ///
+    /// The handle is kept if the budget allows it, and dropped otherwise.
+    pub fn first_seen_handle(&mut self, handle: Handle<File>) -> bool {
+        let id = Handle::id(&handle);
+        if self.unpinned.contains(&id) {
+            return false;
+        }
+        if self.pinned.len() < self.budget {
+            self.pinned.insert(handle)
+        } else {
+            !self.pinned.contains(&id) && self.unpinned.insert(id)
+        }
+    }
+
+    /// Returns true if a file with the given identity was seen.
+    pub fn contains(&self, id: &FileId) -> bool {
+        self.pinned.contains(id) || self.unpinned.contains(id)
+    }
+
+    /// The number of distinct files seen.
+    pub fn len(&self) -> usize {
+        self.pinned.len() + self.unpinned.len()
+    }
+
+    /// Returns true if no file was seen.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of files kept open.
+    pub fn pinned(&self) -> usize {
+        self.pinned.len()
+    }
+}
+
+impl Default for Deduplicator {
+    fn default() -> Deduplicator {
+        Deduplicator::new()
+    }
+}
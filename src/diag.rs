@@ -0,0 +1,47 @@
+//! Internal instrumentation: diagnostics emitted through the `log` crate
+//! when the `log` feature is enabled, and failure injection points when the
+//! `failpoints` feature is enabled.
+//!
+//! Without the `log` feature, the logging macros still type-check their
+//! arguments, so that values used only for diagnostics do not cause unused
+//! warnings.
+
+// Not every platform and feature combination emits debug diagnostics.
+#[allow(unused_macros)]
+macro_rules! log_debug {
+    ($($arg:tt)+) => {{
+        #[cfg(feature = "log")]
+        ::log::debug!(target: "cross_file_id", $($arg)+);
+        #[cfg(not(feature = "log"))]
+        let _ = format_args!($($arg)+);
+    }};
+}
+
+macro_rules! log_warn {
+    ($($arg:tt)+) => {{
+        #[cfg(feature = "log")]
+        ::log::warn!(target: "cross_file_id", $($arg)+);
+        #[cfg(not(feature = "log"))]
+        let _ = format_args!($($arg)+);
+    }};
+}
+
+// Return early with an injected error if the given `FailPoint` is armed.
+macro_rules! fail_point {
+    ($point:ident) => {
+        #[cfg(feature = "failpoints")]
+        $crate::failpoints::hit($crate::failpoints::FailPoint::$point)?;
+    };
+}
+
+// Pass an identity observed by a verification step through the
+// `IdentityChange` fail point.
+macro_rules! fail_identity {
+    ($id:expr) => {{
+        #[cfg(feature = "failpoints")]
+        let id = $crate::failpoints::observe($id);
+        #[cfg(not(feature = "failpoints"))]
+        let id = $id;
+        id
+    }};
+}
@@ -0,0 +1,189 @@
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{AsRawFilelike, FileId, Handle, imp};
+
+/// An open directory whose identity is pinned for the lifetime of the value.
+///
+/// A `DirHandle` keeps the directory open, so the identity it reports stays
+/// valid even if the directory is renamed or replaced at its original path.
+#[derive(Debug)]
+pub struct DirHandle {
+    handle: Handle<File>,
+    path: PathBuf,
+}
+
+impl DirHandle {
+    /// Open the directory at the given path.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the path cannot be
+    /// opened, or does not refer to a directory.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<DirHandle> {
+        let path = path.as_ref();
+        let handle = Handle::from_path(path)?;
+        if !handle.metadata()?.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a directory", path.display()),
+            ));
+        }
+        Ok(DirHandle { handle, path: path.to_path_buf() })
+    }
+
+    /// The path this directory was opened with.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The identity of this directory.
+    pub fn id(&self) -> FileId {
+        Handle::id(&self.handle)
+    }
+
+    /// The underlying handle to the directory.
+    pub fn handle(&self) -> &Handle<File> {
+        &self.handle
+    }
+
+    /// Read the entries of this directory.
+    ///
+    /// The entries are read through the open directory rather than its
+    /// path, so they are those of the directory this handle refers to even
+    /// if another one has since taken its path. Hermit can only list a
+    /// directory by path.
+    ///
+    /// Each entry carries a [`WeakId`] where the platform reports one as
+    /// part of the directory listing, so walkers can filter entries cheaply
+    /// and only call [`DirEntry::pin`] on the ones they recurse into.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the directory cannot be
+    /// read.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn read(&self) -> io::Result<ReadDir<'_>> {
+        let raw = self.handle.as_raw_filelike();
+        Ok(ReadDir { parent: self, inner: imp::read_dir(raw, &self.path)? })
+    }
+}
+
+impl PartialEq for DirHandle {
+    fn eq(&self, other: &DirHandle) -> bool {
+        self.handle == other.handle
+    }
+}
+
+impl Eq for DirHandle {}
+
+/// An identity taken from a directory listing without opening the file.
+///
+/// Weak ids are derived from the parent directory's device and the entry's
+/// inode number as reported by the directory listing. They are cheap, but
+/// they are not guaranteed to be correct: the entry may be a mount point on
+/// a different device, or it may be replaced between the listing and its
+/// use. Use [`DirEntry::pin`] to obtain a strong identity.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct WeakId(FileId);
+
+impl WeakId {
+    /// Returns true if this weak id agrees with the given strong identity.
+    #[must_use]
+    pub fn matches(&self, id: &FileId) -> bool {
+        self.0 == *id
+    }
+
+    /// Return the identity this weak id claims, without verification.
+    pub fn assume_valid(&self) -> FileId {
+        self.0.clone()
+    }
+}
+
+/// An iterator over the entries of a [`DirHandle`].
+///
+/// Returned by [`DirHandle::read`].
+#[derive(Debug)]
+pub struct ReadDir<'a> {
+    parent: &'a DirHandle,
+    inner: imp::ReadDir,
+}
+
+impl<'a> Iterator for ReadDir<'a> {
+    type Item = io::Result<DirEntry>;
+
+    fn next(&mut self) -> Option<io::Result<DirEntry>> {
+        let entry = match self.inner.next()? {
+            Ok(entry) => entry,
+            Err(err) => return Some(Err(err)),
+        };
+        let weak_id = imp::entry_weak_id(&self.parent.id().0, &entry)
+            .map(|id| WeakId(FileId(id)));
+        let name = entry.into_name();
+        let path = self.parent.path.join(&name);
+        Some(Ok(DirEntry { path, name, weak_id }))
+    }
+}
+
+/// An entry yielded by [`ReadDir`].
+#[derive(Debug)]
+pub struct DirEntry {
+    path: PathBuf,
+    name: OsString,
+    weak_id: Option<WeakId>,
+}
+
+impl DirEntry {
+    /// The full path of this entry.
+    pub fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    /// The file name of this entry.
+    pub fn file_name(&self) -> OsString {
+        self.name.clone()
+    }
+
+    /// The file type of this entry, without following symbolic links.
+    ///
+    /// The type is looked up through the path of the entry.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the metadata of the
+    /// entry cannot be read.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn file_type(&self) -> io::Result<fs::FileType> {
+        Ok(fs::symlink_metadata(&self.path)?.file_type())
+    }
+
+    /// The weak identity of this entry, if the platform provides one.
+    pub fn weak_id(&self) -> Option<&WeakId> {
+        self.weak_id.as_ref()
+    }
+
+    /// Open this entry and return a handle with a strong identity.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the entry cannot be
+    /// opened.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn pin(&self) -> io::Result<Handle<File>> {
+        Handle::from_path(&self.path)
+    }
+
+    /// Open this entry as a directory.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the entry cannot be
+    /// opened, or is not a directory.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn pin_dir(&self) -> io::Result<DirHandle> {
+        DirHandle::open(&self.path)
+    }
+}
@@ -0,0 +1,120 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    Capabilities, DeviceClass, Error, FileId, Handle, capabilities, imp,
+};
+
+/// What [`doctor`] found out about a path.
+///
+/// This is meant to be logged, or attached to bug reports, by applications
+/// whose correctness depends on file identities. Its [`Display`] form lists
+/// one finding per line.
+///
+/// [`Display`]: https://doc.rust-lang.org/std/fmt/trait.Display.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DoctorReport {
+    /// The path that was examined.
+    pub path: PathBuf,
+    /// The kind of file at the path.
+    pub class: DeviceClass,
+    /// The identity of the file.
+    pub id: FileId,
+    /// The number of significant bits of the file index (the inode number
+    /// on Unix, the file id on Windows), or zero where there is no index.
+    /// Indexes wider than 64 bits come from filesystems such as ReFS.
+    pub index_bits: u32,
+    /// Taking the identity through the path again gave the same result.
+    pub consistent: bool,
+    /// Taking the identity through two independent mechanisms gave the same
+    /// result. See [`FileId::from_file_like_verified`].
+    pub verified: bool,
+    /// What the crate can do on this platform.
+    pub capabilities: Capabilities,
+    /// Degraded ways of taking identities that are in effect.
+    pub fallbacks: Vec<&'static str>,
+}
+
+impl DoctorReport {
+    /// Returns true if identities of files at this path can be relied on:
+    /// the file lives on a filesystem, its identity is not a heuristic and
+    /// is pinned while it is open, and every check agreed.
+    pub fn is_reliable(&self) -> bool {
+        self.class.is_filesystem_object()
+            && !self.capabilities.heuristic
+            && self.capabilities.pins_while_open
+            && self.consistent
+            && self.verified
+    }
+}
+
+impl fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let caps = &self.capabilities;
+        writeln!(f, "path: {}", self.path.display())?;
+        writeln!(f, "class: {}", self.class)?;
+        writeln!(f, "id: {}", self.id)?;
+        writeln!(f, "index bits: {}", self.index_bits)?;
+        writeln!(f, "consistent: {}", self.consistent)?;
+        writeln!(f, "verified: {}", self.verified)?;
+        writeln!(f, "heuristic: {}", caps.heuristic)?;
+        writeln!(f, "pins while open: {}", caps.pins_while_open)?;
+        writeln!(f, "stable across reboots: {}", caps.stable_across_reboots)?;
+        writeln!(f, "mount ids: {}", caps.mount_ids)?;
+        writeln!(f, "current path: {}", caps.current_path)?;
+        writeln!(f, "open no follow: {}", caps.open_no_follow)?;
+        writeln!(f, "minimal access: {}", caps.minimal_access)?;
+        for fallback in &self.fallbacks {
+            writeln!(f, "fallback: {}", fallback)?;
+        }
+        write!(f, "reliable: {}", self.is_reliable())
+    }
+}
+
+/// Examine how reliably the file at a path can be identified.
+///
+/// The file is opened, classified and identified, and its identity is taken
+/// again through the path and through a second mechanism. Disagreements are
+/// reported rather than returned as errors.
+///
+/// # Examples
+///
+/// Logging the state of a data directory at startup:
+///
+/// ```rust
+/// let report = cross_file_id::doctor(".")?;
+/// if !report.is_reliable() {
+///     eprintln!("file identities may be unreliable:\n{}", report);
+/// }
+/// # Ok::<_, std::io::Error>(())
+/// ```
+///
+/// # Errors
+/// This function will return an [`io::Error`] if the path cannot be opened,
+/// or the identity of the file cannot be taken at all.
+///
+/// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+pub fn doctor<P: AsRef<Path>>(path: P) -> io::Result<DoctorReport> {
+    let path = path.as_ref();
+    let handle = Handle::from_path(path)?;
+    let id = Handle::id(&handle);
+    let verified = match FileId::from_file_like_verified(&*handle) {
+        Ok(verified) => verified == id,
+        Err(err) => match Error::of(&err) {
+            Some(Error::IdentityMismatch { .. }) => false,
+            _ => return Err(err),
+        },
+    };
+    Ok(DoctorReport {
+        path: path.to_path_buf(),
+        class: Handle::device_class(&handle)?,
+        index_bits: id.0.index_bits(),
+        consistent: FileId::from_path(path)? == id,
+        verified,
+        capabilities: capabilities(),
+        fallbacks: imp::fallbacks(&id.0),
+        id,
+    })
+}
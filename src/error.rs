@@ -0,0 +1,281 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::{DeviceClass, FileId};
+
+/// The reason an operation of this crate failed.
+///
+/// The functions of this crate return [`io::Error`], so that they compose
+/// with the standard library. When a failure is specific to this crate, the
+/// returned error wraps one of these values, which [`Error::of`] recovers.
+/// The [`io::ErrorKind`] of the wrapping error is that of
+/// [`Error::kind`], so code that only looks at the kind keeps working.
+///
+/// A wrapping error has no [`raw_os_error`](io::Error::raw_os_error) of
+/// its own, even when it stands for the failure of a system call. The
+/// error code of the underlying failure is kept by [`Error::raw_os_error`]
+/// instead.
+///
+/// # Examples
+///
+/// Telling a missing file apart from a handle that has no identity:
+///
+/// ```rust
+/// use cross_file_id::{Error, FileId};
+///
+/// let err = FileId::from_path("does/not/exist").unwrap_err();
+/// match Error::of(&err) {
+///     Some(Error::OpenFailed { path, source }) => {
+///         assert_eq!(path.to_str(), Some("does/not/exist"));
+///         assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+///     }
+///     other => panic!("unexpected error: {:?}", other),
+/// }
+/// ```
+///
+/// Recovering the error code of the underlying failure:
+///
+/// ```rust
+/// use cross_file_id::{Error, FileId};
+///
+/// let err = FileId::from_path("does/not/exist").unwrap_err();
+/// assert_eq!(err.raw_os_error(), None);
+/// assert!(Error::of(&err).and_then(Error::raw_os_error).is_some());
+/// ```
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The file is of a kind that has no identity on this platform, such
+    /// as a socket or `NUL` on Windows.
+    UnsupportedHandleType {
+        /// The kind of the file.
+        class: DeviceClass,
+    },
+    /// The operation is not supported on this platform.
+    UnsupportedPlatform {
+        /// A description of the operation.
+        operation: &'static str,
+    },
+    /// A path could not be opened, or its identity could not be taken.
+    OpenFailed {
+        /// The path.
+        path: PathBuf,
+        /// The underlying error.
+        source: io::Error,
+    },
+    /// The metadata of an open file could not be queried.
+    MetadataFailed {
+        /// The underlying error.
+        source: io::Error,
+    },
+    /// Two independent ways of taking the identity of the same open file
+    /// disagreed, which points at a bug in the filesystem or its driver.
+    IdentityMismatch {
+        /// The identity from the primary mechanism.
+        primary: FileId,
+        /// The identity from the secondary mechanism.
+        secondary: FileId,
+    },
+    /// A directory was entered again while inside of it, as happens when a
+    /// symbolic link points at one of its ancestors.
+    FilesystemLoop {
+        /// The identity of the directory.
+        id: FileId,
+        /// The depth at which the directory was first entered, from zero
+        /// for the outermost directory.
+        depth: usize,
+    },
+    /// A [`DeletionGuard`](crate::DeletionGuard) refused an operation on a
+    /// protected file.
+    Protected {
+        /// The path the operation was to act on.
+        path: PathBuf,
+        /// The identity of the protected file.
+        id: FileId,
+    },
+    /// A file was replaced by another one, as detected by a
+    /// [`VerifiedReader`](crate::VerifiedReader),
+    /// [`Handle::try_map`](crate::Handle::try_map) or
+    /// [`reopen_verified`](crate::reopen_verified).
+    Replaced {
+        /// The identity of the expected file.
+        expected: FileId,
+        /// The identity found instead.
+        actual: FileId,
+        /// The watched path that names another file, or `None` if the
+        /// descriptor itself changed.
+        path: Option<PathBuf>,
+    },
+    /// A file that was being read from shrunk, as detected by a
+    /// [`VerifiedReader`](crate::VerifiedReader).
+    Truncated {
+        /// The identity of the file.
+        id: FileId,
+        /// The length of the file at the previous check.
+        before: u64,
+        /// The length of the file now.
+        after: u64,
+    },
+    /// A file that was being read from was written to, as detected by a
+    /// [`VerifiedReader`](crate::VerifiedReader) from its length and its
+    /// timestamps.
+    Modified {
+        /// The identity of the file.
+        id: FileId,
+    },
+    /// An operation did not complete within its timeout, as happens when a
+    /// network filesystem stops responding.
+    TimedOut {
+        /// A description of the operation.
+        operation: &'static str,
+        /// The timeout.
+        timeout: Duration,
+    },
+    /// An operation with a timeout was not attempted, because all the
+    /// worker threads that run such operations are busy, as happens when
+    /// they are stuck on a network filesystem that stopped responding.
+    WorkersBusy {
+        /// A description of the operation.
+        operation: &'static str,
+        /// The number of worker threads.
+        workers: usize,
+    },
+}
+
+impl Error {
+    /// Return the error of this crate wrapped by an [`io::Error`], if any.
+    pub fn of(err: &io::Error) -> Option<&Error> {
+        err.get_ref()?.downcast_ref()
+    }
+
+    /// The kind of the [`io::Error`] this error is wrapped in.
+    ///
+    /// Errors that wrap an underlying error have its kind.
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            Error::UnsupportedHandleType { .. }
+            | Error::IdentityMismatch { .. }
+            | Error::FilesystemLoop { .. }
+            | Error::Replaced { .. }
+            | Error::Truncated { .. }
+            | Error::Modified { .. } => io::ErrorKind::InvalidData,
+            Error::UnsupportedPlatform { .. } => io::ErrorKind::Unsupported,
+            Error::Protected { .. } => io::ErrorKind::PermissionDenied,
+            Error::TimedOut { .. } => io::ErrorKind::TimedOut,
+            Error::WorkersBusy { .. } => io::ErrorKind::ResourceBusy,
+            Error::OpenFailed { source, .. }
+            | Error::MetadataFailed { source } => source.kind(),
+        }
+    }
+
+    /// The OS error code of the underlying failure, if it came from a
+    /// system call.
+    ///
+    /// This is the [`raw_os_error`](io::Error::raw_os_error) that the
+    /// wrapping [`io::Error`] would have had if this crate had returned the
+    /// underlying error as is.
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match self {
+            Error::OpenFailed { source, .. }
+            | Error::MetadataFailed { source } => source.raw_os_error(),
+            _ => None,
+        }
+    }
+
+    /// Wrap the failure to open or stat `path`, unless it is already an
+    /// error of this crate.
+    pub(crate) fn open_failed(path: &Path, source: io::Error) -> io::Error {
+        if Error::of(&source).is_some() {
+            return source;
+        }
+        Error::OpenFailed { path: path.to_path_buf(), source }.into()
+    }
+
+    /// Wrap the failure to query an open file, unless it is already an
+    /// error of this crate.
+    pub(crate) fn metadata_failed(source: io::Error) -> io::Error {
+        if Error::of(&source).is_some() {
+            return source;
+        }
+        Error::MetadataFailed { source }.into()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnsupportedHandleType { class } => {
+                write!(f, "a {} has no file identity on this platform", class)
+            }
+            Error::UnsupportedPlatform { operation } => {
+                write!(f, "{} is not supported on this platform", operation)
+            }
+            Error::OpenFailed { path, source } => {
+                write!(f, "failed to open {}: {}", path.display(), source)
+            }
+            Error::MetadataFailed { source } => {
+                write!(f, "failed to query an open file: {}", source)
+            }
+            Error::IdentityMismatch { primary, secondary } => write!(
+                f,
+                "the identity of an open file is both {} and {}",
+                primary, secondary
+            ),
+            Error::FilesystemLoop { id, depth } => write!(
+                f,
+                "filesystem loop: {} was already entered at depth {}",
+                id, depth
+            ),
+            Error::Protected { path, id } => write!(
+                f,
+                "refusing to operate on {}, which is protected ({})",
+                path.display(),
+                id
+            ),
+            Error::Replaced { expected, actual, path: None } => {
+                write!(f, "file {} was replaced by {}", expected, actual)
+            }
+            Error::Replaced { expected, actual, path: Some(path) } => write!(
+                f,
+                "{} now names {} instead of {}, which was being read",
+                path.display(),
+                actual,
+                expected
+            ),
+            Error::Truncated { id, before, after } => write!(
+                f,
+                "file {} shrunk from {} to {} bytes while being read",
+                id, before, after
+            ),
+            Error::Modified { id } => {
+                write!(f, "file {} was modified while being read", id)
+            }
+            Error::TimedOut { operation, timeout } => {
+                write!(f, "{} timed out after {:?}", operation, timeout)
+            }
+            Error::WorkersBusy { operation, workers } => write!(
+                f,
+                "{} was not attempted: all {} worker threads are busy",
+                operation, workers
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::OpenFailed { source, .. }
+            | Error::MetadataFailed { source } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        io::Error::new(err.kind(), err)
+    }
+}
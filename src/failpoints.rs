@@ -0,0 +1,165 @@
+//! Failure injection for testing recovery paths.
+//!
+//! This module is only available with the `failpoints` feature, which is
+//! meant for test builds only. Arming a [`FailPoint`] makes the next
+//! operations of this crate that reach it fail, as if the race or error it
+//! stands for had happened:
+//!
+//! * [`FailPoint::Open`] fails opening a file by path, e.g. in
+//!   [`Handle::from_path`](crate::Handle::from_path).
+//! * [`FailPoint::Stat`] fails taking the identity of a file, e.g. in
+//!   [`FileId::from_file_like`](crate::FileId::from_file_like).
+//! * [`FailPoint::IdentityChange`] makes the file seen by a verification
+//!   step have a different identity than expected, as if it had been
+//!   replaced between the check and its use. This affects verifying
+//!   operations such as [`Handle::try_from_raw`](crate::Handle::try_from_raw).
+//!
+//! Fail points are armed per thread, so that tests running in parallel do
+//! not interfere with each other. Operations running on other threads are
+//! not affected, including those given a timeout, which run on a worker
+//! thread.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use cross_file_id::Handle;
+//! use cross_file_id::failpoints::{self, FailPoint};
+//!
+//! let _armed = failpoints::arm(FailPoint::Open, 1);
+//! assert!(Handle::from_path(".").is_err());
+//! assert!(Handle::from_path(".").is_ok());
+//! ```
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+
+use crate::FileId;
+
+/// A point in the operations of this crate where a failure can be injected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FailPoint {
+    /// Opening a file by path fails.
+    Open,
+    /// Obtaining the identity of an open file fails.
+    Stat,
+    /// A verified file turns out to have a different identity.
+    IdentityChange,
+}
+
+thread_local! {
+    static ARMED: RefCell<HashMap<FailPoint, usize>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Arm a fail point for the current thread, so that the next `times`
+/// operations reaching it fail.
+///
+/// The fail point is disarmed when the returned guard is dropped, even if it
+/// has not been reached `times` times. Arming a point that is already armed
+/// replaces its count.
+pub fn arm(point: FailPoint, times: usize) -> Armed {
+    ARMED.with(|armed| armed.borrow_mut().insert(point, times));
+    Armed { point }
+}
+
+/// A guard for an armed fail point, returned by [`arm`].
+#[derive(Debug)]
+#[must_use = "the fail point is disarmed when the guard is dropped"]
+pub struct Armed {
+    point: FailPoint,
+}
+
+impl Armed {
+    /// The number of failures still to be injected.
+    pub fn remaining(&self) -> usize {
+        ARMED.with(|armed| {
+            armed.borrow().get(&self.point).copied().unwrap_or(0)
+        })
+    }
+}
+
+impl Drop for Armed {
+    fn drop(&mut self) {
+        ARMED.with(|armed| armed.borrow_mut().remove(&self.point));
+    }
+}
+
+/// Returns true, and consumes one failure, if the point is armed.
+fn take(point: FailPoint) -> bool {
+    ARMED.with(|armed| match armed.borrow_mut().get_mut(&point) {
+        Some(remaining) if *remaining > 0 => {
+            *remaining -= 1;
+            true
+        }
+        _ => false,
+    })
+}
+
+pub(crate) fn hit(point: FailPoint) -> io::Result<()> {
+    if take(point) {
+        log_debug!("injecting failure at {:?}", point);
+        return Err(io::Error::other(format!(
+            "injected failure at {:?}",
+            point
+        )));
+    }
+    Ok(())
+}
+
+/// Returns an identity that differs from `id` if
+/// [`FailPoint::IdentityChange`] is armed, and `id` otherwise.
+pub(crate) fn observe(id: FileId) -> FileId {
+    if take(FailPoint::IdentityChange) {
+        log_debug!("injecting an identity change of {:?}", id);
+        // An identity exists, so its volume has bounds.
+        if let Some(range) = FileId::volume_range(id.volume()) {
+            let (min, max) = range.into_inner();
+            return if max != id { max } else { min };
+        }
+    }
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use io_lifetimes::raw::IntoRawFilelike;
+
+    use super::{FailPoint, arm};
+    use crate::testing::TempDir;
+    use crate::{FileId, Handle};
+
+    #[test]
+    fn open_and_stat() {
+        let tdir = TempDir::new().unwrap();
+        let file = File::create(tdir.path().join("a")).unwrap();
+
+        let armed = arm(FailPoint::Open, 2);
+        assert!(Handle::from_path(tdir.path()).is_err());
+        assert_eq!(armed.remaining(), 1);
+        drop(armed);
+        assert!(Handle::from_path(tdir.path()).is_ok());
+
+        let _armed = arm(FailPoint::Stat, 1);
+        assert!(FileId::from_file_like(&file).is_err());
+        assert!(FileId::from_file_like(&file).is_ok());
+    }
+
+    #[test]
+    fn identity_change() {
+        let tdir = TempDir::new().unwrap();
+        File::create(tdir.path().join("a")).unwrap();
+        let handle = Handle::from_path(tdir.path().join("a")).unwrap();
+        let id = Handle::id(&handle);
+
+        let _armed = arm(FailPoint::IdentityChange, 1);
+        let raw = handle.try_clone().unwrap().into_raw_filelike();
+        let err =
+            unsafe { Handle::<File>::try_from_raw(raw, &id) }.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        let raw = handle.try_clone().unwrap().into_raw_filelike();
+        assert!(unsafe { Handle::<File>::try_from_raw(raw, &id) }.is_ok());
+    }
+}
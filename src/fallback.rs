@@ -0,0 +1,383 @@
+//! A portable backend for platforms whose standard library exposes file
+//! descriptors but no device or inode numbers, such as Hermit.
+//!
+//! The identity of a file is derived from the [`Metadata`] the standard
+//! library provides: its type, and its creation time if the platform records
+//! one, or else its modification time and length. This is weaker than a
+//! device and inode number: two distinct files created at the same instant
+//! share an identity, and without a creation time the identity of a file
+//! changes whenever it is written. Every file is on volume 0.
+
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{File, Metadata};
+use std::hash::Hash;
+use std::io;
+use std::mem::ManuallyDrop;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use io_lifetimes::raw::{AsRawFilelike, RawFilelike};
+
+use crate::{DeviceClass, Error};
+
+/// The platform tag that starts the packed form of a metadata `FileId`.
+const PACKED_TAG: u8 = 4;
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FileId {
+    volume: u64,
+    kind: u8,
+    created: bool,
+    stamp: u64,
+    len: u64,
+}
+
+fn unsupported<T>(operation: &'static str) -> io::Result<T> {
+    Err(Error::UnsupportedPlatform { operation }.into())
+}
+
+/// Nanoseconds since the Unix epoch, saturating at both ends.
+fn nanos(time: SystemTime) -> u64 {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    u64::try_from(since.as_nanos()).unwrap_or(u64::MAX)
+}
+
+fn metadata(f: RawFilelike) -> io::Result<Metadata> {
+    if !is_valid_raw(f) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid file descriptor",
+        ));
+    }
+    // SAFETY: the descriptor is borrowed for the duration of the call, and
+    // `ManuallyDrop` keeps it from being closed.
+    let file = ManuallyDrop::new(unsafe { File::from_raw_fd(f) });
+    file.metadata()
+}
+
+// Without an extension trait, the standard library only tells files,
+// directories and links apart.
+fn classify(md: &Metadata) -> DeviceClass {
+    let ty = md.file_type();
+    if ty.is_file() {
+        DeviceClass::RegularFile
+    } else if ty.is_dir() {
+        DeviceClass::Directory
+    } else if ty.is_symlink() {
+        DeviceClass::Symlink
+    } else {
+        DeviceClass::Other
+    }
+}
+
+pub fn device_class(f: RawFilelike) -> io::Result<DeviceClass> {
+    Ok(classify(&metadata(f)?))
+}
+
+pub fn link_count(_f: RawFilelike) -> io::Result<u64> {
+    unsupported("counting the links to a file")
+}
+
+impl FileId {
+    fn from_metadata(md: &Metadata) -> FileId {
+        let ty = md.file_type();
+        let kind = if ty.is_file() {
+            1
+        } else if ty.is_dir() {
+            2
+        } else if ty.is_symlink() {
+            3
+        } else {
+            0
+        };
+        match md.created() {
+            Ok(created) => FileId {
+                volume: 0,
+                kind,
+                created: true,
+                stamp: nanos(created),
+                len: 0,
+            },
+            Err(_) => FileId {
+                volume: 0,
+                kind,
+                created: false,
+                stamp: md.modified().map(nanos).unwrap_or(0),
+                len: md.len(),
+            },
+        }
+    }
+
+    pub fn from_filelike(f: RawFilelike) -> io::Result<FileId> {
+        Ok(FileId::from_metadata(&metadata(f)?))
+    }
+
+    /// Identities come from `Metadata` alone, so there is nothing to check
+    /// them against.
+    pub fn from_filelike_verified(f: RawFilelike) -> io::Result<FileId> {
+        FileId::from_filelike(f)
+    }
+
+    pub fn with_subvolume(self, _f: RawFilelike) -> io::Result<FileId> {
+        Ok(self)
+    }
+
+    pub fn with_mount(self, _f: RawFilelike) -> io::Result<FileId> {
+        Ok(self)
+    }
+
+    pub fn is_mount_aware(&self) -> bool {
+        false
+    }
+
+    pub fn is_subvolume_aware(&self) -> bool {
+        false
+    }
+
+    pub fn from_filelike_classified(
+        f: RawFilelike,
+    ) -> io::Result<(FileId, DeviceClass)> {
+        let md = metadata(f)?;
+        Ok((FileId::from_metadata(&md), classify(&md)))
+    }
+
+    pub fn from_filelike_normalized(f: RawFilelike) -> io::Result<FileId> {
+        FileId::from_filelike(f)
+    }
+
+    pub fn from_path(path: &Path) -> io::Result<FileId> {
+        Ok(FileId::from_metadata(&std::fs::metadata(path)?))
+    }
+
+    pub fn from_path_no_follow(path: &Path) -> io::Result<FileId> {
+        Ok(FileId::from_metadata(&std::fs::symlink_metadata(path)?))
+    }
+
+    pub fn volume(&self) -> u64 {
+        self.volume
+    }
+
+    /// There is no file index.
+    pub fn index_bits(&self) -> u32 {
+        0
+    }
+
+    pub fn volume_bound(volume: u64, max: bool) -> Option<FileId> {
+        Some(if max {
+            FileId {
+                volume,
+                kind: u8::MAX,
+                created: true,
+                stamp: u64::MAX,
+                len: u64::MAX,
+            }
+        } else {
+            FileId { volume, kind: 0, created: false, stamp: 0, len: 0 }
+        })
+    }
+
+    #[cfg(feature = "test-util")]
+    pub fn fabricated(volume: u64, index: u64) -> FileId {
+        FileId { volume, kind: 1, created: true, stamp: index, len: 0 }
+    }
+
+    /// Layout: tag, volume, kind, creation flag, stamp and length (all
+    /// big-endian), zero padding.
+    pub fn pack(&self) -> [u8; 32] {
+        let mut packed = [0; 32];
+        packed[0] = PACKED_TAG;
+        packed[1..9].copy_from_slice(&self.volume.to_be_bytes());
+        packed[9] = self.kind;
+        packed[10] = self.created.into();
+        packed[11..19].copy_from_slice(&self.stamp.to_be_bytes());
+        packed[19..27].copy_from_slice(&self.len.to_be_bytes());
+        packed
+    }
+
+    pub fn unpack(packed: &[u8; 32]) -> Option<FileId> {
+        if packed[0] != PACKED_TAG
+            || packed[10] > 1
+            || packed[27..].iter().any(|&b| b != 0)
+        {
+            return None;
+        }
+        Some(FileId {
+            volume: u64::from_be_bytes(packed[1..9].try_into().unwrap()),
+            kind: packed[9],
+            created: packed[10] == 1,
+            stamp: u64::from_be_bytes(packed[11..19].try_into().unwrap()),
+            len: u64::from_be_bytes(packed[19..27].try_into().unwrap()),
+        })
+    }
+}
+
+/// Formatted as `volume:kind:created:stamp:len`, all in decimal.
+impl fmt::Display for FileId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}:{}:{}",
+            self.volume,
+            self.kind,
+            u8::from(self.created),
+            self.stamp,
+            self.len
+        )
+    }
+}
+
+impl FileId {
+    /// Parse the form produced by `Display`.
+    pub fn parse(s: &str) -> Option<FileId> {
+        let mut fields = s.split(':');
+        let id = FileId {
+            volume: crate::parse_decimal(fields.next()?)?,
+            kind: crate::parse_decimal(fields.next()?)?,
+            created: match fields.next()? {
+                "0" => false,
+                "1" => true,
+                _ => return None,
+            },
+            stamp: crate::parse_decimal(fields.next()?)?,
+            len: crate::parse_decimal(fields.next()?)?,
+        };
+        fields.next().is_none().then_some(id)
+    }
+}
+
+// Taking the metadata of every entry would cost a call per entry, which the
+// weak identity is meant to avoid.
+pub fn entry_weak_id(_parent: &FileId, _entry: &DirEntry) -> Option<FileId> {
+    None
+}
+
+/// An entry read from a directory listing.
+pub struct DirEntry(std::fs::DirEntry);
+
+impl DirEntry {
+    pub fn into_name(self) -> OsString {
+        self.0.file_name()
+    }
+}
+
+/// A directory listing, which can only be taken by path.
+#[derive(Debug)]
+pub struct ReadDir(std::fs::ReadDir);
+
+pub fn read_dir(_dir: RawFilelike, path: &Path) -> io::Result<ReadDir> {
+    Ok(ReadDir(std::fs::read_dir(path)?))
+}
+
+impl Iterator for ReadDir {
+    type Item = io::Result<DirEntry>;
+
+    fn next(&mut self) -> Option<io::Result<DirEntry>> {
+        Some(self.0.next()?.map(DirEntry))
+    }
+}
+
+impl<F> AsRawFd for crate::Handle<F>
+where
+    F: AsRawFd,
+{
+    fn as_raw_fd(&self) -> RawFd {
+        self.handle.as_raw_fd()
+    }
+}
+
+impl<F> AsFd for crate::Handle<F>
+where
+    F: AsFd,
+{
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.handle.as_fd()
+    }
+}
+
+impl<F> FromRawFd for crate::Handle<F>
+where
+    F: AsRawFilelike + FromRawFd,
+{
+    unsafe fn from_raw_fd(fd: RawFd) -> crate::Handle<F> {
+        let handle = unsafe { F::from_raw_fd(fd) };
+        crate::Handle::from_file_like(handle).expect("from_raw_fd failed")
+    }
+}
+
+impl<F> IntoRawFd for crate::Handle<F>
+where
+    F: IntoRawFd,
+{
+    fn into_raw_fd(self) -> RawFd {
+        self.handle.into_raw_fd()
+    }
+}
+
+pub fn fallbacks(_id: &FileId) -> Vec<&'static str> {
+    vec!["no file index, identities are derived from metadata"]
+}
+
+pub fn capabilities() -> crate::Capabilities {
+    crate::Capabilities {
+        identity: true,
+        // Identities are derived from metadata.
+        heuristic: true,
+        pins_while_open: false,
+        stable_across_reboots: false,
+        mount_ids: false,
+        reflink_detection: false,
+        current_path: false,
+        open_no_follow: false,
+        minimal_access: false,
+        mount_watch: false,
+    }
+}
+
+pub fn current_path(_f: RawFilelike) -> io::Result<PathBuf> {
+    unsupported("recovering the path of an open file")
+}
+
+#[cfg(feature = "mount-watch")]
+pub fn mounted_volumes() -> io::Result<std::collections::HashSet<u64>> {
+    unsupported("listing mounted volumes")
+}
+
+pub fn is_valid_raw(f: RawFilelike) -> bool {
+    f >= 0
+}
+
+pub fn set_inheritable(_f: RawFilelike, _inheritable: bool) -> io::Result<()> {
+    unsupported("changing the inheritance of a descriptor")
+}
+
+pub fn open_file(path: &Path) -> io::Result<File> {
+    std::fs::OpenOptions::new().read(true).open(path)
+}
+
+pub fn open_with_options(
+    path: &Path,
+    options: &crate::HandleOptions,
+) -> io::Result<File> {
+    if options.follow_symlinks {
+        open_file(path)
+    } else {
+        open_file_no_follow(path)
+    }
+}
+
+pub fn open_file_at(_dir: RawFilelike, _path: &Path) -> io::Result<File> {
+    unsupported("opening a path relative to a directory")
+}
+
+pub fn open_beneath(
+    _dir: RawFilelike,
+    _path: &Path,
+) -> io::Result<Option<File>> {
+    unsupported("opening a path beneath a directory")
+}
+
+pub fn open_file_no_follow(_path: &Path) -> io::Result<File> {
+    unsupported("opening a symbolic link")
+}
@@ -0,0 +1,131 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::FileId;
+use crate::options::IdentityMode;
+
+/// Why [`gc_persisted_ids`] sorted an identity the way it did.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum GcReason {
+    /// The path still names the file with this identity.
+    Matches,
+    /// The resolver returned no path for the identity.
+    Unresolved,
+    /// Nothing exists at the path anymore.
+    Missing,
+    /// The path names a different file.
+    Replaced {
+        /// The identity of the file now at the path.
+        actual: FileId,
+    },
+    /// The identity of the file at the path could not be obtained, for
+    /// instance because of missing permissions or an unreachable network
+    /// mount.
+    Unverifiable {
+        /// The error obtaining the identity.
+        source: io::Error,
+    },
+}
+
+/// A persisted identity, as sorted by [`gc_persisted_ids`].
+#[derive(Debug)]
+pub struct GcEntry {
+    /// The persisted identity.
+    pub id: FileId,
+    /// The path the resolver returned for it, if any.
+    pub path: Option<PathBuf>,
+    /// Why the identity was sorted this way.
+    pub reason: GcReason,
+}
+
+/// The result of [`gc_persisted_ids`].
+///
+/// Every identity given to it ends up in exactly one of the lists, in the
+/// order they were given.
+#[derive(Debug, Default)]
+pub struct GcReport {
+    /// The identities whose path still names the same file, with reason
+    /// [`GcReason::Matches`].
+    pub keep: Vec<GcEntry>,
+    /// The identities that no longer refer to anything, with reason
+    /// [`GcReason::Unresolved`], [`GcReason::Missing`] or
+    /// [`GcReason::Replaced`]. These can be removed from the store.
+    pub drop: Vec<GcEntry>,
+    /// The identities that could not be checked, with reason
+    /// [`GcReason::Unverifiable`]. These should be kept and checked again
+    /// later.
+    pub uncertain: Vec<GcEntry>,
+}
+
+/// Check persisted identities against the paths they were recorded for,
+/// sorting out the ones that are stale.
+///
+/// Applications that persist [`FileId`]s, for example in an index or a
+/// cache, usually record the path of each file along with it. Since an
+/// identity is only meaningful while its file exists, such stores must be
+/// cleaned up from time to time: `resolver` returns the recorded path of an
+/// identity, and the identity of the file now at that path is taken, without
+/// keeping it open, to tell whether the entry is still valid. Identities
+/// that include a mount id, as taken by
+/// [`FileId::from_file_like_mount_aware`], are compared with the identity
+/// of the file at the path taken the same way.
+///
+/// A match means that the path names a file with the same identity. As with
+/// any identity taken without keeping the file open, this cannot tell the
+/// file apart from a new one that was given a reused identity.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use std::collections::BTreeMap;
+/// use std::path::PathBuf;
+///
+/// use cross_file_id::{FileId, gc_persisted_ids};
+///
+/// fn clean(index: &mut BTreeMap<FileId, PathBuf>) {
+///     let ids: Vec<FileId> = index.keys().cloned().collect();
+///     let report = gc_persisted_ids(ids, |id| index.get(id).cloned());
+///     for entry in report.drop {
+///         index.remove(&entry.id);
+///     }
+/// }
+/// ```
+pub fn gc_persisted_ids<I, R, P>(ids: I, mut resolver: R) -> GcReport
+where
+    I: IntoIterator<Item = FileId>,
+    R: FnMut(&FileId) -> Option<P>,
+    P: AsRef<Path>,
+{
+    let mut report = GcReport::default();
+    for id in ids {
+        let Some(path) = resolver(&id) else {
+            report.drop.push(GcEntry {
+                id,
+                path: None,
+                reason: GcReason::Unresolved,
+            });
+            continue;
+        };
+        let path = path.as_ref().to_path_buf();
+        let (list, reason) = match IdentityMode::of(&id).id_of_path(&path) {
+            Ok(actual) if actual == id => {
+                (&mut report.keep, GcReason::Matches)
+            }
+            Ok(actual) => (&mut report.drop, GcReason::Replaced { actual }),
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::NotFound | io::ErrorKind::NotADirectory
+                ) =>
+            {
+                (&mut report.drop, GcReason::Missing)
+            }
+            Err(source) => {
+                (&mut report.uncertain, GcReason::Unverifiable { source })
+            }
+        };
+        list.push(GcEntry { id, path: Some(path), reason });
+    }
+    report
+}
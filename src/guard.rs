@@ -0,0 +1,142 @@
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{Error, FileId, IdSet};
+
+/// A safety check for destructive operations, refusing to touch protected
+/// files.
+///
+/// The protected files are recorded by identity, so a path that reaches one
+/// of them through a symbolic link, a bind mount, a different drive letter
+/// or a different spelling is refused as well, which string matching on
+/// paths cannot guarantee. Only the file a path names is checked: removing a
+/// directory that contains a protected file is not refused.
+///
+/// As with any [`IdSet`], the identities are only meaningful while the
+/// protected files exist, so a guard should be built shortly before it is
+/// used rather than kept for the lifetime of a long-running process.
+///
+/// # Examples
+///
+/// Refusing to remove the user's home directory, or the tool's own
+/// configuration:
+///
+/// ```rust,no_run
+/// use cross_file_id::DeletionGuard;
+///
+/// let guard = DeletionGuard::new().protecting(["./tool.toml"]);
+/// for path in std::env::args_os().skip(1) {
+///     guard.check(&path)?;
+///     std::fs::remove_file(&path)?;
+/// }
+/// # Ok::<_, std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DeletionGuard {
+    protected: IdSet,
+}
+
+impl DeletionGuard {
+    /// Create a guard that protects the paths no tool should remove: the
+    /// root directory, the Windows directory and the root of the drive it is
+    /// on, the home directory of the current user, and the running
+    /// executable.
+    ///
+    /// Paths that cannot be resolved on this system are skipped.
+    pub fn new() -> DeletionGuard {
+        DeletionGuard::with_paths(special_paths())
+    }
+
+    /// Create a guard that protects the files at the given paths, and
+    /// nothing else.
+    ///
+    /// Paths that cannot be resolved, such as paths that do not exist, are
+    /// skipped.
+    pub fn with_paths<I, P>(paths: I) -> DeletionGuard
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        DeletionGuard::default().protecting(paths)
+    }
+
+    /// Create a guard that protects the given identities.
+    pub fn with_ids(protected: IdSet) -> DeletionGuard {
+        DeletionGuard { protected }
+    }
+
+    /// Also protect the files at the given paths.
+    ///
+    /// Paths that cannot be resolved, such as paths that do not exist, are
+    /// skipped.
+    pub fn protecting<I, P>(self, paths: I) -> DeletionGuard
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let added =
+            paths.into_iter().filter_map(|path| FileId::from_path(path).ok());
+        DeletionGuard {
+            protected: self.protected.iter().chain(added).collect(),
+        }
+    }
+
+    /// The protected identities.
+    pub fn protected(&self) -> &IdSet {
+        &self.protected
+    }
+
+    /// Returns true if the given identity is protected.
+    pub fn is_protected(&self, id: &FileId) -> bool {
+        id.is_any_of(&self.protected)
+    }
+
+    /// Check that the file at `path` may be operated on.
+    ///
+    /// A final symbolic link is not followed, as removing a link leaves its
+    /// target alone, except on platforms that cannot take the identity of a
+    /// link. A path that does not exist passes the check.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] of kind
+    /// [`io::ErrorKind::PermissionDenied`], wrapping
+    /// [`Error::Protected`], if the file is protected. It also fails if the
+    /// identity of the file cannot be obtained for any reason other than the
+    /// file not existing.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn check<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let id = match FileId::from_path_no_follow(path) {
+            Err(err) if err.kind() == io::ErrorKind::Unsupported => {
+                FileId::from_path(path)
+            }
+            result => result,
+        };
+        match id {
+            Ok(id) if self.is_protected(&id) => {
+                Err(Error::Protected { path: path.to_path_buf(), id }.into())
+            }
+            Ok(_) => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+fn special_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if cfg!(windows) {
+        if let Some(mut root) = env::var_os("SystemDrive") {
+            root.push("\\");
+            paths.push(root.into());
+        }
+        paths.extend(env::var_os("SystemRoot").map(PathBuf::from));
+    } else {
+        paths.push(PathBuf::from("/"));
+    }
+    paths.extend(env::home_dir());
+    paths.extend(env::current_exe().ok());
+    paths
+}
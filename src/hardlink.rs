@@ -0,0 +1,83 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{AsRawFilelike, FileId, FileIdMap, imp};
+
+/// The paths of a file with several hard links, as found by
+/// [`hard_link_groups`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HardLinkGroup {
+    /// The identity of the file.
+    pub id: FileId,
+    /// The number of hard links to the file, as reported by the filesystem
+    /// when the first of its paths was opened.
+    ///
+    /// This is more than the number of paths when some links to the file
+    /// were not among the paths given.
+    pub links: u64,
+    /// The paths that name the file, in the order they were given.
+    pub paths: Vec<PathBuf>,
+}
+
+/// Group the paths that are hard links to the same file.
+///
+/// The link count of every file, `st_nlink` on Unix and WASI and
+/// `NumberOfLinks` on Windows, is checked first: files with a single link
+/// cannot share an identity with another path, so they are not grouped, and
+/// only files with several links are. This tells apart files that are
+/// genuinely hard linked, which a backup tool should link again on restore,
+/// from plain files.
+///
+/// Groups are returned in the order their first path was given. A group can
+/// have a single path, when the other links to the file were not given.
+/// Symbolic links are followed, so only the paths of regular files should be
+/// given.
+///
+/// The files are not kept open, so that a large tree cannot exhaust the open
+/// files of the process. A file deleted during the call can then have its
+/// identity reused by a new file with several links, and be grouped with it.
+///
+/// # Errors
+/// This function will return the first [`io::Error`] encountered while
+/// opening the paths or querying the files. Link counts cannot be queried on
+/// platforms other than Unix, Windows and WASI.
+///
+/// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use cross_file_id::hard_link_groups;
+///
+/// for group in hard_link_groups(["./a", "./b", "./c"])? {
+///     println!("{} of {} links:", group.paths.len(), group.links);
+///     for path in group.paths {
+///         println!("  {}", path.display());
+///     }
+/// }
+/// # Ok::<_, std::io::Error>(())
+/// ```
+pub fn hard_link_groups<I, P>(paths: I) -> io::Result<Vec<HardLinkGroup>>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+{
+    let mut groups: Vec<HardLinkGroup> = Vec::new();
+    let mut index = FileIdMap::new();
+    for path in paths {
+        let path = path.as_ref();
+        fail_point!(Open);
+        let file = imp::open_file(path)?;
+        let links = imp::link_count(file.as_raw_filelike())?;
+        if links <= 1 {
+            continue;
+        }
+        let id = FileId::from_file_like(&file)?;
+        let group = *index.entry(id.clone()).or_insert_with(|| {
+            groups.push(HardLinkGroup { id, links, paths: Vec::new() });
+            groups.len() - 1
+        });
+        groups[group].paths.push(path.to_path_buf());
+    }
+    Ok(groups)
+}
@@ -0,0 +1,282 @@
+use std::io;
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+#[cfg(windows)]
+use std::os::windows::io::{
+    AsRawHandle, BorrowedHandle, IntoRawHandle, OwnedHandle, RawHandle,
+};
+
+use crate::{AsRawFilelike, Handle, imp};
+#[cfg(any(unix, windows, target_os = "wasi", target_os = "hermit"))]
+use crate::{FileId, FromRawFilelike, RawFilelike};
+
+impl<F> Handle<F>
+where
+    F: AsRawFilelike,
+{
+    /// Control whether the underlying file is inherited by child processes.
+    ///
+    /// On Unix this clears or sets the close-on-exec flag of the file
+    /// descriptor. On Windows it sets or clears the handle's inheritance
+    /// flag. The standard library opens files as non-inheritable, so this
+    /// must be called before spawning a child that should receive the file.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the flags of the
+    /// underlying file cannot be changed.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn inheritable(&self, inheritable: bool) -> io::Result<()> {
+        imp::set_inheritable(self.handle.as_raw_filelike(), inheritable)
+    }
+}
+
+#[cfg(any(unix, windows, target_os = "wasi", target_os = "hermit"))]
+impl<F> Handle<F>
+where
+    F: AsRawFilelike + FromRawFilelike,
+{
+    /// Construct a handle from a raw descriptor or handle, verifying that it
+    /// refers to the expected file.
+    ///
+    /// This is intended for a child process that was handed an inherited file
+    /// by its parent, together with the identity the parent verified. It
+    /// guards against the descriptor number having been closed and reused, or
+    /// otherwise not referring to the file the parent intended.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be an open descriptor or handle owned by the caller.
+    /// Ownership is transferred to this function, and the file is closed if
+    /// the identity does not match.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the identity of `raw`
+    /// cannot be obtained, or with [`io::ErrorKind::InvalidData`] if it does
+    /// not match `expected`.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub unsafe fn try_from_raw(
+        raw: RawFilelike,
+        expected: &FileId,
+    ) -> io::Result<Self> {
+        let file = unsafe { F::from_raw_filelike(raw) };
+        let handle = Handle::from_file_like(file)?;
+        if fail_identity!(handle.identity.clone()) != *expected {
+            log_warn!(
+                "inherited file {:?} does not match expected identity {:?}",
+                handle.identity,
+                expected
+            );
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "inherited file does not match the expected identity",
+            ));
+        }
+        Ok(handle)
+    }
+
+    /// Construct a handle from the textual form of an inherited descriptor
+    /// or handle, verifying that it refers to the expected file.
+    ///
+    /// This accepts the value produced by `FdHandoff::value` on Unix or
+    /// `HandleHandoff::value` on Windows, as passed to the child through its
+    /// command line or environment.
+    ///
+    /// # Safety
+    ///
+    /// The parsed descriptor or handle must be open and owned by the caller,
+    /// as with [`try_from_raw`](Handle::try_from_raw).
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] with
+    /// [`io::ErrorKind::InvalidInput`] if `value` cannot be parsed, and
+    /// otherwise fails as [`try_from_raw`](Handle::try_from_raw) does.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub unsafe fn try_from_raw_value(
+        value: &str,
+        expected: &FileId,
+    ) -> io::Result<Self> {
+        let raw = parse_raw(value).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid inherited file value: {:?}", value),
+            )
+        })?;
+        unsafe { Handle::try_from_raw(raw, expected) }
+    }
+}
+
+#[cfg(unix)]
+fn parse_raw(value: &str) -> Option<RawFilelike> {
+    value.parse::<RawFd>().ok().filter(|fd| *fd >= 0)
+}
+
+#[cfg(windows)]
+fn parse_raw(value: &str) -> Option<RawFilelike> {
+    value.parse::<usize>().ok().map(|h| h as RawHandle)
+}
+
+#[cfg(any(target_os = "wasi", target_os = "hermit"))]
+fn parse_raw(_value: &str) -> Option<RawFilelike> {
+    None
+}
+
+/// A duplicate of a handle's descriptor placed at a fixed descriptor number,
+/// ready to be inherited by a child process.
+///
+/// Created by [`Handle::keep_at`]. The duplicated descriptor is owned by this
+/// value and is closed when it is dropped, which is typically done right
+/// after the child has been spawned. The child reconstructs the handle with
+/// [`Handle::try_from_raw`], passing the descriptor number and the identity
+/// reported by [`id`](FdHandoff::id).
+#[cfg(unix)]
+#[derive(Debug)]
+#[must_use = "the duplicated descriptor is closed when this is dropped"]
+pub struct FdHandoff {
+    fd: OwnedFd,
+    id: FileId,
+}
+
+#[cfg(unix)]
+impl FdHandoff {
+    /// The descriptor number the file was placed at.
+    pub fn fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+
+    /// The identity the child should verify the descriptor against.
+    pub fn id(&self) -> &FileId {
+        &self.id
+    }
+
+    /// The descriptor number as a string, suitable for passing to the child
+    /// through its command line or environment.
+    pub fn value(&self) -> String {
+        self.fd().to_string()
+    }
+
+    /// Release ownership of the descriptor without closing it.
+    pub fn into_raw_fd(self) -> RawFd {
+        self.fd.into_raw_fd()
+    }
+}
+
+#[cfg(unix)]
+impl<F> Handle<F>
+where
+    F: AsRawFd,
+{
+    /// Duplicate the underlying descriptor to the descriptor number `fd`,
+    /// and mark it as inheritable.
+    ///
+    /// This packages the common pattern of passing a verified file to a
+    /// spawned worker at a well-known descriptor number (e.g. 3). Any file
+    /// that was previously open at `fd` is silently closed, as with
+    /// `dup2(2)`.
+    ///
+    /// # Safety
+    ///
+    /// `fd` must either not be open, or be owned by the caller, who gives up
+    /// ownership of it. The returned [`FdHandoff`] then owns the descriptor,
+    /// so nothing else may use or close it.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the descriptor cannot be
+    /// duplicated, or with [`io::ErrorKind::InvalidInput`] if `fd` is the
+    /// handle's own descriptor.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub unsafe fn keep_at(&self, fd: RawFd) -> io::Result<FdHandoff> {
+        let src = self.handle.as_raw_fd();
+        if src == fd {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot keep a descriptor at its own number",
+            ));
+        }
+        // SAFETY: dup2 either fails or returns `fd`, which is then a fresh
+        // duplicate. The caller guarantees that nothing else owns `fd`.
+        let fd = unsafe {
+            if libc::dup2(src, fd) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            OwnedFd::from_raw_fd(fd)
+        };
+        imp::set_inheritable(fd.as_raw_fd(), true)?;
+        Ok(FdHandoff { fd, id: self.identity.clone() })
+    }
+}
+
+/// An inheritable duplicate of a handle, ready to be passed to a child
+/// process.
+///
+/// Created by [`Handle::prepare_handoff`]. Windows handle values are
+/// preserved across inheritance, so the child can reconstruct the handle from
+/// [`value`](HandleHandoff::value) with [`Handle::try_from_raw_value`]. The
+/// duplicate is owned by this value and is closed when it is dropped, which
+/// is typically done right after the child has been spawned.
+#[cfg(windows)]
+#[derive(Debug)]
+#[must_use = "the duplicated handle is closed when this is dropped"]
+pub struct HandleHandoff {
+    handle: OwnedHandle,
+    id: FileId,
+}
+
+#[cfg(windows)]
+impl HandleHandoff {
+    /// The raw value of the inheritable handle.
+    pub fn raw_handle(&self) -> RawHandle {
+        self.handle.as_raw_handle()
+    }
+
+    /// The identity the child should verify the handle against.
+    pub fn id(&self) -> &FileId {
+        &self.id
+    }
+
+    /// The handle value as a string, suitable for passing to the child
+    /// through its command line or environment.
+    pub fn value(&self) -> String {
+        (self.raw_handle() as usize).to_string()
+    }
+
+    /// Release ownership of the handle without closing it.
+    pub fn into_raw_handle(self) -> RawHandle {
+        self.handle.into_raw_handle()
+    }
+}
+
+#[cfg(windows)]
+impl<F> Handle<F>
+where
+    F: AsRawHandle,
+{
+    /// Create an inheritable duplicate of the underlying handle, to be
+    /// passed to a spawned child process.
+    ///
+    /// Only handles marked inheritable are passed to children. The standard
+    /// library opens every handle as non-inheritable, as it marks every
+    /// descriptor it opens close-on-exec on Unix, and lets a spawned child
+    /// inherit every inheritable handle of the process, however its stdio is
+    /// configured. The duplicate therefore reaches any child spawned while
+    /// it is alive, and should be dropped right after spawning the intended
+    /// one.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the handle cannot be
+    /// duplicated or marked inheritable.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn prepare_handoff(&self) -> io::Result<HandleHandoff> {
+        // SAFETY: The handle is kept open by `self` for the duration of the
+        // borrow.
+        let handle =
+            unsafe { BorrowedHandle::borrow_raw(self.handle.as_raw_handle()) }
+                .try_clone_to_owned()?;
+        imp::set_inheritable(handle.as_raw_handle(), true)?;
+        Ok(HandleHandoff { handle, id: self.identity.clone() })
+    }
+}
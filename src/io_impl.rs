@@ -0,0 +1,136 @@
+//! Forwarding of the standard I/O traits, so that a handle can be used
+//! wherever its underlying object can, without giving up the identity.
+//!
+//! The methods that the underlying object may specialize are forwarded
+//! along with the required ones.
+
+use std::io::{
+    self, BufRead, IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write,
+};
+
+use crate::Handle;
+
+impl<F: Read> Read for Handle<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.handle.read(buf)
+    }
+
+    fn read_vectored(
+        &mut self,
+        bufs: &mut [IoSliceMut<'_>],
+    ) -> io::Result<usize> {
+        self.handle.read_vectored(bufs)
+    }
+
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        self.handle.read_to_end(buf)
+    }
+
+    fn read_to_string(&mut self, buf: &mut String) -> io::Result<usize> {
+        self.handle.read_to_string(buf)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.handle.read_exact(buf)
+    }
+}
+
+impl<F> Read for &Handle<F>
+where
+    for<'a> &'a F: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&self.handle).read(buf)
+    }
+
+    fn read_vectored(
+        &mut self,
+        bufs: &mut [IoSliceMut<'_>],
+    ) -> io::Result<usize> {
+        (&self.handle).read_vectored(bufs)
+    }
+
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        (&self.handle).read_to_end(buf)
+    }
+
+    fn read_to_string(&mut self, buf: &mut String) -> io::Result<usize> {
+        (&self.handle).read_to_string(buf)
+    }
+}
+
+impl<F: Write> Write for Handle<F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.handle.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.handle.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.handle.flush()
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.handle.write_all(buf)
+    }
+}
+
+impl<F> Write for &Handle<F>
+where
+    for<'a> &'a F: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&self.handle).write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        (&self.handle).write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&self.handle).flush()
+    }
+}
+
+impl<F: Seek> Seek for Handle<F> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.handle.seek(pos)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        self.handle.stream_position()
+    }
+}
+
+impl<F> Seek for &Handle<F>
+where
+    for<'a> &'a F: Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        (&self.handle).seek(pos)
+    }
+}
+
+impl<F: BufRead> BufRead for Handle<F> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.handle.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.handle.consume(amt)
+    }
+
+    fn read_until(
+        &mut self,
+        byte: u8,
+        buf: &mut Vec<u8>,
+    ) -> io::Result<usize> {
+        self.handle.read_until(byte, buf)
+    }
+
+    fn read_line(&mut self, buf: &mut String) -> io::Result<usize> {
+        self.handle.read_line(buf)
+    }
+}
@@ -0,0 +1,48 @@
+use std::mem::ManuallyDrop;
+
+use crate::{FileId, Handle};
+
+/// A file identity that is guaranteed to remain valid for the lifetime of
+/// the process.
+///
+/// Created by [`Handle::leak`], which intentionally never closes the
+/// underlying file. Unlike a bare [`FileId`], this can be stored and compared
+/// freely without any additional rules.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LeakedId(FileId);
+
+impl LeakedId {
+    /// The identity of the leaked file.
+    pub fn id(&self) -> &FileId {
+        &self.0
+    }
+}
+
+impl AsRef<FileId> for LeakedId {
+    fn as_ref(&self) -> &FileId {
+        &self.0
+    }
+}
+
+impl PartialEq<FileId> for LeakedId {
+    fn eq(&self, other: &FileId) -> bool {
+        self.0 == *other
+    }
+}
+
+impl<F> Handle<F> {
+    /// Consume the handle without ever closing the underlying file.
+    ///
+    /// This is meant for programs that pin a small number of critical files
+    /// (such as their own executable or data directory) for the whole
+    /// lifetime of the process, and don't want to store the handle anywhere.
+    /// Each call permanently consumes an open file resource.
+    ///
+    /// This is provided as an associated function instead of a method
+    /// to ensure that operations that rely on the value being accessible via
+    /// dereference aren't accidentally masked.
+    pub fn leak(this: Self) -> LeakedId {
+        let this = ManuallyDrop::new(this);
+        LeakedId(this.identity.clone())
+    }
+}
@@ -9,22 +9,173 @@
 //! Other types are provided to provide a "safer" interface for using file identity
 //! which ensures that the file remains open for the lifetime of the identity.
 #![warn(missing_docs)]
+// The stand-ins of the unknown backend are not nameable by other crates.
+#![cfg_attr(
+    not(any(unix, windows, target_os = "wasi", target_os = "hermit")),
+    allow(private_bounds, private_interfaces)
+)]
 
 #[cfg(doctest)]
 doc_comment::doctest!("../README.md");
 
 use std::io::{self, Stderr, Stdout};
-use std::path::Path;
-use std::{fs::File, io::Stdin};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::{
+    fs::{File, Metadata},
+    io::Stdin,
+};
 
-use io_lifetimes::raw::{AsRawFilelike, RawFilelike};
+// io-lifetimes is empty on other platforms, where the unknown backend stands
+// in for the parts of it that every platform needs.
+#[cfg(not(any(
+    unix,
+    windows,
+    target_os = "wasi",
+    target_os = "hermit"
+)))]
+use imp::{AsRawFilelike, RawFilelike};
+#[cfg(any(unix, windows, target_os = "wasi", target_os = "hermit"))]
+use io_lifetimes::raw::{
+    AsRawFilelike, FromRawFilelike, IntoRawFilelike, RawFilelike,
+};
+#[cfg(any(unix, windows, target_os = "wasi", target_os = "hermit"))]
+use io_lifetimes::{
+    AsFilelike, BorrowedFilelike, FromFilelike, OwnedFilelike,
+};
+
+#[macro_use]
+mod diag;
 
 // Import the platform-specific implementation.
 #[cfg_attr(unix, path = "unix.rs")]
 #[cfg_attr(windows, path = "win.rs")]
-#[cfg_attr(not(any(unix, windows)), path = "unknown.rs")]
+#[cfg_attr(target_os = "wasi", path = "wasi.rs")]
+#[cfg_attr(target_os = "hermit", path = "fallback.rs")]
+#[cfg_attr(
+    not(any(unix, windows, target_os = "wasi", target_os = "hermit")),
+    path = "unknown.rs"
+)]
 mod imp;
 
+mod alias;
+mod anchor;
+#[cfg(any(test, feature = "testing"))]
+mod assert;
+#[cfg(feature = "async-io")]
+mod async_io;
+#[cfg(any(unix, windows, target_os = "wasi", target_os = "hermit"))]
+mod borrowed;
+mod buffered;
+mod bulk;
+#[cfg(feature = "camino")]
+mod camino_impl;
+mod caps;
+mod change;
+mod class;
+mod collections;
+mod deadline;
+mod dedup;
+mod dir;
+mod doctor;
+mod error;
+mod gc;
+mod guard;
+mod hardlink;
+mod inherit;
+mod io_impl;
+mod leak;
+mod meter;
+#[cfg(windows)]
+mod msys;
+mod options;
+#[cfg(any(unix, windows, target_os = "wasi", target_os = "hermit"))]
+mod owned;
+mod process;
+mod reopen;
+#[cfg(feature = "serde")]
+mod serde_impl;
+mod shared;
+mod source;
+mod timed;
+#[cfg(feature = "tokio")]
+mod tokio_impl;
+mod unique;
+#[cfg(feature = "uuid")]
+mod uuid_impl;
+mod verified;
+
+#[cfg(feature = "broker")]
+pub mod broker;
+#[cfg(feature = "failpoints")]
+pub mod failpoints;
+#[cfg(all(
+    feature = "test-util",
+    any(unix, windows, target_os = "wasi", target_os = "hermit")
+))]
+pub mod mock;
+#[cfg(feature = "mount-watch")]
+pub mod mounts;
+#[cfg(feature = "debug-registry")]
+pub mod registry;
+#[cfg(feature = "signed")]
+pub mod signed;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+pub use crate::alias::{
+    DirAlias, dirs_alias, find_aliases, is_ancestor_of, paths_lexically_equal,
+};
+pub use crate::anchor::Anchor;
+#[cfg(any(test, feature = "testing"))]
+#[doc(hidden)]
+pub use crate::assert::{
+    __assert_file_identity, __assert_replaced_atomically, FileOperand, Operand,
+};
+#[cfg(any(unix, windows, target_os = "wasi", target_os = "hermit"))]
+pub use crate::borrowed::BorrowedHandle;
+#[cfg(feature = "rayon")]
+pub use crate::bulk::RayonSpawner;
+pub use crate::bulk::{
+    Sequential, Spawner, Task, ThreadSpawner, ids_from_paths,
+    ids_from_paths_no_follow,
+};
+pub use crate::caps::{Capabilities, capabilities};
+pub use crate::change::{IdChange, IdChangeKind};
+pub use crate::class::DeviceClass;
+pub use crate::collections::{
+    AncestorStack, FileIdMap, HandleSet, HandleSetIntoIter, HandleSetIter,
+    IdSet, ScopedFileIdMap, SmallHandleVec,
+};
+pub use crate::deadline::{ids_from_paths_until, is_same_file_path_timeout};
+pub use crate::dedup::Deduplicator;
+pub use crate::dir::{DirEntry, DirHandle, ReadDir, WeakId};
+pub use crate::doctor::{DoctorReport, doctor};
+pub use crate::error::Error;
+pub use crate::gc::{GcEntry, GcReason, GcReport, gc_persisted_ids};
+pub use crate::guard::DeletionGuard;
+pub use crate::hardlink::{HardLinkGroup, hard_link_groups};
+#[cfg(unix)]
+pub use crate::inherit::FdHandoff;
+#[cfg(windows)]
+pub use crate::inherit::HandleHandoff;
+pub use crate::leak::LeakedId;
+pub use crate::meter::{Usage, UsageMeter};
+#[cfg(windows)]
+pub use crate::msys::{is_same_file_msys_path, msys_to_windows_path};
+pub use crate::options::HandleOptions;
+#[cfg(any(unix, windows, target_os = "wasi", target_os = "hermit"))]
+pub use crate::owned::OwnedFileId;
+pub use crate::process::{ChildStream, verify_child_stream};
+pub use crate::reopen::reopen_verified;
+pub use crate::shared::SharedHandle;
+pub use crate::source::{FileIdentitySource, OsIdentity};
+pub use crate::timed::TimedId;
+pub use crate::unique::UniqueFiles;
+pub use crate::verified::VerifiedReader;
+
+use crate::options::IdentityMode;
+
 /// A cross-platform representation of a file's identity.
 ///
 /// This represents an OS unique identifier for a file. Two files with the same
@@ -36,9 +187,70 @@ mod imp;
 /// This does not hold onto any system resources, so it is safe to store and
 /// copy, but if the safety of the program is dependent on the identity
 /// remaining valid, then the file must be kept open by this process.
+///
+/// A file seen through two bind mounts has a single identity. Some overlayfs
+/// setups reuse device and inode numbers across mounts, and files on them
+/// can then be mistaken for one another; on Linux 5.8 and later,
+/// [`from_file_like_mount_aware`](FileId::from_file_like_mount_aware) tells
+/// them apart by the mount they were reached through.
+///
+/// # Ordering
+///
+/// Identities are ordered by [volume](FileId::volume) first, so all the
+/// identities of a volume form a contiguous range. Together with
+/// [`volume_range`](FileId::volume_range), this allows a
+/// `BTreeMap<FileId, V>` to be queried or pruned one volume at a time. The
+/// order within a volume is unspecified, but is consistent with the order of
+/// the [packed forms](FileId::to_packed) of identities from the same
+/// platform.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct FileId(imp::FileId);
 
+/// A compact form for log lines and error messages.
+///
+/// On Unix and WASI, this is `dev:ino` in decimal, followed on Linux by
+/// `:mnt` when the mount id was asked for and by `:subvol` when the btrfs
+/// subvolume id was. On Windows, it is `volume:fileid`, with the volume
+/// serial number and the 128-bit file id in hexadecimal, or the 64-bit file
+/// index on systems without `FileIdInfo`. Windows pipes are formatted as
+/// `pipe:hash`, with the 128-bit hash of the pipe name in hexadecimal, which
+/// only identifies the pipe while it exists, and consoles as
+/// `console:object:in` or `console:object:out`, where the object is only
+/// meaningful within the process. The form is specific to the
+/// platform, like the identity itself, and can be parsed back with
+/// [`parse_file_id`].
+impl std::fmt::Display for FileId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Parses the form produced by [`Display`](std::fmt::Display), as
+/// [`parse_file_id`] does.
+impl std::str::FromStr for FileId {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<FileId> {
+        imp::FileId::parse(s).map(FileId).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{:?} is not a file identity of this platform", s),
+            )
+        })
+    }
+}
+
+/// Parse a decimal number in the form produced by `Display`, so that each
+/// identity has a single spelling: without a sign or leading zeros.
+#[cfg(any(unix, target_os = "wasi", target_os = "hermit"))]
+fn parse_decimal<T: std::str::FromStr>(s: &str) -> Option<T> {
+    let canonical = s == "0" || !s.starts_with('0');
+    if !canonical || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
 impl FileId {
     /// Extract a file identity from any type that implements the
     /// [`AsRawOsFile`] trait, and thus the platform-specific traits
@@ -53,7 +265,433 @@ impl FileId {
     ///
     /// This does not take ownership of the OS file or alter its state.
     pub fn from_raw(os_file: RawFilelike) -> io::Result<Self> {
-        imp::FileId::from_filelike(os_file).map(FileId)
+        fail_point!(Stat);
+        imp::FileId::from_filelike(os_file)
+            .map(FileId)
+            .map_err(Error::metadata_failed)
+    }
+
+    /// Extract a file identity from any type that implements [`AsFd`].
+    ///
+    /// This is [`from_file_like`](FileId::from_file_like) for the types of
+    /// the standard library's I/O safety traits, such as [`BorrowedFd`] and
+    /// [`OwnedFd`]. Code that is generic over platforms can instead pass
+    /// the [`BorrowedFilelike`] returned by
+    /// [`AsFilelike::as_filelike`](io_lifetimes::AsFilelike::as_filelike)
+    /// to [`from_file_like`](FileId::from_file_like).
+    ///
+    /// This does not take ownership of the file or alter its state.
+    ///
+    /// [`AsFd`]: https://doc.rust-lang.org/std/os/fd/trait.AsFd.html
+    /// [`BorrowedFd`]: https://doc.rust-lang.org/std/os/fd/struct.BorrowedFd.html
+    /// [`OwnedFd`]: https://doc.rust-lang.org/std/os/fd/struct.OwnedFd.html
+    /// [`BorrowedFilelike`]: io_lifetimes::BorrowedFilelike
+    #[cfg(any(unix, target_os = "wasi"))]
+    pub fn from_fd<F: std::os::fd::AsFd>(file: &F) -> io::Result<Self> {
+        Self::from_file_like(&file.as_fd())
+    }
+
+    /// Extract a file identity from any type that implements [`AsHandle`].
+    ///
+    /// This is [`from_file_like`](FileId::from_file_like) for the types of
+    /// the standard library's I/O safety traits, such as
+    /// [`BorrowedHandle`](std::os::windows::io::BorrowedHandle) and
+    /// [`OwnedHandle`](std::os::windows::io::OwnedHandle). Code that is
+    /// generic over platforms can instead pass the [`BorrowedFilelike`]
+    /// returned by
+    /// [`AsFilelike::as_filelike`](io_lifetimes::AsFilelike::as_filelike)
+    /// to [`from_file_like`](FileId::from_file_like).
+    ///
+    /// This does not take ownership of the file or alter its state.
+    ///
+    /// [`AsHandle`]: https://doc.rust-lang.org/std/os/windows/io/trait.AsHandle.html
+    /// [`BorrowedFilelike`]: io_lifetimes::BorrowedFilelike
+    #[cfg(windows)]
+    pub fn from_handle<F: std::os::windows::io::AsHandle>(
+        file: &F,
+    ) -> io::Result<Self> {
+        Self::from_file_like(&file.as_handle())
+    }
+
+    /// Extract the identity of the file at a path, without keeping the file
+    /// open.
+    ///
+    /// Symbolic links are followed. On Unix this is a single `stat` call; on
+    /// Windows a handle is opened just long enough to query the identity.
+    ///
+    /// Since the file is not kept open, nothing prevents it from being
+    /// deleted and its identity reused by a new file right after this
+    /// returns. This is meant for momentary comparisons where that race is
+    /// acceptable; use [`Handle::from_path`] when the identity must stay
+    /// valid.
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] if the file cannot be
+    /// found or its identity cannot be obtained.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn from_path<P: AsRef<Path>>(path: P) -> io::Result<FileId> {
+        fail_point!(Stat);
+        let path = path.as_ref();
+        imp::FileId::from_path(path)
+            .map(FileId)
+            .map_err(|err| Error::open_failed(path, err))
+    }
+
+    /// Extract the identity of the executable of the running process,
+    /// without keeping it open.
+    ///
+    /// The executable is found as with [`Handle::current_exe`], which should
+    /// be used instead when the identity must stay valid.
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] if the path of the
+    /// executable cannot be found or opened.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn current_exe() -> io::Result<FileId> {
+        Handle::current_exe().map(|handle| Handle::id(&handle))
+    }
+
+    /// Extract the identity of the file at a path without following a final
+    /// symbolic link, and without keeping the file open.
+    ///
+    /// If the path names a symbolic link, this is the identity of the link
+    /// itself rather than of its target. On Windows, this also applies to
+    /// junctions and other reparse points. Otherwise, this is the same as
+    /// [`from_path`](FileId::from_path), with the same caveats.
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] if the path cannot be
+    /// found or its identity cannot be obtained.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn from_path_no_follow<P: AsRef<Path>>(path: P) -> io::Result<FileId> {
+        fail_point!(Stat);
+        let path = path.as_ref();
+        imp::FileId::from_path_no_follow(path)
+            .map(FileId)
+            .map_err(|err| Error::open_failed(path, err))
+    }
+
+    /// Return the identity in a packed, canonical 32-byte form.
+    ///
+    /// The first byte is a platform tag, so that packed identities from
+    /// different platforms never compare equal. The remaining bytes hold the
+    /// platform-specific identity fields in big-endian order, followed by zero
+    /// padding. Two identities are equal exactly when their packed forms are
+    /// equal.
+    pub fn to_packed(&self) -> [u8; 32] {
+        self.0.pack()
+    }
+
+    /// Decode an identity from the packed form produced by
+    /// [`to_packed`](FileId::to_packed).
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] with
+    /// [`io::ErrorKind::InvalidData`] if the packed form is malformed, or was
+    /// produced on a different platform.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn from_packed(packed: &[u8; 32]) -> io::Result<FileId> {
+        imp::FileId::unpack(packed).map(FileId).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "packed identity is malformed or from another platform",
+            )
+        })
+    }
+
+    /// The length of the binary form produced by
+    /// [`to_bytes`](FileId::to_bytes).
+    pub const BYTES_LEN: usize = 32;
+
+    /// Return the identity in a fixed-size binary form, suitable for storing
+    /// in an on-disk index.
+    ///
+    /// This is the same as the packed form, and its layout is stable across
+    /// releases. The first byte is a platform tag, followed by the identity
+    /// fields and zero padding up to [`BYTES_LEN`](FileId::BYTES_LEN) bytes:
+    ///
+    /// * Unix, tag 1: the device, the inode and the mount id, 8 bytes each,
+    ///   then the btrfs subvolume id in 7 bytes. The mount id is zero unless
+    ///   it was asked for with
+    ///   [`from_file_like_mount_aware`](FileId::from_file_like_mount_aware),
+    ///   and the subvolume id unless it was asked for with
+    ///   [`from_file_like_subvolume_aware`](FileId::from_file_like_subvolume_aware),
+    ///   in which case the device is the folded btrfs filesystem UUID.
+    /// * Windows, tag 2: the 8-byte volume serial number, then the 16-byte
+    ///   file id as reported by the OS, then a byte set to 1 if the identity
+    ///   came from `GetFileInformationByHandle`, on systems without
+    ///   `FileIdInfo`. The 64-bit file index then takes the place of the
+    ///   file id, little-endian. For pipes, that byte is 2, the volume is
+    ///   zero and the file id is a hash of the pipe name. Another pipe may
+    ///   get the same name once the pipe is closed, so these identities are
+    ///   not worth persisting. Only named pipes with a single instance, such
+    ///   as those of `std::io::pipe`, have an identity. For consoles, it
+    ///   is 3, the volume is zero and the file id is the number the process
+    ///   gave the console object, little-endian, followed by a byte set to 1
+    ///   for input. Such identities are not worth persisting either.
+    /// * WASI, tag 3: the device and the inode, 8 bytes each.
+    /// * Hermit, tag 4: the 8-byte volume, the 1-byte file type, a 1-byte
+    ///   flag set if the creation time is known, then the creation or
+    ///   modification time and the length, 8 bytes each.
+    ///
+    /// Integers are big-endian, so the byte-wise order of the binary forms
+    /// is the same as the order of the identities. See
+    /// [`as_sortable_bytes`](FileId::as_sortable_bytes).
+    pub fn to_bytes(&self) -> [u8; FileId::BYTES_LEN] {
+        self.to_packed()
+    }
+
+    /// Return the identity as a key whose byte-wise lexicographic order is
+    /// the same as the order of the identities.
+    ///
+    /// This makes identities usable directly as keys of ordered key-value
+    /// stores such as LMDB, sled or RocksDB, where range scans (for example
+    /// over the bytes of [`min_for_volume`](FileId::min_for_volume) and
+    /// [`max_for_volume`](FileId::max_for_volume)) then visit the same
+    /// identities, in the same order, as a `BTreeMap` would. The key is the
+    /// binary form of [`to_bytes`](FileId::to_bytes), and is decoded with
+    /// [`from_bytes`](FileId::from_bytes).
+    pub fn as_sortable_bytes(&self) -> [u8; FileId::BYTES_LEN] {
+        self.to_packed()
+    }
+
+    /// Decode an identity from the binary form produced by
+    /// [`to_bytes`](FileId::to_bytes).
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] with
+    /// [`io::ErrorKind::InvalidData`] if `bytes` is not
+    /// [`BYTES_LEN`](FileId::BYTES_LEN) bytes long, is malformed, or was
+    /// produced on a different platform.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<FileId> {
+        let packed: &[u8; FileId::BYTES_LEN] =
+            bytes.try_into().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "identity is {} bytes long, expected {}",
+                        bytes.len(),
+                        FileId::BYTES_LEN
+                    ),
+                )
+            })?;
+        FileId::from_packed(packed)
+    }
+
+    /// The volume the file resides on.
+    ///
+    /// On Unix, this is the device number (`st_dev`). On Windows, it is the
+    /// volume serial number, or zero for pipes and consoles.
+    pub fn volume(&self) -> u64 {
+        self.0.volume()
+    }
+
+    /// The smallest identity on the given volume.
+    ///
+    /// This does not refer to any file, and is only meant to be used as the
+    /// bound of a range. See [`volume_range`](FileId::volume_range).
+    ///
+    /// Returns `None` on platforms other than Unix, Windows, WASI and Hermit,
+    /// where there are no identities.
+    pub fn min_for_volume(volume: u64) -> Option<FileId> {
+        imp::FileId::volume_bound(volume, false).map(FileId)
+    }
+
+    /// The largest identity on the given volume.
+    ///
+    /// This does not refer to any file, and is only meant to be used as the
+    /// bound of a range. See [`volume_range`](FileId::volume_range).
+    ///
+    /// Returns `None` where [`min_for_volume`](FileId::min_for_volume) does.
+    pub fn max_for_volume(volume: u64) -> Option<FileId> {
+        imp::FileId::volume_bound(volume, true).map(FileId)
+    }
+
+    /// The range containing every identity on the given volume.
+    ///
+    /// Returns `None` where [`min_for_volume`](FileId::min_for_volume) does.
+    ///
+    /// # Examples
+    ///
+    /// Dropping all the entries of a volume that was unmounted:
+    ///
+    /// ```rust
+    /// use std::collections::BTreeMap;
+    ///
+    /// use cross_file_id::FileId;
+    ///
+    /// fn evict(index: &mut BTreeMap<FileId, String>, volume: u64) {
+    ///     // Without identities, the index is empty.
+    ///     let Some(range) = FileId::volume_range(volume) else {
+    ///         return;
+    ///     };
+    ///     let ids: Vec<FileId> = index
+    ///         .range(range)
+    ///         .map(|(id, _)| id.clone())
+    ///         .collect();
+    ///     for id in ids {
+    ///         index.remove(&id);
+    ///     }
+    /// }
+    /// ```
+    pub fn volume_range(volume: u64) -> Option<RangeInclusive<FileId>> {
+        Some(FileId::min_for_volume(volume)?..=FileId::max_for_volume(volume)?)
+    }
+
+    /// Make up an identity, for tests that do not touch the filesystem.
+    ///
+    /// Identities with the same volume and index are equal, and the
+    /// identity is on the given [`volume`](FileId::volume). It may or may
+    /// not also be the identity of a real file, so made up identities must
+    /// not be mixed with real ones. See [`MockFs`](crate::mock::MockFs) for
+    /// assigning them to paths.
+    ///
+    /// This is only available with the `test-util` feature, on the
+    /// platforms that have identities.
+    #[cfg(all(
+        feature = "test-util",
+        any(unix, windows, target_os = "wasi", target_os = "hermit")
+    ))]
+    pub fn fabricated(volume: u64, index: u64) -> FileId {
+        FileId(imp::FileId::fabricated(volume, index))
+    }
+
+    /// Compare two identities in constant time.
+    ///
+    /// This compares the packed forms of the identities without
+    /// data-dependent branches, for use where identities act as capability
+    /// tokens and timing side channels must be avoided.
+    #[cfg(feature = "subtle")]
+    pub fn ct_eq(&self, other: &FileId) -> subtle::Choice {
+        subtle::ConstantTimeEq::ct_eq(
+            &self.to_packed()[..],
+            &other.to_packed()[..],
+        )
+    }
+
+    /// Extract a file identity that is normalized across the different ways
+    /// a file can be reached.
+    ///
+    /// On Windows, a local volume reached through an administrative share
+    /// (e.g. `\\server\C$\foo`) can report a different volume serial
+    /// number than the same volume reached through its drive letter
+    /// (`C:\foo`). This resolves the file to its volume GUID path and takes
+    /// the identity from there, so that both forms compare equal. This costs
+    /// an additional open of the file.
+    ///
+    /// On other platforms, this is equivalent to [`from_file_like`].
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] if the identity cannot be
+    /// obtained. On Windows, this includes files that have no volume GUID
+    /// path, such as files on a remote server, and files whose volume GUID
+    /// path names another file by the time it is opened, in which case the
+    /// error wraps [`Error::Replaced`].
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    /// [`from_file_like`]: #method.from_file_like
+    pub fn from_file_like_normalized<F: AsRawFilelike>(
+        file: &F,
+    ) -> io::Result<Self> {
+        fail_point!(Stat);
+        imp::FileId::from_filelike_normalized(file.as_raw_filelike())
+            .map(FileId)
+            .map_err(Error::metadata_failed)
+    }
+
+    /// Extract a file identity through two independent mechanisms, and fail
+    /// if they disagree.
+    ///
+    /// This is for callers that would rather fail than risk a silent
+    /// misidentification caused by a filesystem or driver bug. On Linux,
+    /// `statx` is checked against `fstat`. On Windows, `FileIdInfo` is
+    /// checked against `GetFileInformationByHandle`, unless the file id does
+    /// not fit in 64 bits, as on ReFS. Elsewhere, there is a single
+    /// mechanism, and this is equivalent to [`from_file_like`].
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] if the identity cannot be
+    /// obtained, or one wrapping [`Error::IdentityMismatch`] if the two
+    /// mechanisms disagree.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    /// [`from_file_like`]: #method.from_file_like
+    pub fn from_file_like_verified<F: AsRawFilelike>(
+        file: &F,
+    ) -> io::Result<Self> {
+        fail_point!(Stat);
+        imp::FileId::from_filelike_verified(file.as_raw_filelike())
+            .map(FileId)
+            .map_err(Error::metadata_failed)
+    }
+
+    /// Extract a file identity that includes the btrfs subvolume of the
+    /// file.
+    ///
+    /// Each btrfs subvolume numbers its inodes independently, and is only
+    /// told apart from the others by an anonymous device number, which can
+    /// change between mounts. This replaces the device number with the
+    /// filesystem UUID, folded to 64 bits, and adds the subvolume id, both
+    /// of which are stable. Tools comparing identities across mounts, such
+    /// as backup tools, then neither merge files from different subvolumes
+    /// nor lose track of files when the device number changes. It costs an
+    /// `fstatfs` call, plus two `ioctl` calls on btrfs.
+    ///
+    /// The identity only differs from that of [`from_file_like`] for files
+    /// on btrfs, on Linux. Identities taken this way should only be compared
+    /// with identities also taken this way.
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] if the identity or the
+    /// subvolume cannot be obtained.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    /// [`from_file_like`]: #method.from_file_like
+    pub fn from_file_like_subvolume_aware<F: AsRawFilelike>(
+        file: &F,
+    ) -> io::Result<Self> {
+        fail_point!(Stat);
+        let raw = file.as_raw_filelike();
+        imp::FileId::from_filelike(raw)
+            .and_then(|id| id.with_subvolume(raw))
+            .map(FileId)
+            .map_err(Error::metadata_failed)
+    }
+
+    /// Extract a file identity that includes the mount the file was reached
+    /// through.
+    ///
+    /// The same file reached through two bind mounts has two such
+    /// identities. This tells apart files on overlayfs setups that reuse
+    /// device and inode numbers across mounts, at the cost of a `statx`
+    /// call.
+    ///
+    /// The identity only differs from that of [`from_file_like`] on Linux
+    /// 5.8 and later, where `statx` reports the mount id, as told by
+    /// [`Capabilities::mount_ids`]. Identities taken this way should only be
+    /// compared with identities also taken this way.
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] if the identity or the
+    /// mount cannot be obtained.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    /// [`from_file_like`]: #method.from_file_like
+    pub fn from_file_like_mount_aware<F: AsRawFilelike>(
+        file: &F,
+    ) -> io::Result<Self> {
+        fail_point!(Stat);
+        let raw = file.as_raw_filelike();
+        imp::FileId::from_filelike(raw)
+            .and_then(|id| id.with_mount(raw))
+            .map(FileId)
+            .map_err(Error::metadata_failed)
     }
 }
 
@@ -73,6 +711,10 @@ impl FileId {
 pub struct Handle<F> {
     handle: F,
     identity: FileId,
+    kind: Option<DeviceClass>,
+    mode: IdentityMode,
+    #[cfg(feature = "debug-registry")]
+    _registration: registry::Registration,
 }
 
 impl<F> Handle<F> {
@@ -86,7 +728,26 @@ impl<F> Handle<F> {
     /// must ensure that the value of type F ensures that the file remains
     /// open for the lifetime of the Handle.
     pub unsafe fn from_parts(handle: F, identity: FileId) -> Self {
-        Handle { handle, identity }
+        let mode = IdentityMode::of(&identity);
+        Handle::new(handle, identity, None, mode)
+    }
+
+    // All construction goes through here so that optional bookkeeping is
+    // kept in a single place.
+    fn new(
+        handle: F,
+        identity: FileId,
+        kind: Option<DeviceClass>,
+        mode: IdentityMode,
+    ) -> Self {
+        Handle {
+            #[cfg(feature = "debug-registry")]
+            _registration: registry::Registration::new::<F>(&identity),
+            handle,
+            identity,
+            kind,
+            mode,
+        }
     }
 
     /// Consume the handle and return the underlying file-like object.
@@ -106,137 +767,552 @@ impl<F> Handle<F> {
     pub fn id(this: &Self) -> FileId {
         this.identity.clone()
     }
-}
 
-impl<F> Handle<F>
-where
-    F: AsRawFilelike,
-{
-    /// Construct a handle from any type that implements the platform-specific
-    /// traits that provide access to raw OS representations of files.
+    /// Consume the handle and return the underlying file-like object
+    /// together with its identity.
     ///
-    /// The resulting handle will act as a wrapper around the given file-like
-    /// object, and will ensure that the file remains open for the lifetime of
-    /// the handle.
-    pub fn from_file_like(file: F) -> io::Result<Self> {
-        let file_id = FileId::from_file_like(&file)?;
-        Ok(Handle { handle: file, identity: file_id })
-    }
-}
-
-impl<F> std::ops::Deref for Handle<F> {
-    type Target = F;
-
-    fn deref(&self) -> &F {
-        &self.handle
-    }
-}
-
-impl<F> std::ops::DerefMut for Handle<F> {
-    fn deref_mut(&mut self) -> &mut F {
-        &mut self.handle
-    }
-}
-
-impl<F1, F2> std::cmp::PartialEq<Handle<F2>> for Handle<F1> {
-    fn eq(&self, other: &Handle<F2>) -> bool {
-        self.identity == other.identity
+    /// The identity is only guaranteed to stay valid while the file-like
+    /// object keeps the file open. The parts can be put back together with
+    /// [`from_parts`](Handle::from_parts).
+    ///
+    /// This is provided as an associated function instead of a method
+    /// to ensure that operations that rely on the value being accessible via
+    /// dereference aren't accidentally masked.
+    pub fn into_parts(this: Self) -> (F, FileId) {
+        (this.handle, this.identity)
     }
-}
-
-impl<F> std::cmp::Eq for Handle<F> {}
 
-impl<F1, F2> std::cmp::PartialOrd<Handle<F2>> for Handle<F1> {
-    fn partial_cmp(&self, other: &Handle<F2>) -> Option<std::cmp::Ordering> {
-        self.identity.partial_cmp(&other.identity)
+    /// Borrow the underlying file-like object and the identity, without
+    /// cloning the identity.
+    ///
+    /// This is provided as an associated function instead of a method
+    /// to ensure that operations that rely on the value being accessible via
+    /// dereference aren't accidentally masked.
+    pub fn parts(this: &Self) -> (&F, &FileId) {
+        (&this.handle, &this.identity)
     }
-}
 
-impl<F> std::cmp::Ord for Handle<F> {
-    fn cmp(&self, other: &Handle<F>) -> std::cmp::Ordering {
-        self.identity.cmp(&other.identity)
+    /// Transform the underlying object, keeping the identity without
+    /// querying it again.
+    ///
+    /// This is meant for wrapping the file in another type, such as a
+    /// buffered reader or a type of the caller's own.
+    ///
+    /// This is provided as an associated function instead of a method
+    /// to ensure that operations that rely on the value being accessible via
+    /// dereference aren't accidentally masked.
+    ///
+    /// # Safety
+    ///
+    /// As with [`from_parts`](Handle::from_parts), the value returned by `f`
+    /// must keep the file it was given open for the lifetime of the new
+    /// handle. Use [`try_map`](Handle::try_map) when this cannot be
+    /// guaranteed.
+    pub unsafe fn map<G>(this: Self, f: impl FnOnce(F) -> G) -> Handle<G> {
+        Handle::map_inner(this, f)
     }
-}
 
-impl<F> std::hash::Hash for Handle<F> {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.identity.hash(state);
+    // Wrap the underlying object without querying the identity again. This
+    // is only sound when `f` keeps the file it is given open.
+    pub(crate) fn map_inner<G>(
+        this: Self,
+        f: impl FnOnce(F) -> G,
+    ) -> Handle<G> {
+        let Handle { handle, identity, kind, mode, .. } = this;
+        Handle::new(f(handle), identity, kind, mode)
     }
 }
 
-impl Handle<File> {
-    /// Construct a handle from a path.
+impl<F> Handle<F> {
+    /// Transform the underlying object, and check that the result still
+    /// refers to the same file.
     ///
-    /// Note that the underlying [`File`] is opened in read-only mode on all
-    /// platforms.
+    /// This is the safe counterpart of [`map`](Handle::map): the identity
+    /// of the new object is taken and compared with the identity of the
+    /// handle, which costs a system call. The new object must therefore
+    /// give access to its descriptor or handle; wrappers that do not, such
+    /// as [`BufReader`](std::io::BufReader), need [`map`](Handle::map).
     ///
-    /// [`File`]: https://doc.rust-lang.org/std/fs/struct.File.html
+    /// This is provided as an associated function instead of a method
+    /// to ensure that operations that rely on the value being accessible via
+    /// dereference aren't accidentally masked.
     ///
     /// # Errors
-    /// This method will return an [`io::Error`] if the path cannot
-    /// be opened, or the file's metadata cannot be obtained.
-    /// The most common reasons for this are: the path does not
-    /// exist, or there were not enough permissions.
+    /// This function will return an [`io::Error`] if the identity of the new
+    /// object cannot be obtained, or one wrapping [`Error::Replaced`] if it
+    /// refers to another file. The new object is dropped in both cases.
     ///
     /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
     ///
     /// # Examples
-    /// Check that two paths are not the same file:
+    ///
+    /// Sharing the file of a handle:
     ///
     /// ```rust,no_run
-    /// # use std::error::Error;
+    /// use std::sync::Arc;
+    ///
     /// use cross_file_id::Handle;
     ///
-    /// # fn try_main() -> Result<(), Box<dyn Error>> {
-    /// let source = Handle::from_path("./source")?;
-    /// let target = Handle::from_path("./target")?;
-    /// assert_ne!(source, target, "The files are the same.");
-    /// # Ok(())
-    /// # }
-    /// #
-    /// # fn main() {
-    /// #     try_main().unwrap();
-    /// # }
+    /// let handle = Handle::from_path("./data")?;
+    /// let shared = Handle::try_map(handle, Arc::new)?;
+    /// # Ok::<_, std::io::Error>(())
     /// ```
-    pub fn from_path<P: AsRef<Path>>(p: P) -> io::Result<Self> {
-        // Because this is intended to work as either a file OR directory, we have to
-        // delegate this to the implementation to open it.
-        let file = imp::open_file(p.as_ref())?;
-        Self::from_file_like(file)
+    pub fn try_map<G: AsRawFilelike>(
+        this: Self,
+        f: impl FnOnce(F) -> G,
+    ) -> io::Result<Handle<G>> {
+        let expected = this.identity.clone();
+        let mapped = Handle::map_inner(this, f);
+        let actual = mapped.mode.id_of_raw(mapped.handle.as_raw_filelike())?;
+        if actual != expected {
+            return Err(
+                Error::Replaced { expected, actual, path: None }.into()
+            );
+        }
+        Ok(mapped)
     }
 
-    /// Construct a handle from a file.
+    /// Returns true if `path` currently names the file of this handle.
+    ///
+    /// This is the check that security-sensitive code needs between
+    /// validating a file and acting on its path: if an attacker swapped the
+    /// path for another file, or for a symbolic link to one, in the
+    /// meantime, this returns false. Symbolic links are followed, and the
+    /// file at `path` is kept open while the identities are compared, so its
+    /// identity cannot be reused in between. A path that no longer exists
+    /// does not refer to the file.
     ///
     /// # Errors
-    /// This method will return an [`io::Error`] if the metadata for
-    /// the given [`File`] cannot be obtained.
+    /// This method will return an [`io::Error`] if the path exists but
+    /// cannot be opened, or its identity cannot be obtained.
     ///
     /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
-    /// [`File`]: https://doc.rust-lang.org/std/fs/struct.File.html
     ///
     /// # Examples
-    /// Check that two files are not in fact the same file:
     ///
     /// ```rust,no_run
-    /// # use std::error::Error;
-    /// # use std::fs::File;
     /// use cross_file_id::Handle;
     ///
-    /// # fn try_main() -> Result<(), Box<dyn Error>> {
-    /// let source = File::open("./source")?;
-    /// let target = File::open("./target")?;
-    ///
-    /// assert_ne!(
-    ///     Handle::from_file(source)?,
-    ///     Handle::from_file(target)?,
-    ///     "The files are the same."
-    /// );
-    /// #     Ok(())
-    /// # }
-    /// #
-    /// # fn main() {
-    /// #     try_main().unwrap();
-    /// # }
+    /// let validated = Handle::from_path("/etc/app/config")?;
+    /// // ... check ownership and permissions through `validated` ...
+    /// if !validated.refers_to_path("/etc/app/config")? {
+    ///     panic!("the configuration was swapped while being validated");
+    /// }
+    /// # Ok::<_, std::io::Error>(())
+    /// ```
+    #[must_use = "the path may name another file if this returns false"]
+    pub fn refers_to_path<P: AsRef<Path>>(&self, path: P) -> io::Result<bool> {
+        fail_point!(Open);
+        let file = match imp::open_file(path.as_ref()) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(false);
+            }
+            Err(err) => return Err(err),
+        };
+        let actual = self.mode.id_of_raw(file.as_raw_filelike())?;
+        Ok(fail_identity!(actual) == self.identity)
+    }
+}
+
+#[cfg(any(unix, windows, target_os = "wasi", target_os = "hermit"))]
+impl<F> Handle<F>
+where
+    F: IntoRawFilelike,
+{
+    /// Consume the handle and return an owned descriptor or handle for the
+    /// file, together with the identity it was verified with.
+    ///
+    /// This allows the file to be handed to another library that works with
+    /// [`OwnedFd`] or [`OwnedHandle`] without losing track of its identity,
+    /// and without going through raw values.
+    ///
+    /// This is provided as an associated function instead of a method
+    /// to ensure that operations that rely on the value being accessible via
+    /// dereference aren't accidentally masked.
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] with
+    /// [`io::ErrorKind::InvalidInput`] if the underlying file-like object
+    /// does not hold a valid descriptor or handle, which can only happen for
+    /// handles built with [`from_parts`](Handle::from_parts).
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    /// [`OwnedFd`]: https://doc.rust-lang.org/std/os/fd/struct.OwnedFd.html
+    /// [`OwnedHandle`]: https://doc.rust-lang.org/std/os/windows/io/struct.OwnedHandle.html
+    pub fn try_into_std_owned(
+        this: Self,
+    ) -> io::Result<(OwnedFilelike, FileId)> {
+        let raw = this.handle.into_raw_filelike();
+        if !imp::is_valid_raw(raw) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "handle does not hold a valid file descriptor or handle",
+            ));
+        }
+        // SAFETY: The raw value was just released by its owner, and was
+        // checked not to be a sentinel value.
+        let owned = unsafe { OwnedFilelike::from_raw_filelike(raw) };
+        Ok((owned, this.identity))
+    }
+}
+
+#[cfg(any(unix, windows, target_os = "wasi", target_os = "hermit"))]
+impl<F> Handle<F>
+where
+    F: AsRawFilelike + FromFilelike,
+{
+    /// Duplicate the underlying descriptor or handle, and return a new
+    /// handle to the same file with the same identity.
+    ///
+    /// The identity is not queried again, so this is cheap enough to hand a
+    /// pinned file to several worker threads. As with
+    /// [`File::try_clone`], both handles share the file offset, and closing
+    /// one of them leaves the other open.
+    ///
+    /// This works for any type that can be built from an owned descriptor
+    /// or handle, such as [`File`]. It is provided as an associated function
+    /// instead of a method to ensure that operations that rely on the value
+    /// being accessible via dereference, such as [`File::try_clone`],
+    /// aren't accidentally masked.
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] if the descriptor or
+    /// handle cannot be duplicated, for instance because the process has run
+    /// out of them.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    /// [`File`]: https://doc.rust-lang.org/std/fs/struct.File.html
+    /// [`File::try_clone`]: https://doc.rust-lang.org/std/fs/struct.File.html#method.try_clone
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use std::thread;
+    ///
+    /// use cross_file_id::Handle;
+    ///
+    /// let handle = Handle::from_path("./data")?;
+    /// let copy = Handle::try_clone(&handle)?;
+    /// thread::spawn(move || assert_eq!(copy.metadata().unwrap().len(), 0));
+    /// # Ok::<_, std::io::Error>(())
+    /// ```
+    pub fn try_clone(this: &Self) -> io::Result<Handle<F>> {
+        // SAFETY: The file is kept open by `this` for the duration of the
+        // borrow.
+        let borrowed = unsafe {
+            BorrowedFilelike::borrow_raw(this.handle.as_raw_filelike())
+        };
+        let owned = borrowed.try_clone_to_owned()?;
+        Ok(Handle::new(
+            F::from_filelike(owned),
+            this.identity.clone(),
+            this.kind,
+            this.mode,
+        ))
+    }
+}
+
+impl<F> Handle<F>
+where
+    F: AsRawFilelike,
+{
+    /// Construct a handle from any type that implements the platform-specific
+    /// traits that provide access to raw OS representations of files.
+    ///
+    /// The resulting handle will act as a wrapper around the given file-like
+    /// object, and will ensure that the file remains open for the lifetime of
+    /// the handle.
+    pub fn from_file_like(file: F) -> io::Result<Self> {
+        let (file_id, kind) = FileId::from_file_like_classified(&file)?;
+        Ok(Handle::new(file, file_id, Some(kind), IdentityMode::default()))
+    }
+
+    /// Returns the current path of the file this handle refers to.
+    ///
+    /// This may differ from the path the file was opened with if the file
+    /// has since been renamed. On Windows, drive letters created with
+    /// `SUBST` are resolved to the directory they point at, and mapped
+    /// network drives are resolved to their UNC path, so a file yields the
+    /// same path no matter which drive letter it was opened through.
+    ///
+    /// The path is recovered through `/proc/self/fd` on Linux and Android,
+    /// `F_GETPATH` on macOS and iOS, and `GetFinalPathNameByHandleW` on
+    /// Windows. It is meant for diagnostics: the file may be renamed again
+    /// right after the call, and on Linux, the path of a file that has been
+    /// deleted ends with ` (deleted)`.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the path cannot be
+    /// determined, including on platforms where this is not supported.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn current_path(&self) -> io::Result<PathBuf> {
+        imp::current_path(self.handle.as_raw_filelike())
+    }
+
+    /// Take the identity of the underlying file again, and return true if it
+    /// still matches the identity of the handle.
+    ///
+    /// A handle built by this crate from an object that keeps its file open
+    /// always passes this check. It fails when the contract of
+    /// [`from_parts`](Handle::from_parts) or of `from_raw_fd` was broken, or
+    /// when other code closed the descriptor or handle and the number was
+    /// reused for another file.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the identity of the file
+    /// cannot be obtained, for instance because the descriptor was closed.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    #[must_use = "the handle may refer to another file if this returns false"]
+    pub fn revalidate(&self) -> io::Result<bool> {
+        let raw = self.handle.as_raw_filelike();
+        let actual = fail_identity!(self.mode.id_of_raw(raw)?);
+        if actual != self.identity {
+            log_warn!(
+                "handle for {:?} now refers to {:?}",
+                self.identity,
+                actual
+            );
+            return Ok(false);
+        }
+        Ok(true)
+    }
+}
+
+/// Cloning a handle clones the underlying object, and reuses the identity
+/// without querying it again.
+///
+/// This is meant for cheaply cloneable objects that share the same open
+/// file, such as `Arc<File>` or a borrowed descriptor. A type whose clones
+/// refer to a different file would give the clone a wrong identity.
+impl<F: Clone> Clone for Handle<F> {
+    fn clone(&self) -> Handle<F> {
+        Handle::new(
+            self.handle.clone(),
+            self.identity.clone(),
+            self.kind,
+            self.mode,
+        )
+    }
+}
+
+impl<F> std::ops::Deref for Handle<F> {
+    type Target = F;
+
+    fn deref(&self) -> &F {
+        &self.handle
+    }
+}
+
+impl<F> std::ops::DerefMut for Handle<F> {
+    fn deref_mut(&mut self) -> &mut F {
+        &mut self.handle
+    }
+}
+
+impl<F1, F2> std::cmp::PartialEq<Handle<F2>> for Handle<F1> {
+    fn eq(&self, other: &Handle<F2>) -> bool {
+        self.identity == other.identity
+    }
+}
+
+impl<F> std::cmp::Eq for Handle<F> {}
+
+impl<F1, F2> std::cmp::PartialOrd<Handle<F2>> for Handle<F1> {
+    fn partial_cmp(&self, other: &Handle<F2>) -> Option<std::cmp::Ordering> {
+        self.identity.partial_cmp(&other.identity)
+    }
+}
+
+impl<F> std::cmp::Ord for Handle<F> {
+    fn cmp(&self, other: &Handle<F>) -> std::cmp::Ordering {
+        self.identity.cmp(&other.identity)
+    }
+}
+
+impl<F> std::hash::Hash for Handle<F> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.identity.hash(state);
+    }
+}
+
+/// Handles compare, order and hash by identity, so collections of handles
+/// can be queried with a bare [`FileId`].
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use std::collections::HashSet;
+///
+/// use cross_file_id::{FileId, Handle};
+///
+/// let mut open = HashSet::new();
+/// open.insert(Handle::from_path("./data")?);
+/// assert!(open.contains(&FileId::from_path("./data")?));
+/// # Ok::<_, std::io::Error>(())
+/// ```
+impl<F> std::borrow::Borrow<FileId> for Handle<F> {
+    fn borrow(&self) -> &FileId {
+        &self.identity
+    }
+}
+
+impl<F> AsRef<FileId> for Handle<F> {
+    fn as_ref(&self) -> &FileId {
+        &self.identity
+    }
+}
+
+impl Handle<File> {
+    /// Construct a handle from a path.
+    ///
+    /// Note that the underlying [`File`] is opened in read-only mode on all
+    /// platforms.
+    ///
+    /// Directories can be opened as well. On Windows, where
+    /// [`File::open`] fails for directories, the path is opened with
+    /// `FILE_FLAG_BACKUP_SEMANTICS`, and directory junctions are followed
+    /// like symbolic links.
+    ///
+    /// Opening for reading requires read permission. To take the identity
+    /// of a file that can be stat'ed but not read, use
+    /// [`HandleOptions::minimal_access`], which opens it with `O_PATH` on
+    /// Linux.
+    ///
+    /// [`File`]: https://doc.rust-lang.org/std/fs/struct.File.html
+    /// [`File::open`]: https://doc.rust-lang.org/std/fs/struct.File.html#method.open
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the path cannot
+    /// be opened, or the file's metadata cannot be obtained.
+    /// The most common reasons for this are: the path does not
+    /// exist, or there were not enough permissions.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    ///
+    /// # Examples
+    /// Check that two paths are not the same file:
+    ///
+    /// ```rust,no_run
+    /// # use std::error::Error;
+    /// use cross_file_id::Handle;
+    ///
+    /// # fn try_main() -> Result<(), Box<dyn Error>> {
+    /// let source = Handle::from_path("./source")?;
+    /// let target = Handle::from_path("./target")?;
+    /// assert_ne!(source, target, "The files are the same.");
+    /// # Ok(())
+    /// # }
+    /// #
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    pub fn from_path<P: AsRef<Path>>(p: P) -> io::Result<Self> {
+        // Because this is intended to work as either a file OR directory, we have to
+        // delegate this to the implementation to open it.
+        fail_point!(Open);
+        let path = p.as_ref();
+        let file = imp::open_file(path)
+            .map_err(|err| Error::open_failed(path, err))?;
+        Self::from_file_like(file)
+    }
+
+    /// Construct a handle to the executable of the running process.
+    ///
+    /// On Linux and Android, `/proc/self/exe` is opened, which refers to the
+    /// file the process was started from even if it has since been renamed,
+    /// deleted or replaced. Elsewhere, and where `/proc` is not mounted, the
+    /// path from [`std::env::current_exe`] is opened, which comes from
+    /// `GetModuleFileNameW` on Windows and may name another file if the
+    /// executable was replaced.
+    ///
+    /// Self-updating programs can compare this handle with the file they
+    /// are about to overwrite, to avoid overwriting the running binary.
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] if the path of the
+    /// executable cannot be found or opened.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use cross_file_id::Handle;
+    ///
+    /// let exe = Handle::current_exe()?;
+    /// let target = Handle::from_path("/usr/local/bin/tool")?;
+    /// if exe == target {
+    ///     // Install the new version next to it and rename it over instead.
+    /// }
+    /// # Ok::<_, std::io::Error>(())
+    /// ```
+    pub fn current_exe() -> io::Result<Self> {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if let Ok(handle) = Handle::from_path("/proc/self/exe") {
+            return Ok(handle);
+        }
+        Handle::from_path(std::env::current_exe()?)
+    }
+
+    /// Construct a handle to the file at a path, without following a final
+    /// symbolic link.
+    ///
+    /// If the path names a symbolic link, the handle refers to the link
+    /// itself, which keeps its identity valid. On Linux the link is opened
+    /// with `O_PATH`, and on macOS with `O_SYMLINK`; the resulting file can
+    /// be used for its metadata and identity, but not read from. On Windows,
+    /// junctions and other reparse points are opened rather than followed.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the path cannot be opened
+    /// or its identity cannot be obtained. On Unix platforms other than
+    /// Linux and macOS, opening a symbolic link this way fails.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn from_path_no_follow<P: AsRef<Path>>(p: P) -> io::Result<Self> {
+        fail_point!(Open);
+        let path = p.as_ref();
+        let file = imp::open_file_no_follow(path)
+            .map_err(|err| Error::open_failed(path, err))?;
+        Self::from_file_like(file)
+    }
+
+    /// Construct a handle from a file.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the metadata for
+    /// the given [`File`] cannot be obtained.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    /// [`File`]: https://doc.rust-lang.org/std/fs/struct.File.html
+    ///
+    /// # Examples
+    /// Check that two files are not in fact the same file:
+    ///
+    /// ```rust,no_run
+    /// # use std::error::Error;
+    /// # use std::fs::File;
+    /// use cross_file_id::Handle;
+    ///
+    /// # fn try_main() -> Result<(), Box<dyn Error>> {
+    /// let source = File::open("./source")?;
+    /// let target = File::open("./target")?;
+    ///
+    /// assert_ne!(
+    ///     Handle::from_file(source)?,
+    ///     Handle::from_file(target)?,
+    ///     "The files are the same."
+    /// );
+    /// #     Ok(())
+    /// # }
+    /// #
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
     /// ```
     pub fn from_file(file: File) -> io::Result<Self> {
         Self::from_file_like(file)
@@ -302,6 +1378,7 @@ impl Handle<Stdin> {
     ///
     /// ```text
     /// > example
+    /// stdout == stderr
     /// > example > result 2>&1
     /// > type result
     /// stdout == stderr
@@ -347,18 +1424,75 @@ impl Handle<Stderr> {
     }
 }
 
+/// Parse an identity from the text form produced by its
+/// [`Display`](std::fmt::Display) implementation.
+///
+/// This makes it possible to print identities in one run of a program and
+/// read them back in another, as long as both run on the same platform and
+/// the files have stayed in place.
+///
+/// # Errors
+/// This function will return an [`io::Error`] with
+/// [`io::ErrorKind::InvalidData`] if the text is not an identity in the form
+/// of the current platform.
+///
+/// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+///
+/// # Example
+///
+/// ```rust
+/// use cross_file_id::{FileId, parse_file_id};
+///
+/// let id = FileId::from_path(".")?;
+/// assert_eq!(parse_file_id(&id.to_string())?, id);
+/// assert!(parse_file_id("not an id").is_err());
+/// # Ok::<_, std::io::Error>(())
+/// ```
+pub fn parse_file_id(s: &str) -> io::Result<FileId> {
+    s.parse()
+}
+
 /// Returns true if the two file-like objects refer to the same file.
 ///
 /// This works for any types that implement the platform-specific traits
 /// that provide access to raw OS representations of files.
+///
+/// The [`DeviceClass`] of both files is compared before their identities,
+/// so files of different classes are never the same file, even where one
+/// of them has no identity on this platform, such as a socket on Windows.
+///
+/// # Errors
+/// This function will return an [`io::Error`] if either file cannot be
+/// queried, or if a file of the same class as the other one has no
+/// identity on this platform.
+///
+/// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
 pub fn is_same_file<F1, F2>(h1: &F1, h2: &F2) -> io::Result<bool>
 where
     F1: AsRawFilelike,
     F2: AsRawFilelike,
 {
-    let id1 = FileId::from_file_like(h1)?;
-    let id2 = FileId::from_file_like(h2)?;
-    Ok(id1 == id2)
+    let file1 = FileId::from_file_like_classified(h1);
+    let file2 = FileId::from_file_like_classified(h2);
+    if let (Some(class1), Some(class2)) = (class_of(&file1), class_of(&file2))
+        && class1 != class2
+    {
+        return Ok(false);
+    }
+    Ok(file1? == file2?)
+}
+
+/// The class of a file queried with
+/// [`FileId::from_file_like_classified`], which is also known when the
+/// file has no identity.
+fn class_of(file: &io::Result<(FileId, DeviceClass)>) -> Option<DeviceClass> {
+    match file {
+        Ok((_, class)) => Some(*class),
+        Err(err) => match Error::of(err) {
+            Some(Error::UnsupportedHandleType { class }) => Some(*class),
+            _ => None,
+        },
+    }
 }
 
 /// Returns true if the two file paths may correspond to the same file.
@@ -385,116 +1519,194 @@ where
     P: AsRef<Path>,
     Q: AsRef<Path>,
 {
-    Ok(Handle::from_path(path1)? == Handle::from_path(path2)?)
+    // Both files stay open while they are compared, so that neither
+    // identity can be reused by another file in the meantime. No `Handle`
+    // is built, which keeps this path free of allocations for short paths.
+    let (path1, path2) = (path1.as_ref(), path2.as_ref());
+    fail_point!(Open);
+    let file1 =
+        imp::open_file(path1).map_err(|err| Error::open_failed(path1, err))?;
+    fail_point!(Open);
+    let file2 =
+        imp::open_file(path2).map_err(|err| Error::open_failed(path2, err))?;
+    is_same_file(&file1, &file2)
 }
 
-#[cfg(test)]
-mod tests {
-    use std::env;
-    use std::error;
-    use std::fs::{self, File};
-    use std::io;
-    use std::path::{Path, PathBuf};
-    use std::result;
-
-    use super::is_same_file_path;
-
-    type Result<T> = result::Result<T, Box<dyn error::Error + Send + Sync>>;
-
-    /// Create an error from a format!-like syntax.
-    macro_rules! err {
-        ($($tt:tt)*) => {
-            Box::<dyn error::Error + Send + Sync>::from(format!($($tt)*))
+/// Returns true if all the paths name the same file.
+///
+/// As with [`is_same_file`], a file of another [`DeviceClass`] than the
+/// first one is never the same file. Each path is opened once. The first file stays open while the others are
+/// compared with it, one at a time, so that its identity cannot be reused
+/// by another file in the meantime. Comparing the paths pairwise with
+/// [`is_same_file_path`] would open the first path again for every other
+/// one. An empty set of paths is trivially the same file.
+///
+/// # Errors
+/// This function will return an [`io::Error`] if any of the paths cannot be
+/// opened. Paths after the first one naming another file are not opened, so
+/// errors they would cause are not reported.
+///
+/// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use cross_file_id::is_same_file_all;
+///
+/// assert!(is_same_file_all(["./foo", "././foo", "./bar/../foo"])?);
+/// # Ok::<_, std::io::Error>(())
+/// ```
+pub fn is_same_file_all<I, P>(paths: I) -> io::Result<bool>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+{
+    let mut paths = paths.into_iter();
+    let Some(first) = paths.next() else {
+        return Ok(true);
+    };
+    let first = first.as_ref();
+    fail_point!(Open);
+    let first =
+        imp::open_file(first).map_err(|err| Error::open_failed(first, err))?;
+    let (id, class) = FileId::from_file_like_classified(&first)?;
+    for path in paths {
+        let path = path.as_ref();
+        fail_point!(Open);
+        let file = imp::open_file(path)
+            .map_err(|err| Error::open_failed(path, err))?;
+        let other = FileId::from_file_like_classified(&file);
+        if class_of(&other).is_some_and(|other| other != class)
+            || other?.0 != id
+        {
+            return Ok(false);
         }
     }
+    Ok(true)
+}
 
-    /// A simple wrapper for creating a temporary directory that is
-    /// automatically deleted when it's dropped.
-    ///
-    /// We use this in lieu of tempfile because tempfile brings in too many
-    /// dependencies.
-    #[derive(Debug)]
-    struct TempDir(PathBuf);
-
-    impl Drop for TempDir {
-        fn drop(&mut self) {
-            fs::remove_dir_all(&self.0).unwrap();
-        }
-    }
+/// Returns true if the two file paths name the same file, without following
+/// a final symbolic link in either of them.
+///
+/// A symbolic link is then only the same as itself, or as a hard link to
+/// it, and never the same as its target. This is what tools that manage
+/// farms of symbolic links need to tell whether two paths are the same
+/// link. Both files are opened as with
+/// [`Handle::from_path_no_follow`], and stay open while they are compared.
+///
+/// # Errors
+/// This function will return an [`io::Error`] if any of the two paths cannot
+/// be opened. On Unix platforms other than Linux and macOS, opening a
+/// symbolic link this way fails.
+///
+/// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use cross_file_id::{is_same_file_no_follow, is_same_file_path};
+///
+/// // `current` is a symbolic link to `v2`.
+/// assert!(is_same_file_path("./current", "./v2")?);
+/// assert!(!is_same_file_no_follow("./current", "./v2")?);
+/// # Ok::<_, std::io::Error>(())
+/// ```
+pub fn is_same_file_no_follow<P, Q>(path1: P, path2: Q) -> io::Result<bool>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let (path1, path2) = (path1.as_ref(), path2.as_ref());
+    fail_point!(Open);
+    let file1 = imp::open_file_no_follow(path1)
+        .map_err(|err| Error::open_failed(path1, err))?;
+    fail_point!(Open);
+    let file2 = imp::open_file_no_follow(path2)
+        .map_err(|err| Error::open_failed(path2, err))?;
+    is_same_file(&file1, &file2)
+}
 
-    impl TempDir {
-        /// Create a new empty temporary directory under the system's
-        /// configured temporary directory.
-        fn new() -> Result<TempDir> {
-            #![allow(deprecated)]
+/// Returns true if the two file paths, relative to an open directory, name
+/// the same file.
+///
+/// The paths are resolved against `dir` itself rather than against its
+/// path, with `openat` on Unix, `path_open` on WASI and `NtCreateFile` on
+/// Windows. Programs that only hold a directory, such as sandboxed ones,
+/// can then compare files below it, and the comparison is not affected by
+/// the directory being renamed or a component of its path being replaced
+/// in the meantime. As with [`is_same_file_path`], both files stay open
+/// while they are compared.
+///
+/// # Errors
+/// This function will return an [`io::Error`] if any of the two paths cannot
+/// be opened relative to `dir`. On Windows, the paths must be relative and
+/// must not climb above `dir`.
+///
+/// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use cross_file_id::{Handle, is_same_file_at};
+///
+/// let project = Handle::from_path("./project")?;
+/// assert!(is_same_file_at(&*project, "src/lib.rs", "./src/../src/lib.rs")?);
+/// # Ok::<_, std::io::Error>(())
+/// ```
+pub fn is_same_file_at<D, P, Q>(
+    dir: &D,
+    path1: P,
+    path2: Q,
+) -> io::Result<bool>
+where
+    D: AsRawFilelike,
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let dir = dir.as_raw_filelike();
+    let (path1, path2) = (path1.as_ref(), path2.as_ref());
+    fail_point!(Open);
+    let file1 = imp::open_file_at(dir, path1)
+        .map_err(|err| Error::open_failed(path1, err))?;
+    fail_point!(Open);
+    let file2 = imp::open_file_at(dir, path2)
+        .map_err(|err| Error::open_failed(path2, err))?;
+    is_same_file(&file1, &file2)
+}
 
-            use std::sync::atomic::{
-                ATOMIC_USIZE_INIT, AtomicUsize, Ordering,
-            };
+/// The metadata of a file-like object, which is only borrowed.
+#[cfg(any(unix, windows, target_os = "wasi", target_os = "hermit"))]
+fn metadata_of<F: AsRawFilelike + ?Sized>(file: &F) -> io::Result<Metadata> {
+    // SAFETY: `file` keeps the underlying file open for the duration of
+    // this borrow.
+    let borrowed =
+        unsafe { BorrowedFilelike::borrow_raw(file.as_raw_filelike()) };
+    borrowed.as_filelike_view::<File>().metadata()
+}
 
-            static TRIES: usize = 100;
-            static COUNTER: AtomicUsize = ATOMIC_USIZE_INIT;
+#[cfg(not(any(unix, windows, target_os = "wasi", target_os = "hermit")))]
+fn metadata_of<F: AsRawFilelike + ?Sized>(_file: &F) -> io::Result<Metadata> {
+    Err(Error::UnsupportedPlatform { operation: "file metadata" }.into())
+}
 
-            let tmpdir = env::temp_dir();
-            for _ in 0..TRIES {
-                let count = COUNTER.fetch_add(1, Ordering::SeqCst);
-                let path = tmpdir.join("rust-walkdir").join(count.to_string());
-                if path.is_dir() {
-                    continue;
-                }
-                fs::create_dir_all(&path).map_err(|e| {
-                    err!("failed to create {}: {}", path.display(), e)
-                })?;
-                return Ok(TempDir(path));
-            }
-            Err(err!("failed to create temp dir after {} tries", TRIES))
-        }
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io;
+    #[cfg(windows)]
+    use std::path::{Path, PathBuf};
 
-        /// Return the underlying path to this temporary directory.
-        fn path(&self) -> &Path {
-            &self.0
-        }
-    }
+    use super::testing::{SecondFs, TempDir, soft_link_dir, soft_link_file};
+    use super::{
+        DirAlias, DirHandle, FileIdMap, HandleSet, dirs_alias, find_aliases,
+        is_same_file_path,
+    };
 
     fn tmpdir() -> TempDir {
         TempDir::new().unwrap()
     }
 
-    #[cfg(unix)]
-    pub fn soft_link_dir<P: AsRef<Path>, Q: AsRef<Path>>(
-        src: P,
-        dst: Q,
-    ) -> io::Result<()> {
-        use std::os::unix::fs::symlink;
-        symlink(src, dst)
-    }
-
-    #[cfg(unix)]
-    pub fn soft_link_file<P: AsRef<Path>, Q: AsRef<Path>>(
-        src: P,
-        dst: Q,
-    ) -> io::Result<()> {
-        soft_link_dir(src, dst)
-    }
-
-    #[cfg(windows)]
-    pub fn soft_link_dir<P: AsRef<Path>, Q: AsRef<Path>>(
-        src: P,
-        dst: Q,
-    ) -> io::Result<()> {
-        use std::os::windows::fs::symlink_dir;
-        symlink_dir(src, dst)
-    }
-
-    #[cfg(windows)]
-    pub fn soft_link_file<P: AsRef<Path>, Q: AsRef<Path>>(
-        src: P,
-        dst: Q,
-    ) -> io::Result<()> {
-        use std::os::windows::fs::symlink_file;
-        symlink_file(src, dst)
-    }
-
     // These tests are rather uninteresting. The really interesting tests
     // would stress the edge cases. On Unix, this might be comparing two files
     // on different mount points with the same inode number. On Windows, this
@@ -541,6 +1753,18 @@ mod tests {
         assert!(!is_same_file_path(dir.join("a"), dir.join("b")).unwrap());
     }
 
+    #[test]
+    fn not_same_file_other_class() {
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        let file = File::create(dir.join("a")).unwrap();
+        let (reader, writer) = io::pipe().unwrap();
+        assert!(!super::is_same_file(&file, &reader).unwrap());
+        assert!(!super::is_same_file(&reader, &file).unwrap());
+        assert!(super::is_same_file(&reader, &writer).unwrap());
+    }
+
     #[test]
     fn same_file_hard() {
         let tdir = tmpdir();
@@ -571,15 +1795,2549 @@ mod tests {
         assert!(is_same_file_path(dir.join("a"), dir.join("alink")).unwrap());
     }
 
+    #[cfg(windows)]
+    #[test]
+    fn same_dir_junction() {
+        use super::testing::junction;
+        use super::{FileId, Handle, is_same_file};
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        fs::create_dir(dir.join("a")).unwrap();
+        fs::create_dir(dir.join("b")).unwrap();
+        junction(dir.join("a"), dir.join("ajunction")).unwrap();
+
+        let a = Handle::from_path(dir.join("a")).unwrap();
+        let junction = Handle::from_path(dir.join("ajunction")).unwrap();
+        let b = Handle::from_path(dir.join("b")).unwrap();
+        assert_eq!(a, junction);
+        assert_ne!(a, b);
+        assert!(is_same_file(&*a, &*junction).unwrap());
+        assert!(
+            is_same_file_path(dir.join("a"), dir.join("ajunction")).unwrap()
+        );
+        assert_ne!(
+            FileId::from_path_no_follow(dir.join("ajunction")).unwrap(),
+            Handle::id(&a)
+        );
+    }
+
+    #[test]
+    fn not_same_file_cross_device() {
+        let Some(second) = SecondFs::new().unwrap() else {
+            return;
+        };
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        File::create(second.path().join("a")).unwrap();
+        assert!(
+            !is_same_file_path(dir.join("a"), second.path().join("a"))
+                .unwrap()
+        );
+        assert!(!dirs_alias(dir, second.path()).unwrap());
+    }
+
+    #[test]
+    fn dirs_alias_soft() {
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        fs::create_dir(dir.join("a")).unwrap();
+        fs::create_dir(dir.join("b")).unwrap();
+        soft_link_dir(dir.join("a"), dir.join("alink")).unwrap();
+        assert!(dirs_alias(dir.join("a"), dir.join("alink")).unwrap());
+        assert!(!dirs_alias(dir.join("a"), dir.join("b")).unwrap());
+    }
+
+    #[test]
+    fn dirs_alias_not_dir() {
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        assert!(dirs_alias(dir.join("a"), dir.join("a")).is_err());
+    }
+
+    #[test]
+    fn find_aliases_cycle() {
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        fs::create_dir_all(dir.join("a").join("b")).unwrap();
+        soft_link_dir(dir.join("a"), dir.join("a").join("b").join("up"))
+            .unwrap();
+        let aliases = find_aliases(dir).unwrap();
+        assert_eq!(
+            aliases,
+            vec![DirAlias {
+                original: dir.join("a"),
+                alias: dir.join("a").join("b").join("up"),
+            }]
+        );
+    }
+
+    #[test]
+    fn dir_handle_read_pin() {
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        fs::create_dir(dir.join("b")).unwrap();
+        let dir_handle = DirHandle::open(dir).unwrap();
+        let mut entries: Vec<_> =
+            dir_handle.read().unwrap().map(|e| e.unwrap()).collect();
+        entries.sort_by_key(|e| e.file_name());
+        assert_eq!(entries.len(), 2);
+        for entry in &entries {
+            let pinned = entry.pin().unwrap();
+            if let Some(weak) = entry.weak_id() {
+                assert!(weak.matches(&super::Handle::id(&pinned)));
+            }
+        }
+        assert!(entries[0].pin_dir().is_err());
+        assert!(entries[1].pin_dir().is_ok());
+        assert!(entries[0].file_type().unwrap().is_file());
+        assert!(entries[1].file_type().unwrap().is_dir());
+    }
+
+    #[test]
+    fn dir_handle_reads_through_handle() {
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        fs::create_dir(dir.join("a")).unwrap();
+        for i in 0..300 {
+            File::create(dir.join("a").join(format!("old-{i:03}"))).unwrap();
+        }
+        let dir_handle = DirHandle::open(dir.join("a")).unwrap();
+        fs::rename(dir.join("a"), dir.join("b")).unwrap();
+        fs::create_dir(dir.join("a")).unwrap();
+        File::create(dir.join("a/new")).unwrap();
+
+        let mut names: Vec<_> = dir_handle
+            .read()
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        let expected: Vec<_> =
+            (0..300).map(|i| format!("old-{i:03}")).collect();
+        assert_eq!(names, expected);
+        // Listings do not share a position.
+        let mut first = dir_handle.read().unwrap();
+        first.next().unwrap().unwrap();
+        assert_eq!(dir_handle.read().unwrap().count(), 300);
+        assert_eq!(first.count(), 299);
+    }
+
+    #[test]
+    fn timed_id_expiry() {
+        use std::time::Duration;
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        let a = File::create(dir.join("a")).unwrap();
+        let b = File::create(dir.join("b")).unwrap();
+        let id = super::FileId::from_file_like(&a).unwrap();
+
+        let mut fresh = id.clone().with_ttl(Duration::from_secs(3600));
+        assert_eq!(fresh.is_same_as(&id), Some(true));
+        assert!(fresh.revalidate(&a).unwrap());
+        assert!(!fresh.revalidate(&b).unwrap());
+
+        let expired = id.clone().with_ttl(Duration::ZERO);
+        assert!(expired.is_expired());
+        assert_eq!(expired.id(), None);
+        assert_eq!(expired.is_same_as(&id), None);
+    }
+
+    #[test]
+    fn normalized_id_same_file() {
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        let a = File::create(dir.join("a")).unwrap();
+        let b = File::open(dir.join("a")).unwrap();
+        assert_eq!(
+            super::FileId::from_file_like_normalized(&a).unwrap(),
+            super::FileId::from_file_like_normalized(&b).unwrap()
+        );
+        // A local file is already reached through its own volume.
+        assert_eq!(
+            super::FileId::from_file_like_normalized(&a).unwrap(),
+            super::FileId::from_file_like(&a).unwrap()
+        );
+        // Pipes are left as is.
+        let (reader, _writer) = io::pipe().unwrap();
+        assert_eq!(
+            super::FileId::from_file_like_normalized(&reader).unwrap(),
+            super::FileId::from_file_like(&reader).unwrap()
+        );
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos", windows))]
+    #[test]
+    fn current_path_after_rename() {
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        let handle = super::Handle::from_path(dir.join("a")).unwrap();
+        fs::rename(dir.join("a"), dir.join("b")).unwrap();
+        let expected = fs::canonicalize(dir.join("b")).unwrap();
+        let actual = fs::canonicalize(handle.current_path().unwrap()).unwrap();
+        assert_eq!(actual, expected);
+    }
+
+    /// Map a free drive letter to `dir` with `subst`, returning the letter,
+    /// or `None` if no mapping could be created.
+    #[cfg(windows)]
+    fn subst_drive(dir: &Path) -> Option<char> {
+        use std::process::Command;
+
+        let letter = ('D'..='Z')
+            .rev()
+            .find(|l| !Path::new(&format!("{}:\\", l)).exists())?;
+        let status = Command::new("subst")
+            .arg(format!("{}:", letter))
+            .arg(dir)
+            .status()
+            .ok()?;
+        status.success().then_some(letter)
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn subst_drive_resolves() {
+        use std::process::Command;
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        let Some(letter) = subst_drive(dir) else {
+            return;
+        };
+        let via_subst = PathBuf::from(format!("{}:\\a", letter));
+        let result = (|| -> io::Result<_> {
+            let direct = super::Handle::from_path(dir.join("a"))?;
+            let mapped = super::Handle::from_path(&via_subst)?;
+            Ok((
+                direct == mapped,
+                direct.current_path()?,
+                mapped.current_path()?,
+            ))
+        })();
+        Command::new("subst")
+            .arg(format!("{}:", letter))
+            .arg("/D")
+            .status()
+            .unwrap();
+        let (same, direct_path, mapped_path) = result.unwrap();
+        assert!(same);
+        assert_eq!(direct_path, mapped_path);
+    }
+
+    #[test]
+    fn file_id_from_path() {
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        fs::hard_link(dir.join("a"), dir.join("alink")).unwrap();
+        let handle = super::Handle::from_path(dir.join("a")).unwrap();
+        let id = super::FileId::from_path(dir.join("alink")).unwrap();
+        assert_eq!(id, super::Handle::id(&handle));
+        assert_eq!(
+            super::FileId::from_path(dir).unwrap().volume(),
+            id.volume()
+        );
+        assert!(super::FileId::from_path(dir.join("missing")).is_err());
+    }
+
+    #[test]
+    fn no_follow_identity() {
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        soft_link_file(dir.join("a"), dir.join("alink")).unwrap();
+        soft_link_file(dir.join("a"), dir.join("alink2")).unwrap();
+
+        let target = super::FileId::from_path(dir.join("alink")).unwrap();
+        let link =
+            super::FileId::from_path_no_follow(dir.join("alink")).unwrap();
+        let link2 =
+            super::FileId::from_path_no_follow(dir.join("alink2")).unwrap();
+        assert_eq!(target, super::FileId::from_path(dir.join("a")).unwrap());
+        assert_ne!(link, target);
+        assert_ne!(link, link2);
+        assert_eq!(
+            super::FileId::from_path_no_follow(dir.join("a")).unwrap(),
+            target
+        );
+
+        #[cfg(any(target_os = "linux", target_os = "macos", windows))]
+        {
+            let handle =
+                super::Handle::from_path_no_follow(dir.join("alink")).unwrap();
+            assert_eq!(super::Handle::id(&handle), link);
+        }
+    }
+
+    #[test]
+    fn handle_options() {
+        use super::{FileId, Handle, HandleOptions};
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        soft_link_file(dir.join("a"), dir.join("alink")).unwrap();
+        let target = FileId::from_path(dir.join("a")).unwrap();
+
+        let handle = HandleOptions::new().open(dir.join("alink")).unwrap();
+        assert_eq!(Handle::id(&handle), target);
+        let handle = HandleOptions::new().open(dir).unwrap();
+        assert_eq!(Handle::id(&handle), FileId::from_path(dir).unwrap());
+        let handle = HandleOptions::new()
+            .minimal_access(true)
+            .open(dir.join("a"))
+            .unwrap();
+        assert_eq!(Handle::id(&handle), target);
+
+        #[cfg(any(target_os = "linux", target_os = "macos", windows))]
+        {
+            let handle = HandleOptions::new()
+                .follow_symlinks(false)
+                .open(dir.join("alink"))
+                .unwrap();
+            assert_eq!(
+                Handle::id(&handle),
+                FileId::from_path_no_follow(dir.join("alink")).unwrap()
+            );
+        }
+
+        let handle = HandleOptions::new()
+            .verify_identity(true)
+            .open(dir.join("a"))
+            .unwrap();
+        assert_eq!(Handle::id(&handle), target);
+        assert_eq!(FileId::from_file_like_verified(&*handle).unwrap(), target);
+
+        let aware = HandleOptions::new()
+            .subvolume_aware(true)
+            .verify_identity(true)
+            .open(dir.join("a"))
+            .unwrap();
+        let subvolume_id =
+            FileId::from_file_like_subvolume_aware(&*aware).unwrap();
+        assert_eq!(Handle::id(&aware), subvolume_id);
+        assert_eq!(
+            FileId::from_file_like_subvolume_aware(&*handle).unwrap(),
+            subvolume_id
+        );
+        assert_eq!(
+            FileId::from_bytes(&subvolume_id.to_bytes()).unwrap(),
+            subvolume_id
+        );
+
+        let err = HandleOptions::new().open(dir.join("missing")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn minimal_access_unreadable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        use super::{DeviceClass, FileId, Handle, HandleOptions};
+
+        let tdir = tmpdir();
+        let path = tdir.path().join("secret");
+        File::create(&path).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o000)).unwrap();
+
+        let handle =
+            HandleOptions::new().minimal_access(true).open(&path).unwrap();
+        assert_eq!(Handle::id(&handle), FileId::from_path(&path).unwrap());
+        assert_eq!(
+            Handle::device_class(&handle).unwrap(),
+            DeviceClass::RegularFile
+        );
+        // An O_PATH descriptor cannot be read from.
+        assert!(io::Read::read(&mut &*handle, &mut [0; 1]).is_err());
+    }
+
+    #[test]
+    fn buffered_handles() {
+        use std::io::{BufRead, Write};
+
+        use super::Handle;
+
+        let tdir = tmpdir();
+        let path = tdir.path().join("a");
+        let file = File::create(&path).unwrap();
+        let mut writer =
+            Handle::buffered_writer(Handle::from_file_like(file).unwrap());
+        writeln!(writer, "hello").unwrap();
+        writer.flush().unwrap();
+
+        let mut reader =
+            Handle::buffered_reader(Handle::from_path(&path).unwrap());
+        assert_eq!(reader, writer);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "hello\n");
+    }
+
+    #[test]
+    fn into_stdio() {
+        use std::process::Command;
+
+        use super::{ChildStream, verify_child_stream};
+
+        let tdir = tmpdir();
+        File::create(tdir.path().join("log")).unwrap();
+        File::create(tdir.path().join("other")).unwrap();
+        let log = super::Handle::from_path(tdir.path().join("log")).unwrap();
+        let (stdio, id) = super::Handle::into_stdio(log);
+        assert_eq!(
+            super::FileId::from_path(tdir.path().join("log")).unwrap(),
+            id
+        );
+
+        if !cfg!(target_os = "linux") {
+            return;
+        }
+        let mut child =
+            Command::new("sleep").arg("5").stdout(stdio).spawn().unwrap();
+        let other =
+            super::FileId::from_path(tdir.path().join("other")).unwrap();
+        let result = (
+            verify_child_stream(&child, ChildStream::Stdout, &id),
+            verify_child_stream(&child, ChildStream::Stdout, &other),
+        );
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(result.0.unwrap());
+        assert!(!result.1.unwrap());
+    }
+
+    #[test]
+    fn into_std_owned() {
+        let tdir = tmpdir();
+        File::create(tdir.path().join("a")).unwrap();
+        let handle = super::Handle::from_path(tdir.path().join("a")).unwrap();
+        let id = super::Handle::id(&handle);
+
+        let (owned, owned_id) =
+            super::Handle::try_into_std_owned(handle).unwrap();
+        assert_eq!(owned_id, id);
+        assert_eq!(super::FileId::from_file_like(&owned).unwrap(), id);
+    }
+
+    #[test]
+    fn try_clone_keeps_identity() {
+        use std::io::{Read, Seek, SeekFrom};
+
+        let tdir = tmpdir();
+        fs::write(tdir.path().join("a"), b"hello").unwrap();
+        let mut handle =
+            super::Handle::from_path(tdir.path().join("a")).unwrap();
+        let mut copy = super::Handle::try_clone(&handle).unwrap();
+        assert_eq!(copy, handle);
+        assert_eq!(super::Handle::kind(&copy), super::Handle::kind(&handle));
+
+        // Both share the file offset.
+        handle.seek(SeekFrom::Start(1)).unwrap();
+        drop(handle);
+        let mut rest = String::new();
+        copy.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "ello");
+    }
+
+    #[test]
+    fn shared_handles() {
+        use std::collections::HashSet;
+
+        use super::{Handle, SharedHandle};
+
+        let tdir = tmpdir();
+        File::create(tdir.path().join("a")).unwrap();
+        File::create(tdir.path().join("b")).unwrap();
+        let a = SharedHandle::new(
+            Handle::from_path(tdir.path().join("a")).unwrap(),
+        );
+        let a2 = SharedHandle::from(
+            Handle::from_path(tdir.path().join("a")).unwrap(),
+        );
+        let b = SharedHandle::new(
+            Handle::from_path(tdir.path().join("b")).unwrap(),
+        );
+        let clone = a.clone();
+        assert!(SharedHandle::ptr_eq(&a, &clone));
+        assert!(!SharedHandle::ptr_eq(&a, &a2));
+        assert_eq!(a, a2);
+        assert_ne!(a, b);
+        assert_eq!(SharedHandle::id(&a), Handle::id(&a2));
+
+        let set: HashSet<_> = [a.clone(), a2, b].into_iter().collect();
+        assert_eq!(set.len(), 2);
+        drop(set);
+        let a = SharedHandle::try_unwrap(a).unwrap_err();
+        drop(clone);
+        let handle = SharedHandle::try_unwrap(a).unwrap();
+        assert!(handle.metadata().unwrap().is_file());
+    }
+
+    #[test]
+    fn owned_file_id() {
+        use super::{FileId, Handle, OwnedFileId};
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+        File::create(dir.join("a")).unwrap();
+        File::create(dir.join("b")).unwrap();
+
+        let file = File::open(dir.join("a")).unwrap();
+        let a = OwnedFileId::from_file_like(&file).unwrap();
+        drop(file);
+        let handle = Handle::from_path(dir.join("a")).unwrap();
+        let from_handle = OwnedFileId::from_handle(&handle).unwrap();
+        drop(handle);
+        let b = OwnedFileId::from_path(dir.join("b")).unwrap();
+
+        assert_eq!(a, from_handle);
+        assert_ne!(a, b);
+        assert_eq!(a, FileId::from_path(dir.join("a")).unwrap());
+        assert_eq!(a.try_clone().unwrap().id(), a.id());
+    }
+
+    #[test]
+    fn borrowed_handles() {
+        use io_lifetimes::AsFilelike;
+
+        use super::{BorrowedHandle, Handle};
+
+        let tdir = tmpdir();
+        File::create(tdir.path().join("a")).unwrap();
+        File::create(tdir.path().join("b")).unwrap();
+        let a = File::open(tdir.path().join("a")).unwrap();
+        let b = Handle::from_path(tdir.path().join("b")).unwrap();
+
+        let borrowed_a =
+            BorrowedHandle::from_borrowed(a.as_filelike()).unwrap();
+        let borrowed_b = Handle::as_borrowed(&b);
+        assert_ne!(borrowed_a, borrowed_b);
+        assert_eq!(borrowed_b, b);
+        assert_eq!(
+            Handle::id(&borrowed_a),
+            super::FileId::from_file_like(&a).unwrap()
+        );
+    }
+
+    #[test]
+    fn io_safety_constructors() {
+        use io_lifetimes::AsFilelike;
+
+        use super::FileId;
+
+        let tdir = tmpdir();
+        let file = File::create(tdir.path().join("a")).unwrap();
+        let id = FileId::from_file_like(&file).unwrap();
+        assert_eq!(FileId::from_file_like(&file.as_filelike()).unwrap(), id);
+        #[cfg(unix)]
+        {
+            use std::os::fd::{AsFd, OwnedFd};
+
+            assert_eq!(FileId::from_fd(&file.as_fd()).unwrap(), id);
+            assert_eq!(FileId::from_fd(&OwnedFd::from(file)).unwrap(), id);
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::io::{AsHandle, OwnedHandle};
+
+            assert_eq!(FileId::from_handle(&file.as_handle()).unwrap(), id);
+            assert_eq!(
+                FileId::from_handle(&OwnedHandle::from(file)).unwrap(),
+                id
+            );
+        }
+    }
+
+    #[test]
+    fn handle_as_filelike() {
+        use io_lifetimes::AsFilelike;
+
+        fn len<F: AsFilelike>(file: &F) -> u64 {
+            file.as_filelike_view::<File>().metadata().unwrap().len()
+        }
+
+        let tdir = tmpdir();
+        fs::write(tdir.path().join("a"), b"hello").unwrap();
+        let handle = super::Handle::from_path(tdir.path().join("a")).unwrap();
+        assert_eq!(len(&handle), 5);
+        #[cfg(unix)]
+        assert_eq!(
+            super::FileId::from_fd(&handle).unwrap(),
+            super::Handle::id(&handle)
+        );
+    }
+
+    #[test]
+    fn handle_io_traits() {
+        use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+
+        use super::Handle;
+
+        let tdir = tmpdir();
+        let path = tdir.path().join("a");
+        let mut writer =
+            Handle::from_file(File::create(&path).unwrap()).unwrap();
+        writer.write_all(b"hello\nworld\n").unwrap();
+        (&writer).flush().unwrap();
+
+        let mut reader = Handle::from_path(&path).unwrap();
+        let mut copy = Vec::new();
+        io::copy(&mut reader, &mut copy).unwrap();
+        assert_eq!(copy, b"hello\nworld\n");
+        (&reader).seek(SeekFrom::Start(6)).unwrap();
+        let mut rest = String::new();
+        (&reader).read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "world\n");
+
+        reader.rewind().unwrap();
+        let mut lines = Handle::buffered_reader(reader);
+        let mut line = String::new();
+        lines.read_line(&mut line).unwrap();
+        assert_eq!(line, "hello\n");
+        let mut rest = String::new();
+        BufReader::new(lines).read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "world\n");
+    }
+
+    #[test]
+    fn map_handles() {
+        use std::io::{BufRead, BufReader};
+        use std::sync::Arc;
+
+        use super::{Error, Handle};
+
+        let tdir = tmpdir();
+        fs::write(tdir.path().join("a"), b"hello\n").unwrap();
+        File::create(tdir.path().join("b")).unwrap();
+        let handle = Handle::from_path(tdir.path().join("a")).unwrap();
+        let id = Handle::id(&handle);
+
+        let mut reader = unsafe { Handle::map(handle, BufReader::new) };
+        assert_eq!(Handle::id(&reader), id);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "hello\n");
+
+        let file = unsafe { Handle::map(reader, BufReader::into_inner) };
+        let shared = Handle::try_map(file, Arc::new).unwrap();
+        assert_eq!(Handle::id(&shared), id);
+        let b = tdir.path().join("b");
+        let err =
+            Handle::try_map(shared, |_| File::open(&b).unwrap()).unwrap_err();
+        assert!(matches!(Error::of(&err), Some(Error::Replaced { .. })));
+    }
+
+    #[test]
+    fn handle_parts() {
+        use super::{FileId, Handle};
+
+        let tdir = tmpdir();
+        File::create(tdir.path().join("a")).unwrap();
+        let handle = Handle::from_path(tdir.path().join("a")).unwrap();
+        let (file, id) = Handle::parts(&handle);
+        assert_eq!(FileId::from_file_like(file).unwrap(), *id);
+
+        let expected = Handle::id(&handle);
+        let (file, id) = Handle::into_parts(handle);
+        assert_eq!(id, expected);
+        let handle = unsafe { Handle::from_parts(file, id) };
+        assert_eq!(Handle::id(&handle), expected);
+    }
+
+    #[test]
+    fn handle_lookup_by_id() {
+        use std::collections::{BTreeSet, HashMap};
+
+        use super::{FileId, Handle};
+
+        let tdir = tmpdir();
+        File::create(tdir.path().join("a")).unwrap();
+        File::create(tdir.path().join("b")).unwrap();
+        let a = FileId::from_path(tdir.path().join("a")).unwrap();
+        let b = FileId::from_path(tdir.path().join("b")).unwrap();
+
+        let mut map = HashMap::new();
+        map.insert(Handle::from_path(tdir.path().join("a")).unwrap(), 1);
+        assert_eq!(map.get(&a), Some(&1));
+        assert_eq!(map.get(&b), None);
+
+        let mut set = BTreeSet::new();
+        set.insert(Handle::from_path(tdir.path().join("b")).unwrap());
+        assert!(set.contains(&b));
+        assert!(!set.contains(&a));
+        let handle = set.first().unwrap();
+        assert_eq!(AsRef::<FileId>::as_ref(handle), &b);
+    }
+
+    #[test]
+    fn clone_shared_handles() {
+        use std::sync::Arc;
+
+        use super::Handle;
+
+        let tdir = tmpdir();
+        File::create(tdir.path().join("a")).unwrap();
+        let file = Arc::new(File::open(tdir.path().join("a")).unwrap());
+        let handle = Handle::from_file_like(file).unwrap();
+        let clone = handle.clone();
+        assert_eq!(clone, handle);
+        assert!(Arc::ptr_eq(&clone, &handle));
+        assert_eq!(Handle::kind(&clone), Handle::kind(&handle));
+
+        let borrowed = Handle::as_borrowed(&handle);
+        assert_eq!(borrowed.clone(), borrowed);
+    }
+
+    #[test]
+    fn revalidate_handles() {
+        use super::{FileId, Handle};
+
+        let tdir = tmpdir();
+        File::create(tdir.path().join("a")).unwrap();
+        File::create(tdir.path().join("b")).unwrap();
+        let a = Handle::from_path(tdir.path().join("a")).unwrap();
+        assert!(a.revalidate().unwrap());
+
+        // A handle whose identity does not belong to its file.
+        let b = FileId::from_path(tdir.path().join("b")).unwrap();
+        let file = File::open(tdir.path().join("a")).unwrap();
+        let wrong = unsafe { Handle::from_parts(file, b) };
+        assert!(!wrong.revalidate().unwrap());
+    }
+
+    #[test]
+    fn handle_refers_to_path() {
+        use super::Handle;
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+        File::create(dir.join("a")).unwrap();
+        File::create(dir.join("b")).unwrap();
+        soft_link_file(dir.join("a"), dir.join("alink")).unwrap();
+
+        let a = Handle::from_path(dir.join("a")).unwrap();
+        assert!(a.refers_to_path(dir.join("a")).unwrap());
+        assert!(a.refers_to_path(dir.join("alink")).unwrap());
+        assert!(!a.refers_to_path(dir.join("b")).unwrap());
+        fs::rename(dir.join("b"), dir.join("a")).unwrap();
+        assert!(!a.refers_to_path(dir.join("a")).unwrap());
+        assert!(!a.refers_to_path(dir.join("missing")).unwrap());
+    }
+
+    #[test]
+    fn reopen_verified_upgrades_access() {
+        use std::fs::{self, File, OpenOptions};
+        use std::io::Write;
+
+        use super::{Error, Handle, reopen_verified};
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        File::create(dir.join("b")).unwrap();
+        let a = Handle::from_path(dir.join("a")).unwrap();
+        let mut options = OpenOptions::new();
+        options.write(true);
+        let mut writable =
+            reopen_verified(&a, dir.join("a"), &options).unwrap();
+        assert_eq!(writable, a);
+        writable.write_all(b"data").unwrap();
+        drop(writable);
+        assert_eq!(fs::read(dir.join("a")).unwrap(), b"data");
+
+        let err = reopen_verified(&a, dir.join("b"), &options).unwrap_err();
+        match Error::of(&err) {
+            Some(Error::Replaced { path, .. }) => {
+                assert_eq!(path.as_deref(), Some(&*dir.join("b")));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert!(reopen_verified(&a, dir.join("missing"), &options).is_err());
+
+        // A FIFO swapped in for the file is refused rather than waited on.
+        #[cfg(unix)]
+        {
+            use std::ffi::CString;
+            use std::os::unix::ffi::OsStrExt;
+
+            fs::remove_file(dir.join("a")).unwrap();
+            let path = CString::new(dir.join("a").as_os_str().as_bytes());
+            // SAFETY: the path is a valid C string.
+            let made = unsafe { libc::mkfifo(path.unwrap().as_ptr(), 0o600) };
+            assert_eq!(made, 0);
+            let mut options = OpenOptions::new();
+            options.read(true);
+            let err =
+                reopen_verified(&a, dir.join("a"), &options).unwrap_err();
+            assert!(matches!(Error::of(&err), Some(Error::Replaced { .. })));
+        }
+    }
+
+    #[test]
+    fn same_file_all() {
+        use super::is_same_file_all;
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        File::create(dir.join("b")).unwrap();
+        fs::hard_link(dir.join("a"), dir.join("alink")).unwrap();
+        assert!(is_same_file_all(std::iter::empty::<&str>()).unwrap());
+        assert!(is_same_file_all([dir.join("a")]).unwrap());
+        assert!(
+            is_same_file_all([
+                dir.join("a"),
+                dir.join("alink"),
+                dir.join("a")
+            ])
+            .unwrap()
+        );
+        assert!(
+            !is_same_file_all([
+                dir.join("a"),
+                dir.join("alink"),
+                dir.join("b")
+            ])
+            .unwrap()
+        );
+        assert!(
+            is_same_file_all([dir.join("a"), dir.join("missing")]).is_err()
+        );
+    }
+
+    #[test]
+    fn unique_files() {
+        use super::UniqueFiles;
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        File::create(dir.join("b")).unwrap();
+        fs::hard_link(dir.join("a"), dir.join("alink")).unwrap();
+        let paths = [
+            dir.join("a"),
+            dir.join("alink"),
+            dir.join("missing"),
+            dir.join("b"),
+            dir.join("a"),
+        ];
+        for mut unique in [
+            UniqueFiles::new(paths.clone()),
+            UniqueFiles::pinned(paths.clone()),
+        ] {
+            assert_eq!(unique.next().unwrap().unwrap(), dir.join("a"));
+            assert!(unique.next().unwrap().is_err());
+            assert_eq!(unique.next().unwrap().unwrap(), dir.join("b"));
+            assert!(unique.next().is_none());
+            assert_eq!(unique.len_seen(), 2);
+        }
+    }
+
+    #[test]
+    fn file_id_map_entries() {
+        use super::{FileIdMap, Handle};
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        File::create(dir.join("b")).unwrap();
+        fs::hard_link(dir.join("a"), dir.join("alink")).unwrap();
+        let mut counts = FileIdMap::new();
+        for name in ["a", "alink", "b", "a"] {
+            *counts.entry_for_path(dir.join(name)).unwrap().or_insert(0) += 1;
+        }
+        assert_eq!(counts.len(), 2);
+        let a = Handle::from_path(dir.join("a")).unwrap();
+        assert_eq!(counts.get_by_handle(&a), Some(&3));
+        *counts.get_mut_by_handle(&a).unwrap() = 0;
+        assert_eq!(counts.get(&Handle::id(&a)), Some(&0));
+        assert!(counts.entry_for_path(dir.join("missing")).is_err());
+    }
+
+    #[test]
+    fn deduplicator_budget() {
+        use super::{Deduplicator, FileId, Handle};
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        for name in ["a", "b", "c"] {
+            File::create(dir.join(name)).unwrap();
+        }
+        fs::hard_link(dir.join("a"), dir.join("alink")).unwrap();
+        let mut seen = Deduplicator::with_fd_budget(2);
+        assert!(seen.first_seen(dir.join("a")).unwrap());
+        assert!(!seen.first_seen(dir.join("alink")).unwrap());
+        assert!(seen.first_seen(dir.join("b")).unwrap());
+        assert!(seen.first_seen(dir.join("c")).unwrap());
+        assert!(!seen.first_seen(dir.join("c")).unwrap());
+        assert!(!seen.first_seen(dir.join("a")).unwrap());
+        assert!(seen.first_seen(dir.join("missing")).is_err());
+        assert!(
+            !seen.first_seen_handle(Handle::from_path(dir.join("b")).unwrap())
+        );
+        assert_eq!(seen.len(), 3);
+        assert_eq!(seen.pinned(), 2);
+        assert!(seen.contains(&FileId::from_path(dir.join("c")).unwrap()));
+
+        let mut seen = Deduplicator::new();
+        assert!(seen.first_seen(dir.join("a")).unwrap());
+        assert!(!seen.first_seen(dir.join("alink")).unwrap());
+        assert_eq!(seen.pinned(), 0);
+    }
+
+    #[test]
+    fn hard_link_groups_by_link_count() {
+        use super::{FileId, hard_link_groups};
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        for name in ["a", "b", "c"] {
+            File::create(dir.join(name)).unwrap();
+        }
+        fs::hard_link(dir.join("a"), dir.join("a2")).unwrap();
+        fs::hard_link(dir.join("a"), dir.join("a3")).unwrap();
+        fs::hard_link(dir.join("c"), dir.join("c2")).unwrap();
+        let groups = hard_link_groups(
+            ["a", "b", "c", "a2", "a3"].map(|name| dir.join(name)),
+        )
+        .unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].id, FileId::from_path(dir.join("a")).unwrap());
+        assert_eq!(groups[0].links, 3);
+        assert_eq!(
+            groups[0].paths,
+            ["a", "a2", "a3"].map(|name| dir.join(name))
+        );
+        assert_eq!(groups[1].links, 2);
+        assert_eq!(groups[1].paths, [dir.join("c")]);
+        assert!(hard_link_groups([dir.join("missing")]).is_err());
+    }
+
+    #[test]
+    fn inherited_identity_verified() {
+        use io_lifetimes::raw::IntoRawFilelike;
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        File::create(dir.join("b")).unwrap();
+        let a = super::Handle::from_path(dir.join("a")).unwrap();
+        let b = super::Handle::from_path(dir.join("b")).unwrap();
+        a.inheritable(true).unwrap();
+        a.inheritable(false).unwrap();
+
+        let raw = a.try_clone().unwrap().into_raw_filelike();
+        let child: super::Handle<File> = unsafe {
+            super::Handle::try_from_raw(raw, &super::Handle::id(&a))
+        }
+        .unwrap();
+        assert_eq!(child, a);
+
+        let raw = a.try_clone().unwrap().into_raw_filelike();
+        let err = unsafe {
+            super::Handle::<File>::try_from_raw(raw, &super::Handle::id(&b))
+        }
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn inheritable_clears_cloexec() {
+        use std::os::unix::io::AsRawFd;
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        let a = super::Handle::from_path(dir.join("a")).unwrap();
+        let cloexec = || unsafe {
+            libc::fcntl(a.as_raw_fd(), libc::F_GETFD) & libc::FD_CLOEXEC != 0
+        };
+        assert!(cloexec());
+        a.inheritable(true).unwrap();
+        assert!(!cloexec());
+        a.inheritable(false).unwrap();
+        assert!(cloexec());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keep_at_handoff() {
+        use std::os::unix::io::{AsFd, IntoRawFd};
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        let a = super::Handle::from_path(dir.join("a")).unwrap();
+        // Reserve the target number with a descriptor owned by the test, and
+        // hand it over, so that no other test can open a file at that number
+        // while it is free.
+        let target = a.as_fd().try_clone_to_owned().unwrap().into_raw_fd();
+        let handoff = unsafe { a.keep_at(target) }.unwrap();
+        assert_eq!(handoff.fd(), target);
+        let cloexec = unsafe { libc::fcntl(target, libc::F_GETFD) };
+        assert_eq!(cloexec & libc::FD_CLOEXEC, 0);
+
+        let id = handoff.id().clone();
+        let child: super::Handle<File> =
+            unsafe { super::Handle::try_from_raw(handoff.into_raw_fd(), &id) }
+                .unwrap();
+        assert_eq!(child, a);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn prepare_handoff() {
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        let a = super::Handle::from_path(dir.join("a")).unwrap();
+        let handoff = a.prepare_handoff().unwrap();
+        let id = handoff.id().clone();
+        let value = handoff.value();
+        let _ = handoff.into_raw_handle();
+        let child: super::Handle<File> =
+            unsafe { super::Handle::try_from_raw_value(&value, &id) }.unwrap();
+        assert_eq!(child, a);
+    }
+
+    #[test]
+    fn try_from_raw_value_invalid() {
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        let a = super::Handle::from_path(dir.join("a")).unwrap();
+        let id = super::Handle::id(&a);
+        for value in ["", "abc", "-1"] {
+            let err = unsafe {
+                super::Handle::<File>::try_from_raw_value(value, &id)
+            }
+            .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn leaked_id_stays_valid() {
+        // Leak a handle to something that outlives the test, since a leaked
+        // file in a temporary directory would prevent its removal on Windows.
+        let a = super::Handle::from_path(env::current_dir().unwrap()).unwrap();
+        let id = super::Handle::id(&a);
+        let leaked = super::Handle::leak(a);
+        assert_eq!(leaked, id);
+        assert_eq!(leaked.id(), &id);
+    }
+
+    #[test]
+    fn assert_file_macros() {
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        File::create(dir.join("b")).unwrap();
+        fs::hard_link(dir.join("a"), dir.join("alink")).unwrap();
+        let a = super::Handle::from_path(dir.join("a")).unwrap();
+
+        crate::assert_same_file!(dir.join("a"), dir.join("alink"));
+        crate::assert_same_file!(a, dir.join("alink"), "context {}", 1);
+        crate::assert_different_file!(&a, File::open(dir.join("b")).unwrap());
+
+        let message = std::panic::catch_unwind(|| {
+            crate::assert_same_file!(dir.join("a"), dir.join("b"));
+        })
+        .unwrap_err();
+        let message = message.downcast_ref::<String>().unwrap();
+        assert!(message.contains("refer to different files"));
+        assert!(message.contains("regular file"));
+
+        let message = std::panic::catch_unwind(|| {
+            crate::assert_different_file!("/nonexistent/path", dir.join("b"));
+        })
+        .unwrap_err();
+        let message = message.downcast_ref::<String>().unwrap();
+        assert!(message.contains("could not be identified"));
+    }
+
+    #[test]
+    fn replaced_atomically() {
+        let tdir = tmpdir();
+        let dir = tdir.path();
+        let target = dir.join("target");
+
+        fs::write(&target, "old").unwrap();
+        let value = crate::assert_replaced_atomically!(&target, {
+            fs::write(dir.join("tmp"), "new").unwrap();
+            fs::rename(dir.join("tmp"), &target).unwrap();
+            42
+        });
+        assert_eq!(value, 42);
+
+        let result = std::panic::catch_unwind(|| {
+            crate::assert_replaced_atomically!(&target, {
+                fs::write(&target, "rewritten in place").unwrap();
+            });
+        });
+        assert!(result.is_err());
+    }
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn uuid_from_identity() {
+        use uuid::{Uuid, Variant, Version};
+
+        use super::FileId;
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        File::create(dir.join("b")).unwrap();
+        fs::hard_link(dir.join("a"), dir.join("alink")).unwrap();
+        let a = FileId::from_path(dir.join("a")).unwrap();
+        let alink = FileId::from_path(dir.join("alink")).unwrap();
+        let b = FileId::from_path(dir.join("b")).unwrap();
+
+        let uuid = a.to_uuid(&Uuid::NAMESPACE_OID);
+        assert_eq!(uuid.get_version(), Some(Version::Custom));
+        assert_eq!(uuid.get_variant(), Variant::RFC4122);
+        assert_eq!(uuid, alink.to_uuid(&Uuid::NAMESPACE_OID));
+        assert_ne!(uuid, b.to_uuid(&Uuid::NAMESPACE_OID));
+        assert_ne!(uuid, a.to_uuid(&Uuid::NAMESPACE_URL));
+
+        // The algorithm is stable: pin the UUID of a fixed identity, whose
+        // binary form is the platform's tag followed by zeros.
+        let expected = match a.to_bytes()[0] {
+            1 => "56ebb73b-3d8a-8a3a-883f-eee55f727774",
+            2 => "793a6797-a2d4-88be-83e6-0941402f1938",
+            3 => "31f7eb73-7587-8209-825b-7628b68acbb4",
+            _ => "ab73fdf4-3b00-860c-8438-885d61b801a2",
+        };
+        let mut bytes = [0; FileId::BYTES_LEN];
+        bytes[0] = a.to_bytes()[0];
+        if let Ok(fixed) = FileId::from_bytes(&bytes) {
+            let uuid = fixed.to_uuid(&Uuid::NAMESPACE_OID);
+            assert_eq!(uuid.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn capabilities_match_behavior() {
+        use super::{Handle, capabilities};
+
+        let caps = capabilities();
+        assert!(caps.identity);
+        assert!(!caps.reflink_detection);
+        #[cfg(any(unix, windows))]
+        assert!(caps.pins_while_open && !caps.heuristic);
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+        File::create(dir.join("a")).unwrap();
+        soft_link_file(dir.join("a"), dir.join("alink")).unwrap();
+
+        let handle = Handle::from_path(dir.join("a")).unwrap();
+        assert_eq!(handle.current_path().is_ok(), caps.current_path);
+        assert_eq!(
+            Handle::from_path_no_follow(dir.join("alink")).is_ok(),
+            caps.open_no_follow
+        );
+    }
+
+    #[test]
+    fn doctor_report() {
+        use super::{DeviceClass, FileId, doctor};
+
+        let tdir = tmpdir();
+        let path = tdir.path().join("a");
+        File::create(&path).unwrap();
+
+        let report = doctor(&path).unwrap();
+        assert_eq!(report.path, path);
+        assert_eq!(report.class, DeviceClass::RegularFile);
+        assert_eq!(report.id, FileId::from_path(&path).unwrap());
+        assert!(report.consistent);
+        assert!(report.verified);
+        #[cfg(any(unix, windows))]
+        {
+            assert!(report.index_bits > 0);
+            assert!(report.is_reliable());
+        }
+        let text = report.to_string();
+        assert!(text.starts_with(&format!("path: {}\n", path.display())));
+        assert!(text.contains(&format!("id: {}\n", report.id)));
+
+        let dir = doctor(tdir.path()).unwrap();
+        assert_eq!(dir.class, DeviceClass::Directory);
+        assert!(doctor(tdir.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn id_changes() {
+        use super::{FileId, IdChange, IdChangeKind};
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+        let path = dir.join("a");
+
+        assert_eq!(IdChange::observe(&path, None).unwrap(), None);
+        File::create(&path).unwrap();
+        let change = IdChange::observe(&path, None).unwrap().unwrap();
+        assert_eq!(change.kind, IdChangeKind::Created);
+        assert_eq!(change.path, path);
+        let old = change.new_id.unwrap();
+        assert_eq!(IdChange::observe(&path, Some(&old)).unwrap(), None);
+
+        File::create(dir.join("b")).unwrap();
+        let new = FileId::from_path(dir.join("b")).unwrap();
+        fs::rename(&path, dir.join("c")).unwrap();
+        fs::rename(dir.join("b"), &path).unwrap();
+        let change = IdChange::observe(&path, Some(&old)).unwrap().unwrap();
+        assert_eq!(change.kind, IdChangeKind::Replaced);
+        assert_eq!(change.old_id.as_ref(), Some(&old));
+        assert_eq!(change.new_id.as_ref(), Some(&new));
+
+        let moved = IdChange::moved(old.clone(), dir.join("c"));
+        assert_eq!(moved.kind, IdChangeKind::Moved);
+        assert_eq!(
+            moved.to_string(),
+            format!("{} moved ({})", dir.join("c").display(), old)
+        );
+
+        fs::remove_file(&path).unwrap();
+        let change = IdChange::observe(&path, Some(&new)).unwrap().unwrap();
+        assert_eq!(change.kind, IdChangeKind::Deleted);
+        assert_eq!(change.new_id, None);
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&change).unwrap();
+            let back: IdChange = serde_json::from_str(&json).unwrap();
+            assert_eq!(back, change);
+        }
+    }
+
+    #[test]
+    fn sortable_bytes() {
+        use super::FileId;
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        let mut ids = vec![FileId::from_path(dir).unwrap()];
+        for name in ["a", "b", "c", "d"] {
+            File::create(dir.join(name)).unwrap();
+            ids.push(FileId::from_path(dir.join(name)).unwrap());
+        }
+        let volume = ids[0].volume();
+        for volume in [
+            0,
+            1,
+            volume.wrapping_sub(1),
+            volume,
+            volume.wrapping_add(1),
+            u64::MAX,
+        ] {
+            ids.push(FileId::min_for_volume(volume).unwrap());
+            ids.push(FileId::max_for_volume(volume).unwrap());
+        }
+        // Vary each byte of a real identity, keeping the variants that are
+        // still well-formed.
+        let bytes = ids[1].to_bytes();
+        for i in 1..FileId::BYTES_LEN {
+            for value in [0, 1, 0x7f, 0x80, 0xff] {
+                let mut variant = bytes;
+                variant[i] = value;
+                if let Ok(id) = FileId::from_bytes(&variant) {
+                    ids.push(id);
+                }
+            }
+        }
+
+        for a in &ids {
+            for b in &ids {
+                assert_eq!(
+                    a.as_sortable_bytes().cmp(&b.as_sortable_bytes()),
+                    a.cmp(b),
+                    "{} and {}",
+                    a,
+                    b
+                );
+            }
+            assert_eq!(
+                &FileId::from_bytes(&a.as_sortable_bytes()).unwrap(),
+                a
+            );
+        }
+    }
+
+    #[test]
+    fn packed_form() {
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        File::create(dir.join("b")).unwrap();
+        fs::hard_link(dir.join("a"), dir.join("alink")).unwrap();
+        let id = |name| {
+            super::Handle::id(
+                &super::Handle::from_path(dir.join(name)).unwrap(),
+            )
+        };
+        let (a, alink, b) = (id("a"), id("alink"), id("b"));
+        assert_eq!(a.to_packed(), alink.to_packed());
+        assert_ne!(a.to_packed(), b.to_packed());
+        #[cfg(feature = "subtle")]
+        {
+            assert!(bool::from(a.ct_eq(&alink)));
+            assert!(!bool::from(a.ct_eq(&b)));
+        }
+
+        assert_eq!(super::FileId::from_packed(&a.to_packed()).unwrap(), a);
+        let mut foreign = a.to_packed();
+        foreign[0] = if cfg!(windows) { 1 } else { 2 };
+        assert!(super::FileId::from_packed(&foreign).is_err());
+        // The Unix form uses all 32 bytes.
+        #[cfg(not(unix))]
+        {
+            let mut padded = a.to_packed();
+            padded[31] = 1;
+            assert!(super::FileId::from_packed(&padded).is_err());
+        }
+    }
+
+    #[cfg(feature = "camino")]
+    #[test]
+    fn camino_paths() {
+        use camino::Utf8PathBuf;
+
+        let tdir = tmpdir();
+        let dir = Utf8PathBuf::try_from(tdir.path().to_path_buf()).unwrap();
+        let a = dir.join("a");
+        File::create(&a).unwrap();
+
+        let handle = super::Handle::from_path(&a).unwrap();
+        assert_eq!(
+            super::FileId::from_path(&a).unwrap(),
+            super::Handle::id(&handle)
+        );
+        assert!(is_same_file_path(&a, a.as_std_path()).unwrap());
+        crate::assert_same_file!(&a, handle);
+        #[cfg(any(target_os = "linux", windows))]
+        assert_eq!(
+            fs::canonicalize(handle.current_utf8_path().unwrap()).unwrap(),
+            fs::canonicalize(&a).unwrap(),
+        );
+
+        let dir_handle = DirHandle::open(&dir).unwrap();
+        assert_eq!(dir_handle.utf8_path(), Some(dir.as_path()));
+    }
+
+    #[test]
+    fn lexically_equal_paths() {
+        use super::paths_lexically_equal;
+
+        assert!(paths_lexically_equal("a/b", "a//b/."));
+        assert!(paths_lexically_equal("./a/b", "a/./b"));
+        assert!(!paths_lexically_equal("a/b", "a/c"));
+        assert!(!paths_lexically_equal("a/b", "a/b/c"));
+        assert!(!paths_lexically_equal("/a/b", "a/b"));
+        // `..` may cross a symbolic link, so it is not resolved.
+        assert!(!paths_lexically_equal("a/../b", "b"));
+        #[cfg(not(target_vendor = "apple"))]
+        assert!(!paths_lexically_equal("caf\u{e9}", "cafe\u{301}"));
+    }
+
+    #[cfg(target_vendor = "apple")]
+    #[test]
+    fn lexically_equal_decomposed() {
+        use super::paths_lexically_equal;
+
+        let composed = "caf\u{e9}";
+        let decomposed = "cafe\u{301}";
+        assert!(paths_lexically_equal(composed, decomposed));
+        assert!(paths_lexically_equal(
+            format!("{}/x", composed),
+            format!("{}/x", decomposed)
+        ));
+        assert!(!paths_lexically_equal(composed, "cafe"));
+
+        let tdir = tmpdir();
+        File::create(tdir.path().join(composed)).unwrap();
+        assert!(
+            is_same_file_path(
+                tdir.path().join(composed),
+                tdir.path().join(decomposed)
+            )
+            .unwrap()
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn win32_path_names() {
+        use super::FileId;
+
+        let tdir = tmpdir();
+        let a = tdir.path().join("a");
+        File::create(&a).unwrap();
+        let id = FileId::from_path(&a).unwrap();
+        assert_eq!(FileId::from_path(tdir.path().join("a. .")).unwrap(), id);
+
+        for device in ["NUL", "con.txt", "COM1"] {
+            let err = FileId::from_path(tdir.path().join(device)).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            let err = super::Handle::from_path(device).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn device_classes() {
+        use super::{DeviceClass, FileId, Handle};
+
+        let tdir = tmpdir();
+        let a = tdir.path().join("a");
+        let file = File::create(&a).unwrap();
+        let (id, class) = FileId::from_file_like_classified(&file).unwrap();
+        assert_eq!(id, FileId::from_path(&a).unwrap());
+        assert_eq!(class, DeviceClass::RegularFile);
+        assert!(class.is_filesystem_object());
+
+        let dir = Handle::from_path(tdir.path()).unwrap();
+        assert_eq!(
+            Handle::device_class(&dir).unwrap(),
+            DeviceClass::Directory
+        );
+        assert_eq!(Handle::kind(&dir), Some(DeviceClass::Directory));
+        let file = Handle::from_file_like(file).unwrap();
+        assert_eq!(Handle::kind(&file), Some(DeviceClass::RegularFile));
+        let parts = unsafe {
+            Handle::from_parts(Handle::into_inner(file), id.clone())
+        };
+        assert_eq!(Handle::kind(&parts), None);
+        assert_eq!(
+            Handle::device_class(&parts).unwrap(),
+            DeviceClass::RegularFile
+        );
+
+        let (reader, _writer) = io::pipe().unwrap();
+        assert_eq!(DeviceClass::of(&reader).unwrap(), DeviceClass::Pipe);
+        let reader = Handle::from_file_like(reader).unwrap();
+        assert_eq!(Handle::kind(&reader), Some(DeviceClass::Pipe));
+
+        #[cfg(unix)]
+        {
+            let null = File::open("/dev/null").unwrap();
+            assert_eq!(
+                DeviceClass::of(&null).unwrap(),
+                DeviceClass::CharDevice
+            );
+            let (socket, _) = std::os::unix::net::UnixStream::pair().unwrap();
+            assert_eq!(DeviceClass::of(&socket).unwrap(), DeviceClass::Socket);
+        }
+        #[cfg(windows)]
+        {
+            let null = File::open("NUL").unwrap();
+            assert_eq!(
+                DeviceClass::of(&null).unwrap(),
+                DeviceClass::CharDevice
+            );
+        }
+    }
+
+    #[test]
+    fn structured_errors() {
+        use std::path::Path;
+
+        use super::{
+            Error, FileId, Handle, is_same_file_all, is_same_file_at,
+            is_same_file_no_follow,
+        };
+
+        let tdir = tmpdir();
+        let missing = tdir.path().join("missing");
+        let present = tdir.path().join("present");
+        File::create(&present).unwrap();
+        let dir = Handle::from_path(tdir.path()).unwrap();
+        for (err, expected) in [
+            (FileId::from_path(&missing).unwrap_err(), missing.as_path()),
+            (Handle::from_path(&missing).unwrap_err(), &missing),
+            (is_same_file_path(&present, &missing).unwrap_err(), &missing),
+            (is_same_file_all([&present, &missing]).unwrap_err(), &missing),
+            (
+                is_same_file_no_follow(&missing, &present).unwrap_err(),
+                &missing,
+            ),
+            (
+                is_same_file_at(&*dir, "present", "missing").unwrap_err(),
+                Path::new("missing"),
+            ),
+        ] {
+            assert_eq!(err.kind(), io::ErrorKind::NotFound);
+            match Error::of(&err) {
+                Some(error @ Error::OpenFailed { path, source }) => {
+                    assert_eq!(path, expected);
+                    assert_eq!(source.kind(), io::ErrorKind::NotFound);
+                    // The code is kept by the crate error alone.
+                    assert_eq!(err.raw_os_error(), None);
+                    assert!(source.raw_os_error().is_some());
+                    assert_eq!(error.raw_os_error(), source.raw_os_error());
+                }
+                other => panic!("unexpected error: {:?}", other),
+            }
+        }
+        assert!(Error::of(&io::Error::other("foreign")).is_none());
+
+        let (reader, writer) = io::pipe().unwrap();
+        let id = FileId::from_file_like(&reader).unwrap();
+        assert_eq!(FileId::from_file_like(&writer).unwrap(), id);
+    }
+
+    #[cfg(all(
+        target_os = "linux",
+        any(target_env = "gnu", target_env = "musl")
+    ))]
+    #[test]
+    fn statx_fallback() {
+        use std::os::unix::fs::MetadataExt;
+
+        use super::imp::statx::{Fallback, fallback};
+
+        let err = |errno| io::Error::from_raw_os_error(errno);
+        assert_eq!(fallback(&err(libc::ENOSYS)), Some(Fallback::Missing));
+        assert_eq!(fallback(&err(libc::EPERM)), Some(Fallback::Denied));
+        assert_eq!(fallback(&err(libc::ENOENT)), None);
+        assert_eq!(fallback(&err(libc::EACCES)), None);
+
+        // Identities from `statx` are those `fstat` gives when falling back.
+        let tdir = tmpdir();
+        let file = File::create(tdir.path().join("a")).unwrap();
+        let md = file.metadata().unwrap();
+        let dev_ino = format!("{}:{}", md.dev(), md.ino());
+        let id = super::FileId::from_file_like(&file).unwrap();
+        assert_eq!(id.to_string(), dev_ino);
+        assert_eq!(
+            super::FileId::from_path(tdir.path().join("a")).unwrap(),
+            id
+        );
+        if super::capabilities().mount_ids {
+            let aware =
+                super::FileId::from_file_like_mount_aware(&file).unwrap();
+            assert_ne!(aware, id);
+            assert!(aware.to_string().starts_with(&format!("{}:", dev_ino)));
+        }
+    }
+
+    /// Check that every API that takes the identity of a handle again takes
+    /// it the way the handle was opened.
+    fn check_identity_mode(options: &super::HandleOptions) {
+        use std::fs::OpenOptions;
+        use std::io::Read;
+        use std::sync::Arc;
+
+        use super::{
+            Handle, VerifiedReader, gc_persisted_ids, reopen_verified,
+        };
+
+        let tdir = tmpdir();
+        let path = tdir.path().join("a");
+        fs::write(&path, "data").unwrap();
+
+        let handle = options.open(&path).unwrap();
+        let id = Handle::id(&handle);
+        assert!(handle.revalidate().unwrap());
+        assert!(handle.refers_to_path(&path).unwrap());
+        let reopened =
+            reopen_verified(&handle, &path, OpenOptions::new().read(true))
+                .unwrap();
+        assert_eq!(Handle::id(&reopened), id);
+        assert!(reopened.revalidate().unwrap());
+
+        let report = gc_persisted_ids([id.clone()], |_| Some(&path));
+        assert_eq!(report.keep.len(), 1);
+
+        let shared = Handle::try_map(handle, Arc::new).unwrap();
+        assert!(shared.revalidate().unwrap());
+        let handle = options.open(&path).unwrap();
+        let mut reader = VerifiedReader::new(handle).watching(&path);
+        let mut data = String::new();
+        reader.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "data");
+    }
+
+    #[test]
+    fn mount_aware_handles() {
+        check_identity_mode(super::HandleOptions::new().mount_aware(true));
+    }
+
+    #[test]
+    fn subvolume_aware_handles() {
+        check_identity_mode(
+            super::HandleOptions::new()
+                .subvolume_aware(true)
+                .mount_aware(true),
+        );
+        check_identity_mode(super::HandleOptions::new().subvolume_aware(true));
+    }
+
+    #[test]
+    fn display() {
+        let tdir = tmpdir();
+        File::create(tdir.path().join("a")).unwrap();
+        let id = super::FileId::from_path(tdir.path().join("a")).unwrap();
+        let text = id.to_string();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            let md = fs::metadata(tdir.path().join("a")).unwrap();
+            let rest =
+                text.strip_prefix(&format!("{}:{}", md.dev(), md.ino()));
+            assert_eq!(rest, Some(""));
+
+            let file = File::open(tdir.path().join("a")).unwrap();
+            let aware =
+                super::FileId::from_file_like_mount_aware(&file).unwrap();
+            let rest = aware
+                .to_string()
+                .strip_prefix(&format!("{}:{}", md.dev(), md.ino()))
+                .map(str::to_owned);
+            if super::capabilities().mount_ids {
+                let mnt = rest.unwrap();
+                let mnt = mnt.strip_prefix(':').unwrap();
+                assert!(mnt.parse::<u64>().unwrap() > 0);
+            } else {
+                assert_eq!(rest.as_deref(), Some(""));
+            }
+        }
+        #[cfg(windows)]
+        {
+            let (volume, file) = text.split_once(':').unwrap();
+            assert_eq!(volume.len(), 16);
+            assert_eq!(file.len(), 32);
+        }
+    }
+
+    #[test]
+    fn parse() {
+        use super::{FileId, parse_file_id};
+
+        let tdir = tmpdir();
+        File::create(tdir.path().join("a")).unwrap();
+        let id = FileId::from_path(tdir.path().join("a")).unwrap();
+        assert_eq!(id.to_string().parse::<FileId>().unwrap(), id);
+        assert_eq!(parse_file_id(&id.to_string()).unwrap(), id);
+
+        for bad in [
+            "",
+            ":",
+            "1",
+            "1:",
+            ":1",
+            "1:2:3:4:5",
+            "x:1",
+            "1 :2",
+            "+1:2",
+            "1:+2",
+            "01:2",
+            "1:02",
+            "1:00",
+            "-1:2",
+        ] {
+            let err = parse_file_id(bad).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+        #[cfg(unix)]
+        {
+            assert!(parse_file_id(&format!("1:{}0", u64::MAX)).is_err());
+            assert!(parse_file_id("1:2:0").is_err());
+            let id = parse_file_id("1:2:3").unwrap();
+            assert_eq!(id.to_string(), "1:2:3");
+            assert_ne!(id, parse_file_id("1:2").unwrap());
+            for good in ["1:2:0:5", "1:2:3:5"] {
+                let id = parse_file_id(good).unwrap();
+                assert_eq!(id.to_string(), good);
+                assert_eq!(FileId::from_bytes(&id.to_bytes()).unwrap(), id);
+            }
+            let max = format!("1:2:0:{}", (1u64 << 56) - 1);
+            assert!(parse_file_id(&max).is_ok());
+            for bad in [
+                "1:2:3:0",
+                "1:2:0:72057594037927936",
+                "1:2:3:x",
+                "1:2:03",
+                "1:2:+3",
+                "1:2:3:05",
+            ] {
+                assert!(parse_file_id(bad).is_err(), "{}", bad);
+            }
+        }
+        #[cfg(windows)]
+        assert!(
+            parse_file_id(&format!("{}:{}", "0".repeat(16), "g".repeat(32)))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        use super::FileId;
+
+        let tdir = tmpdir();
+        File::create(tdir.path().join("a")).unwrap();
+        let id = FileId::from_path(tdir.path().join("a")).unwrap();
+
+        let bytes = id.to_bytes();
+        assert_eq!(bytes.len(), FileId::BYTES_LEN);
+        assert_eq!(FileId::from_bytes(&bytes).unwrap(), id);
+
+        let err = FileId::from_bytes(&bytes[..31]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let mut long = bytes.to_vec();
+        long.push(0);
+        assert!(FileId::from_bytes(&long).is_err());
+        let mut foreign = bytes;
+        foreign[0] = 0;
+        assert!(FileId::from_bytes(&foreign).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        use super::FileId;
+
+        let tdir = tmpdir();
+        let file = File::create(tdir.path().join("a")).unwrap();
+        let id = FileId::from_file_like(&file).unwrap();
+
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(serde_json::from_str::<FileId>(&json).unwrap(), id);
+
+        let mut foreign = id.to_packed();
+        foreign[0] = if cfg!(windows) { 1 } else { 2 };
+        let json = serde_json::to_string(&foreign.to_vec()).unwrap();
+        assert!(serde_json::from_str::<FileId>(&json).is_err());
+        assert!(serde_json::from_str::<FileId>("[1, 2, 3]").is_err());
+    }
+
+    #[test]
+    fn volume_range() {
+        use std::collections::BTreeMap;
+
+        use super::FileId;
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        let mut index = BTreeMap::new();
+        for name in ["a", "b", "c"] {
+            let file = File::create(dir.join(name)).unwrap();
+            index.insert(FileId::from_file_like(&file).unwrap(), name);
+        }
+        let volume = index.keys().next().unwrap().volume();
+        assert!(index.keys().all(|id| id.volume() == volume));
+        let range = |volume| FileId::volume_range(volume).unwrap();
+        assert!(range(volume).start() < range(volume).end());
+        assert_eq!(index.range(range(volume)).count(), 3);
+        assert_eq!(index.range(range(volume + 1)).count(), 0);
+        if let Some(prev) = volume.checked_sub(1) {
+            assert_eq!(index.range(range(prev)).count(), 0);
+        }
+    }
+
+    #[test]
+    fn handle_set_collect() {
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        File::create(dir.join("b")).unwrap();
+        fs::hard_link(dir.join("a"), dir.join("alink")).unwrap();
+        let paths = ["a", "b", "alink"].map(|name| dir.join(name));
+
+        let set: HandleSet<File> = paths
+            .iter()
+            .map(super::Handle::from_path)
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(set.len(), 2);
+        assert_eq!(HandleSet::from_paths(&paths).unwrap().len(), 2);
+        assert!(HandleSet::from_paths([dir.join("missing")]).is_err());
+
+        let map = FileIdMap::from_paths(paths.iter().zip(1..)).unwrap();
+        assert_eq!(map.len(), 2);
+        let a = super::Handle::from_path(dir.join("a")).unwrap();
+        assert_eq!(map.get(&super::Handle::id(&a)), Some(&3));
+    }
+
+    #[test]
+    fn bulk_spawners() {
+        use std::num::NonZeroUsize;
+
+        use super::{
+            Sequential, Spawner, ThreadSpawner, ids_from_paths,
+            ids_from_paths_no_follow,
+        };
+
+        fn check<S: Spawner>(spawner: S, dir: &std::path::Path) {
+            let paths: Vec<_> =
+                ["a", "b", "alink", "missing"].map(|n| dir.join(n)).into();
+            let ids = ids_from_paths(&spawner, &paths);
+            assert_eq!(ids.len(), 4);
+            assert_eq!(ids[0].as_ref().unwrap(), ids[2].as_ref().unwrap());
+            assert_ne!(ids[0].as_ref().unwrap(), ids[1].as_ref().unwrap());
+            assert!(ids[3].is_err());
+            let ids = ids_from_paths_no_follow(&spawner, &paths);
+            assert_eq!(ids[0].as_ref().unwrap(), ids[2].as_ref().unwrap());
+            assert!(ids[3].is_err());
+
+            let set =
+                HandleSet::from_paths_with(&spawner, &paths[..3]).unwrap();
+            assert_eq!(set.len(), 2);
+            assert!(HandleSet::from_paths_with(&spawner, &paths).is_err());
+        }
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+        File::create(dir.join("a")).unwrap();
+        File::create(dir.join("b")).unwrap();
+        fs::hard_link(dir.join("a"), dir.join("alink")).unwrap();
+
+        check(Sequential, dir);
+        check(ThreadSpawner::default(), dir);
+        check(ThreadSpawner::new(NonZeroUsize::new(3).unwrap()), dir);
+        #[cfg(feature = "rayon")]
+        check(super::RayonSpawner, dir);
+    }
+
+    #[test]
+    fn handle_set_preserving_order() {
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        let names = ["d", "b", "a", "c"];
+        for name in names {
+            File::create(dir.join(name)).unwrap();
+        }
+        fs::hard_link(dir.join("a"), dir.join("alink")).unwrap();
+        let mut set = HandleSet::preserving_order();
+        for name in ["d", "b", "alink", "a", "c", "b"] {
+            set.insert(super::Handle::from_path(dir.join(name)).unwrap());
+        }
+        let expected: Vec<_> = names
+            .iter()
+            .map(|name| super::FileId::from_path(dir.join(name)).unwrap())
+            .collect();
+        let ids: Vec<_> = set.iter().map(super::Handle::id).collect();
+        assert_eq!(ids, expected);
+
+        set.remove(&expected[1]);
+        let owned: Vec<_> =
+            set.into_iter().map(|handle| super::Handle::id(&handle)).collect();
+        assert_eq!(
+            owned,
+            [expected[0].clone(), expected[2].clone(), expected[3].clone()]
+        );
+    }
+
+    #[test]
+    fn evict_volume() {
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        File::create(dir.join("b")).unwrap();
+        let paths = ["a", "b"].map(|name| dir.join(name));
+        let mut set = HandleSet::from_paths(&paths).unwrap();
+        let mut map = FileIdMap::from_paths(paths.iter().zip(1..)).unwrap();
+        let volume =
+            set.iter().next().map(super::Handle::id).unwrap().volume();
+
+        assert_eq!(set.evict_volume(volume.wrapping_add(1)), 0);
+        assert_eq!(map.evict_volume(volume.wrapping_add(1)), 0);
+        assert_eq!(set.evict_volume(volume), 2);
+        assert_eq!(map.evict_volume(volume), 2);
+        assert!(set.is_empty() && map.is_empty());
+    }
+
+    #[test]
+    fn usage_meter() {
+        use super::{FileId, Usage, UsageMeter};
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        File::create(dir.join("b")).unwrap();
+        let a = FileId::from_path(dir.join("a")).unwrap();
+        let b = FileId::from_path(dir.join("b")).unwrap();
+
+        let mut meter = UsageMeter::new();
+        meter.record_open(&a);
+        meter.record_read(&a, 10);
+        fs::rename(dir.join("a"), dir.join("renamed")).unwrap();
+        let renamed = FileId::from_path(dir.join("renamed")).unwrap();
+        meter.record_open(&renamed);
+        meter.record_read(&renamed, u64::MAX);
+        meter.record_open(&b);
+
+        assert_eq!(meter.get(&a), Usage { opens: 2, bytes_read: u64::MAX });
+        assert_eq!(meter.snapshot().len(), 2);
+        let snapshot = meter.take_snapshot();
+        assert!(snapshot.windows(2).all(|w| w[0].0 < w[1].0));
+        assert!(meter.is_empty());
+        assert_eq!(meter.get(&b), Usage::default());
+    }
+
+    #[test]
+    fn id_set_membership() {
+        use super::{FileId, IdSet};
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+        let mut ids = Vec::new();
+        for name in ["a", "b", "c", "d"] {
+            File::create(dir.join(name)).unwrap();
+            ids.push(FileId::from_path(dir.join(name)).unwrap());
+        }
+        fs::hard_link(dir.join("a"), dir.join("alink")).unwrap();
+
+        let set: IdSet = ids[..3].iter().chain(&ids[..1]).collect();
+        assert_eq!(set.len(), 3);
+        assert!(!set.is_empty());
+        assert!(FileId::from_path(dir.join("alink")).unwrap().is_any_of(&set));
+        assert!(ids[2].is_any_of(&set));
+        assert!(!ids[3].is_any_of(&set));
+        assert!(!FileId::from_path(dir).unwrap().is_any_of(&set));
+
+        let mut sorted = ids[..3].to_vec();
+        sorted.sort();
+        assert_eq!(set.iter().collect::<Vec<_>>(), sorted);
+        assert!(IdSet::default().is_empty());
+        assert!(!ids[0].is_any_of(&IdSet::default()));
+    }
+
+    #[test]
+    fn anchored_paths() {
+        use super::{Anchor, FileId, Handle};
+
+        let tdir = tmpdir();
+        let root = tdir.path().join("root");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        File::create(root.join("sub/a")).unwrap();
+        File::create(tdir.path().join("outside")).unwrap();
+
+        let anchor = Anchor::new(&root).unwrap();
+        anchor.verify_root().unwrap();
+        assert_eq!(
+            anchor.resolve("./sub/../sub/a").unwrap(),
+            root.join("sub/a")
+        );
+        let a = anchor.open("sub/a").unwrap();
+        assert_eq!(
+            Handle::id(&a),
+            FileId::from_path(root.join("sub/a")).unwrap()
+        );
+        assert_eq!(Handle::id(&anchor.open(".").unwrap()), anchor.root().id());
+        for escape in ["../outside", "sub/../../outside", "/outside"] {
+            let err = anchor.open(escape).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{}", escape);
+        }
+
+        #[cfg(unix)]
+        {
+            soft_link_file(tdir.path().join("outside"), root.join("link"))
+                .unwrap();
+            let err = anchor.open("link").unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+            soft_link_file("../../outside", root.join("sub/up")).unwrap();
+            let err = anchor.open("sub/up").unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+            soft_link_file("sub/a", root.join("inner")).unwrap();
+            assert_eq!(
+                Handle::id(&anchor.open("inner").unwrap()),
+                Handle::id(&a)
+            );
+        }
+
+        fs::rename(&root, tdir.path().join("moved")).unwrap();
+        fs::create_dir(&root).unwrap();
+        let err = anchor.verify_root().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(anchor.open("sub/a").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn walk_beneath_follows_links_inside() {
+        use std::os::unix::fs::symlink;
+        use std::path::Path;
+
+        use super::{AsRawFilelike, FileId, Handle};
+
+        let tdir = tmpdir();
+        let root = tdir.path().join("root");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        File::create(root.join("sub/a")).unwrap();
+        File::create(tdir.path().join("outside")).unwrap();
+        symlink("sub", root.join("dir")).unwrap();
+        symlink("../sub/a", root.join("sub/back")).unwrap();
+        symlink("../outside", root.join("up")).unwrap();
+        symlink(root.join("sub/a"), root.join("absolute")).unwrap();
+        symlink("looping", root.join("looping")).unwrap();
+
+        let dir = Handle::from_path(&root).unwrap();
+        let walk = |path: &str| {
+            super::imp::walk_beneath(dir.as_raw_filelike(), Path::new(path))
+        };
+        let a = FileId::from_path(root.join("sub/a")).unwrap();
+        for path in ["sub/a", "dir/a", "sub/back", "dir/back", "dir/../sub/a"]
+        {
+            let file = walk(path).unwrap().expect(path);
+            assert_eq!(FileId::from_file_like(&file).unwrap(), a, "{}", path);
+        }
+        let file = walk("sub/..").unwrap().unwrap();
+        assert_eq!(FileId::from_file_like(&file).unwrap(), Handle::id(&dir));
+        for path in ["up", "absolute", "..", "sub/../.."] {
+            assert!(walk(path).unwrap().is_none(), "{}", path);
+        }
+        assert!(walk("looping").is_err());
+        assert!(walk("sub/missing").is_err());
+    }
+
+    #[test]
+    fn same_file_no_follow() {
+        use super::is_same_file_no_follow;
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+        File::create(dir.join("a")).unwrap();
+        fs::create_dir(dir.join("d")).unwrap();
+        soft_link_file(dir.join("a"), dir.join("alink")).unwrap();
+        soft_link_dir(dir.join("d"), dir.join("dlink")).unwrap();
+
+        assert!(is_same_file_no_follow(dir.join("a"), dir.join("a")).unwrap());
+        assert!(
+            is_same_file_no_follow(dir.join("alink"), dir.join("./alink"))
+                .unwrap()
+        );
+        assert!(
+            !is_same_file_no_follow(dir.join("a"), dir.join("alink")).unwrap()
+        );
+        assert!(
+            !is_same_file_no_follow(dir.join("d"), dir.join("dlink")).unwrap()
+        );
+        assert!(is_same_file_path(dir.join("d"), dir.join("dlink")).unwrap());
+        assert!(is_same_file_no_follow(dir.join("a"), dir.join("b")).is_err());
+    }
+
+    #[test]
+    fn same_file_at() {
+        use super::is_same_file_at;
+
+        let tdir = tmpdir();
+        let root = tdir.path().join("root");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        File::create(root.join("sub/a")).unwrap();
+        File::create(root.join("b")).unwrap();
+        fs::hard_link(root.join("b"), root.join("sub/blink")).unwrap();
+
+        let dir = super::Handle::from_path(&root).unwrap();
+        let dir = &*dir;
+        // The directory is used, not its path.
+        fs::rename(&root, tdir.path().join("moved")).unwrap();
+        assert!(is_same_file_at(dir, "sub/a", "./sub/../sub/a").unwrap());
+        assert!(is_same_file_at(dir, "b", "sub/blink").unwrap());
+        assert!(!is_same_file_at(dir, "b", "sub/a").unwrap());
+        assert!(is_same_file_at(dir, "sub", "sub").unwrap());
+        let err = is_same_file_at(dir, "b", "missing").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn small_handle_vec() {
+        use super::{Error, FileId, Handle, SmallHandleVec};
+
+        let tdir = tmpdir();
+        let mut dirs = vec![tdir.path().to_path_buf()];
+        for depth in 0..12 {
+            let dir = dirs[depth].join(format!("d{}", depth));
+            fs::create_dir(&dir).unwrap();
+            dirs.push(dir);
+        }
+
+        let mut stack = SmallHandleVec::new();
+        for dir in &dirs {
+            stack.push(Handle::from_path(dir).unwrap()).unwrap();
+        }
+        assert_eq!(stack.len(), dirs.len());
+        for (depth, dir) in [(0, &dirs[0]), (10, &dirs[10])] {
+            let err = stack.push(Handle::from_path(dir).unwrap()).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert!(matches!(
+                Error::of(&err),
+                Some(Error::FilesystemLoop { depth: d, .. }) if *d == depth
+            ));
+        }
+        assert_eq!(stack.len(), dirs.len());
+
+        let outer = Handle::id(stack.iter().next().unwrap());
+        assert!(stack.contains(&outer));
+        assert!(stack.pop_if(&outer).is_none());
+        while let Some(last) = stack.last().map(Handle::id) {
+            let popped = stack.pop_if(&last).unwrap();
+            assert_eq!(Handle::id(&popped), last);
+            assert!(!stack.contains(&last));
+            stack.push(popped).unwrap();
+            stack.pop();
+        }
+        assert!(stack.is_empty());
+        stack.push(Handle::from_path(&dirs[3]).unwrap()).unwrap();
+        assert!(stack.contains(&FileId::from_path(&dirs[3]).unwrap()));
+        assert!(!stack.contains(&outer));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn mock_fs() {
+        use super::FileId;
+        use super::mock::MockFs;
+
+        let mut fs = MockFs::new();
+        let config = fs.create("/etc/config");
+        assert_eq!(config, FileId::fabricated(1, 1));
+        assert_eq!(config.volume(), 1);
+        fs.hard_link("/etc/config", "/home/./config").unwrap();
+        let link = fs.symlink("/home/config", "/tmp/link");
+        fs.symlink("/tmp/loop", "/tmp/loop");
+        assert_eq!(fs.id("/tmp/link").unwrap(), config);
+        assert_eq!(fs.id_no_follow("/tmp/link").unwrap(), link);
+        assert!(fs.is_same_file("//etc/config", "/tmp/link").unwrap());
+        assert_eq!(
+            fs.id("/tmp/loop").unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        fs.rename("/etc/config", "/etc/config.old").unwrap();
+        assert!(!fs.exists("/etc/config"));
+        assert_eq!(fs.id("/etc/config.old").unwrap(), config);
+        let other = fs.create("/etc/config");
+        assert!(!fs.is_same_file("/etc/config", "/tmp/link").unwrap());
+        assert_eq!(
+            fs.id("/missing").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        fs.reuse_identities(true);
+        fs.remove("/etc/config").unwrap();
+        assert_eq!(fs.create("/etc/new"), other);
+        fs.remove("/etc/config.old").unwrap();
+        assert_ne!(fs.create("/etc/newer"), config);
+        assert_ne!(MockFs::on_volume(2).create("/etc/config"), config);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn identity_sources() {
+        use super::mock::MockFs;
+        use super::{
+            Deduplicator, FileIdMap, FileIdentitySource, IdSet, OsIdentity,
+            UniqueFiles,
+        };
+
+        let mut fs = MockFs::new();
+        let a = fs.create("/a");
+        fs.hard_link("/a", "/alink").unwrap();
+        fs.create("/b");
+        let mut seen = Deduplicator::with_fd_budget(8);
+        assert!(seen.first_seen_via(&fs, "/a").unwrap());
+        assert!(!seen.first_seen_via(&fs, "/alink").unwrap());
+        assert!(seen.first_seen_via(&fs, "/b").unwrap());
+        assert!(seen.first_seen_via(&fs, "/missing").is_err());
+        assert_eq!((seen.len(), seen.pinned()), (2, 0));
+
+        let mut names = FileIdMap::new();
+        for path in ["/a", "/b", "/alink"] {
+            names.entry_for_path_via(&fs, path).unwrap().or_insert(path);
+        }
+        assert_eq!(names.get(&a), Some(&"/a"));
+        assert_eq!(names.len(), 2);
+
+        let set = IdSet::from_paths_via(&fs, ["/a", "/alink", "/b"]).unwrap();
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(&a));
+        assert!(IdSet::from_paths_via(&fs, ["/a", "/missing"]).is_err());
+
+        let unique: Vec<_> = UniqueFiles::via(&fs, ["/a", "/b", "/alink"])
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(unique, ["/a", "/b"]);
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+        let file = File::create(dir.join("a")).unwrap();
+        let id = super::FileId::from_path(dir.join("a")).unwrap();
+        assert_eq!(OsIdentity.id_from_path(&dir.join("a")).unwrap(), id);
+        assert_eq!(OsIdentity.id_from_file_like(&file).unwrap(), id);
+        assert_eq!(
+            IdSet::from_paths([dir.join("a")]).unwrap().iter().next(),
+            Some(id)
+        );
+        assert_eq!(
+            fs.id_from_file_like(&file).unwrap_err().kind(),
+            io::ErrorKind::Unsupported
+        );
+    }
+
+    #[test]
+    fn current_exe_identity() {
+        use super::{FileId, Handle};
+
+        let exe = Handle::current_exe().unwrap();
+        assert_eq!(Handle::id(&exe), FileId::current_exe().unwrap());
+        let path = std::env::current_exe().unwrap();
+        assert_eq!(Handle::id(&exe), FileId::from_path(path).unwrap());
+        assert_ne!(
+            Handle::id(&exe),
+            FileId::from_path(tmpdir().path()).unwrap()
+        );
+    }
+
+    #[test]
+    fn ancestor_by_identity() {
+        use super::is_ancestor_of;
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        fs::create_dir_all(dir.join("root/sub/deep")).unwrap();
+        fs::create_dir(dir.join("outside")).unwrap();
+        File::create(dir.join("root/sub/file")).unwrap();
+        File::create(dir.join("outside/secret")).unwrap();
+        soft_link_dir(dir.join("outside"), dir.join("root/escape")).unwrap();
+        let root = dir.join("root");
+        assert!(is_ancestor_of(&root, &root).unwrap());
+        assert!(is_ancestor_of(&root, root.join("sub/deep")).unwrap());
+        assert!(is_ancestor_of(&root, root.join("sub/file")).unwrap());
+        assert!(is_ancestor_of(&root, root.join("sub/deep/../file")).unwrap());
+        assert!(!is_ancestor_of(root.join("sub"), &root).unwrap());
+        assert!(!is_ancestor_of(&root, dir.join("outside")).unwrap());
+        #[cfg(unix)]
+        assert!(!is_ancestor_of(&root, root.join("escape/secret")).unwrap());
+        soft_link_file(dir.join("outside/secret"), root.join("passwd"))
+            .unwrap();
+        assert!(!is_ancestor_of(&root, root.join("passwd")).unwrap());
+        soft_link_file(root.join("sub/file"), dir.join("outside/inside"))
+            .unwrap();
+        assert!(is_ancestor_of(&root, dir.join("outside/inside")).unwrap());
+        assert!(is_ancestor_of(&root, root.join("missing")).is_err());
+    }
+
+    #[test]
+    fn ancestor_stack_detects_loops() {
+        use super::{AncestorStack, Handle};
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        soft_link_dir(dir.join("a"), dir.join("a/b/up")).unwrap();
+        let mut ancestors = AncestorStack::new();
+        ancestors.push(Handle::from_path(dir.join("a")).unwrap()).unwrap();
+        let b = Handle::from_path(dir.join("a/b")).unwrap();
+        assert!(!ancestors.contains_handle(&b));
+        ancestors.push(b).unwrap();
+        assert!(ancestors.contains_path(dir.join("a/b/up")).unwrap());
+        ancestors.pop();
+        ancestors.pop();
+        assert!(!ancestors.contains_path(dir.join("a/b/up")).unwrap());
+        assert!(ancestors.contains_path(dir.join("missing")).is_err());
+    }
+
+    #[test]
+    fn deletion_guard() {
+        use super::{DeletionGuard, Error};
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+        File::create(dir.join("config")).unwrap();
+        File::create(dir.join("other")).unwrap();
+        fs::hard_link(dir.join("config"), dir.join("alias")).unwrap();
+
+        let guard = DeletionGuard::new()
+            .protecting([dir.join("config"), dir.join("missing")]);
+        guard.check(dir.join("other")).unwrap();
+        guard.check(dir.join("missing")).unwrap();
+        let err = guard.check(dir.join("alias")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        match Error::of(&err) {
+            Some(Error::Protected { path, .. }) => {
+                assert_eq!(path, &dir.join("alias"));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert!(guard.check(std::env::current_exe().unwrap()).is_err());
+        if cfg!(unix) {
+            assert!(guard.check("/").is_err());
+        }
+
+        let only = DeletionGuard::with_paths([dir.join("other")]);
+        assert_eq!(only.protected().len(), 1);
+        only.check(dir.join("config")).unwrap();
+        assert!(only.check(dir.join("other")).is_err());
+    }
+
+    #[test]
+    fn verified_reader() {
+        use std::io::Read;
+
+        use super::{Error, Handle, VerifiedReader};
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+        fs::write(dir.join("a"), b"0123456789").unwrap();
+        fs::write(dir.join("b"), b"abcdefghij").unwrap();
+
+        let mut reader =
+            VerifiedReader::new(Handle::from_path(dir.join("a")).unwrap())
+                .check_every_bytes(4)
+                .watching(dir.join("a"));
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"0123");
+        fs::rename(dir.join("b"), dir.join("a")).unwrap();
+        let err = reader.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        match Error::of(&err) {
+            Some(Error::Replaced { path, .. }) => {
+                assert_eq!(path.as_deref(), Some(&*dir.join("a")));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert!(reader.read(&mut buf).is_err());
+
+        let writer =
+            fs::OpenOptions::new().write(true).open(dir.join("a")).unwrap();
+        let mut reader =
+            VerifiedReader::new(Handle::from_path(dir.join("a")).unwrap())
+                .check_every_bytes(0);
+        reader.read_exact(&mut buf[..1]).unwrap();
+        writer.set_len(1).unwrap();
+        match Error::of(&reader.read(&mut buf).unwrap_err()) {
+            Some(Error::Truncated { before: 10, after: 1, .. }) => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        // Shrinking the file and growing it back is still a write.
+        let mut reader =
+            VerifiedReader::new(Handle::from_path(dir.join("a")).unwrap())
+                .check_every_bytes(0);
+        reader.read_exact(&mut buf[..1]).unwrap();
+        let modified = fs::metadata(dir.join("a")).unwrap().modified();
+        writer.set_len(0).unwrap();
+        writer.set_len(1).unwrap();
+        // Make the write visible even with coarse timestamps.
+        let later = modified.unwrap() + std::time::Duration::from_secs(1);
+        writer.set_modified(later).unwrap();
+        match Error::of(&reader.read(&mut buf).unwrap_err()) {
+            Some(Error::Modified { .. }) => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn timeouts() {
+        use std::time::{Duration, Instant};
+
+        use super::{
+            Error, FileId, HandleOptions, Sequential, ids_from_paths_until,
+            is_same_file_path_timeout,
+        };
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+        File::create(dir.join("a")).unwrap();
+
+        let timeout = Duration::from_secs(60);
+        assert_eq!(
+            FileId::from_path_timeout(dir.join("a"), timeout).unwrap(),
+            FileId::from_path(dir.join("a")).unwrap()
+        );
+        assert!(
+            is_same_file_path_timeout(dir.join("a"), dir.join("a"), timeout)
+                .unwrap()
+        );
+        let handle = HandleOptions::new()
+            .timeout(Some(timeout))
+            .open(dir.join("a"))
+            .unwrap();
+        assert_eq!(
+            super::Handle::id(&handle),
+            FileId::from_path(dir.join("a")).unwrap()
+        );
+        let err = HandleOptions::new()
+            .timeout(Some(timeout))
+            .open(dir.join("missing"))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        let ids = ids_from_paths_until(
+            &Sequential,
+            &[dir.join("a"), dir.join("missing")],
+            Instant::now() + timeout,
+        );
+        assert!(ids[0].is_ok());
+        assert_eq!(
+            ids[1].as_ref().unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        // Opening a FIFO for reading blocks until a writer shows up.
+        #[cfg(unix)]
+        {
+            use std::ffi::CString;
+            use std::os::unix::ffi::OsStrExt;
+
+            let fifo = dir.join("fifo");
+            let c_path = CString::new(fifo.as_os_str().as_bytes()).unwrap();
+            assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+            let err = HandleOptions::new()
+                .timeout(Some(Duration::from_millis(50)))
+                .open(&fifo)
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+            assert!(matches!(Error::of(&err), Some(Error::TimedOut { .. })));
+            // Unblock the worker thread.
+            fs::OpenOptions::new().write(true).open(&fifo).unwrap();
+        }
+    }
+
+    #[test]
+    fn gc_persisted() {
+        use std::collections::HashMap;
+
+        use super::{FileId, GcReason, gc_persisted_ids};
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+        File::create(dir.join("a")).unwrap();
+        File::create(dir.join("b")).unwrap();
+        File::create(dir.join("c")).unwrap();
+        let a = FileId::from_path(dir.join("a")).unwrap();
+        let b = FileId::from_path(dir.join("b")).unwrap();
+        let c = FileId::from_path(dir.join("c")).unwrap();
+        let d = FileId::from_path(dir).unwrap();
+
+        let mut index = HashMap::new();
+        index.insert(a.clone(), dir.join("a"));
+        index.insert(b.clone(), dir.join("c"));
+        index.insert(c.clone(), dir.join("missing"));
+        let report = gc_persisted_ids(
+            [a.clone(), b.clone(), c.clone(), d.clone()],
+            |id| index.get(id),
+        );
+
+        assert_eq!(report.keep.len(), 1);
+        assert_eq!(report.keep[0].id, a);
+        assert!(matches!(report.keep[0].reason, GcReason::Matches));
+        assert!(report.uncertain.is_empty());
+        let dropped: Vec<_> = report.drop.iter().map(|e| &e.id).collect();
+        assert_eq!(dropped, [&b, &c, &d]);
+        assert!(matches!(
+            &report.drop[0].reason,
+            GcReason::Replaced { actual } if *actual == c
+        ));
+        assert!(matches!(report.drop[1].reason, GcReason::Missing));
+        assert!(matches!(report.drop[2].reason, GcReason::Unresolved));
+        assert_eq!(report.drop[2].path, None);
+    }
+
+    #[test]
+    fn scoped_maps_are_isolated() {
+        use super::{FileId, ScopedFileIdMap};
+
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        let a = FileId::from_path(dir.join("a")).unwrap();
+        let mut cache = ScopedFileIdMap::new();
+        cache.scope_mut("alice").insert(a.clone(), "alice's");
+        cache.scope_mut("bob");
+
+        assert_eq!(cache.scope(&"alice").unwrap().get(&a), Some(&"alice's"));
+        assert_eq!(cache.scope(&"bob").unwrap().get(&a), None);
+        // A scope exists, empty, once it has been asked for.
+        assert!(cache.scope(&"bob").unwrap().is_empty());
+        assert_eq!(cache.len(), 2);
+        assert!(cache.scope(&"carol").is_none());
+
+        assert_eq!(cache.evict_volume(a.volume()), 1);
+        assert!(cache.is_empty());
+        cache.scope_mut("bob").insert(a.clone(), "bob's");
+        assert_eq!(cache.remove_scope(&"bob").unwrap().len(), 1);
+        assert!(cache.is_empty());
+    }
+
+    #[cfg(feature = "debug-registry")]
+    #[test]
+    fn registry_tracks_handles() {
+        let tdir = tmpdir();
+        let dir = tdir.path();
+
+        File::create(dir.join("a")).unwrap();
+        let handle = super::Handle::from_path(dir.join("a")).unwrap();
+        let id = super::Handle::id(&handle);
+        let is_live =
+            || super::registry::live_handles().iter().any(|h| h.id == id);
+        assert!(is_live());
+        drop(handle);
+        assert!(!is_live());
+    }
+
     #[test]
     fn test_send() {
         fn assert_send<T: Send>() {}
         assert_send::<super::Handle<File>>();
+        assert_send::<super::SharedHandle<File>>();
     }
 
     #[test]
     fn test_sync() {
         fn assert_sync<T: Sync>() {}
         assert_sync::<super::Handle<File>>();
+        assert_sync::<super::SharedHandle<File>>();
     }
 }
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+
+use crate::FileId;
+
+/// The usage recorded for one file by a [`UsageMeter`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Usage {
+    /// The number of times the file was opened.
+    pub opens: u64,
+    /// The number of bytes read from the file.
+    pub bytes_read: u64,
+}
+
+/// Counters of file accesses, keyed by file identity.
+///
+/// Keying usage by identity rather than by path means that it follows a file
+/// across renames, and that a file reached through several links is only
+/// counted once. Counters saturate at `u64::MAX` instead of overflowing.
+///
+/// As with [`FileId`] itself, the keys are only guaranteed to be meaningful
+/// while the files they were taken from are kept open by the caller.
+#[derive(Debug, Clone, Default)]
+pub struct UsageMeter {
+    usage: HashMap<FileId, Usage>,
+}
+
+impl UsageMeter {
+    /// Create a meter with no recorded usage.
+    pub fn new() -> UsageMeter {
+        UsageMeter { usage: HashMap::new() }
+    }
+
+    fn entry(&mut self, id: &FileId) -> &mut Usage {
+        self.usage.entry(id.clone()).or_default()
+    }
+
+    /// Record that the file was opened.
+    pub fn record_open(&mut self, id: &FileId) {
+        let usage = self.entry(id);
+        usage.opens = usage.opens.saturating_add(1);
+    }
+
+    /// Record that `bytes` bytes were read from the file.
+    pub fn record_read(&mut self, id: &FileId, bytes: u64) {
+        let usage = self.entry(id);
+        usage.bytes_read = usage.bytes_read.saturating_add(bytes);
+    }
+
+    /// The usage recorded for the file, which is zero if none was.
+    pub fn get(&self, id: &FileId) -> Usage {
+        self.usage.get(id).copied().unwrap_or_default()
+    }
+
+    /// The number of files with recorded usage.
+    pub fn len(&self) -> usize {
+        self.usage.len()
+    }
+
+    /// Returns true if no usage was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.usage.is_empty()
+    }
+
+    /// Export the recorded usage, ordered by identity.
+    pub fn snapshot(&self) -> Vec<(FileId, Usage)> {
+        let mut snapshot: Vec<_> = self
+            .usage
+            .iter()
+            .map(|(id, usage)| (id.clone(), *usage))
+            .collect();
+        snapshot.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        snapshot
+    }
+
+    /// Export the recorded usage, ordered by identity, and reset the meter.
+    ///
+    /// This is meant for periodic reporting, where each report covers the
+    /// accesses since the previous one.
+    pub fn take_snapshot(&mut self) -> Vec<(FileId, Usage)> {
+        let mut snapshot: Vec<_> = self.usage.drain().collect();
+        snapshot.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        snapshot
+    }
+
+    /// Remove the usage of every file on the given volume, returning the
+    /// number of files removed.
+    pub fn evict_volume(&mut self, volume: u64) -> usize {
+        let before = self.usage.len();
+        self.usage.retain(|id, _| id.volume() != volume);
+        before - self.usage.len()
+    }
+}
@@ -0,0 +1,289 @@
+//! A virtual filesystem with made up identities, for deterministic tests.
+//!
+//! This module is only available with the `test-util` feature, on the
+//! platforms that have identities. Code that
+//! decides whether paths name the same file can be unit tested against a
+//! [`MockFs`], which assigns [fabricated](crate::FileId::fabricated)
+//! identities to virtual paths, instead of against temporary directories
+//! on the real filesystem. Hard links, symbolic links, renames and the
+//! reuse of the identity of a deleted file can all be set up in a few
+//! lines, and behave the same on every one of these platforms.
+//!
+//! Paths are only names: they are compared component by component, ignoring
+//! redundant separators and `.` components, but `..` components are not
+//! resolved and nothing is read from the real filesystem.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use cross_file_id::mock::MockFs;
+//!
+//! let mut fs = MockFs::new();
+//! fs.create("/etc/config");
+//! fs.hard_link("/etc/config", "/home/config")?;
+//! fs.symlink("/etc/config", "/tmp/link");
+//! assert!(fs.is_same_file("/home/config", "/tmp/link")?);
+//! # Ok::<_, std::io::Error>(())
+//! ```
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::FileId;
+
+/// How many symbolic links are followed before a path is considered to be
+/// part of a loop, as `ELOOP` on Linux.
+const MAX_LINKS: usize = 40;
+
+#[derive(Debug, Clone)]
+enum Entry {
+    File(FileId),
+    Symlink { target: PathBuf, id: FileId },
+}
+
+/// A virtual filesystem that maps paths to fabricated identities.
+///
+/// Every file created gets a new identity on the volume of the filesystem.
+/// Identities are not reused unless [`reuse_identities`] is enabled, in
+/// which case a file created after another one was removed gets its
+/// identity, which reproduces the stale identity bugs that keeping files
+/// open prevents.
+///
+/// [`reuse_identities`]: MockFs::reuse_identities
+#[derive(Debug, Clone)]
+pub struct MockFs {
+    entries: HashMap<PathBuf, Entry>,
+    volume: u64,
+    next_index: u64,
+    reuse: bool,
+    // The identities of removed files, most recent last.
+    freed: Vec<FileId>,
+}
+
+impl MockFs {
+    /// Create an empty filesystem on volume 1.
+    pub fn new() -> MockFs {
+        MockFs::on_volume(1)
+    }
+
+    /// Create an empty filesystem whose identities are on the given volume.
+    ///
+    /// Filesystems on different volumes never share identities, which can
+    /// stand for separate mounts.
+    pub fn on_volume(volume: u64) -> MockFs {
+        MockFs {
+            entries: HashMap::new(),
+            volume,
+            next_index: 1,
+            reuse: false,
+            freed: Vec::new(),
+        }
+    }
+
+    /// Whether a new file gets the identity of the last file removed whose
+    /// identity was not reused yet. Defaults to false.
+    pub fn reuse_identities(&mut self, reuse: bool) -> &mut MockFs {
+        self.reuse = reuse;
+        self
+    }
+
+    fn new_id(&mut self) -> FileId {
+        if self.reuse
+            && let Some(id) = self.freed.pop()
+        {
+            return id;
+        }
+        let id = FileId::fabricated(self.volume, self.next_index);
+        self.next_index += 1;
+        id
+    }
+
+    /// Create a new file at `path`, replacing whatever was there, and return
+    /// its identity.
+    pub fn create<P: AsRef<Path>>(&mut self, path: P) -> FileId {
+        let id = self.new_id();
+        self.replace(normalize(path.as_ref()), Entry::File(id.clone()));
+        id
+    }
+
+    /// Make `link` another name for the file at `original`, replacing
+    /// whatever was at `link`. A final symbolic link in `original` is not
+    /// followed, as with `link` on Linux.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] of kind
+    /// [`io::ErrorKind::NotFound`] if nothing exists at `original`.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn hard_link<P, Q>(&mut self, original: P, link: Q) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let entry = self.lookup(original.as_ref())?.clone();
+        self.replace(normalize(link.as_ref()), entry);
+        Ok(())
+    }
+
+    /// Create a symbolic link at `link` that points at `target`, replacing
+    /// whatever was at `link`, and return the identity of the link itself.
+    ///
+    /// The target does not need to exist. It is resolved every time the
+    /// link is followed, from the root of the filesystem.
+    pub fn symlink<P, Q>(&mut self, target: P, link: Q) -> FileId
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let id = self.new_id();
+        let target = normalize(target.as_ref());
+        let entry = Entry::Symlink { target, id: id.clone() };
+        self.replace(normalize(link.as_ref()), entry);
+        id
+    }
+
+    /// Remove the name `path`, without following a final symbolic link.
+    ///
+    /// Once the last name of a file is removed, its identity may be reused
+    /// if [`reuse_identities`](MockFs::reuse_identities) is enabled.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] of kind
+    /// [`io::ErrorKind::NotFound`] if nothing exists at `path`.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn remove<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let path = normalize(path.as_ref());
+        match self.entries.remove(&path) {
+            Some(entry) => {
+                self.release(entry);
+                Ok(())
+            }
+            None => Err(not_found(&path)),
+        }
+    }
+
+    /// Move the name `from` to `to`, replacing whatever was at `to`. The
+    /// file keeps its identity.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] of kind
+    /// [`io::ErrorKind::NotFound`] if nothing exists at `from`.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn rename<P, Q>(&mut self, from: P, to: Q) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let from = normalize(from.as_ref());
+        let entry =
+            self.entries.remove(&from).ok_or_else(|| not_found(&from))?;
+        self.replace(normalize(to.as_ref()), entry);
+        Ok(())
+    }
+
+    /// The identity of the file at `path`, following symbolic links, as
+    /// [`FileId::from_path`] does.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] of kind
+    /// [`io::ErrorKind::NotFound`] if nothing exists at `path` or a symbolic
+    /// link points at nothing, or of kind [`io::ErrorKind::InvalidData`] if
+    /// following the links does not end.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn id<P: AsRef<Path>>(&self, path: P) -> io::Result<FileId> {
+        let mut path = normalize(path.as_ref());
+        for _ in 0..=MAX_LINKS {
+            match self.entries.get(&path) {
+                Some(Entry::File(id)) => return Ok(id.clone()),
+                Some(Entry::Symlink { target, .. }) => path = target.clone(),
+                None => return Err(not_found(&path)),
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("too many levels of symbolic links: {}", path.display()),
+        ))
+    }
+
+    /// The identity of the file at `path`, without following a final
+    /// symbolic link, as [`FileId::from_path_no_follow`] does.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] of kind
+    /// [`io::ErrorKind::NotFound`] if nothing exists at `path`.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn id_no_follow<P: AsRef<Path>>(&self, path: P) -> io::Result<FileId> {
+        Ok(match *self.lookup(path.as_ref())? {
+            Entry::File(ref id) | Entry::Symlink { ref id, .. } => id.clone(),
+        })
+    }
+
+    /// Returns true if the two paths name the same file, following symbolic
+    /// links, as [`is_same_file_path`](crate::is_same_file_path) does.
+    ///
+    /// # Errors
+    /// This method fails as [`id`](MockFs::id) does for either path.
+    pub fn is_same_file<P, Q>(&self, a: P, b: Q) -> io::Result<bool>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        Ok(self.id(a)? == self.id(b)?)
+    }
+
+    /// Returns true if something exists at `path`, without following a
+    /// final symbolic link.
+    pub fn exists<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.entries.contains_key(&normalize(path.as_ref()))
+    }
+
+    fn lookup(&self, path: &Path) -> io::Result<&Entry> {
+        let path = normalize(path);
+        self.entries.get(&path).ok_or_else(|| not_found(&path))
+    }
+
+    fn replace(&mut self, path: PathBuf, entry: Entry) {
+        if let Some(old) = self.entries.insert(path, entry) {
+            self.release(old);
+        }
+    }
+
+    /// Free the identity of an entry that was unlinked, if no other name
+    /// refers to it.
+    fn release(&mut self, entry: Entry) {
+        let id = match entry {
+            Entry::File(id) | Entry::Symlink { id, .. } => id,
+        };
+        let linked = self.entries.values().any(|entry| match *entry {
+            Entry::File(ref other) | Entry::Symlink { id: ref other, .. } => {
+                *other == id
+            }
+        });
+        if !linked {
+            self.freed.push(id);
+        }
+    }
+}
+
+impl Default for MockFs {
+    fn default() -> MockFs {
+        MockFs::new()
+    }
+}
+
+/// The path without redundant separators and `.` components.
+fn normalize(path: &Path) -> PathBuf {
+    path.components().filter(|c| *c != Component::CurDir).collect()
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("no such file in the mock filesystem: {}", path.display()),
+    )
+}
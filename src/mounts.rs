@@ -0,0 +1,285 @@
+//! Detection of mounted and unmounted volumes.
+//!
+//! This module is only available with the `mount-watch` feature. Identities
+//! are only meaningful while the volume they were taken from stays mounted:
+//! once a removable drive is unplugged, another volume may be given the same
+//! volume number, and the identities of its files may collide with stale
+//! entries. A [`MountWatcher`] reports volumes as they come and go, so that
+//! caches and indexes can evict or revalidate the affected entries.
+//!
+//! On Linux, the mounted volumes are read from `/proc/self/mountinfo`, and
+//! local mount points are statted for the device their files report. On
+//! Windows, every volume is enumerated and its root opened, which also finds
+//! volumes mounted on a folder. Other platforms are not supported.
+//!
+//! # Examples
+//!
+//! Evicting the entries of unmounted volumes from a shared map:
+//!
+//! ```rust,no_run
+//! use std::sync::{Arc, Mutex};
+//! use std::time::Duration;
+//!
+//! use cross_file_id::FileIdMap;
+//! use cross_file_id::mounts::{MountEvent, MountWatcher};
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let index: Arc<Mutex<FileIdMap<String>>> = Arc::default();
+//! let watched = Arc::clone(&index);
+//! let _watch = MountWatcher::new()?.spawn(
+//!     Duration::from_secs(1),
+//!     move |event| {
+//!         if let MountEvent::Unmounted(volume) = event {
+//!             watched.lock().unwrap().evict_volume(volume);
+//!         }
+//!     },
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashSet;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::imp;
+
+/// A change in the set of mounted volumes.
+///
+/// Volumes are identified as by [`FileId::volume`](crate::FileId::volume).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MountEvent {
+    /// A volume was mounted.
+    Mounted(u64),
+    /// A volume was unmounted.
+    Unmounted(u64),
+}
+
+impl MountEvent {
+    /// The volume the event is about.
+    pub fn volume(&self) -> u64 {
+        match *self {
+            MountEvent::Mounted(volume) | MountEvent::Unmounted(volume) => {
+                volume
+            }
+        }
+    }
+}
+
+/// Returns the volumes that are currently mounted.
+///
+/// # Errors
+/// This function will return an [`io::Error`] if the mounted volumes cannot
+/// be listed, or with [`io::ErrorKind::Unsupported`] on platforms other than
+/// Linux and Windows.
+///
+/// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+pub fn mounted_volumes() -> io::Result<HashSet<u64>> {
+    imp::mounted_volumes()
+}
+
+/// Watches for volumes being mounted and unmounted, by comparing snapshots of
+/// the mounted volumes.
+#[derive(Debug)]
+pub struct MountWatcher {
+    volumes: HashSet<u64>,
+}
+
+impl MountWatcher {
+    /// Create a watcher, taking a first snapshot of the mounted volumes.
+    ///
+    /// # Errors
+    /// This function fails as [`mounted_volumes`] does.
+    pub fn new() -> io::Result<MountWatcher> {
+        Ok(MountWatcher { volumes: mounted_volumes()? })
+    }
+
+    /// The volumes that were mounted at the last snapshot.
+    pub fn volumes(&self) -> &HashSet<u64> {
+        &self.volumes
+    }
+
+    /// Take a new snapshot, returning the changes since the previous one.
+    ///
+    /// A volume that was unmounted and mounted again between two snapshots
+    /// is not reported.
+    ///
+    /// # Errors
+    /// This method fails as [`mounted_volumes`] does, in which case the
+    /// previous snapshot is kept.
+    pub fn poll(&mut self) -> io::Result<Vec<MountEvent>> {
+        let volumes = mounted_volumes()?;
+        let events = self
+            .volumes
+            .difference(&volumes)
+            .map(|&volume| MountEvent::Unmounted(volume))
+            .chain(
+                volumes
+                    .difference(&self.volumes)
+                    .map(|&volume| MountEvent::Mounted(volume)),
+            )
+            .collect();
+        self.volumes = volumes;
+        Ok(events)
+    }
+
+    /// Poll for changes on a background thread, calling `callback` for
+    /// every event.
+    ///
+    /// Polls that fail are skipped. The thread stops when the returned
+    /// [`MountWatch`] is dropped, which the callback may do itself: the
+    /// callback is not called with any lock held, and dropping the watch
+    /// from its own thread does not wait for it.
+    pub fn spawn<C>(
+        mut self,
+        interval: Duration,
+        mut callback: C,
+    ) -> MountWatch
+    where
+        C: FnMut(MountEvent) + Send + 'static,
+    {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread = thread::spawn({
+            let stop = Arc::clone(&stop);
+            move || {
+                let (stopped, wake) = &*stop;
+                loop {
+                    let guard = stopped.lock().unwrap();
+                    let (guard, _) = wake
+                        .wait_timeout_while(guard, interval, |stopped| {
+                            !*stopped
+                        })
+                        .unwrap();
+                    if *guard {
+                        return;
+                    }
+                    drop(guard);
+                    let events = self.poll().unwrap_or_else(|err| {
+                        log_warn!("failed to poll mounted volumes: {}", err);
+                        Vec::new()
+                    });
+                    for event in events {
+                        log_debug!("volume change: {:?}", event);
+                        callback(event);
+                    }
+                }
+            }
+        });
+        MountWatch { stop, thread: Some(thread) }
+    }
+}
+
+/// A background thread started by [`MountWatcher::spawn`].
+///
+/// Dropping this value stops the thread and waits for it to finish.
+#[derive(Debug)]
+#[must_use = "the watch stops when it is dropped"]
+pub struct MountWatch {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for MountWatch {
+    fn drop(&mut self) {
+        let (stopped, wake) = &*self.stop;
+        *stopped.lock().unwrap() = true;
+        wake.notify_all();
+        if let Some(thread) = self.thread.take()
+            && thread.thread().id() != thread::current().id()
+        {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(all(test, any(target_os = "linux", windows)))]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use super::{MountEvent, MountWatcher};
+    use crate::FileId;
+    use crate::testing::SecondFs;
+
+    fn volume_of(fs: &SecondFs) -> u64 {
+        let root = crate::Handle::from_path(fs.path()).unwrap();
+        FileId::volume(&crate::Handle::id(&root))
+    }
+
+    #[test]
+    fn poll_reports_changes() {
+        let mut watcher = MountWatcher::new().unwrap();
+        assert!(!watcher.volumes().is_empty());
+        let Some(second) = SecondFs::new().unwrap() else {
+            return;
+        };
+        let volume = volume_of(&second);
+        assert!(
+            watcher.poll().unwrap().contains(&MountEvent::Mounted(volume))
+        );
+        drop(second);
+        let events = watcher.poll().unwrap();
+        assert!(events.contains(&MountEvent::Unmounted(volume)));
+    }
+
+    #[test]
+    fn spawn_reports_changes() {
+        let (tx, rx) = mpsc::channel();
+        let watch = MountWatcher::new().unwrap().spawn(
+            Duration::from_millis(10),
+            move |event| {
+                let _ = tx.send(event);
+            },
+        );
+        let Some(second) = SecondFs::new().unwrap() else {
+            return;
+        };
+        let volume = volume_of(&second);
+        let wait_for = |expected| loop {
+            let event = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+            if event == expected {
+                break;
+            }
+        };
+        wait_for(MountEvent::Mounted(volume));
+        drop(second);
+        wait_for(MountEvent::Unmounted(volume));
+        drop(watch);
+    }
+
+    #[test]
+    fn volumes_of_files_are_mounted() {
+        let volumes = super::mounted_volumes().unwrap();
+        let tdir = crate::testing::TempDir::new().unwrap();
+        for path in [std::path::Path::new("/"), tdir.path()] {
+            let id = FileId::from_path(path).unwrap();
+            assert!(volumes.contains(&id.volume()), "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn callback_can_stop_the_watch() {
+        use std::sync::{Arc, Mutex};
+
+        let (tx, rx) = mpsc::channel();
+        let slot: Arc<Mutex<Option<super::MountWatch>>> = Arc::default();
+        let watch =
+            MountWatcher::new().unwrap().spawn(Duration::from_millis(10), {
+                let slot = Arc::clone(&slot);
+                move |_| {
+                    drop(slot.lock().unwrap().take());
+                    let _ = tx.send(());
+                }
+            });
+        *slot.lock().unwrap() = Some(watch);
+        let Some(second) = SecondFs::new().unwrap() else {
+            drop(slot.lock().unwrap().take());
+            return;
+        };
+        rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(slot.lock().unwrap().is_none());
+        drop(second);
+    }
+}
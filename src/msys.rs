@@ -0,0 +1,145 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{FileId, Handle, is_same_file_path};
+
+/// Convert a Cygwin or MSYS style path into a native Windows path.
+///
+/// Both `/cygdrive/c/foo` (Cygwin) and `/c/foo` (MSYS) are converted to
+/// `C:\foo`. Returns `None` if the path is not in one of those forms, which
+/// includes POSIX paths such as `/usr/bin` whose location depends on where
+/// the toolchain is installed.
+///
+/// The other path-based APIs of this crate take native paths as they are,
+/// since `/c/foo` is also a valid native path, to `\c\foo` on the current
+/// drive. The APIs that accept paths in either form are
+/// [`Handle::from_msys_path`], [`FileId::from_msys_path`] and
+/// [`is_same_file_msys_path`].
+pub fn msys_to_windows_path<P: AsRef<Path>>(path: P) -> Option<PathBuf> {
+    let path = path.as_ref().to_str()?;
+    let rest = path.strip_prefix('/')?;
+    let rest = rest.strip_prefix("cygdrive/").unwrap_or(rest);
+    let mut components = rest.splitn(2, '/');
+    let drive = components.next()?;
+    let mut chars = drive.chars();
+    let letter = chars.next().filter(|c| c.is_ascii_alphabetic())?;
+    if chars.next().is_some() {
+        return None;
+    }
+    let mut converted = format!("{}:\\", letter.to_ascii_uppercase());
+    if let Some(tail) = components.next() {
+        converted.push_str(&tail.replace('/', "\\"));
+    }
+    Some(PathBuf::from(converted))
+}
+
+impl Handle<File> {
+    /// Construct a handle from a path that may be in Cygwin or MSYS form.
+    ///
+    /// The path is converted with [`msys_to_windows_path`] if possible, and
+    /// used as-is otherwise, so native Windows paths are accepted too.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the path cannot be
+    /// opened, or the file's metadata cannot be obtained.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn from_msys_path<P: AsRef<Path>>(p: P) -> io::Result<Self> {
+        Handle::from_path(native(p.as_ref()))
+    }
+}
+
+impl FileId {
+    /// Extract the identity of the file at a path that may be in Cygwin or
+    /// MSYS form.
+    ///
+    /// The path is converted as by [`Handle::from_msys_path`].
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] if the path cannot be
+    /// opened, or the file's identity cannot be obtained.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn from_msys_path<P: AsRef<Path>>(p: P) -> io::Result<FileId> {
+        FileId::from_path(native(p.as_ref()))
+    }
+}
+
+/// Returns true if the two paths, either of which may be in Cygwin or MSYS
+/// form, name the same file.
+///
+/// The paths are converted as by [`Handle::from_msys_path`], and then
+/// compared with [`is_same_file_path`].
+///
+/// # Errors
+/// This function will return an [`io::Error`] if any of the two paths cannot
+/// be opened.
+///
+/// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+pub fn is_same_file_msys_path<P, Q>(path1: P, path2: Q) -> io::Result<bool>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    is_same_file_path(native(path1.as_ref()), native(path2.as_ref()))
+}
+
+/// The native form of a path that may be in Cygwin or MSYS form.
+fn native(path: &Path) -> Cow<'_, Path> {
+    match msys_to_windows_path(path) {
+        Some(converted) => Cow::Owned(converted),
+        None => Cow::Borrowed(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{FileId, is_same_file_msys_path, msys_to_windows_path};
+
+    fn convert(path: &str) -> Option<PathBuf> {
+        msys_to_windows_path(path)
+    }
+
+    #[test]
+    fn cygdrive() {
+        assert_eq!(convert("/cygdrive/c/foo/bar"), Some(r"C:\foo\bar".into()));
+        assert_eq!(convert("/cygdrive/d"), Some(r"D:\".into()));
+    }
+
+    #[test]
+    fn msys() {
+        assert_eq!(convert("/c/foo/bar"), Some(r"C:\foo\bar".into()));
+        assert_eq!(convert("/e/"), Some(r"E:\".into()));
+    }
+
+    #[test]
+    fn not_converted() {
+        assert_eq!(convert("/usr/bin"), None);
+        assert_eq!(convert(r"C:\foo"), None);
+        assert_eq!(convert("foo/bar"), None);
+    }
+
+    #[test]
+    fn path_apis() {
+        let dir = std::env::current_dir().unwrap();
+        let dir = dir.to_str().unwrap();
+        // Only paths on a drive letter have an MSYS form.
+        let Some(rest) = dir.get(1..).and_then(|r| r.strip_prefix(":\\"))
+        else {
+            return;
+        };
+        let drive = dir[..1].to_ascii_lowercase();
+        let msys = format!("/{}/{}", drive, rest.replace('\\', "/"));
+        let cygwin = format!("/cygdrive{}", msys);
+        assert_eq!(
+            FileId::from_msys_path(&msys).unwrap(),
+            FileId::from_path(dir).unwrap()
+        );
+        assert!(is_same_file_msys_path(&cygwin, dir).unwrap());
+        assert!(is_same_file_msys_path(&msys, &cygwin).unwrap());
+    }
+}
@@ -0,0 +1,264 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::deadline::run_with_timeout;
+use crate::{AsRawFilelike, Error, FileId, Handle, RawFilelike, imp};
+
+/// How the identity of a handle was taken, so that the identity of its file
+/// can be taken again the same way.
+///
+/// A handle opened with [`HandleOptions::mount_aware`] would otherwise never
+/// match an identity taken again by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct IdentityMode {
+    /// The btrfs subvolume is included, as by
+    /// [`FileId::from_file_like_subvolume_aware`].
+    pub(crate) subvolume: bool,
+    /// The mount id is included, as by
+    /// [`FileId::from_file_like_mount_aware`].
+    pub(crate) mount: bool,
+}
+
+impl IdentityMode {
+    /// The mode an identity was taken in, as far as it can be told from the
+    /// identity itself.
+    pub(crate) fn of(id: &FileId) -> IdentityMode {
+        IdentityMode {
+            subvolume: id.0.is_subvolume_aware(),
+            mount: id.0.is_mount_aware(),
+        }
+    }
+
+    /// Add what this mode asks for to an identity taken by default.
+    pub(crate) fn apply(
+        self,
+        id: imp::FileId,
+        raw: RawFilelike,
+    ) -> io::Result<imp::FileId> {
+        let id = if self.subvolume { id.with_subvolume(raw)? } else { id };
+        if self.mount { id.with_mount(raw) } else { Ok(id) }
+    }
+
+    /// Take the identity of an open file in this mode.
+    pub(crate) fn id_of_raw(self, raw: RawFilelike) -> io::Result<FileId> {
+        if self == IdentityMode::default() {
+            return FileId::from_raw(raw);
+        }
+        fail_point!(Stat);
+        imp::FileId::from_filelike(raw)
+            .and_then(|id| self.apply(id, raw))
+            .map(FileId)
+            .map_err(Error::metadata_failed)
+    }
+
+    /// Take the identity of the file at a path in this mode, following
+    /// symbolic links and without keeping the file open.
+    pub(crate) fn id_of_path(self, path: &Path) -> io::Result<FileId> {
+        if self == IdentityMode::default() {
+            return FileId::from_path(path);
+        }
+        fail_point!(Open);
+        let file = imp::open_file(path)
+            .map_err(|err| Error::open_failed(path, err))?;
+        self.id_of_raw(file.as_raw_filelike())
+    }
+}
+
+/// Options for opening a path as a [`Handle`].
+///
+/// [`Handle::from_path`] opens files for reading, follows symbolic links
+/// and, on Windows, opens directories with backup semantics and shares the
+/// file with every other opener. These options make each of those choices
+/// explicit. Options that do not apply to the current platform are ignored.
+///
+/// # Examples
+///
+/// Taking the identity of a symbolic link while only asking for the access
+/// needed to query it:
+///
+/// ```rust,no_run
+/// use cross_file_id::HandleOptions;
+///
+/// let link = HandleOptions::new()
+///     .follow_symlinks(false)
+///     .minimal_access(true)
+///     .open("./link")?;
+/// # Ok::<_, std::io::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct HandleOptions {
+    pub(crate) follow_symlinks: bool,
+    pub(crate) backup_semantics: bool,
+    pub(crate) share_read: bool,
+    pub(crate) share_write: bool,
+    pub(crate) share_delete: bool,
+    pub(crate) minimal_access: bool,
+    pub(crate) verify_identity: bool,
+    pub(crate) subvolume_aware: bool,
+    pub(crate) mount_aware: bool,
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl HandleOptions {
+    /// Create options that open paths the way [`Handle::from_path`] does.
+    pub fn new() -> HandleOptions {
+        HandleOptions {
+            follow_symlinks: true,
+            backup_semantics: true,
+            share_read: true,
+            share_write: true,
+            share_delete: true,
+            minimal_access: false,
+            verify_identity: false,
+            subvolume_aware: false,
+            mount_aware: false,
+            timeout: None,
+        }
+    }
+
+    /// Whether a final symbolic link is followed. Defaults to true.
+    ///
+    /// When false, the handle refers to the link itself, as with
+    /// [`Handle::from_path_no_follow`], and shares its limitations.
+    pub fn follow_symlinks(&mut self, follow: bool) -> &mut HandleOptions {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// Whether Windows opens the path with `FILE_FLAG_BACKUP_SEMANTICS`,
+    /// which is required to open directories. Defaults to true.
+    pub fn backup_semantics(&mut self, backup: bool) -> &mut HandleOptions {
+        self.backup_semantics = backup;
+        self
+    }
+
+    /// Whether Windows lets others open the file for reading while the
+    /// handle is open. Defaults to true.
+    pub fn share_read(&mut self, share: bool) -> &mut HandleOptions {
+        self.share_read = share;
+        self
+    }
+
+    /// Whether Windows lets others open the file for writing while the
+    /// handle is open. Defaults to true.
+    pub fn share_write(&mut self, share: bool) -> &mut HandleOptions {
+        self.share_write = share;
+        self
+    }
+
+    /// Whether Windows lets others delete or rename the file while the
+    /// handle is open. Defaults to true.
+    pub fn share_delete(&mut self, share: bool) -> &mut HandleOptions {
+        self.share_delete = share;
+        self
+    }
+
+    /// Whether to ask only for the access needed to take the identity of
+    /// the file, rather than read access. Defaults to false.
+    ///
+    /// This allows files that cannot be read to be opened. On Linux, the
+    /// file is opened with `O_PATH`, and on Windows with
+    /// `FILE_READ_ATTRIBUTES`. In both cases, the resulting file cannot be
+    /// read from. Elsewhere, read access is still requested.
+    pub fn minimal_access(&mut self, minimal: bool) -> &mut HandleOptions {
+        self.minimal_access = minimal;
+        self
+    }
+
+    /// Whether the identity is taken through two independent mechanisms,
+    /// failing if they disagree. Defaults to false.
+    ///
+    /// See [`FileId::from_file_like_verified`].
+    pub fn verify_identity(&mut self, verify: bool) -> &mut HandleOptions {
+        self.verify_identity = verify;
+        self
+    }
+
+    /// Whether the identity includes the btrfs subvolume of the file.
+    /// Defaults to false.
+    ///
+    /// See [`FileId::from_file_like_subvolume_aware`].
+    pub fn subvolume_aware(&mut self, aware: bool) -> &mut HandleOptions {
+        self.subvolume_aware = aware;
+        self
+    }
+
+    /// Whether the identity includes the mount the file was reached
+    /// through. Defaults to false.
+    ///
+    /// See [`FileId::from_file_like_mount_aware`].
+    pub fn mount_aware(&mut self, aware: bool) -> &mut HandleOptions {
+        self.mount_aware = aware;
+        self
+    }
+
+    /// How long opening the path may take before giving up, or `None` to
+    /// wait for as long as it takes. Defaults to `None`.
+    ///
+    /// With a timeout, the path is opened on a worker thread, so that a
+    /// network filesystem that stopped responding cannot hang the caller.
+    /// When the timeout elapses, the worker thread is left to finish the
+    /// blocked open on its own, and closes the file if it ever gets one.
+    pub fn timeout(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> &mut HandleOptions {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Open the path with these options.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the path cannot be opened
+    /// or its identity cannot be obtained, or if following symbolic links is
+    /// disabled on a platform that cannot open them. If a
+    /// [timeout](HandleOptions::timeout) is set and elapses, the error is of
+    /// kind [`io::ErrorKind::TimedOut`] and wraps [`Error::TimedOut`]; if
+    /// too many earlier opens are still blocked, it wraps
+    /// [`Error::WorkersBusy`] instead.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> io::Result<Handle<File>> {
+        if let Some(timeout) = self.timeout {
+            let options = HandleOptions { timeout: None, ..self.clone() };
+            let path = path.as_ref().to_path_buf();
+            return run_with_timeout("opening a path", timeout, move || {
+                options.open(path)
+            });
+        }
+        fail_point!(Open);
+        let path = path.as_ref();
+        let file = imp::open_with_options(path, self)
+            .map_err(|err| Error::open_failed(path, err))?;
+        if !self.verify_identity && !self.subvolume_aware && !self.mount_aware
+        {
+            return Handle::from_file_like(file);
+        }
+        fail_point!(Stat);
+        let raw = file.as_raw_filelike();
+        let mode = IdentityMode {
+            subvolume: self.subvolume_aware,
+            mount: self.mount_aware,
+        };
+        let id = if self.verify_identity {
+            imp::FileId::from_filelike_verified(raw)
+        } else {
+            imp::FileId::from_filelike(raw)
+        };
+        let id = id
+            .and_then(|id| mode.apply(id, raw))
+            .map(FileId)
+            .map_err(Error::metadata_failed)?;
+        let kind = imp::device_class(raw).ok();
+        Ok(Handle::new(file, id, kind, mode))
+    }
+}
+
+impl Default for HandleOptions {
+    fn default() -> HandleOptions {
+        HandleOptions::new()
+    }
+}
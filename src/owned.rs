@@ -0,0 +1,150 @@
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::Path;
+
+use io_lifetimes::raw::AsRawFilelike;
+use io_lifetimes::{BorrowedFilelike, OwnedFilelike};
+
+use crate::{FileId, Handle};
+
+/// A file identity that keeps its own descriptor or handle to the file
+/// open.
+///
+/// A bare [`FileId`] is only guaranteed to be meaningful while the file it
+/// was taken from stays open, which is up to the caller. An `OwnedFileId`
+/// duplicates the descriptor or handle it is built from, so the identity is
+/// valid for the lifetime of the value itself, whatever happens to the
+/// original. It costs one open file resource, like a [`Handle`], but does
+/// not expose the file.
+///
+/// It compares, orders and hashes by identity.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use std::fs::File;
+///
+/// use cross_file_id::OwnedFileId;
+///
+/// let file = File::open("./data")?;
+/// let id = OwnedFileId::from_file_like(&file)?;
+/// drop(file);
+/// assert_eq!(id, OwnedFileId::from_path("./data")?);
+/// # Ok::<_, std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct OwnedFileId {
+    // Kept open to pin the identity.
+    file: OwnedFilelike,
+    id: FileId,
+}
+
+impl OwnedFileId {
+    /// Take the identity of a file-like object, duplicating its descriptor
+    /// or handle to keep the file open.
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] if the identity cannot be
+    /// obtained or the descriptor or handle cannot be duplicated.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn from_file_like<F: AsRawFilelike>(
+        file: &F,
+    ) -> io::Result<OwnedFileId> {
+        let id = FileId::from_file_like(file)?;
+        Ok(OwnedFileId { file: duplicate(file)?, id })
+    }
+
+    /// Take the identity of a handle, duplicating its descriptor or handle
+    /// to keep the file open.
+    ///
+    /// The identity of the handle is reused rather than queried again.
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] if the descriptor or
+    /// handle cannot be duplicated.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn from_handle<F: AsRawFilelike>(
+        handle: &Handle<F>,
+    ) -> io::Result<OwnedFileId> {
+        Ok(OwnedFileId { file: duplicate(&**handle)?, id: Handle::id(handle) })
+    }
+
+    /// Open the file at a path, as [`Handle::from_path`] does, and keep it
+    /// open for as long as the identity is.
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] if the path cannot be
+    /// opened or its identity cannot be obtained.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn from_path<P: AsRef<Path>>(path: P) -> io::Result<OwnedFileId> {
+        let handle = Handle::from_path(path)?;
+        let id = Handle::id(&handle);
+        Ok(OwnedFileId { file: Handle::into_inner(handle).into(), id })
+    }
+
+    /// The identity of the file.
+    pub fn id(&self) -> &FileId {
+        &self.id
+    }
+
+    /// Duplicate the descriptor or handle again, for another identity that
+    /// is valid on its own.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the descriptor or handle
+    /// cannot be duplicated.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn try_clone(&self) -> io::Result<OwnedFileId> {
+        Ok(OwnedFileId { file: self.file.try_clone()?, id: self.id.clone() })
+    }
+}
+
+fn duplicate<F: AsRawFilelike>(file: &F) -> io::Result<OwnedFilelike> {
+    // SAFETY: `file` keeps the underlying file open for the duration of
+    // this borrow.
+    unsafe { BorrowedFilelike::borrow_raw(file.as_raw_filelike()) }
+        .try_clone_to_owned()
+}
+
+impl AsRef<FileId> for OwnedFileId {
+    fn as_ref(&self) -> &FileId {
+        &self.id
+    }
+}
+
+impl PartialEq for OwnedFileId {
+    fn eq(&self, other: &OwnedFileId) -> bool {
+        self.id == other.id
+    }
+}
+
+impl PartialEq<FileId> for OwnedFileId {
+    fn eq(&self, other: &FileId) -> bool {
+        self.id == *other
+    }
+}
+
+impl Eq for OwnedFileId {}
+
+impl PartialOrd for OwnedFileId {
+    fn partial_cmp(&self, other: &OwnedFileId) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OwnedFileId {
+    fn cmp(&self, other: &OwnedFileId) -> Ordering {
+        self.id.cmp(&other.id)
+    }
+}
+
+impl Hash for OwnedFileId {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
@@ -0,0 +1,82 @@
+use std::fs::File;
+use std::io;
+use std::process::{Child, Stdio};
+
+use crate::{FileId, Handle};
+
+impl Handle<File> {
+    /// Consume the handle and return it as a [`Stdio`] for a child process,
+    /// together with the identity it was verified with.
+    ///
+    /// The identity can be checked again once the child is running with
+    /// [`verify_child_stream`], to make sure that the child was attached to
+    /// exactly this file.
+    ///
+    /// This is provided as an associated function instead of a method
+    /// to ensure that operations that rely on the value being accessible via
+    /// dereference aren't accidentally masked.
+    ///
+    /// [`Stdio`]: https://doc.rust-lang.org/std/process/struct.Stdio.html
+    pub fn into_stdio(this: Self) -> (Stdio, FileId) {
+        let identity = Handle::id(&this);
+        (Stdio::from(Handle::into_inner(this)), identity)
+    }
+}
+
+/// One of the standard streams of a child process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChildStream {
+    /// The standard input.
+    Stdin,
+    /// The standard output.
+    Stdout,
+    /// The standard error.
+    Stderr,
+}
+
+impl ChildStream {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn fd(self) -> i32 {
+        match self {
+            ChildStream::Stdin => 0,
+            ChildStream::Stdout => 1,
+            ChildStream::Stderr => 2,
+        }
+    }
+}
+
+/// Check that a standard stream of a running child process refers to the
+/// expected file.
+///
+/// This is meant to be called right after spawning a child with a stream
+/// from [`Handle::into_stdio`]. It only tells what the stream refers to at
+/// the time of the call, since the child may redirect its own streams.
+///
+/// This is only supported on Linux and Android, where the streams of the
+/// child are inspected through `/proc`.
+///
+/// # Errors
+/// This function will return an [`io::Error`] if the stream of the child
+/// cannot be inspected, e.g. because the child has already exited, or with
+/// [`io::ErrorKind::Unsupported`] on other platforms.
+///
+/// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+pub fn verify_child_stream(
+    child: &Child,
+    stream: ChildStream,
+    expected: &FileId,
+) -> io::Result<bool> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        let path = format!("/proc/{}/fd/{}", child.id(), stream.fd());
+        Ok(FileId::from_path(path)? == *expected)
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        let _ = (child, stream, expected);
+        Err(crate::Error::UnsupportedPlatform {
+            operation: "inspecting the streams of a child",
+        }
+        .into())
+    }
+}
@@ -0,0 +1,88 @@
+//! A process-wide registry of live handles, for debugging descriptor leaks.
+//!
+//! When the `debug-registry` feature is enabled, every [`Handle`] registers
+//! itself on construction and removes itself when dropped. The registry can
+//! be queried at any time to find out which files are being kept open by
+//! this crate, and optionally where each handle was created.
+//!
+//! Creation backtraces are captured with [`Backtrace::capture`], so they are
+//! only recorded when backtraces are enabled through the `RUST_BACKTRACE` or
+//! `RUST_LIB_BACKTRACE` environment variables.
+//!
+//! [`Handle`]: crate::Handle
+
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::FileId;
+
+/// A snapshot of a single live handle in the registry.
+#[derive(Debug, Clone)]
+pub struct LiveHandle {
+    /// The identity of the file the handle keeps open.
+    pub id: FileId,
+    /// The name of the type wrapped by the handle.
+    pub type_name: &'static str,
+    /// Where the handle was created, if backtraces are enabled.
+    pub backtrace: Option<Arc<Backtrace>>,
+}
+
+static NEXT_TOKEN: AtomicU64 = AtomicU64::new(0);
+static REGISTRY: Mutex<BTreeMap<u64, LiveHandle>> =
+    Mutex::new(BTreeMap::new());
+
+fn lock() -> MutexGuard<'static, BTreeMap<u64, LiveHandle>> {
+    // A panic while holding the lock cannot leave the map in an inconsistent
+    // state, so poisoning can safely be ignored.
+    REGISTRY.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Returns a snapshot of all handles that are currently alive.
+///
+/// Handles are listed in creation order.
+pub fn live_handles() -> Vec<LiveHandle> {
+    lock().values().cloned().collect()
+}
+
+/// Returns the number of handles that are currently alive.
+pub fn live_count() -> usize {
+    lock().len()
+}
+
+/// Membership of a single handle in the registry.
+///
+/// Dropping the registration removes the handle from the registry.
+pub(crate) struct Registration(u64);
+
+impl Registration {
+    pub(crate) fn new<F>(id: &FileId) -> Registration {
+        let token = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+        let backtrace = Backtrace::capture();
+        let backtrace = match backtrace.status() {
+            BacktraceStatus::Captured => Some(Arc::new(backtrace)),
+            _ => None,
+        };
+        let entry = LiveHandle {
+            id: id.clone(),
+            type_name: std::any::type_name::<F>(),
+            backtrace,
+        };
+        lock().insert(token, entry);
+        Registration(token)
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        lock().remove(&self.0);
+    }
+}
+
+impl fmt::Debug for Registration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Registration").field(&self.0).finish()
+    }
+}
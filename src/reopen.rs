@@ -0,0 +1,86 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+use crate::{AsRawFilelike, Error, Handle, imp};
+
+/// Open the file of a handle again by path, with other access rights,
+/// failing unless the path still names the same file.
+///
+/// This is how a handle opened for reading is upgraded to one that can
+/// write: the file is reopened through `path`, and the identity of the new
+/// handle is compared with the identity of `handle`. If the path was
+/// swapped for another file or a symbolic link to one in the meantime, the
+/// new handle is dropped and an error is returned, so the caller never
+/// writes to a file it did not mean to.
+///
+/// The check happens after the open, so options with side effects, such as
+/// [`truncate`](OpenOptions::truncate) or
+/// [`create`](OpenOptions::create), apply to whatever file the path names
+/// at the time. Truncate through the new handle once it has been verified
+/// instead.
+///
+/// On Unix, the path is opened with `O_NONBLOCK` and `O_NOCTTY`, in place of
+/// any [custom flags](std::os::unix::fs::OpenOptionsExt::custom_flags) of
+/// `options`, so that a FIFO swapped in for the file cannot block the call
+/// and a terminal cannot become the controlling terminal of the process.
+/// `O_NONBLOCK` is cleared once the file is verified.
+///
+/// # Errors
+/// This function will return an [`io::Error`] if the path cannot be opened
+/// or the identity of the new handle cannot be obtained, or one wrapping
+/// [`Error::Replaced`] if the path names another file than `handle`.
+///
+/// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use std::fs::OpenOptions;
+/// use std::io::Write;
+///
+/// use cross_file_id::{Handle, reopen_verified};
+///
+/// let config = Handle::from_path("./config")?;
+/// // ... check that the contents of the file can be overwritten ...
+/// let mut config = reopen_verified(
+///     &config,
+///     "./config",
+///     OpenOptions::new().write(true),
+/// )?;
+/// config.write_all(b"updated")?;
+/// # Ok::<_, std::io::Error>(())
+/// ```
+pub fn reopen_verified<F, P: AsRef<Path>>(
+    handle: &Handle<F>,
+    path: P,
+    options: &OpenOptions,
+) -> io::Result<Handle<File>> {
+    #[cfg(unix)]
+    let options = &{
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let mut options = options.clone();
+        options.custom_flags(libc::O_NONBLOCK | libc::O_NOCTTY);
+        options
+    };
+    fail_point!(Open);
+    let path = path.as_ref();
+    let file =
+        options.open(path).map_err(|err| Error::open_failed(path, err))?;
+    let raw = file.as_raw_filelike();
+    let id = handle.mode.id_of_raw(raw)?;
+    let kind = imp::device_class(raw).ok();
+    let reopened = Handle::new(file, id, kind, handle.mode);
+    if reopened.identity != handle.identity {
+        return Err(Error::Replaced {
+            expected: Handle::id(handle),
+            actual: Handle::id(&reopened),
+            path: Some(path.to_path_buf()),
+        }
+        .into());
+    }
+    #[cfg(unix)]
+    imp::set_blocking(reopened.as_raw_filelike())?;
+    Ok(reopened)
+}
@@ -0,0 +1,69 @@
+//! `Serialize` and `Deserialize` for `FileId`.
+//!
+//! An identity is serialized as its 32-byte packed form, which starts with a
+//! platform tag. Deserializing an identity that was serialized on another
+//! platform fails, rather than producing an identity that could compare
+//! equal to an unrelated local file.
+
+use std::fmt;
+
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use crate::FileId;
+
+impl Serialize for FileId {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.to_packed())
+    }
+}
+
+struct PackedVisitor;
+
+impl PackedVisitor {
+    fn finish<E: de::Error>(packed: &[u8]) -> Result<FileId, E> {
+        let packed: &[u8; 32] = packed
+            .try_into()
+            .map_err(|_| E::invalid_length(packed.len(), &PackedVisitor))?;
+        FileId::from_packed(packed).map_err(E::custom)
+    }
+}
+
+impl<'de> Visitor<'de> for PackedVisitor {
+    type Value = FileId;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a 32-byte packed file identity")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<FileId, E> {
+        PackedVisitor::finish(v)
+    }
+
+    // Formats without a native byte string type, such as JSON, encode bytes
+    // as a sequence of integers.
+    fn visit_seq<A: SeqAccess<'de>>(
+        self,
+        mut seq: A,
+    ) -> Result<FileId, A::Error> {
+        let mut packed = Vec::with_capacity(32);
+        while let Some(byte) = seq.next_element::<u8>()? {
+            if packed.len() == 32 {
+                return Err(de::Error::invalid_length(33, &self));
+            }
+            packed.push(byte);
+        }
+        PackedVisitor::finish(&packed)
+    }
+}
+
+impl<'de> Deserialize<'de> for FileId {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<FileId, D::Error> {
+        deserializer.deserialize_bytes(PackedVisitor)
+    }
+}
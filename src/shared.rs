@@ -0,0 +1,119 @@
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use crate::{FileId, Handle};
+
+/// A reference-counted [`Handle`] that is cheap to clone.
+///
+/// Every clone refers to the same open file, which stays open as long as any
+/// clone is alive, so the identity stays valid for as long as any of them is
+/// used. Like a handle, a shared handle compares, orders and hashes by
+/// identity, and can be stored in several data structures at once, such as
+/// the ancestor stack and the visited set of a traversal. It is `Send` and
+/// `Sync` whenever the underlying file-like object is.
+///
+/// A shared handle dereferences to the [`Handle`] it wraps.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use std::collections::HashSet;
+///
+/// use cross_file_id::{Handle, SharedHandle};
+///
+/// let root = SharedHandle::new(Handle::from_path(".")?);
+/// let stack = vec![root.clone()];
+/// let mut visited = HashSet::new();
+/// visited.insert(root);
+/// assert!(visited.contains(&stack[0]));
+/// # Ok::<_, std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct SharedHandle<F>(Arc<Handle<F>>);
+
+impl<F> SharedHandle<F> {
+    /// Wrap a handle so that it can be shared.
+    pub fn new(handle: Handle<F>) -> SharedHandle<F> {
+        SharedHandle(Arc::new(handle))
+    }
+
+    /// Get the file identity for this handle.
+    ///
+    /// This is provided as an associated function instead of a method
+    /// to ensure that operations that rely on the value being accessible via
+    /// dereference aren't accidentally masked.
+    pub fn id(this: &Self) -> FileId {
+        Handle::id(&this.0)
+    }
+
+    /// Return the wrapped handle if this is the only clone left, and the
+    /// shared handle otherwise.
+    ///
+    /// This is provided as an associated function instead of a method
+    /// to ensure that operations that rely on the value being accessible via
+    /// dereference aren't accidentally masked.
+    pub fn try_unwrap(this: Self) -> Result<Handle<F>, SharedHandle<F>> {
+        Arc::try_unwrap(this.0).map_err(SharedHandle)
+    }
+
+    /// Returns true if the two shared handles are clones of each other.
+    ///
+    /// Shared handles built from different handles to the same file compare
+    /// equal, but are not clones of each other.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        Arc::ptr_eq(&this.0, &other.0)
+    }
+}
+
+impl<F> Clone for SharedHandle<F> {
+    fn clone(&self) -> SharedHandle<F> {
+        SharedHandle(Arc::clone(&self.0))
+    }
+}
+
+impl<F> From<Handle<F>> for SharedHandle<F> {
+    fn from(handle: Handle<F>) -> SharedHandle<F> {
+        SharedHandle::new(handle)
+    }
+}
+
+impl<F> std::ops::Deref for SharedHandle<F> {
+    type Target = Handle<F>;
+
+    fn deref(&self) -> &Handle<F> {
+        &self.0
+    }
+}
+
+impl<F1, F2> PartialEq<SharedHandle<F2>> for SharedHandle<F1> {
+    fn eq(&self, other: &SharedHandle<F2>) -> bool {
+        *self.0 == *other.0
+    }
+}
+
+impl<F1, F2> PartialEq<Handle<F2>> for SharedHandle<F1> {
+    fn eq(&self, other: &Handle<F2>) -> bool {
+        *self.0 == *other
+    }
+}
+
+impl<F> Eq for SharedHandle<F> {}
+
+impl<F1, F2> PartialOrd<SharedHandle<F2>> for SharedHandle<F1> {
+    fn partial_cmp(&self, other: &SharedHandle<F2>) -> Option<Ordering> {
+        (*self.0).partial_cmp(&*other.0)
+    }
+}
+
+impl<F> Ord for SharedHandle<F> {
+    fn cmp(&self, other: &SharedHandle<F>) -> Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+impl<F> Hash for SharedHandle<F> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
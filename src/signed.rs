@@ -0,0 +1,167 @@
+//! File identities as signed capability tokens.
+//!
+//! This module is only available with the `signed` feature. A [`SignedId`]
+//! binds a [`FileId`] and an application-defined scope (such as a mode or a
+//! client name) together with an HMAC-SHA256 tag computed with a key that
+//! only the application knows. The token can be handed to an untrusted
+//! component, and when it comes back the application can verify that it was
+//! issued by itself and has not been modified.
+//!
+//! The usual rules for [`FileId`] still apply: a token is only meaningful
+//! while the file it refers to is kept open by the issuer.
+
+use std::io;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::FileId;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Prefixed to every MAC input, so that tags produced by this module cannot be
+// confused with tags produced by the application for other purposes with
+// the same key.
+const DOMAIN: &[u8] = b"cross-file-id signed id v1";
+
+const PACKED_LEN: usize = 32;
+const TAG_LEN: usize = 32;
+
+/// A [`FileId`] and a scope, signed with an application-supplied key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SignedId {
+    packed: [u8; PACKED_LEN],
+    scope: Vec<u8>,
+    tag: [u8; TAG_LEN],
+}
+
+fn mac(key: &[u8], packed: &[u8; PACKED_LEN], scope: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key)
+        .expect("HMAC accepts keys of any size");
+    mac.update(DOMAIN);
+    mac.update(packed);
+    mac.update(&(scope.len() as u64).to_be_bytes());
+    mac.update(scope);
+    mac
+}
+
+impl SignedId {
+    /// Sign an identity and scope with the given key.
+    pub fn sign(key: &[u8], id: &FileId, scope: &[u8]) -> SignedId {
+        let packed = id.to_packed();
+        let tag = mac(key, &packed, scope).finalize().into_bytes().into();
+        SignedId { packed, scope: scope.to_vec(), tag }
+    }
+
+    /// The scope the identity was signed with.
+    ///
+    /// The scope is only trustworthy after [`verify`](SignedId::verify) has
+    /// succeeded.
+    pub fn scope(&self) -> &[u8] {
+        &self.scope
+    }
+
+    /// Returns true if the token was signed with the given key and has not
+    /// been modified.
+    ///
+    /// The comparison of the tags is done in constant time.
+    #[must_use]
+    pub fn verify(&self, key: &[u8]) -> bool {
+        mac(key, &self.packed, &self.scope).verify_slice(&self.tag).is_ok()
+    }
+
+    /// Returns true if the token is valid for the given key and refers to
+    /// the given identity.
+    #[must_use]
+    pub fn verify_for(&self, key: &[u8], id: &FileId) -> bool {
+        // Check the signature first, so that the result does not depend on
+        // an attacker-controlled identity before the token is authenticated.
+        self.verify(key) && self.claims(id)
+    }
+
+    /// Returns true if the token claims the given identity, without checking
+    /// the signature.
+    ///
+    /// This is for components that do not hold the key, such as a sandboxed
+    /// child checking that a descriptor it received matches the token that
+    /// came with it. It says nothing about who issued the token.
+    #[must_use]
+    pub fn claims(&self, id: &FileId) -> bool {
+        self.packed == id.to_packed()
+    }
+
+    #[cfg(feature = "broker")]
+    pub(crate) fn packed(&self) -> &[u8; PACKED_LEN] {
+        &self.packed
+    }
+
+    /// Encode the token as bytes, for transfer to another component.
+    ///
+    /// The layout is the packed identity (32 bytes), followed by the tag (32
+    /// bytes), followed by the scope.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes =
+            Vec::with_capacity(PACKED_LEN + TAG_LEN + self.scope.len());
+        bytes.extend_from_slice(&self.packed);
+        bytes.extend_from_slice(&self.tag);
+        bytes.extend_from_slice(&self.scope);
+        bytes
+    }
+
+    /// Decode a token produced by [`to_bytes`](SignedId::to_bytes).
+    ///
+    /// This does not verify the token; use [`verify`](SignedId::verify) or
+    /// [`verify_for`](SignedId::verify_for) before trusting it.
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] with
+    /// [`io::ErrorKind::InvalidData`] if the input is too short.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<SignedId> {
+        if bytes.len() < PACKED_LEN + TAG_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "signed id is too short",
+            ));
+        }
+        let (packed, rest) = bytes.split_at(PACKED_LEN);
+        let (tag, scope) = rest.split_at(TAG_LEN);
+        Ok(SignedId {
+            packed: packed.try_into().unwrap(),
+            scope: scope.to_vec(),
+            tag: tag.try_into().unwrap(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::SignedId;
+    use crate::FileId;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn sign_and_verify() {
+        let tdir = TempDir::new().unwrap();
+        let a = File::create(tdir.path().join("a")).unwrap();
+        let b = File::create(tdir.path().join("b")).unwrap();
+        let a = FileId::from_file_like(&a).unwrap();
+        let b = FileId::from_file_like(&b).unwrap();
+
+        let token = SignedId::sign(b"key", &a, b"read");
+        let decoded = SignedId::from_bytes(&token.to_bytes()).unwrap();
+        assert_eq!(decoded, token);
+        assert!(decoded.verify(b"key"));
+        assert!(decoded.verify_for(b"key", &a));
+        assert!(!decoded.verify_for(b"key", &b));
+        assert!(!decoded.verify(b"other key"));
+
+        let mut tampered = token.to_bytes();
+        *tampered.last_mut().unwrap() = b'x';
+        assert!(!SignedId::from_bytes(&tampered).unwrap().verify(b"key"));
+        assert!(SignedId::from_bytes(&tampered[..10]).is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use std::io;
+use std::path::Path;
+
+use crate::{AsRawFilelike, FileId};
+
+/// Where identities of paths come from.
+///
+/// The machinery of this crate that only needs identities, rather than open
+/// files, can take them from any source: [`Deduplicator::first_seen_via`],
+/// [`FileIdMap::entry_for_path_via`], [`IdSet::from_paths_via`] and
+/// [`UniqueFiles::via`] accept one. [`OsIdentity`] takes them from the
+/// operating system, as the rest of the crate does, while embedders such as
+/// virtual or remote filesystems and test harnesses can plug in their own
+/// scheme. With the `test-util` feature, the `MockFs` of the `mock` module
+/// is a source as well.
+///
+/// A [`Handle`](crate::Handle), and the collections of handles, always take
+/// identities from the operating system, since a handle takes the identity
+/// of its file again to verify it or to follow it to another descriptor.
+///
+/// Identities from different sources must not be mixed, as they may
+/// collide.
+///
+/// [`Deduplicator::first_seen_via`]: crate::Deduplicator::first_seen_via
+/// [`FileIdMap::entry_for_path_via`]: crate::FileIdMap::entry_for_path_via
+/// [`IdSet::from_paths_via`]: crate::IdSet::from_paths_via
+/// [`UniqueFiles::via`]: crate::UniqueFiles::via
+///
+/// # Examples
+///
+/// A source for a remote filesystem whose server reports identities:
+///
+/// ```rust
+/// use std::io;
+/// use std::path::Path;
+///
+/// use cross_file_id::{FileId, FileIdentitySource};
+///
+/// struct Remote;
+///
+/// impl Remote {
+///     fn fetch_id(&self, path: &Path) -> io::Result<Vec<u8>> {
+///         // ... ask the server ...
+///         # Ok(FileId::from_path(path)?.to_bytes().to_vec())
+///     }
+/// }
+///
+/// impl FileIdentitySource for Remote {
+///     fn id_from_path(&self, path: &Path) -> io::Result<FileId> {
+///         FileId::from_bytes(&self.fetch_id(path)?)
+///     }
+/// }
+///
+/// assert!(Remote.id_from_path(Path::new(".")).is_ok());
+/// ```
+pub trait FileIdentitySource {
+    /// The identity of the file at a path, following symbolic links.
+    ///
+    /// # Errors
+    /// This method returns an [`io::Error`] if the path has no identity,
+    /// e.g. because it does not exist.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    fn id_from_path(&self, path: &Path) -> io::Result<FileId>;
+
+    /// The identity of the file at a path, without following a final
+    /// symbolic link.
+    ///
+    /// # Errors
+    /// This method returns an [`io::Error`] if the path has no identity. The
+    /// default implementation fails with [`io::ErrorKind::Unsupported`].
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    fn id_from_path_no_follow(&self, path: &Path) -> io::Result<FileId> {
+        let _ = path;
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "this identity source cannot open symbolic links",
+        ))
+    }
+
+    /// The identity of an open file.
+    ///
+    /// # Errors
+    /// This method returns an [`io::Error`] if the file has no identity. The
+    /// default implementation fails with [`io::ErrorKind::Unsupported`], for
+    /// sources whose files are never opened through the operating system.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    fn id_from_file_like(
+        &self,
+        file: &dyn AsRawFilelike,
+    ) -> io::Result<FileId> {
+        let _ = file;
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "this identity source cannot identify open files",
+        ))
+    }
+}
+
+impl<S: FileIdentitySource + ?Sized> FileIdentitySource for &S {
+    fn id_from_path(&self, path: &Path) -> io::Result<FileId> {
+        (**self).id_from_path(path)
+    }
+
+    fn id_from_path_no_follow(&self, path: &Path) -> io::Result<FileId> {
+        (**self).id_from_path_no_follow(path)
+    }
+
+    fn id_from_file_like(
+        &self,
+        file: &dyn AsRawFilelike,
+    ) -> io::Result<FileId> {
+        (**self).id_from_file_like(file)
+    }
+}
+
+/// The identities of the operating system, as taken by
+/// [`FileId::from_path`], [`FileId::from_path_no_follow`] and
+/// [`FileId::from_file_like`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsIdentity;
+
+impl FileIdentitySource for OsIdentity {
+    fn id_from_path(&self, path: &Path) -> io::Result<FileId> {
+        FileId::from_path(path)
+    }
+
+    fn id_from_path_no_follow(&self, path: &Path) -> io::Result<FileId> {
+        FileId::from_path_no_follow(path)
+    }
+
+    fn id_from_file_like(
+        &self,
+        file: &dyn AsRawFilelike,
+    ) -> io::Result<FileId> {
+        FileId::from_raw(file.as_raw_filelike())
+    }
+}
+
+#[cfg(all(
+    feature = "test-util",
+    any(unix, windows, target_os = "wasi", target_os = "hermit")
+))]
+impl FileIdentitySource for crate::mock::MockFs {
+    fn id_from_path(&self, path: &Path) -> io::Result<FileId> {
+        self.id(path)
+    }
+
+    fn id_from_path_no_follow(&self, path: &Path) -> io::Result<FileId> {
+        self.id_no_follow(path)
+    }
+}
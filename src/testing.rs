@@ -0,0 +1,431 @@
+//! Utilities for testing code that depends on file identity.
+//!
+//! This module is only available with the `testing` feature. It provides a
+//! self-cleaning temporary directory, cross-platform link helpers, and a
+//! [`Fixture`] type for building the usual identity scenarios (hard links,
+//! symbolic links, distinct files with identical contents) in a few lines.
+//!
+//! These helpers deliberately avoid the `tempfile` crate to keep the
+//! dependency footprint of this crate small.
+
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A simple wrapper for creating a temporary directory that is
+/// automatically deleted when it's dropped.
+#[derive(Debug)]
+#[must_use = "the directory is deleted when it is dropped"]
+pub struct TempDir(PathBuf);
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        // Failing to clean up should not turn a passing test into a panic
+        // during unwinding, so errors are ignored.
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+impl TempDir {
+    /// Create a new empty temporary directory under the system's
+    /// configured temporary directory.
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] if the directory cannot be
+    /// created.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn new() -> io::Result<TempDir> {
+        static TRIES: usize = 100;
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let base = env::temp_dir().join("cross-file-id");
+        fs::create_dir_all(&base)?;
+        for _ in 0..TRIES {
+            let count = COUNTER.fetch_add(1, Ordering::SeqCst);
+            let name = format!("{}-{}", process::id(), count);
+            let path = base.join(name);
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(TempDir(path)),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    continue;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Err(io::Error::other(format!(
+            "failed to create temp dir after {} tries",
+            TRIES
+        )))
+    }
+
+    /// Return the underlying path to this temporary directory.
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+/// Create a symbolic link to a directory.
+///
+/// `src` is the existing directory, and `dst` is the link to create.
+#[cfg(unix)]
+pub fn soft_link_dir<P: AsRef<Path>, Q: AsRef<Path>>(
+    src: P,
+    dst: Q,
+) -> io::Result<()> {
+    use std::os::unix::fs::symlink;
+    symlink(src, dst)
+}
+
+/// Create a symbolic link to a file.
+///
+/// `src` is the existing file, and `dst` is the link to create.
+#[cfg(unix)]
+pub fn soft_link_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    src: P,
+    dst: Q,
+) -> io::Result<()> {
+    soft_link_dir(src, dst)
+}
+
+/// Create a symbolic link to a directory.
+///
+/// `src` is the existing directory, and `dst` is the link to create.
+#[cfg(windows)]
+pub fn soft_link_dir<P: AsRef<Path>, Q: AsRef<Path>>(
+    src: P,
+    dst: Q,
+) -> io::Result<()> {
+    use std::os::windows::fs::symlink_dir;
+    symlink_dir(src, dst)
+}
+
+/// Create a directory junction.
+///
+/// `src` is the existing directory, and `dst` is the junction to create.
+/// Unlike symbolic links, junctions can be created without any privilege.
+#[cfg(windows)]
+pub fn junction<P: AsRef<Path>, Q: AsRef<Path>>(
+    src: P,
+    dst: Q,
+) -> io::Result<()> {
+    let status = std::process::Command::new("cmd")
+        .arg("/C")
+        .arg("mklink")
+        .arg("/J")
+        .arg(dst.as_ref())
+        .arg(src.as_ref())
+        .stdout(std::process::Stdio::null())
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("mklink failed: {}", status)));
+    }
+    Ok(())
+}
+
+/// Create a symbolic link to a file.
+///
+/// `src` is the existing file, and `dst` is the link to create.
+#[cfg(windows)]
+pub fn soft_link_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    src: P,
+    dst: Q,
+) -> io::Result<()> {
+    use std::os::windows::fs::symlink_file;
+    symlink_file(src, dst)
+}
+
+/// Create a hard link to a file.
+///
+/// `src` is the existing file, and `dst` is the link to create.
+pub fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(
+    src: P,
+    dst: Q,
+) -> io::Result<()> {
+    fs::hard_link(src, dst)
+}
+
+/// A temporary directory with helpers for building identity scenarios.
+///
+/// All names given to the methods are relative to the fixture's directory,
+/// and each method returns the full path of what it created. Parent
+/// directories are created as needed.
+///
+/// # Examples
+///
+/// ```rust
+/// # #[cfg(feature = "testing")]
+/// # fn main() -> std::io::Result<()> {
+/// use cross_file_id::is_same_file_path;
+/// use cross_file_id::testing::Fixture;
+///
+/// let fixture = Fixture::new()?;
+/// let (a, b) = fixture.hard_linked_pair("a", "b")?;
+/// assert!(is_same_file_path(&a, &b)?);
+/// # Ok(())
+/// # }
+/// # #[cfg(not(feature = "testing"))]
+/// # fn main() {}
+/// ```
+#[derive(Debug)]
+pub struct Fixture {
+    dir: TempDir,
+}
+
+impl Fixture {
+    /// Create a fixture in a new temporary directory.
+    pub fn new() -> io::Result<Fixture> {
+        Ok(Fixture { dir: TempDir::new()? })
+    }
+
+    /// The root directory of the fixture.
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    fn prepare(&self, name: &str) -> io::Result<PathBuf> {
+        let path = self.dir.path().join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(path)
+    }
+
+    /// Create an empty file.
+    pub fn file(&self, name: &str) -> io::Result<PathBuf> {
+        let path = self.prepare(name)?;
+        File::create(&path)?;
+        Ok(path)
+    }
+
+    /// Create a file with the given contents.
+    pub fn file_with<C: AsRef<[u8]>>(
+        &self,
+        name: &str,
+        contents: C,
+    ) -> io::Result<PathBuf> {
+        let path = self.prepare(name)?;
+        fs::write(&path, contents)?;
+        Ok(path)
+    }
+
+    /// Create a directory.
+    pub fn dir(&self, name: &str) -> io::Result<PathBuf> {
+        let path = self.prepare(name)?;
+        fs::create_dir_all(&path)?;
+        Ok(path)
+    }
+
+    /// Create a hard link named `link` to the existing file `original`.
+    pub fn hard_link(
+        &self,
+        original: &str,
+        link: &str,
+    ) -> io::Result<PathBuf> {
+        let path = self.prepare(link)?;
+        hard_link(self.dir.path().join(original), &path)?;
+        Ok(path)
+    }
+
+    /// Create a symbolic link named `link` to the existing file `original`.
+    #[cfg(any(unix, windows))]
+    pub fn soft_link_file(
+        &self,
+        original: &str,
+        link: &str,
+    ) -> io::Result<PathBuf> {
+        let path = self.prepare(link)?;
+        soft_link_file(self.dir.path().join(original), &path)?;
+        Ok(path)
+    }
+
+    /// Create a symbolic link named `link` to the existing directory
+    /// `original`.
+    #[cfg(any(unix, windows))]
+    pub fn soft_link_dir(
+        &self,
+        original: &str,
+        link: &str,
+    ) -> io::Result<PathBuf> {
+        let path = self.prepare(link)?;
+        soft_link_dir(self.dir.path().join(original), &path)?;
+        Ok(path)
+    }
+
+    /// Create a file and a hard link to it: two paths, one file.
+    pub fn hard_linked_pair(
+        &self,
+        original: &str,
+        link: &str,
+    ) -> io::Result<(PathBuf, PathBuf)> {
+        Ok((self.file(original)?, self.hard_link(original, link)?))
+    }
+
+    /// Create a file and a symbolic link to it: two paths, one file.
+    #[cfg(any(unix, windows))]
+    pub fn soft_linked_pair(
+        &self,
+        original: &str,
+        link: &str,
+    ) -> io::Result<(PathBuf, PathBuf)> {
+        Ok((self.file(original)?, self.soft_link_file(original, link)?))
+    }
+
+    /// Create two distinct files with identical contents: two paths, two
+    /// files that only a content comparison would confuse.
+    pub fn identical_copies<C: AsRef<[u8]>>(
+        &self,
+        first: &str,
+        second: &str,
+        contents: C,
+    ) -> io::Result<(PathBuf, PathBuf)> {
+        let contents = contents.as_ref();
+        Ok((
+            self.file_with(first, contents)?,
+            self.file_with(second, contents)?,
+        ))
+    }
+}
+
+/// A second, temporary filesystem mounted for the duration of a test.
+///
+/// This is used to exercise cross-device identity cases, such as two files
+/// with the same inode number on different devices. Creating a filesystem
+/// requires privileges that are often unavailable, so [`SecondFs::new`]
+/// returns `None` when it cannot create one, and tests should skip
+/// themselves in that case.
+///
+/// On Linux, this mounts a small `tmpfs`, which requires `CAP_SYS_ADMIN`. On
+/// Windows, this creates, formats and mounts a small VHD with `diskpart`,
+/// which requires administrator rights. Other platforms are not supported.
+/// The filesystem is unmounted and removed when the value is dropped.
+#[derive(Debug)]
+#[must_use = "the filesystem is unmounted when it is dropped"]
+pub struct SecondFs {
+    mount_point: PathBuf,
+    // Kept last so that the directory is removed after unmounting.
+    dir: TempDir,
+}
+
+impl SecondFs {
+    /// Mount a new filesystem, or return `None` if the current process does
+    /// not have the privileges to do so.
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] if the mount point cannot
+    /// be prepared, or mounting fails for a reason other than a lack of
+    /// privileges.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn new() -> io::Result<Option<SecondFs>> {
+        let dir = TempDir::new()?;
+        let mount_point = dir.path().join("mnt");
+        fs::create_dir(&mount_point)?;
+        // Built before mounting, so that dropping it on every way out
+        // undoes a mount that failed halfway, such as a VHD that was
+        // attached but could not be formatted.
+        let second = SecondFs { mount_point, dir };
+        if !mount_second_fs(second.dir.path(), &second.mount_point)? {
+            return Ok(None);
+        }
+        Ok(Some(second))
+    }
+
+    /// The root directory of the mounted filesystem.
+    pub fn path(&self) -> &Path {
+        &self.mount_point
+    }
+}
+
+impl Drop for SecondFs {
+    fn drop(&mut self) {
+        unmount_second_fs(self.dir.path(), &self.mount_point);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn mount_second_fs(_dir: &Path, mount_point: &Path) -> io::Result<bool> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let target = CString::new(mount_point.as_os_str().as_bytes())?;
+    // SAFETY: All pointers are valid NUL-terminated strings for the duration
+    // of the call.
+    let result = unsafe {
+        libc::mount(
+            c"tmpfs".as_ptr(),
+            target.as_ptr(),
+            c"tmpfs".as_ptr(),
+            0,
+            c"size=1m".as_ptr().cast(),
+        )
+    };
+    if result == 0 {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EPERM) | Some(libc::EACCES) => Ok(false),
+        _ => Err(err),
+    }
+}
+
+// Unmounting a directory that is not a mount point fails harmlessly.
+#[cfg(target_os = "linux")]
+fn unmount_second_fs(_dir: &Path, mount_point: &Path) {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    if let Ok(target) = CString::new(mount_point.as_os_str().as_bytes()) {
+        // SAFETY: `target` is a valid NUL-terminated string.
+        unsafe { libc::umount2(target.as_ptr(), libc::MNT_DETACH) };
+    }
+}
+
+#[cfg(windows)]
+fn run_diskpart(dir: &Path, script: &str) -> io::Result<bool> {
+    let script_path = dir.join("diskpart.txt");
+    fs::write(&script_path, script)?;
+    let output =
+        process::Command::new("diskpart").arg("/s").arg(&script_path).output();
+    match output {
+        Ok(output) => Ok(output.status.success()),
+        // diskpart may be unavailable, or elevation may be refused.
+        Err(_) => Ok(false),
+    }
+}
+
+#[cfg(windows)]
+fn mount_second_fs(dir: &Path, mount_point: &Path) -> io::Result<bool> {
+    let vhd = dir.join("second.vhdx");
+    let script = format!(
+        "create vdisk file=\"{}\" maximum=16 type=expandable\n\
+         attach vdisk\n\
+         create partition primary\n\
+         format fs=ntfs quick\n\
+         assign mount=\"{}\"\n",
+        vhd.display(),
+        mount_point.display(),
+    );
+    run_diskpart(dir, &script)
+}
+
+// Detaching a VHD that was never attached fails harmlessly.
+#[cfg(windows)]
+fn unmount_second_fs(dir: &Path, _mount_point: &Path) {
+    let vhd = dir.join("second.vhdx");
+    let script =
+        format!("select vdisk file=\"{}\"\ndetach vdisk\n", vhd.display());
+    let _ = run_diskpart(dir, &script);
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn mount_second_fs(_dir: &Path, _mount_point: &Path) -> io::Result<bool> {
+    Ok(false)
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn unmount_second_fs(_dir: &Path, _mount_point: &Path) {}
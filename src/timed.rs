@@ -0,0 +1,91 @@
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::{AsRawFilelike, FileId};
+
+/// A [`FileId`] that is only trusted for a limited amount of time.
+///
+/// A bare `FileId` is only guaranteed to be meaningful while the file it was
+/// taken from stays open. A `TimedId` encodes a weaker, time-based version of
+/// that rule: once the time-to-live has elapsed, the identity is no longer
+/// available for comparison until it has been revalidated against an open
+/// file.
+///
+/// Created with [`FileId::with_ttl`].
+#[derive(Debug, Clone)]
+pub struct TimedId {
+    id: FileId,
+    acquired: Instant,
+    ttl: Duration,
+}
+
+impl FileId {
+    /// Wrap this identity in a [`TimedId`] that expires after `ttl`.
+    ///
+    /// The time-to-live is measured from the moment this method is called.
+    pub fn with_ttl(self, ttl: Duration) -> TimedId {
+        TimedId { id: self, acquired: Instant::now(), ttl }
+    }
+}
+
+impl TimedId {
+    /// Returns true if the time-to-live has elapsed since the identity was
+    /// acquired or last revalidated.
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        self.acquired.elapsed() >= self.ttl
+    }
+
+    /// The identity, or `None` if it has expired.
+    pub fn id(&self) -> Option<&FileId> {
+        if self.is_expired() { None } else { Some(&self.id) }
+    }
+
+    /// The identity, regardless of whether it has expired.
+    ///
+    /// The caller is responsible for ensuring that the identity is still
+    /// meaningful.
+    pub fn id_unchecked(&self) -> &FileId {
+        &self.id
+    }
+
+    /// The instant at which the identity was acquired or last revalidated.
+    pub fn acquired_at(&self) -> Instant {
+        self.acquired
+    }
+
+    /// The time-to-live of this identity.
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Compare this identity against another one.
+    ///
+    /// Returns `None` if this identity has expired, since the comparison
+    /// can no longer be trusted.
+    pub fn is_same_as(&self, other: &FileId) -> Option<bool> {
+        self.id().map(|id| id == other)
+    }
+
+    /// Revalidate the identity against an open file.
+    ///
+    /// If the file's identity matches the stored one, the expiry clock is
+    /// reset and `true` is returned. Otherwise the stored identity is left
+    /// untouched and `false` is returned.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] if the identity of the file
+    /// cannot be obtained.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn revalidate<F: AsRawFilelike>(
+        &mut self,
+        file: &F,
+    ) -> io::Result<bool> {
+        if FileId::from_file_like(file)? != self.id {
+            return Ok(false);
+        }
+        self.acquired = Instant::now();
+        Ok(true)
+    }
+}
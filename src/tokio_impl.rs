@@ -0,0 +1,100 @@
+//! Async constructors for use with `tokio`.
+//!
+//! Taking the identity of a file is a blocking system call, and opening a
+//! file may block for a long time on a slow filesystem. These constructors
+//! run that work on `tokio`'s blocking pool, so that it does not stall the
+//! reactor.
+
+use std::io;
+use std::path::Path;
+
+use crate::{FileId, Handle};
+
+async fn blocking<T, F>(f: F) -> io::Result<T>
+where
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f).await.map_err(io::Error::other)?
+}
+
+impl FileId {
+    /// Extract the identity of the file at a path on the blocking pool.
+    ///
+    /// This is the async equivalent of [`from_path`](FileId::from_path), and
+    /// shares its caveats. It must be called from within a `tokio` runtime.
+    ///
+    /// # Errors
+    /// This function fails as [`from_path`](FileId::from_path) does.
+    pub async fn from_path_async<P: AsRef<Path>>(
+        path: P,
+    ) -> io::Result<FileId> {
+        let path = path.as_ref().to_path_buf();
+        blocking(move || FileId::from_path(path)).await
+    }
+}
+
+impl Handle<tokio::fs::File> {
+    /// Open the file at a path and take its identity on the blocking pool.
+    ///
+    /// This is the async equivalent of [`from_path`](Handle::from_path). It
+    /// must be called from within a `tokio` runtime.
+    ///
+    /// # Errors
+    /// This function fails as [`from_path`](Handle::from_path) does.
+    pub async fn from_path_async<P: AsRef<Path>>(
+        path: P,
+    ) -> io::Result<Handle<tokio::fs::File>> {
+        let path = path.as_ref().to_path_buf();
+        let handle = blocking(move || Handle::from_path(path)).await?;
+        Ok(Handle::map_inner(handle, tokio::fs::File::from_std))
+    }
+
+    /// Wrap an open `tokio` file, taking its identity on the blocking pool.
+    ///
+    /// [`Handle::from_file_like`] also accepts a `tokio::fs::File`, but takes
+    /// the identity on the calling thread.
+    ///
+    /// # Errors
+    /// This function will return an [`io::Error`] if the identity of the
+    /// file cannot be obtained.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub async fn from_tokio_file(
+        file: tokio::fs::File,
+    ) -> io::Result<Handle<tokio::fs::File>> {
+        blocking(move || Handle::from_file_like(file)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+
+    use crate::testing::TempDir;
+    use crate::{FileId, Handle};
+
+    #[test]
+    fn async_constructors() {
+        let runtime =
+            tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let tdir = TempDir::new().unwrap();
+        let dir = tdir.path();
+        File::create(dir.join("a")).unwrap();
+        fs::hard_link(dir.join("a"), dir.join("alink")).unwrap();
+
+        runtime.block_on(async {
+            let a = Handle::from_path_async(dir.join("a")).await.unwrap();
+            let file = tokio::fs::File::open(dir.join("alink")).await.unwrap();
+            let alink = Handle::from_tokio_file(file).await.unwrap();
+            assert_eq!(a, alink);
+            assert_eq!(
+                FileId::from_path_async(dir.join("a")).await.unwrap(),
+                Handle::id(&a)
+            );
+            assert!(
+                Handle::from_path_async(dir.join("missing")).await.is_err()
+            );
+        });
+    }
+}
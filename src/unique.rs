@@ -0,0 +1,126 @@
+use std::io;
+use std::iter::FusedIterator;
+use std::path::Path;
+
+use crate::{Deduplicator, FileIdentitySource, OsIdentity};
+
+/// An iterator adapter that yields only the first path seen for each file.
+///
+/// Every path from the inner iterator is identified, following symbolic
+/// links, and skipped if an earlier path named the same file. This makes
+/// processing each physical file once straightforward for tools that take
+/// several, possibly overlapping, paths or directory walks.
+///
+/// Paths that cannot be identified are yielded as errors, and iteration can
+/// go on past them.
+///
+/// By default, the files are not kept open, so a file deleted during the
+/// iteration can have its identity reused by a new file, which would then be
+/// skipped. An adapter created with [`pinned`](UniqueFiles::pinned) keeps a
+/// [`Handle`](crate::Handle) to every file it yields instead, at the cost
+/// of one open file resource per file. For a bounded number of open files,
+/// use a [`Deduplicator`] directly. An adapter created with
+/// [`via`](UniqueFiles::via) takes the identities from another
+/// [`FileIdentitySource`] and never keeps files open.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use cross_file_id::UniqueFiles;
+///
+/// let args = ["./a", "./b", "./link-to-a"];
+/// for path in UniqueFiles::new(args) {
+///     println!("{}", path?);
+/// }
+/// # Ok::<_, std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct UniqueFiles<I, S = OsIdentity> {
+    paths: I,
+    seen: Deduplicator,
+    // The source of identities, if not the one of `seen`.
+    source: Option<S>,
+}
+
+impl<I: Iterator> UniqueFiles<I> {
+    /// Deduplicate the paths of an iterator, without keeping the files open.
+    pub fn new<T>(paths: T) -> UniqueFiles<I>
+    where
+        T: IntoIterator<IntoIter = I>,
+    {
+        UniqueFiles {
+            paths: paths.into_iter(),
+            seen: Deduplicator::new(),
+            source: None,
+        }
+    }
+
+    /// Deduplicate the paths of an iterator, keeping every file yielded open
+    /// until the adapter is dropped.
+    pub fn pinned<T>(paths: T) -> UniqueFiles<I>
+    where
+        T: IntoIterator<IntoIter = I>,
+    {
+        UniqueFiles {
+            paths: paths.into_iter(),
+            seen: Deduplicator::with_fd_budget(usize::MAX),
+            source: None,
+        }
+    }
+}
+
+impl<I: Iterator, S: FileIdentitySource> UniqueFiles<I, S> {
+    /// Deduplicate the paths of an iterator by the identities of another
+    /// source.
+    pub fn via<T>(source: S, paths: T) -> UniqueFiles<I, S>
+    where
+        T: IntoIterator<IntoIter = I>,
+    {
+        UniqueFiles {
+            paths: paths.into_iter(),
+            seen: Deduplicator::new(),
+            source: Some(source),
+        }
+    }
+
+    /// The number of distinct files yielded so far.
+    pub fn len_seen(&self) -> usize {
+        self.seen.len()
+    }
+}
+
+impl<I, S> Iterator for UniqueFiles<I, S>
+where
+    I: Iterator,
+    I::Item: AsRef<Path>,
+    S: FileIdentitySource,
+{
+    type Item = io::Result<I::Item>;
+
+    fn next(&mut self) -> Option<io::Result<I::Item>> {
+        for path in self.paths.by_ref() {
+            let seen = match self.source {
+                Some(ref source) => self.seen.first_seen_via(source, &path),
+                None => self.seen.first_seen(&path),
+            };
+            match seen {
+                Ok(true) => return Some(Ok(path)),
+                Ok(false) => {}
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.paths.size_hint().1)
+    }
+}
+
+impl<I, S> FusedIterator for UniqueFiles<I, S>
+where
+    I: FusedIterator,
+    I::Item: AsRef<Path>,
+    S: FileIdentitySource,
+{
+}
@@ -1,12 +1,19 @@
+use std::ffi::{CStr, OsStr, OsString};
+use std::fmt;
 use std::fs::{File, Metadata};
 use std::hash::Hash;
 use std::io;
+use std::os::unix::ffi::OsStrExt;
 use std::os::unix::fs::MetadataExt;
-use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
-use std::path::Path;
+use std::os::unix::io::{
+    AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, RawFd,
+};
+use std::path::{Path, PathBuf};
 
 use io_lifetimes::raw::{AsRawFilelike, FromRawFilelike, RawFilelike};
 
+use crate::DeviceClass;
+
 fn get_metadata_from_raw(fd: RawFilelike) -> io::Result<Metadata> {
     // SAFETY: Although we create a File from the file descriptor, we use
     // into_raw_fd() to avoid the drop closing the file descriptor when
@@ -22,19 +29,645 @@ fn get_metadata_from_raw(fd: RawFilelike) -> io::Result<Metadata> {
     }
 }
 
+/// What this module needs from a `stat` family call.
+///
+/// The mount id is kept apart from the identity, which only includes it
+/// when asked for with `with_mount`.
+pub(crate) struct Stat {
+    id: FileId,
+    mode: u32,
+    mnt: u64,
+}
+
+impl Stat {
+    fn from_metadata(md: &Metadata) -> Stat {
+        Stat {
+            id: FileId { dev: md.dev(), ino: md.ino(), mnt: 0, subvol: 0 },
+            mode: md.mode(),
+            mnt: 0,
+        }
+    }
+}
+
+fn stat_fd(fd: RawFilelike) -> io::Result<Stat> {
+    #[cfg(all(
+        target_os = "linux",
+        any(target_env = "gnu", target_env = "musl")
+    ))]
+    if let Some(stat) = statx::stat(fd, c"", libc::AT_EMPTY_PATH)? {
+        return Ok(stat);
+    }
+    Ok(Stat::from_metadata(&get_metadata_from_raw(fd)?))
+}
+
+fn stat_path(path: &Path, follow: bool) -> io::Result<Stat> {
+    #[cfg(all(
+        target_os = "linux",
+        any(target_env = "gnu", target_env = "musl")
+    ))]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+        let flags = if follow { 0 } else { libc::AT_SYMLINK_NOFOLLOW };
+        if let Some(stat) = statx::stat(libc::AT_FDCWD, &c_path, flags)? {
+            return Ok(stat);
+        }
+    }
+    let md = if follow {
+        std::fs::metadata(path)?
+    } else {
+        std::fs::symlink_metadata(path)?
+    };
+    Ok(Stat::from_metadata(&md))
+}
+
+/// `statx` reports the mount a file was reached through, which `fstat`
+/// does not. Bind mounts and some overlayfs setups show the same device and
+/// inode numbers through different mounts, and only the mount id tells
+/// them apart, for callers that ask for it.
+#[cfg(all(target_os = "linux", any(target_env = "gnu", target_env = "musl")))]
+pub(crate) mod statx {
+    use std::ffi::CStr;
+    use std::io;
+    use std::mem::MaybeUninit;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::{FileId, Stat};
+
+    static UNAVAILABLE: AtomicBool = AtomicBool::new(false);
+
+    /// False once `statx` was found to be missing.
+    pub fn available() -> bool {
+        !UNAVAILABLE.load(Ordering::Relaxed)
+    }
+
+    /// Returns `None` if `statx` is not available, in which case the caller
+    /// falls back to `stat`. Kernels before 4.11 lack it, and some seccomp
+    /// filters reject it with `EPERM`.
+    pub fn stat(
+        dirfd: libc::c_int,
+        path: &CStr,
+        flags: libc::c_int,
+    ) -> io::Result<Option<Stat>> {
+        if UNAVAILABLE.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        let mut buf = MaybeUninit::<libc::statx>::zeroed();
+        let mask = libc::STATX_TYPE | libc::STATX_INO | libc::STATX_MNT_ID;
+        // SAFETY: `path` is NUL-terminated and `buf` is large enough for
+        // the structure the kernel fills in.
+        let ret = unsafe {
+            libc::statx(
+                dirfd,
+                path.as_ptr(),
+                flags | libc::AT_STATX_SYNC_AS_STAT,
+                mask,
+                buf.as_mut_ptr(),
+            )
+        };
+        if ret != 0 {
+            let err = io::Error::last_os_error();
+            return match fallback(&err) {
+                Some(Fallback::Missing) => {
+                    UNAVAILABLE.store(true, Ordering::Relaxed);
+                    Ok(None)
+                }
+                Some(Fallback::Denied) => Ok(None),
+                None => Err(err),
+            };
+        }
+        // SAFETY: The call succeeded, so the kernel filled in `buf`.
+        let buf = unsafe { buf.assume_init() };
+        // Kernels before 5.8 do not report the mount id.
+        let mnt = if buf.stx_mask & libc::STATX_MNT_ID != 0 {
+            buf.stx_mnt_id
+        } else {
+            0
+        };
+        Ok(Some(Stat {
+            id: FileId {
+                dev: libc::makedev(buf.stx_dev_major, buf.stx_dev_minor),
+                ino: buf.stx_ino,
+                mnt: 0,
+                subvol: 0,
+            },
+            mode: buf.stx_mode.into(),
+            mnt,
+        }))
+    }
+
+    /// Why a failed `statx` call falls back to `stat`.
+    #[derive(Debug, PartialEq, Eq)]
+    pub enum Fallback {
+        /// The kernel lacks `statx`, so it is not tried again.
+        Missing,
+        /// A seccomp filter rejected the call, possibly only for some
+        /// arguments, so it is tried again next time.
+        Denied,
+    }
+
+    /// Whether an error from `statx` means falling back to `stat`, rather
+    /// than failing.
+    pub fn fallback(err: &io::Error) -> Option<Fallback> {
+        match err.raw_os_error() {
+            Some(libc::ENOSYS) => Some(Fallback::Missing),
+            Some(libc::EPERM) => Some(Fallback::Denied),
+            _ => None,
+        }
+    }
+
+    /// Whether mount ids can be added to identities.
+    pub fn mount_ids() -> bool {
+        matches!(
+            stat(libc::AT_FDCWD, c"/", 0),
+            Ok(Some(Stat { mnt, .. })) if mnt != 0
+        )
+    }
+}
+
+/// The platform tag that starts the packed form of a Unix `FileId`.
+const PACKED_TAG: u8 = 1;
+
+/// The mount id is zero unless it was asked for, and can only be known on
+/// Linux 5.8 and later. The btrfs subvolume id is zero unless it was asked
+/// for, and at most `SUBVOL_MAX` so that it fits in the packed form.
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct FileId {
     dev: u64,
     ino: u64,
+    mnt: u64,
+    subvol: u64,
 }
 
+/// The largest subvolume id that is kept as is. Larger ids, which btrfs
+/// does not allocate in practice, are all folded into this one.
+const SUBVOL_MAX: u64 = (1 << 56) - 1;
+
 impl FileId {
     pub fn from_filelike(f: RawFilelike) -> io::Result<FileId> {
-        Ok(FileId::from_metadata(&get_metadata_from_raw(f)?))
+        Ok(stat_fd(f)?.id)
+    }
+
+    /// On Linux, the identity from `statx` is checked against `fstat`,
+    /// which does not report the mount id. Elsewhere, `fstat` is the only
+    /// mechanism.
+    pub fn from_filelike_verified(f: RawFilelike) -> io::Result<FileId> {
+        let id = FileId::from_filelike(f)?;
+        let md = get_metadata_from_raw(f)?;
+        if (md.dev(), md.ino()) != (id.dev, id.ino) {
+            let secondary =
+                FileId { dev: md.dev(), ino: md.ino(), mnt: 0, subvol: 0 };
+            return Err(crate::Error::IdentityMismatch {
+                primary: crate::FileId(id),
+                secondary: crate::FileId(secondary),
+            }
+            .into());
+        }
+        Ok(id)
+    }
+
+    /// On btrfs, every subvolume has an inode namespace of its own, told
+    /// apart only by an anonymous device number that can change between
+    /// mounts. The filesystem UUID and the subvolume id are both stable, so
+    /// they take the place of the device number, at the cost of two cheap
+    /// ioctls.
+    #[cfg(target_os = "linux")]
+    pub fn with_subvolume(mut self, f: RawFilelike) -> io::Result<FileId> {
+        if let Some((fsid, subvol)) = btrfs::identity(f)? {
+            self.dev = fsid;
+            self.subvol = subvol.min(SUBVOL_MAX);
+        }
+        Ok(self)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn with_subvolume(self, _f: RawFilelike) -> io::Result<FileId> {
+        Ok(self)
+    }
+
+    /// The same inode reached through two bind mounts has the same identity,
+    /// unless the mount id is added. It is left at zero where `statx` or its
+    /// mount id is not available.
+    #[cfg(all(
+        target_os = "linux",
+        any(target_env = "gnu", target_env = "musl")
+    ))]
+    pub fn with_mount(mut self, f: RawFilelike) -> io::Result<FileId> {
+        if let Some(stat) = statx::stat(f, c"", libc::AT_EMPTY_PATH)? {
+            self.mnt = stat.mnt;
+        }
+        Ok(self)
+    }
+
+    #[cfg(not(all(
+        target_os = "linux",
+        any(target_env = "gnu", target_env = "musl")
+    )))]
+    pub fn with_mount(self, _f: RawFilelike) -> io::Result<FileId> {
+        Ok(self)
+    }
+
+    /// A mount id is never zero, so it is only zero when it was not asked
+    /// for, or could not be known.
+    pub fn is_mount_aware(&self) -> bool {
+        self.mnt != 0
+    }
+
+    /// btrfs subvolume ids start at 5, so the id is only zero when it was
+    /// not asked for, or the file is not on btrfs.
+    pub fn is_subvolume_aware(&self) -> bool {
+        self.subvol != 0
+    }
+
+    pub fn from_filelike_classified(
+        f: RawFilelike,
+    ) -> io::Result<(FileId, DeviceClass)> {
+        let stat = stat_fd(f)?;
+        Ok((stat.id, classify(f, stat.mode)))
+    }
+
+    /// Unix identities do not depend on how the file was reached, unless the
+    /// mount id was asked for, so there is nothing to normalize.
+    pub fn from_filelike_normalized(f: RawFilelike) -> io::Result<FileId> {
+        FileId::from_filelike(f)
+    }
+
+    /// A plain `stat`, so no descriptor is opened at all.
+    pub fn from_path(path: &Path) -> io::Result<FileId> {
+        Ok(stat_path(path, true)?.id)
     }
 
-    pub fn from_metadata(md: &Metadata) -> FileId {
-        FileId { dev: md.dev(), ino: md.ino() }
+    pub fn from_path_no_follow(path: &Path) -> io::Result<FileId> {
+        Ok(stat_path(path, false)?.id)
+    }
+
+    pub fn volume(&self) -> u64 {
+        self.dev
+    }
+
+    pub fn index_bits(&self) -> u32 {
+        u64::BITS - self.ino.leading_zeros()
+    }
+
+    pub fn volume_bound(volume: u64, max: bool) -> Option<FileId> {
+        let fill = if max { u64::MAX } else { 0 };
+        let subvol = if max { SUBVOL_MAX } else { 0 };
+        Some(FileId { dev: volume, ino: fill, mnt: fill, subvol })
+    }
+
+    #[cfg(feature = "test-util")]
+    pub fn fabricated(volume: u64, index: u64) -> FileId {
+        FileId { dev: volume, ino: index, mnt: 0, subvol: 0 }
+    }
+
+    /// Layout: tag, device, inode and mount id (all big-endian), then the
+    /// low 7 bytes of the subvolume id (big-endian).
+    pub fn pack(&self) -> [u8; 32] {
+        let mut packed = [0; 32];
+        packed[0] = PACKED_TAG;
+        packed[1..9].copy_from_slice(&self.dev.to_be_bytes());
+        packed[9..17].copy_from_slice(&self.ino.to_be_bytes());
+        packed[17..25].copy_from_slice(&self.mnt.to_be_bytes());
+        packed[25..32].copy_from_slice(&self.subvol.to_be_bytes()[1..]);
+        packed
+    }
+
+    pub fn unpack(packed: &[u8; 32]) -> Option<FileId> {
+        if packed[0] != PACKED_TAG {
+            return None;
+        }
+        let mut subvol = [0; 8];
+        subvol[1..].copy_from_slice(&packed[25..32]);
+        Some(FileId {
+            dev: u64::from_be_bytes(packed[1..9].try_into().unwrap()),
+            ino: u64::from_be_bytes(packed[9..17].try_into().unwrap()),
+            mnt: u64::from_be_bytes(packed[17..25].try_into().unwrap()),
+            subvol: u64::from_be_bytes(subvol),
+        })
+    }
+}
+
+/// The directory entry's inode is available from `readdir` without a stat
+/// call. The device and mount are assumed to be the parent's, which is
+/// wrong for mount points, so the result is only a hint.
+pub fn entry_weak_id(parent: &FileId, entry: &DirEntry) -> Option<FileId> {
+    Some(FileId { ino: entry.ino, ..*parent })
+}
+
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+use libc::readdir;
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+use libc::readdir64 as readdir;
+
+/// An entry read from a directory stream.
+pub struct DirEntry {
+    name: OsString,
+    ino: u64,
+}
+
+impl DirEntry {
+    pub fn into_name(self) -> OsString {
+        self.name
+    }
+}
+
+/// A directory stream over a description of its own, so that its position
+/// is not shared with the descriptor it was opened from.
+#[derive(Debug)]
+pub struct ReadDir(std::ptr::NonNull<libc::DIR>);
+
+// SAFETY: the stream is only used through `&mut self`, and `readdir` is safe
+// to call from any thread on a stream no other thread uses.
+unsafe impl Send for ReadDir {}
+// SAFETY: no method takes `&self`.
+unsafe impl Sync for ReadDir {}
+
+impl Drop for ReadDir {
+    fn drop(&mut self) {
+        // SAFETY: the stream is open, and owned by this value.
+        unsafe { libc::closedir(self.0.as_ptr()) };
+    }
+}
+
+/// Read a directory through its descriptor, with `fdopendir`.
+///
+/// The descriptor is not duplicated with `dup`, which would share its
+/// position, but `.` is opened relative to it, which reaches the same
+/// directory without going through its path.
+pub fn read_dir(dir: RawFilelike, _path: &Path) -> io::Result<ReadDir> {
+    let flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC;
+    // SAFETY: the path is a valid C string, and an invalid directory is
+    // reported through the result.
+    let fd = unsafe { libc::openat(dir, c".".as_ptr(), flags) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the descriptor was just opened, and the stream takes it over.
+    let stream = unsafe { libc::fdopendir(fd) };
+    match std::ptr::NonNull::new(stream) {
+        Some(stream) => Ok(ReadDir(stream)),
+        None => {
+            let err = io::Error::last_os_error();
+            // SAFETY: the descriptor was not taken over by a stream.
+            unsafe { libc::close(fd) };
+            Err(err)
+        }
+    }
+}
+
+impl Iterator for ReadDir {
+    type Item = io::Result<DirEntry>;
+
+    fn next(&mut self) -> Option<io::Result<DirEntry>> {
+        loop {
+            let cleared = clear_errno();
+            // SAFETY: the stream is open, and only used through `&mut self`.
+            let entry = unsafe { readdir(self.0.as_ptr()) };
+            if entry.is_null() {
+                let err = io::Error::last_os_error();
+                return match err.raw_os_error() {
+                    Some(errno) if cleared && errno != 0 => Some(Err(err)),
+                    _ => None,
+                };
+            }
+            // SAFETY: the entry stays valid until the next call on the
+            // stream, and its name is terminated.
+            let (name, ino) = unsafe {
+                (CStr::from_ptr((*entry).d_name.as_ptr()), entry_ino(&*entry))
+            };
+            let name = name.to_bytes();
+            if name == b"." || name == b".." {
+                continue;
+            }
+            let name = OsStr::from_bytes(name).to_os_string();
+            return Some(Ok(DirEntry { name, ino }));
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+fn entry_ino(entry: &libc::dirent64) -> u64 {
+    entry.d_ino
+}
+
+#[cfg(any(
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+fn entry_ino(entry: &libc::dirent) -> u64 {
+    entry.d_fileno as u64
+}
+
+#[cfg(not(any(
+    all(target_os = "linux", target_env = "gnu"),
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+)))]
+fn entry_ino(entry: &libc::dirent) -> u64 {
+    entry.d_ino as u64
+}
+
+/// Clear `errno`, so that the end of a directory can be told apart from a
+/// failure to read it. Where its location is not known, this returns false,
+/// and a failure ends the listing early.
+fn clear_errno() -> bool {
+    #[cfg(any(target_os = "solaris", target_os = "illumos"))]
+    use libc::___errno as errno;
+    #[cfg(any(
+        target_os = "android",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    ))]
+    use libc::__errno as errno;
+    #[cfg(any(target_os = "linux", target_os = "emscripten"))]
+    use libc::__errno_location as errno;
+    #[cfg(any(target_vendor = "apple", target_os = "freebsd"))]
+    use libc::__error as errno;
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "emscripten",
+        target_os = "android",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_vendor = "apple",
+        target_os = "freebsd",
+        target_os = "solaris",
+        target_os = "illumos",
+    ))]
+    {
+        // SAFETY: `errno` is thread-local, and always writable.
+        unsafe { *errno() = 0 };
+        true
+    }
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "emscripten",
+        target_os = "android",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_vendor = "apple",
+        target_os = "freebsd",
+        target_os = "solaris",
+        target_os = "illumos",
+    )))]
+    {
+        false
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod btrfs {
+    use std::io;
+    use std::mem::MaybeUninit;
+
+    const SUPER_MAGIC: u32 = 0x9123_683e;
+    /// `_IOWR(0x94, 18, struct btrfs_ioctl_ino_lookup_args)`.
+    const IOC_INO_LOOKUP: u32 = 0xd000_9412;
+    /// `_IOR(0x94, 31, struct btrfs_ioctl_fs_info_args)`.
+    const IOC_FS_INFO: u32 = 0x8400_941f;
+    /// The object id of the root directory of every subvolume.
+    const FIRST_FREE_OBJECTID: u64 = 256;
+
+    #[repr(C)]
+    struct InoLookupArgs {
+        treeid: u64,
+        objectid: u64,
+        name: [u8; 4080],
+    }
+
+    #[repr(C)]
+    struct FsInfoArgs {
+        max_id: u64,
+        num_devices: u64,
+        fsid: [u8; 16],
+        rest: [u8; 992],
+    }
+
+    /// The UUID of the filesystem the file is on, folded to 64 bits, and
+    /// the id of the subvolume it is in, or `None` if it is not on btrfs.
+    pub fn identity(fd: libc::c_int) -> io::Result<Option<(u64, u64)>> {
+        let mut fs = MaybeUninit::<libc::statfs>::zeroed();
+        // SAFETY: `fs` is large enough for the structure the kernel fills
+        // in.
+        if unsafe { libc::fstatfs(fd, fs.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: The call succeeded, so the kernel filled in `fs`.
+        let fs = unsafe { fs.assume_init() };
+        if fs.f_type as u32 != SUPER_MAGIC {
+            return Ok(None);
+        }
+        // The flags in `rest` ask for nothing beyond the basic fields when
+        // zeroed.
+        let mut info = FsInfoArgs {
+            max_id: 0,
+            num_devices: 0,
+            fsid: [0; 16],
+            rest: [0; 992],
+        };
+        // SAFETY: `info` has the layout the ioctl expects.
+        if unsafe { libc::ioctl(fd, IOC_FS_INFO as _, &mut info) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let (high, low) = info.fsid.split_at(8);
+        let fsid = u64::from_be_bytes(high.try_into().unwrap())
+            ^ u64::from_be_bytes(low.try_into().unwrap());
+        // A tree id of zero and the first free object id ask for the
+        // containing subvolume, which needs no privilege.
+        let mut args = InoLookupArgs {
+            treeid: 0,
+            objectid: FIRST_FREE_OBJECTID,
+            name: [0; 4080],
+        };
+        // SAFETY: `args` has the layout the ioctl expects.
+        if unsafe { libc::ioctl(fd, IOC_INO_LOOKUP as _, &mut args) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Some((fsid, args.treeid)))
+    }
+}
+
+pub fn device_class(f: RawFilelike) -> io::Result<DeviceClass> {
+    Ok(classify(f, stat_fd(f)?.mode))
+}
+
+pub fn link_count(f: RawFilelike) -> io::Result<u64> {
+    Ok(get_metadata_from_raw(f)?.nlink())
+}
+
+// The file type bits of `st_mode`, which have the same values on every
+// Unix. The `libc` constants differ in type between platforms.
+const S_IFMT: u32 = 0o170000;
+const S_IFSOCK: u32 = 0o140000;
+const S_IFLNK: u32 = 0o120000;
+const S_IFREG: u32 = 0o100000;
+const S_IFBLK: u32 = 0o060000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFCHR: u32 = 0o020000;
+const S_IFIFO: u32 = 0o010000;
+
+fn classify(f: RawFilelike, mode: u32) -> DeviceClass {
+    match mode & S_IFMT {
+        S_IFREG => DeviceClass::RegularFile,
+        S_IFDIR => DeviceClass::Directory,
+        S_IFLNK => DeviceClass::Symlink,
+        S_IFBLK => DeviceClass::BlockDevice,
+        // SAFETY: `isatty` only inspects the descriptor.
+        S_IFCHR if unsafe { libc::isatty(f) } == 1 => DeviceClass::Console,
+        S_IFCHR => DeviceClass::CharDevice,
+        S_IFIFO => DeviceClass::Pipe,
+        S_IFSOCK => DeviceClass::Socket,
+        _ => DeviceClass::Other,
+    }
+}
+
+/// Formatted as `dev:ino` in decimal, followed by `:mnt` when the mount id
+/// or the subvolume id is known, and by `:subvol` when the latter is.
+impl fmt::Display for FileId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.dev, self.ino)?;
+        if self.mnt != 0 || self.subvol != 0 {
+            write!(f, ":{}", self.mnt)?;
+        }
+        if self.subvol != 0 {
+            write!(f, ":{}", self.subvol)?;
+        }
+        Ok(())
+    }
+}
+
+impl FileId {
+    /// Parse the form produced by `Display`, which has a single spelling
+    /// for each identity.
+    pub fn parse(s: &str) -> Option<FileId> {
+        let mut parts = s.splitn(4, ':');
+        let mut decimal = || parts.next().map(crate::parse_decimal::<u64>);
+        let dev = decimal()??;
+        let ino = decimal()??;
+        let mnt = match decimal() {
+            Some(mnt) => Some(mnt?),
+            None => None,
+        };
+        let subvol = match decimal() {
+            Some(subvol) => Some(subvol?),
+            None => None,
+        };
+        let (mnt, subvol) = match (mnt, subvol) {
+            (None, _) => (0, 0),
+            (Some(0), None) => return None,
+            (Some(mnt), None) => (mnt, 0),
+            (Some(mnt), Some(subvol))
+                if subvol != 0 && subvol <= SUBVOL_MAX =>
+            {
+                (mnt, subvol)
+            }
+            (Some(_), Some(_)) => return None,
+        };
+        Some(FileId { dev, ino, mnt, subvol })
     }
 }
 
@@ -52,6 +685,15 @@ where
     }
 }
 
+impl<F> AsFd for crate::Handle<F>
+where
+    F: AsFd,
+{
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.handle.as_fd()
+    }
+}
+
 impl<F> FromRawFd for crate::Handle<F>
 where
     F: AsRawFilelike + FromRawFd,
@@ -71,6 +713,444 @@ where
     }
 }
 
+#[cfg(all(target_os = "linux", any(target_env = "gnu", target_env = "musl")))]
+pub fn fallbacks(_id: &FileId) -> Vec<&'static str> {
+    let mut fallbacks = Vec::new();
+    if !statx::available() {
+        fallbacks.push("statx is unavailable, identities come from fstat");
+    }
+    if !statx::mount_ids() {
+        fallbacks.push("no mount ids, mount-aware identities are the same");
+    }
+    fallbacks
+}
+
+#[cfg(all(
+    target_os = "linux",
+    not(any(target_env = "gnu", target_env = "musl"))
+))]
+pub fn fallbacks(_id: &FileId) -> Vec<&'static str> {
+    vec!["statx is not used with this libc, identities come from fstat"]
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn fallbacks(_id: &FileId) -> Vec<&'static str> {
+    Vec::new()
+}
+
+pub fn capabilities() -> crate::Capabilities {
+    let linux = cfg!(any(target_os = "linux", target_os = "android"));
+    crate::Capabilities {
+        identity: true,
+        heuristic: false,
+        pins_while_open: true,
+        // Device numbers are assigned at mount time.
+        stable_across_reboots: false,
+        #[cfg(all(
+            target_os = "linux",
+            any(target_env = "gnu", target_env = "musl")
+        ))]
+        mount_ids: statx::mount_ids(),
+        #[cfg(not(all(
+            target_os = "linux",
+            any(target_env = "gnu", target_env = "musl")
+        )))]
+        mount_ids: false,
+        reflink_detection: false,
+        current_path: (linux && Path::new("/proc/self/fd").is_dir())
+            || cfg!(any(target_os = "macos", target_os = "ios")),
+        open_no_follow: cfg!(any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "macos",
+            target_os = "ios"
+        )),
+        minimal_access: linux,
+        mount_watch: cfg!(all(feature = "mount-watch", target_os = "linux"))
+            && Path::new("/proc/self/mountinfo").is_file(),
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn current_path(f: RawFilelike) -> io::Result<PathBuf> {
+    std::fs::read_link(format!("/proc/self/fd/{}", f))
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+pub fn current_path(f: RawFilelike) -> io::Result<PathBuf> {
+    use std::ffi::{CStr, OsStr};
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut buf = [0 as libc::c_char; libc::PATH_MAX as usize];
+    // SAFETY: `F_GETPATH` writes a NUL-terminated path of at most
+    // `MAXPATHLEN` bytes, which is `PATH_MAX`, into the buffer.
+    if unsafe { libc::fcntl(f, libc::F_GETPATH, buf.as_mut_ptr()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the buffer was NUL-terminated by `F_GETPATH`.
+    let path = unsafe { CStr::from_ptr(buf.as_ptr()) };
+    Ok(PathBuf::from(OsStr::from_bytes(path.to_bytes())))
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios"
+)))]
+pub fn current_path(_f: RawFilelike) -> io::Result<PathBuf> {
+    Err(crate::Error::UnsupportedPlatform {
+        operation: "recovering the path of an open file",
+    }
+    .into())
+}
+
+/// The devices of every mounted filesystem, from the `major:minor` field of
+/// `/proc/self/mountinfo` and from the mount point itself.
+///
+/// The `major:minor` field is that of the superblock, which is not what
+/// files report on btrfs, where every subvolume has an anonymous device of
+/// its own, nor on overlayfs. The mount point is then statted as well, except
+/// on network filesystems, where `stat` can hang on an unresponsive server
+/// and the field is right anyway.
+#[cfg(all(feature = "mount-watch", target_os = "linux"))]
+pub fn mounted_volumes() -> io::Result<std::collections::HashSet<u64>> {
+    use std::os::unix::ffi::OsStringExt;
+
+    const NETWORK: &[&str] =
+        &["nfs", "nfs4", "cifs", "smb3", "smbfs", "ceph", "9p", "afs"];
+
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo")?;
+    let mut volumes = std::collections::HashSet::new();
+    for line in mountinfo.lines() {
+        let mut fields = line.split(' ');
+        let Some((major, minor)) =
+            fields.nth(2).and_then(|f| f.split_once(':'))
+        else {
+            continue;
+        };
+        if let (Ok(major), Ok(minor)) = (major.parse(), minor.parse()) {
+            volumes.insert(libc::makedev(major, minor));
+        }
+        let Some(mount_point) = fields.nth(1) else {
+            continue;
+        };
+        let fstype = line
+            .split_once(" - ")
+            .and_then(|(_, rest)| rest.split(' ').next())
+            .unwrap_or("");
+        if NETWORK.contains(&fstype) || fstype.starts_with("fuse.") {
+            continue;
+        }
+        let path =
+            std::ffi::OsString::from_vec(unescape_mount_point(mount_point));
+        match std::fs::metadata(&path) {
+            Ok(md) => {
+                volumes.insert(md.dev());
+            }
+            Err(err) => {
+                log_debug!("cannot stat mount point {:?}: {}", path, err);
+            }
+        }
+    }
+    Ok(volumes)
+}
+
+/// Undo the octal escapes of spaces, tabs, newlines and backslashes in the
+/// paths of `/proc/self/mountinfo`.
+#[cfg(all(feature = "mount-watch", target_os = "linux"))]
+fn unescape_mount_point(field: &str) -> Vec<u8> {
+    let bytes = field.as_bytes();
+    let mut path = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes.get(i + 1..i + 4).and_then(|octal| {
+            let octal = std::str::from_utf8(octal).ok()?;
+            u8::from_str_radix(octal, 8).ok()
+        });
+        match escape {
+            Some(byte) if bytes[i] == b'\\' => {
+                path.push(byte);
+                i += 4;
+            }
+            _ => {
+                path.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    path
+}
+
+#[cfg(all(feature = "mount-watch", not(target_os = "linux")))]
+pub fn mounted_volumes() -> io::Result<std::collections::HashSet<u64>> {
+    Err(crate::Error::UnsupportedPlatform {
+        operation: "listing mounted volumes",
+    }
+    .into())
+}
+
+pub fn is_valid_raw(f: RawFilelike) -> bool {
+    f >= 0
+}
+
+pub fn set_inheritable(f: RawFilelike, inheritable: bool) -> io::Result<()> {
+    // SAFETY: F_GETFD and F_SETFD only manipulate the descriptor flags, and
+    // an invalid descriptor is reported through the return value.
+    unsafe {
+        let flags = libc::fcntl(f, libc::F_GETFD);
+        if flags == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let flags = if inheritable {
+            flags & !libc::FD_CLOEXEC
+        } else {
+            flags | libc::FD_CLOEXEC
+        };
+        if libc::fcntl(f, libc::F_SETFD, flags) == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Clear `O_NONBLOCK`, which is set to open files that could block.
+pub fn set_blocking(f: RawFilelike) -> io::Result<()> {
+    // SAFETY: F_GETFL and F_SETFL only manipulate the status flags, and an
+    // invalid descriptor is reported through the return value.
+    unsafe {
+        let flags = libc::fcntl(f, libc::F_GETFL);
+        if flags == -1
+            || libc::fcntl(f, libc::F_SETFL, flags & !libc::O_NONBLOCK) == -1
+        {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
 pub fn open_file(path: &Path) -> io::Result<std::fs::File> {
     std::fs::OpenOptions::new().read(true).open(path)
 }
+
+/// Open a path relative to an open directory, with `openat`.
+pub fn open_file_at(
+    dir: RawFilelike,
+    path: &Path,
+) -> io::Result<std::fs::File> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    // SAFETY: the path is a valid C string, and an invalid directory is
+    // reported through the result.
+    let fd = unsafe {
+        libc::openat(dir, path.as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC)
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the descriptor was just opened, and is owned by nobody else.
+    Ok(unsafe { std::fs::File::from_raw_fd(fd) })
+}
+
+/// Open a path relative to an open directory without leaving it: `..` may
+/// not climb above the directory, and symbolic links may not lead out of
+/// it. Returns `None` if the path leads outside.
+///
+/// Linux 5.6 and later resolve the path in the kernel, with `openat2` and
+/// `RESOLVE_BENEATH`. Elsewhere, or where a seccomp filter rejects
+/// `openat2`, the path is walked one component at a time with `O_NOFOLLOW`,
+/// and symbolic links are read and followed by hand.
+pub fn open_beneath(
+    dir: RawFilelike,
+    path: &Path,
+) -> io::Result<Option<std::fs::File>> {
+    #[cfg(target_os = "linux")]
+    match openat2_beneath(dir, path) {
+        Err(err)
+            if matches!(
+                err.raw_os_error(),
+                Some(libc::ENOSYS | libc::EPERM)
+            ) => {}
+        result => return result,
+    }
+    walk_beneath(dir, path)
+}
+
+#[cfg(target_os = "linux")]
+fn openat2_beneath(
+    dir: RawFilelike,
+    path: &Path,
+) -> io::Result<Option<std::fs::File>> {
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    // SAFETY: `open_how` is plain data, for which zero is a valid value.
+    let mut how: libc::open_how = unsafe { std::mem::zeroed() };
+    how.flags = (libc::O_RDONLY | libc::O_CLOEXEC) as u64;
+    how.resolve = libc::RESOLVE_BENEATH | libc::RESOLVE_NO_MAGICLINKS;
+    loop {
+        // SAFETY: the path is a valid C string and `how` is as large as it
+        // is said to be. An invalid directory is reported through the
+        // result.
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_openat2,
+                dir,
+                path.as_ptr(),
+                &how as *const libc::open_how,
+                std::mem::size_of::<libc::open_how>(),
+            )
+        };
+        if fd >= 0 {
+            // SAFETY: the descriptor was just opened, and is owned by nobody
+            // else.
+            return Ok(Some(unsafe { File::from_raw_fd(fd as RawFd) }));
+        }
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::EXDEV) => return Ok(None),
+            // A rename raced with the resolution of `..`.
+            Some(libc::EAGAIN) => continue,
+            _ => return Err(err),
+        }
+    }
+}
+
+/// The most symbolic links followed by `walk_beneath`, as on Linux.
+const MAX_LINKS: usize = 40;
+
+pub fn walk_beneath(
+    dir: RawFilelike,
+    path: &Path,
+) -> io::Result<Option<std::fs::File>> {
+    let mut pending = Vec::new();
+    push_components(&mut pending, path.as_os_str().as_bytes());
+    // The directories opened below `dir`, so that `..` can go back up
+    // without looking it up, nor climbing above `dir`.
+    let mut stack: Vec<File> = Vec::new();
+    let mut links = 0;
+    while let Some(part) = pending.pop() {
+        if part == b".." {
+            if stack.pop().is_none() {
+                return Ok(None);
+            }
+            continue;
+        }
+        let at = stack.last().map_or(dir, |parent| parent.as_raw_fd());
+        let last = pending.is_empty();
+        let name = std::ffi::CString::new(part)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let mut flags = libc::O_RDONLY | libc::O_CLOEXEC | libc::O_NOFOLLOW;
+        if !last {
+            flags |= libc::O_DIRECTORY;
+        }
+        // SAFETY: the name is a valid C string, and `at` is open.
+        let fd = unsafe { libc::openat(at, name.as_ptr(), flags) };
+        if fd >= 0 {
+            // SAFETY: the descriptor was just opened, and is owned by nobody
+            // else.
+            let file = unsafe { File::from_raw_fd(fd) };
+            if last {
+                return Ok(Some(file));
+            }
+            stack.push(file);
+            continue;
+        }
+        // `O_NOFOLLOW` fails on a symbolic link, with an error that differs
+        // between platforms, so any failure is checked for one.
+        let err = io::Error::last_os_error();
+        let Ok(target) = read_link_at(at, &name) else {
+            return Err(err);
+        };
+        links += 1;
+        if links > MAX_LINKS {
+            return Err(io::Error::from_raw_os_error(libc::ELOOP));
+        }
+        if target.first() == Some(&b'/') {
+            return Ok(None);
+        }
+        push_components(&mut pending, &target);
+    }
+    // The path was empty, or ended with `..`.
+    let at = stack.last().map_or(dir, |parent| parent.as_raw_fd());
+    open_file_at(at, Path::new(".")).map(Some)
+}
+
+/// Push the components of a path in reverse, so that the first one is
+/// popped first. Empty components and `.` are dropped.
+fn push_components(pending: &mut Vec<Vec<u8>>, path: &[u8]) {
+    for part in path.split(|&byte| byte == b'/').rev() {
+        if !part.is_empty() && part != b"." {
+            pending.push(part.to_vec());
+        }
+    }
+}
+
+fn read_link_at(dir: RawFd, name: &CStr) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0u8; 256];
+    loop {
+        // SAFETY: the buffer is as large as it is said to be, and the name
+        // is a valid C string.
+        let len = unsafe {
+            libc::readlinkat(
+                dir,
+                name.as_ptr(),
+                buf.as_mut_ptr().cast(),
+                buf.len(),
+            )
+        };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // The target is truncated if it fills the buffer.
+        if (len as usize) < buf.len() {
+            buf.truncate(len as usize);
+            return Ok(buf);
+        }
+        buf.resize(buf.len() * 2, 0);
+    }
+}
+
+/// The flags that open the path itself, rather than the target of a
+/// symbolic link.
+///
+/// Linux can open a symbolic link as an `O_PATH` descriptor, which supports
+/// `fstat` but not reading, and macOS has `O_SYMLINK` for the same purpose.
+/// Elsewhere, opening a symbolic link fails with `ELOOP`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const NO_FOLLOW: libc::c_int = libc::O_PATH | libc::O_NOFOLLOW;
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+const NO_FOLLOW: libc::c_int = libc::O_SYMLINK;
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios"
+)))]
+const NO_FOLLOW: libc::c_int = libc::O_NOFOLLOW;
+
+/// Open the path itself, rather than the target of a symbolic link.
+pub fn open_file_no_follow(path: &Path) -> io::Result<std::fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    std::fs::OpenOptions::new().read(true).custom_flags(NO_FOLLOW).open(path)
+}
+
+// Backup semantics and share modes only exist on Windows.
+pub fn open_with_options(
+    path: &Path,
+    options: &crate::HandleOptions,
+) -> io::Result<std::fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut flags = 0;
+    if !options.follow_symlinks {
+        flags |= NO_FOLLOW;
+    }
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if options.minimal_access {
+        flags |= libc::O_PATH;
+    }
+    std::fs::OpenOptions::new().read(true).custom_flags(flags).open(path)
+}
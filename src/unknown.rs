@@ -1,83 +1,205 @@
+// This implementation is to allow cross-file-id to be compiled on
+// unsupported platforms in case it was incidentally included as a transitive,
+// unused dependency. No `FileId` can be created, so every operation that
+// would produce one fails at runtime.
+
+use std::convert::Infallible as Never;
+use std::ffi::OsString;
+use std::fmt;
 use std::fs::File;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use io_lifetimes::raw::RawFilelike;
-use std::convert::Infallible as Never;
+use crate::{DeviceClass, Error};
+
+// io-lifetimes is empty on this platform, so these stand in for the parts of
+// it the rest of the crate is written against. The functions that need more
+// of it, to borrow, duplicate or take ownership of files, are not compiled.
+
+/// A placeholder for a raw descriptor or handle, which refers to nothing.
+#[derive(Debug, Clone, Copy)]
+pub struct RawFilelike(());
 
-static ERROR_MESSAGE: &str = "same-file is not supported on this platform.";
+pub trait AsRawFilelike {
+    fn as_raw_filelike(&self) -> RawFilelike;
+}
 
-#[derive(Debug, Clone, Copy, Eq, Hash)]
+impl<T: ?Sized> AsRawFilelike for T {
+    fn as_raw_filelike(&self) -> RawFilelike {
+        RawFilelike(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct FileId(Never);
 
+fn error<T>() -> io::Result<T> {
+    Err(Error::UnsupportedPlatform { operation: "file identity" }.into())
+}
+
+impl fmt::Display for FileId {
+    fn fmt(&self, _f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {}
+    }
+}
+
 impl FileId {
     pub fn from_filelike(_f: RawFilelike) -> io::Result<FileId> {
         error()
     }
-}
 
-impl PartialEq for FileId {
-    fn eq(&self, _other: &FileId) -> bool {
-        match self.0 {}
+    pub fn from_filelike_verified(_f: RawFilelike) -> io::Result<FileId> {
+        error()
     }
-}
 
-impl PartialOrd for FileId {
-    fn partial_cmp(&self, _other: &FileId) -> Option<std::cmp::Ordering> {
+    pub fn with_subvolume(self, _f: RawFilelike) -> io::Result<FileId> {
         match self.0 {}
     }
-}
 
-impl Ord for FileIdentity {
-    fn cmp(&self, _other: &FileIdentity) -> std::cmp::Ordering {
+    pub fn with_mount(self, _f: RawFilelike) -> io::Result<FileId> {
         match self.0 {}
     }
-}
 
-// This implementation is to allow same-file to be compiled on
-// unsupported platforms in case it was incidentally included
-// as a transitive, unused dependency
-#[derive(Debug, Hash)]
-pub struct Handle(Never);
-
-impl Eq for Handle {}
+    pub fn is_mount_aware(&self) -> bool {
+        match self.0 {}
+    }
 
-impl PartialEq for Handle {
-    fn eq(&self, _other: &Handle) -> bool {
+    pub fn is_subvolume_aware(&self) -> bool {
         match self.0 {}
     }
-}
 
-impl Handle {
-    pub fn from_path<P: AsRef<Path>>(_p: P) -> io::Result<Handle> {
+    pub fn from_filelike_classified(
+        _f: RawFilelike,
+    ) -> io::Result<(FileId, DeviceClass)> {
         error()
     }
 
-    pub fn from_file(_file: File) -> io::Result<Handle> {
+    pub fn from_filelike_normalized(_f: RawFilelike) -> io::Result<FileId> {
         error()
     }
 
-    pub fn stdin() -> io::Result<Handle> {
+    pub fn from_path(_path: &Path) -> io::Result<FileId> {
         error()
     }
 
-    pub fn stdout() -> io::Result<Handle> {
+    pub fn from_path_no_follow(_path: &Path) -> io::Result<FileId> {
         error()
     }
 
-    pub fn stderr() -> io::Result<Handle> {
-        error()
+    pub fn volume(&self) -> u64 {
+        match self.0 {}
     }
 
-    pub fn as_file(&self) -> &File {
+    pub fn index_bits(&self) -> u32 {
         match self.0 {}
     }
 
-    pub fn as_file_mut(&self) -> &mut File {
+    pub fn volume_bound(_volume: u64, _max: bool) -> Option<FileId> {
+        None
+    }
+
+    pub fn pack(&self) -> [u8; 32] {
         match self.0 {}
     }
+
+    pub fn unpack(_packed: &[u8; 32]) -> Option<FileId> {
+        None
+    }
+
+    pub fn parse(_s: &str) -> Option<FileId> {
+        None
+    }
 }
 
-fn error<T>() -> io::Result<T> {
-    Err(io::Error::new(io::ErrorKind::Other, ERROR_MESSAGE))
+pub fn device_class(_f: RawFilelike) -> io::Result<DeviceClass> {
+    error()
+}
+
+pub fn link_count(_f: RawFilelike) -> io::Result<u64> {
+    error()
+}
+
+pub fn entry_weak_id(_parent: &FileId, _entry: &DirEntry) -> Option<FileId> {
+    None
+}
+
+pub struct DirEntry(Never);
+
+impl DirEntry {
+    pub fn into_name(self) -> OsString {
+        match self.0 {}
+    }
+}
+
+#[derive(Debug)]
+pub struct ReadDir(Never);
+
+pub fn read_dir(_dir: RawFilelike, _path: &Path) -> io::Result<ReadDir> {
+    error()
+}
+
+impl Iterator for ReadDir {
+    type Item = io::Result<DirEntry>;
+
+    fn next(&mut self) -> Option<io::Result<DirEntry>> {
+        match self.0 {}
+    }
+}
+
+pub fn fallbacks(id: &FileId) -> Vec<&'static str> {
+    match id.0 {}
+}
+
+pub fn capabilities() -> crate::Capabilities {
+    crate::Capabilities {
+        identity: false,
+        heuristic: false,
+        pins_while_open: false,
+        stable_across_reboots: false,
+        mount_ids: false,
+        reflink_detection: false,
+        current_path: false,
+        open_no_follow: false,
+        minimal_access: false,
+        mount_watch: false,
+    }
+}
+
+pub fn current_path(_f: RawFilelike) -> io::Result<PathBuf> {
+    error()
+}
+
+#[cfg(feature = "mount-watch")]
+pub fn mounted_volumes() -> io::Result<std::collections::HashSet<u64>> {
+    error()
+}
+
+pub fn set_inheritable(_f: RawFilelike, _inheritable: bool) -> io::Result<()> {
+    error()
+}
+
+pub fn open_file(_path: &Path) -> io::Result<File> {
+    error()
+}
+
+pub fn open_with_options(
+    _path: &Path,
+    _options: &crate::HandleOptions,
+) -> io::Result<File> {
+    error()
+}
+
+pub fn open_file_at(_dir: RawFilelike, _path: &Path) -> io::Result<File> {
+    error()
+}
+
+pub fn open_beneath(
+    _dir: RawFilelike,
+    _path: &Path,
+) -> io::Result<Option<File>> {
+    error()
+}
+
+pub fn open_file_no_follow(_path: &Path) -> io::Result<File> {
+    error()
 }
@@ -0,0 +1,33 @@
+//! Deterministic UUIDs derived from identities.
+//!
+//! The UUID is a version 8 (custom) UUID whose 122 free bits are taken from
+//! `SHA-256(namespace || to_bytes(id))`: the 16 bytes of the namespace UUID
+//! are followed by the 32-byte binary form of the identity, and the first 16
+//! bytes of the digest have their version and variant bits overwritten as
+//! required by RFC 9562.
+
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::FileId;
+
+impl FileId {
+    /// Derive a version 8 UUID from this identity and a namespace.
+    ///
+    /// The same identity and namespace always give the same UUID, on every
+    /// platform and across releases, so this can be used where keys must be
+    /// UUIDs. Different namespaces give unrelated UUIDs for the same
+    /// identity, so that applications do not collide with each other.
+    ///
+    /// The UUID is the first 16 bytes of the SHA-256 digest of the namespace
+    /// followed by the bytes of [`to_bytes`](FileId::to_bytes), with the
+    /// version and variant bits set. Like the identity itself, the UUID only
+    /// refers to the file while the file exists.
+    pub fn to_uuid(&self, namespace: &Uuid) -> Uuid {
+        let digest = Sha256::new()
+            .chain_update(namespace.as_bytes())
+            .chain_update(self.to_bytes())
+            .finalize();
+        Uuid::new_v8(digest[..16].try_into().unwrap())
+    }
+}
@@ -0,0 +1,222 @@
+use std::fs::Metadata;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{AsRawFilelike, Error, FileId, Handle, metadata_of};
+
+/// A reader that periodically re-checks that it still reads from the file
+/// it was created for.
+///
+/// Every so many bytes, and optionally every so much time, the reader checks
+/// that the file has not been written to since the last check, and, if a
+/// path is [watched](VerifiedReader::watching), that the path still names
+/// the same file. A failed check makes the pending read, and every later
+/// one, fail, so that a long extraction from a file that another user can
+/// write to stops as soon as the file is tampered with, instead of silently
+/// mixing old and new contents.
+///
+/// A descriptor keeps referring to the file it was opened on, whatever
+/// happens to its path, so a replacement of the file is only detected
+/// through a watched path. Writes are detected from the length of the file,
+/// its modification time and, on Unix, its status change time, as far as
+/// the timestamps of the filesystem can tell them apart: a file that is
+/// truncated and written again to its old length within the resolution of
+/// its timestamps goes unnoticed. Appending to the file counts as writing
+/// to it.
+///
+/// The checks are made before reading, so data returned by a read that
+/// precedes a failed check may already come from the tampered file. A final
+/// [`verify`](VerifiedReader::verify) after the end of the stream covers
+/// the last stretch.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use std::io;
+///
+/// use cross_file_id::{Handle, VerifiedReader};
+///
+/// let handle = Handle::from_path("./archive.tar")?;
+/// let mut reader = VerifiedReader::new(handle)
+///     .check_every_bytes(64 * 1024)
+///     .watching("./archive.tar");
+/// io::copy(&mut reader, &mut io::sink())?;
+/// reader.verify()?;
+/// # Ok::<_, io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct VerifiedReader<F> {
+    handle: Handle<F>,
+    path: Option<PathBuf>,
+    every_bytes: u64,
+    every: Option<Duration>,
+    unchecked_bytes: u64,
+    checked_at: Instant,
+    stamp: Option<Stamp>,
+    failed: Option<io::ErrorKind>,
+}
+
+/// What a write to a file changes in its metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    len: u64,
+    modified: Option<SystemTime>,
+    #[cfg(unix)]
+    changed: (i64, i64),
+}
+
+impl Stamp {
+    fn of(metadata: &Metadata) -> Stamp {
+        #[cfg(unix)]
+        use std::os::unix::fs::MetadataExt;
+
+        Stamp {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            #[cfg(unix)]
+            changed: (metadata.ctime(), metadata.ctime_nsec()),
+        }
+    }
+}
+
+impl<F: AsRawFilelike> VerifiedReader<F> {
+    /// The default number of bytes read between two checks.
+    pub const DEFAULT_CHECK_BYTES: u64 = 1024 * 1024;
+
+    /// Wrap a handle, verifying reads against its identity.
+    ///
+    /// Checks are made every
+    /// [`DEFAULT_CHECK_BYTES`](VerifiedReader::DEFAULT_CHECK_BYTES) bytes,
+    /// with no time-based checks and no watched path.
+    pub fn new(handle: Handle<F>) -> VerifiedReader<F> {
+        VerifiedReader {
+            handle,
+            path: None,
+            every_bytes: Self::DEFAULT_CHECK_BYTES,
+            every: None,
+            unchecked_bytes: 0,
+            checked_at: Instant::now(),
+            stamp: None,
+            failed: None,
+        }
+    }
+
+    /// Check after every `bytes` bytes read.
+    ///
+    /// A value of zero checks before every read.
+    #[must_use]
+    pub fn check_every_bytes(mut self, bytes: u64) -> VerifiedReader<F> {
+        self.every_bytes = bytes;
+        self
+    }
+
+    /// Also check when `interval` has elapsed since the last check, however
+    /// few bytes were read in the meantime.
+    #[must_use]
+    pub fn check_every(mut self, interval: Duration) -> VerifiedReader<F> {
+        self.every = Some(interval);
+        self
+    }
+
+    /// Also check that `path` still names the file being read.
+    ///
+    /// This detects the file being replaced by a rename, which leaves the
+    /// descriptor reading the old file. The path is resolved anew on every
+    /// check, following symbolic links.
+    #[must_use]
+    pub fn watching<P: AsRef<Path>>(mut self, path: P) -> VerifiedReader<F> {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// The identity the file is verified against.
+    pub fn id(&self) -> FileId {
+        Handle::id(&self.handle)
+    }
+
+    /// Check the file now, regardless of when it was last checked.
+    ///
+    /// # Errors
+    /// This method will return an [`io::Error`] wrapping
+    /// [`Error::Replaced`] if the watched path no longer refers to the
+    /// expected file, [`Error::Truncated`] if the file has shrunk, or
+    /// [`Error::Modified`] if it was otherwise written to. It also fails if
+    /// the identity of the path or the metadata of the file cannot be
+    /// obtained. Once a check has failed, every later one fails as well.
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+    pub fn verify(&mut self) -> io::Result<()> {
+        if let Some(kind) = self.failed {
+            return Err(io::Error::new(
+                kind,
+                "an earlier identity check of this reader failed",
+            ));
+        }
+        let result = self.check();
+        match &result {
+            Ok(()) => {
+                self.unchecked_bytes = 0;
+                self.checked_at = Instant::now();
+            }
+            Err(err) => self.failed = Some(err.kind()),
+        }
+        result
+    }
+
+    /// Consume the reader and return the handle it reads from.
+    pub fn into_inner(self) -> Handle<F> {
+        self.handle
+    }
+
+    fn check(&mut self) -> io::Result<()> {
+        let expected = Handle::id(&self.handle);
+        if let Some(path) = &self.path {
+            let actual = self.handle.mode.id_of_path(path)?;
+            if actual != expected {
+                return Err(Error::Replaced {
+                    expected,
+                    actual,
+                    path: Some(path.clone()),
+                }
+                .into());
+            }
+        }
+        let stamp = Stamp::of(&metadata_of(&self.handle)?);
+        match self.stamp {
+            Some(before) if stamp.len < before.len => Err(Error::Truncated {
+                id: expected,
+                before: before.len,
+                after: stamp.len,
+            }
+            .into()),
+            Some(before) if stamp != before => {
+                Err(Error::Modified { id: expected }.into())
+            }
+            _ => {
+                self.stamp = Some(stamp);
+                Ok(())
+            }
+        }
+    }
+
+    fn is_due(&self) -> bool {
+        self.stamp.is_none()
+            || self.unchecked_bytes >= self.every_bytes
+            || self
+                .every
+                .is_some_and(|every| self.checked_at.elapsed() >= every)
+    }
+}
+
+impl<F: Read + AsRawFilelike> Read for VerifiedReader<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.failed.is_some() || self.is_due() {
+            self.verify()?;
+        }
+        let read = self.handle.read(buf)?;
+        self.unchecked_bytes =
+            self.unchecked_bytes.saturating_add(read as u64);
+        Ok(read)
+    }
+}
@@ -0,0 +1,413 @@
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs::File;
+use std::hash::Hash;
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, RawFd};
+use std::os::wasi::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use io_lifetimes::raw::{AsRawFilelike, RawFilelike};
+
+use crate::{DeviceClass, Error};
+
+/// The platform tag that starts the packed form of a WASI `FileId`.
+const PACKED_TAG: u8 = 3;
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FileId {
+    dev: u64,
+    ino: u64,
+}
+
+fn unsupported<T>(operation: &'static str) -> io::Result<T> {
+    Err(Error::UnsupportedPlatform { operation }.into())
+}
+
+/// The standard library does not expose the device and inode of a file on
+/// WASI, so they are queried with `fd_filestat_get` directly.
+fn filestat(f: RawFilelike) -> io::Result<wasi::Filestat> {
+    if f < 0 {
+        return Err(io::Error::from_raw_os_error(
+            wasi::ERRNO_BADF.raw().into(),
+        ));
+    }
+    // SAFETY: `fd_filestat_get` only reads the descriptor, and reports an
+    // invalid one through its result.
+    unsafe { wasi::fd_filestat_get(f as wasi::Fd) }
+        .map_err(|errno| io::Error::from_raw_os_error(errno.raw().into()))
+}
+
+// WASI has no pipes or consoles of its own; the streams of the host show up
+// as character devices.
+fn classify(stat: &wasi::Filestat) -> DeviceClass {
+    match stat.filetype {
+        wasi::FILETYPE_REGULAR_FILE => DeviceClass::RegularFile,
+        wasi::FILETYPE_DIRECTORY => DeviceClass::Directory,
+        wasi::FILETYPE_SYMBOLIC_LINK => DeviceClass::Symlink,
+        wasi::FILETYPE_BLOCK_DEVICE => DeviceClass::BlockDevice,
+        wasi::FILETYPE_CHARACTER_DEVICE => DeviceClass::CharDevice,
+        wasi::FILETYPE_SOCKET_DGRAM | wasi::FILETYPE_SOCKET_STREAM => {
+            DeviceClass::Socket
+        }
+        _ => DeviceClass::Other,
+    }
+}
+
+pub fn device_class(f: RawFilelike) -> io::Result<DeviceClass> {
+    Ok(classify(&filestat(f)?))
+}
+
+pub fn link_count(f: RawFilelike) -> io::Result<u64> {
+    Ok(filestat(f)?.nlink)
+}
+
+impl FileId {
+    pub fn from_filelike(f: RawFilelike) -> io::Result<FileId> {
+        let stat = filestat(f)?;
+        Ok(FileId { dev: stat.dev, ino: stat.ino })
+    }
+
+    /// WASI has a single way of querying an open file.
+    pub fn from_filelike_verified(f: RawFilelike) -> io::Result<FileId> {
+        FileId::from_filelike(f)
+    }
+
+    pub fn with_subvolume(self, _f: RawFilelike) -> io::Result<FileId> {
+        Ok(self)
+    }
+
+    pub fn with_mount(self, _f: RawFilelike) -> io::Result<FileId> {
+        Ok(self)
+    }
+
+    pub fn is_mount_aware(&self) -> bool {
+        false
+    }
+
+    pub fn is_subvolume_aware(&self) -> bool {
+        false
+    }
+
+    pub fn from_filelike_classified(
+        f: RawFilelike,
+    ) -> io::Result<(FileId, DeviceClass)> {
+        let stat = filestat(f)?;
+        Ok((FileId { dev: stat.dev, ino: stat.ino }, classify(&stat)))
+    }
+
+    pub fn from_filelike_normalized(f: RawFilelike) -> io::Result<FileId> {
+        FileId::from_filelike(f)
+    }
+
+    pub fn from_path(path: &Path) -> io::Result<FileId> {
+        FileId::from_filelike(open_file(path)?.as_raw_fd())
+    }
+
+    // Statting a path without following links requires a directory
+    // descriptor to resolve it against, which the standard library does not
+    // provide.
+    pub fn from_path_no_follow(_path: &Path) -> io::Result<FileId> {
+        unsupported("taking the identity of a symbolic link")
+    }
+
+    pub fn volume(&self) -> u64 {
+        self.dev
+    }
+
+    pub fn index_bits(&self) -> u32 {
+        u64::BITS - self.ino.leading_zeros()
+    }
+
+    pub fn volume_bound(volume: u64, max: bool) -> Option<FileId> {
+        Some(FileId { dev: volume, ino: if max { u64::MAX } else { 0 } })
+    }
+
+    #[cfg(feature = "test-util")]
+    pub fn fabricated(volume: u64, index: u64) -> FileId {
+        FileId { dev: volume, ino: index }
+    }
+
+    /// Layout: tag, device (big-endian), inode (big-endian), zero padding.
+    pub fn pack(&self) -> [u8; 32] {
+        let mut packed = [0; 32];
+        packed[0] = PACKED_TAG;
+        packed[1..9].copy_from_slice(&self.dev.to_be_bytes());
+        packed[9..17].copy_from_slice(&self.ino.to_be_bytes());
+        packed
+    }
+
+    pub fn unpack(packed: &[u8; 32]) -> Option<FileId> {
+        if packed[0] != PACKED_TAG || packed[17..].iter().any(|&b| b != 0) {
+            return None;
+        }
+        Some(FileId {
+            dev: u64::from_be_bytes(packed[1..9].try_into().unwrap()),
+            ino: u64::from_be_bytes(packed[9..17].try_into().unwrap()),
+        })
+    }
+}
+
+/// Formatted as `dev:ino`, both in decimal.
+impl fmt::Display for FileId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.dev, self.ino)
+    }
+}
+
+impl FileId {
+    /// Parse the form produced by `Display`.
+    pub fn parse(s: &str) -> Option<FileId> {
+        let (dev, ino) = s.split_once(':')?;
+        Some(FileId {
+            dev: crate::parse_decimal(dev)?,
+            ino: crate::parse_decimal(ino)?,
+        })
+    }
+}
+
+/// The directory entry's inode is reported by `fd_readdir`. The device is
+/// assumed to be the parent's, which is wrong for mount points, so the
+/// result is only a hint.
+pub fn entry_weak_id(parent: &FileId, entry: &DirEntry) -> Option<FileId> {
+    Some(FileId { ino: entry.ino, ..*parent })
+}
+
+/// An entry read from a directory listing.
+pub struct DirEntry {
+    name: OsString,
+    ino: u64,
+}
+
+impl DirEntry {
+    pub fn into_name(self) -> OsString {
+        self.name
+    }
+}
+
+/// A directory listing. `fd_readdir` takes the position as a cookie, so
+/// the listing can go through the descriptor it is given, which the caller
+/// keeps open.
+#[derive(Debug)]
+pub struct ReadDir {
+    dir: RawFilelike,
+    buf: Vec<u8>,
+    /// The part of `buf` that was filled in and not consumed yet.
+    start: usize,
+    end: usize,
+    cookie: wasi::Dircookie,
+    done: bool,
+}
+
+/// Read a directory through its descriptor, with `fd_readdir`.
+pub fn read_dir(dir: RawFilelike, _path: &Path) -> io::Result<ReadDir> {
+    Ok(ReadDir {
+        dir,
+        buf: vec![0; 4096],
+        start: 0,
+        end: 0,
+        cookie: 0,
+        done: false,
+    })
+}
+
+impl Iterator for ReadDir {
+    type Item = io::Result<DirEntry>;
+
+    fn next(&mut self) -> Option<io::Result<DirEntry>> {
+        const HEADER: usize = std::mem::size_of::<wasi::Dirent>();
+        loop {
+            if self.end - self.start >= HEADER {
+                // SAFETY: the header is within the filled part of the
+                // buffer, and may not be aligned.
+                let dirent = unsafe {
+                    std::ptr::read_unaligned(
+                        self.buf[self.start..].as_ptr().cast::<wasi::Dirent>(),
+                    )
+                };
+                let name_start = self.start + HEADER;
+                let name_end = name_start + dirent.d_namlen as usize;
+                if name_end <= self.end {
+                    let name = &self.buf[name_start..name_end];
+                    self.start = name_end;
+                    self.cookie = dirent.d_next;
+                    if name == b"." || name == b".." {
+                        continue;
+                    }
+                    let name = OsStr::from_bytes(name).to_os_string();
+                    return Some(Ok(DirEntry { name, ino: dirent.d_ino }));
+                }
+            }
+            // The rest of the buffer holds a truncated entry, if anything.
+            if self.done {
+                return None;
+            }
+            if self.start == 0 && self.end == self.buf.len() {
+                // A single entry does not fit.
+                self.buf.resize(self.buf.len() * 2, 0);
+            }
+            // SAFETY: the buffer is as large as it is said to be, and an
+            // invalid descriptor is reported through the result.
+            let used = unsafe {
+                wasi::fd_readdir(
+                    self.dir as wasi::Fd,
+                    self.buf.as_mut_ptr(),
+                    self.buf.len(),
+                    self.cookie,
+                )
+            };
+            match used {
+                Ok(used) => {
+                    // A listing that does not fill the buffer is complete.
+                    self.done = used < self.buf.len();
+                    self.start = 0;
+                    self.end = used;
+                }
+                Err(errno) => {
+                    self.done = true;
+                    self.start = 0;
+                    self.end = 0;
+                    let errno = errno.raw().into();
+                    return Some(Err(io::Error::from_raw_os_error(errno)));
+                }
+            }
+        }
+    }
+}
+
+impl<F> AsRawFd for crate::Handle<F>
+where
+    F: AsRawFd,
+{
+    fn as_raw_fd(&self) -> RawFd {
+        self.handle.as_raw_fd()
+    }
+}
+
+impl<F> AsFd for crate::Handle<F>
+where
+    F: AsFd,
+{
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.handle.as_fd()
+    }
+}
+
+impl<F> FromRawFd for crate::Handle<F>
+where
+    F: AsRawFilelike + FromRawFd,
+{
+    unsafe fn from_raw_fd(fd: RawFd) -> crate::Handle<F> {
+        let handle = unsafe { F::from_raw_fd(fd) };
+        crate::Handle::from_file_like(handle).expect("from_raw_fd failed")
+    }
+}
+
+impl<F> IntoRawFd for crate::Handle<F>
+where
+    F: IntoRawFd,
+{
+    fn into_raw_fd(self) -> RawFd {
+        self.handle.into_raw_fd()
+    }
+}
+
+pub fn fallbacks(_id: &FileId) -> Vec<&'static str> {
+    Vec::new()
+}
+
+pub fn capabilities() -> crate::Capabilities {
+    crate::Capabilities {
+        identity: true,
+        heuristic: false,
+        pins_while_open: true,
+        stable_across_reboots: false,
+        mount_ids: false,
+        reflink_detection: false,
+        current_path: false,
+        open_no_follow: false,
+        minimal_access: false,
+        mount_watch: false,
+    }
+}
+
+pub fn current_path(_f: RawFilelike) -> io::Result<PathBuf> {
+    unsupported("recovering the path of an open file")
+}
+
+#[cfg(feature = "mount-watch")]
+pub fn mounted_volumes() -> io::Result<std::collections::HashSet<u64>> {
+    unsupported("listing mounted volumes")
+}
+
+pub fn is_valid_raw(f: RawFilelike) -> bool {
+    f >= 0
+}
+
+// WASI has no child processes to inherit descriptors.
+pub fn set_inheritable(_f: RawFilelike, _inheritable: bool) -> io::Result<()> {
+    unsupported("changing the inheritance of a descriptor")
+}
+
+pub fn open_file(path: &Path) -> io::Result<File> {
+    std::fs::OpenOptions::new().read(true).open(path)
+}
+
+pub fn open_with_options(
+    path: &Path,
+    options: &crate::HandleOptions,
+) -> io::Result<File> {
+    if options.follow_symlinks {
+        open_file(path)
+    } else {
+        open_file_no_follow(path)
+    }
+}
+
+/// Open a path relative to an open directory, with `path_open`.
+pub fn open_file_at(dir: RawFilelike, path: &Path) -> io::Result<File> {
+    let path = path.to_str().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "path is not UTF-8")
+    })?;
+    let rights = wasi::RIGHTS_FD_READ
+        | wasi::RIGHTS_FD_READDIR
+        | wasi::RIGHTS_FD_FILESTAT_GET;
+    // SAFETY: `path_open` only reads the directory descriptor, and reports
+    // an invalid one through its result.
+    let fd = unsafe {
+        wasi::path_open(
+            dir as wasi::Fd,
+            wasi::LOOKUPFLAGS_SYMLINK_FOLLOW,
+            path,
+            0,
+            rights,
+            rights,
+            0,
+        )
+    }
+    .map_err(|errno| io::Error::from_raw_os_error(errno.raw().into()))?;
+    // SAFETY: the descriptor was just opened, and is owned by nobody else.
+    Ok(unsafe { File::from_raw_fd(fd as RawFd) })
+}
+
+/// WASI resolves every path beneath the directory it is given, including
+/// symbolic links, and refuses to leave it.
+pub fn open_beneath(
+    dir: RawFilelike,
+    path: &Path,
+) -> io::Result<Option<File>> {
+    match open_file_at(dir, path) {
+        Ok(file) => Ok(Some(file)),
+        Err(err)
+            if err.raw_os_error()
+                == Some(wasi::ERRNO_NOTCAPABLE.raw().into()) =>
+        {
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+pub fn open_file_no_follow(_path: &Path) -> io::Result<File> {
+    unsupported("opening a symbolic link")
+}
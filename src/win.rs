@@ -1,16 +1,51 @@
 use io_lifetimes::raw::{FromRawFilelike, RawFilelike};
+use io_lifetimes::{AsFilelike, BorrowedFilelike};
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::io;
-use std::os::windows::ffi::OsStrExt;
-use std::os::windows::io::{AsRawHandle, IntoRawHandle, RawHandle};
-use std::path::Path;
-use windows::Win32::Foundation::GENERIC_READ;
-use windows::core::PCWSTR;
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::os::windows::io::{
+    AsHandle, AsRawHandle, BorrowedHandle, FromRawHandle, IntoRawHandle,
+    OwnedHandle, RawHandle,
+};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use windows::Wdk::Foundation::OBJECT_ATTRIBUTES;
+use windows::Wdk::Storage::FileSystem::{
+    FILE_OPEN, FILE_OPEN_REPARSE_POINT, FILE_SYNCHRONOUS_IO_NONALERT,
+    NTCREATEFILE_CREATE_OPTIONS, NtCreateFile,
+};
+use windows::Win32::Foundation::{
+    CompareObjectHandles, ERROR_INVALID_LEVEL, ERROR_INVALID_PARAMETER,
+    ERROR_NO_MORE_FILES, ERROR_NOT_SUPPORTED, GENERIC_READ, HANDLE,
+    HANDLE_FLAG_INHERIT, HANDLE_FLAGS, INVALID_HANDLE_VALUE,
+    OBJ_CASE_INSENSITIVE, RtlNtStatusToDosError, SetHandleInformation,
+    UNICODE_STRING,
+};
+use windows::Win32::System::Console::{
+    CONSOLE_MODE, DISABLE_NEWLINE_AUTO_RETURN, ENABLE_LVB_GRID_WORLDWIDE,
+    ENABLE_PROCESSED_OUTPUT, ENABLE_VIRTUAL_TERMINAL_PROCESSING,
+    ENABLE_WRAP_AT_EOL_OUTPUT, GetConsoleMode, GetNumberOfConsoleInputEvents,
+};
+use windows::Win32::System::IO::IO_STATUS_BLOCK;
+use windows::Win32::System::Pipes::GetNamedPipeInfo;
+use windows::core::{PCWSTR, PWSTR};
+
+use crate::{DeviceClass, Error};
 
 use windows::Win32::Storage::FileSystem::{
-    CreateFileW, FILE_FLAG_BACKUP_SEMANTICS, FILE_ID_128, FILE_ID_INFO,
-    FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE, FILE_TYPE_DISK,
-    FileIdInfo, GetFileInformationByHandleEx, GetFileType, OPEN_EXISTING,
+    BY_HANDLE_FILE_INFORMATION, CreateFileW, FILE_ATTRIBUTE_REPARSE_POINT,
+    FILE_ATTRIBUTE_TAG_INFO, FILE_FLAG_BACKUP_SEMANTICS,
+    FILE_FLAG_OPEN_REPARSE_POINT, FILE_FLAGS_AND_ATTRIBUTES,
+    FILE_FULL_DIR_INFO, FILE_GENERIC_READ, FILE_ID_128, FILE_ID_INFO,
+    FILE_READ_ATTRIBUTES, FILE_SHARE_DELETE, FILE_SHARE_MODE, FILE_SHARE_NONE,
+    FILE_SHARE_READ, FILE_SHARE_WRITE, FILE_TYPE_CHAR, FILE_TYPE_DISK,
+    FILE_TYPE_PIPE, FileAttributeTagInfo, FileFullDirectoryInfo, FileIdInfo,
+    FileNameInfo, GETFINALPATHNAMEBYHANDLE_FLAGS, GetFileInformationByHandle,
+    GetFileInformationByHandleEx, GetFileType, GetFinalPathNameByHandleW,
+    OPEN_EXISTING, VOLUME_NAME_DOS, VOLUME_NAME_GUID,
 };
 
 // For correctness, it is critical that both file handles remain open while
@@ -54,13 +89,67 @@ use windows::Win32::Storage::FileSystem::{
 // into the offending directory. As far as failure modes goes, this isn't
 // that bad.
 
-fn compare_file_id_128(a: FILE_ID_128, b: FILE_ID_128) -> std::cmp::Ordering {
-    a.Identifier.cmp(&b.Identifier)
-}
+/// The platform tag that starts the packed form of a Windows `FileId`.
+const PACKED_TAG: u8 = 2;
 
+/// `FileIdInfo` is not supported before Windows 8, nor by some SMB servers.
+/// Identities then come from `GetFileInformationByHandle`, which only has a
+/// 32-bit volume serial number and a 64-bit file index. These are kept in a
+/// variant of their own, so that they never compare equal to a full
+/// identity.
+///
+/// Pipes have neither, but have a name that is shared by both of their
+/// ends. Their identity is a hash of that name, on volume zero. The name is
+/// only unique for pipes with a single instance, such as those of
+/// `std::io::pipe`, and only while the pipe exists. The pipes of
+/// `CreatePipe` all have an empty name, and the instances of a named pipe
+/// that allows several cannot be told apart, so neither has an identity.
+///
+/// Consoles have no identity of their own. The console objects a process
+/// sees are numbered in the order it first sees them, telling apart input
+/// buffers and screen buffers, so the identity of a console is only
+/// meaningful within a process, and only while the object is among those
+/// it saw most recently. Whether the handle is for input or output
+/// is kept as well.
 #[derive(Debug, Clone, PartialEq)]
-pub struct FileId {
-    file_id_info: FILE_ID_INFO,
+pub enum FileId {
+    Info(FILE_ID_INFO),
+    ByHandle { volume: u64, index: u64 },
+    Pipe { name_hash: u128 },
+    Console { object: u64, input: bool },
+}
+
+impl FileId {
+    fn volume_serial(&self) -> u64 {
+        match self {
+            FileId::Info(info) => info.VolumeSerialNumber,
+            FileId::ByHandle { volume, .. } => *volume,
+            FileId::Pipe { .. } | FileId::Console { .. } => 0,
+        }
+    }
+
+    /// The file index is laid out like a 128-bit file id, which is
+    /// little-endian.
+    fn identifier(&self) -> [u8; 16] {
+        match self {
+            FileId::Info(info) => info.FileId.Identifier,
+            FileId::ByHandle { index, .. } => u128::from(*index).to_le_bytes(),
+            FileId::Pipe { name_hash } => name_hash.to_le_bytes(),
+            FileId::Console { object, input } => {
+                (u128::from(*input) << 64 | u128::from(*object)).to_le_bytes()
+            }
+        }
+    }
+
+    /// The byte that tells the variants apart in the packed form.
+    fn kind(&self) -> u8 {
+        match self {
+            FileId::Info(_) => 0,
+            FileId::ByHandle { .. } => 1,
+            FileId::Pipe { .. } => 2,
+            FileId::Console { .. } => 3,
+        }
+    }
 }
 
 impl Eq for FileId {}
@@ -71,52 +160,742 @@ impl PartialOrd for FileId {
     }
 }
 
+/// Ordered like the packed form: volume, identifier, then variant.
 impl Ord for FileId {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.file_id_info
-            .VolumeSerialNumber
-            .cmp(&other.file_id_info.VolumeSerialNumber)
-            .then_with(|| {
-                compare_file_id_128(
-                    self.file_id_info.FileId,
-                    other.file_id_info.FileId,
-                )
-            })
+        self.volume_serial()
+            .cmp(&other.volume_serial())
+            .then_with(|| self.identifier().cmp(&other.identifier()))
+            .then_with(|| self.kind().cmp(&other.kind()))
     }
 }
 
 impl Hash for FileId {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        state.write_u64(self.file_id_info.VolumeSerialNumber);
-        state.write(&self.file_id_info.FileId.Identifier);
+        state.write_u64(self.volume_serial());
+        state.write(&self.identifier());
+        state.write_u8(self.kind());
+    }
+}
+
+/// Classify a handle by its file type. Sockets are reported as pipes, as
+/// `GetFileType` does not tell them apart.
+pub fn device_class(f: RawFilelike) -> io::Result<DeviceClass> {
+    let handle = HANDLE(f);
+    // SAFETY: these calls only query the handle, and report an invalid one
+    // through their result.
+    let file_type = unsafe { GetFileType(handle) };
+    Ok(match file_type {
+        FILE_TYPE_DISK => {
+            // SAFETY: the handle is only borrowed for this call.
+            let file = unsafe { BorrowedFilelike::borrow_raw(f) };
+            let ty = file.as_filelike_view::<std::fs::File>().metadata()?;
+            let ty = ty.file_type();
+            if ty.is_symlink() {
+                DeviceClass::Symlink
+            } else if ty.is_dir() {
+                DeviceClass::Directory
+            } else {
+                DeviceClass::RegularFile
+            }
+        }
+        FILE_TYPE_CHAR if console_mode(handle).is_some() => {
+            DeviceClass::Console
+        }
+        FILE_TYPE_CHAR => DeviceClass::CharDevice,
+        FILE_TYPE_PIPE => DeviceClass::Pipe,
+        _ => {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(0) {
+                return Err(err);
+            }
+            DeviceClass::Other
+        }
+    })
+}
+
+pub fn link_count(f: RawFilelike) -> io::Result<u64> {
+    let mut info = BY_HANDLE_FILE_INFORMATION::default();
+    // SAFETY: the call only queries the handle, and reports an invalid one
+    // through its result.
+    unsafe { GetFileInformationByHandle(HANDLE(f), &mut info)? };
+    Ok(info.nNumberOfLinks.into())
+}
+
+/// Formatted as `volume:fileid`, where the volume serial number and the
+/// 128-bit file id are in hexadecimal, the latter in the byte order reported
+/// by the OS. Identities from `GetFileInformationByHandle` have a 64-bit
+/// file index instead, as a hexadecimal number. Pipes are formatted as
+/// `pipe:hash`, with the 128-bit hash of their name in hexadecimal, and
+/// consoles as `console:object:in` or `console:object:out`, with the number
+/// of the console object in hexadecimal.
+impl fmt::Display for FileId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileId::Pipe { name_hash } => {
+                return write!(f, "pipe:{:032x}", name_hash);
+            }
+            FileId::Console { object, input } => {
+                let direction = if *input { "in" } else { "out" };
+                return write!(f, "console:{:016x}:{}", object, direction);
+            }
+            _ => {}
+        }
+        write!(f, "{:016x}:", self.volume_serial())?;
+        match self {
+            FileId::Info(info) => {
+                for byte in info.FileId.Identifier {
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+            FileId::ByHandle { index, .. } => write!(f, "{:016x}", index),
+            FileId::Pipe { .. } | FileId::Console { .. } => unreachable!(),
+        }
     }
 }
 
 impl FileId {
+    /// Parse the form produced by `Display`.
+    pub fn parse(s: &str) -> Option<FileId> {
+        let (volume, id) = s.split_once(':')?;
+        let hex = |s: &str, len| {
+            s.len() == len && s.bytes().all(|b| b.is_ascii_hexdigit())
+        };
+        if volume == "console" {
+            let (object, direction) = id.split_once(':')?;
+            let input = match direction {
+                "in" => true,
+                "out" => false,
+                _ => return None,
+            };
+            if !hex(object, 16) {
+                return None;
+            }
+            let object = u64::from_str_radix(object, 16).ok()?;
+            return Some(FileId::Console { object, input });
+        }
+        if volume == "pipe" && hex(id, 32) {
+            let name_hash = u128::from_str_radix(id, 16).ok()?;
+            return Some(FileId::Pipe { name_hash });
+        }
+        if !hex(volume, 16) {
+            return None;
+        }
+        let volume = u64::from_str_radix(volume, 16).ok()?;
+        if hex(id, 16) && volume <= u64::from(u32::MAX) {
+            let index = u64::from_str_radix(id, 16).ok()?;
+            return Some(FileId::ByHandle { volume, index });
+        }
+        if !hex(id, 32) {
+            return None;
+        }
+        let mut identifier = [0; 16];
+        for (byte, hex) in identifier.iter_mut().zip(id.as_bytes().chunks(2)) {
+            *byte =
+                u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?;
+        }
+        Some(FileId::Info(FILE_ID_INFO {
+            VolumeSerialNumber: volume,
+            FileId: FILE_ID_128 { Identifier: identifier },
+        }))
+    }
+
     pub fn from_filelike(f: RawFilelike) -> io::Result<FileId> {
-        let file_id_info = unsafe {
-            let handle = windows::Win32::Foundation::HANDLE(f);
-            let file_type = GetFileType(handle);
-            if file_type != FILE_TYPE_DISK {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!(
-                        "Unable to get information about handle of type {:?}",
-                        file_type
-                    ),
-                ));
+        let handle = HANDLE(f);
+        let file_type = unsafe { GetFileType(handle) };
+        if file_type == FILE_TYPE_PIPE {
+            // Sockets are also reported as pipes, but have no name.
+            if let Ok(name) = pipe_name(handle) {
+                if is_unnamed(&name) || !is_single_instance(handle) {
+                    let class = DeviceClass::Pipe;
+                    return Err(Error::UnsupportedHandleType { class }.into());
+                }
+                return Ok(FileId::Pipe { name_hash: fnv1a_128(&name) });
             }
-            let mut info = FILE_ID_INFO::default();
+        }
+        if file_type == FILE_TYPE_CHAR
+            && let Some(mode) = console_mode(handle)
+        {
+            let input = is_console_input(handle, mode);
+            let object = console_object(f)?;
+            return Ok(FileId::Console { object, input });
+        }
+        if file_type != FILE_TYPE_DISK {
+            let class = device_class(f)?;
+            return Err(Error::UnsupportedHandleType { class }.into());
+        }
+        let mut info = FILE_ID_INFO::default();
+        let result = unsafe {
             GetFileInformationByHandleEx(
                 handle,
                 FileIdInfo,
                 &mut info as *mut FILE_ID_INFO as *mut _,
                 std::mem::size_of::<FILE_ID_INFO>() as u32,
-            )?;
-            info
+            )
+        };
+        match result {
+            Ok(()) => Ok(FileId::Info(info)),
+            Err(err) if is_unsupported(&err) => {
+                log_debug!(
+                    "FileIdInfo is unsupported, using \
+                     GetFileInformationByHandle: {}",
+                    err
+                );
+                FileId::from_by_handle(handle)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn from_by_handle(handle: HANDLE) -> io::Result<FileId> {
+        let mut info = BY_HANDLE_FILE_INFORMATION::default();
+        unsafe { GetFileInformationByHandle(handle, &mut info)? };
+        Ok(FileId::ByHandle {
+            volume: info.dwVolumeSerialNumber.into(),
+            index: u64::from(info.nFileIndexHigh) << 32
+                | u64::from(info.nFileIndexLow),
+        })
+    }
+
+    /// The identity from `FileIdInfo` is checked against
+    /// `GetFileInformationByHandle`, which reports the low 32 bits of the
+    /// volume serial number and a 64-bit file index. File ids that do not
+    /// fit in 64 bits, as on ReFS, cannot be checked.
+    ///
+    /// Identities that come from `GetFileInformationByHandle` in the first
+    /// place have nothing to be checked against.
+    pub fn from_filelike_verified(f: RawFilelike) -> io::Result<FileId> {
+        let id = FileId::from_filelike(f)?;
+        let FileId::Info(info) = &id else {
+            return Ok(id);
         };
+        if info.FileId.Identifier[8..].iter().any(|&b| b != 0) {
+            return Ok(id);
+        }
+        let FileId::ByHandle { volume, index } =
+            FileId::from_by_handle(HANDLE(f))?
+        else {
+            unreachable!("from_by_handle always returns ByHandle");
+        };
+        let mut secondary = *info;
+        secondary.VolumeSerialNumber =
+            info.VolumeSerialNumber & !u64::from(u32::MAX) | volume;
+        secondary.FileId.Identifier = u128::from(index).to_le_bytes();
+        let secondary = FileId::Info(secondary);
+        if secondary != id {
+            return Err(Error::IdentityMismatch {
+                primary: crate::FileId(id),
+                secondary: crate::FileId(secondary),
+            }
+            .into());
+        }
+        Ok(id)
+    }
+
+    pub fn with_subvolume(self, _f: RawFilelike) -> io::Result<FileId> {
+        Ok(self)
+    }
+
+    pub fn with_mount(self, _f: RawFilelike) -> io::Result<FileId> {
+        Ok(self)
+    }
+
+    pub fn is_mount_aware(&self) -> bool {
+        false
+    }
+
+    pub fn is_subvolume_aware(&self) -> bool {
+        false
+    }
+
+    pub fn from_filelike_classified(
+        f: RawFilelike,
+    ) -> io::Result<(FileId, DeviceClass)> {
+        Ok((FileId::from_filelike(f)?, device_class(f)?))
+    }
+
+    pub fn volume(&self) -> u64 {
+        self.volume_serial()
+    }
+
+    pub fn index_bits(&self) -> u32 {
+        if let FileId::Pipe { .. } | FileId::Console { .. } = self {
+            return 0;
+        }
+        let index = u128::from_le_bytes(self.identifier());
+        u128::BITS - index.leading_zeros()
+    }
+
+    pub fn volume_bound(volume: u64, max: bool) -> Option<FileId> {
+        let fill = if max { u8::MAX } else { 0 };
+        Some(FileId::Info(FILE_ID_INFO {
+            VolumeSerialNumber: volume,
+            FileId: FILE_ID_128 { Identifier: [fill; 16] },
+        }))
+    }
+
+    #[cfg(feature = "test-util")]
+    pub fn fabricated(volume: u64, index: u64) -> FileId {
+        let mut identifier = [0; 16];
+        identifier[..8].copy_from_slice(&index.to_le_bytes());
+        FileId::Info(FILE_ID_INFO {
+            VolumeSerialNumber: volume,
+            FileId: FILE_ID_128 { Identifier: identifier },
+        })
+    }
+
+    /// The identity is only available through a handle, so a handle is
+    /// opened and closed again right away.
+    pub fn from_path(path: &Path) -> io::Result<FileId> {
+        let file = open_file(path)?;
+        FileId::from_filelike(file.as_raw_handle())
+    }
+
+    pub fn from_path_no_follow(path: &Path) -> io::Result<FileId> {
+        let file = open_file_no_follow(path)?;
+        FileId::from_filelike(file.as_raw_handle())
+    }
+
+    /// Layout: tag, volume serial number (big-endian), 128-bit file id as
+    /// reported by the OS, the variant (0 for `Info`, 1 for `ByHandle`, 2
+    /// for `Pipe`, 3 for `Console`), zero padding.
+    pub fn pack(&self) -> [u8; 32] {
+        let mut packed = [0; 32];
+        packed[0] = PACKED_TAG;
+        packed[1..9].copy_from_slice(&self.volume_serial().to_be_bytes());
+        packed[9..25].copy_from_slice(&self.identifier());
+        packed[25] = self.kind();
+        packed
+    }
+
+    pub fn unpack(packed: &[u8; 32]) -> Option<FileId> {
+        if packed[0] != PACKED_TAG || packed[26..].iter().any(|&b| b != 0) {
+            return None;
+        }
+        let volume = u64::from_be_bytes(packed[1..9].try_into().unwrap());
+        let identifier: [u8; 16] = packed[9..25].try_into().unwrap();
+        match packed[25] {
+            0 => Some(FileId::Info(FILE_ID_INFO {
+                VolumeSerialNumber: volume,
+                FileId: FILE_ID_128 { Identifier: identifier },
+            })),
+            1 if volume <= u64::from(u32::MAX)
+                && identifier[8..].iter().all(|&b| b == 0) =>
+            {
+                let index = u128::from_le_bytes(identifier);
+                Some(FileId::ByHandle { volume, index: index as u64 })
+            }
+            2 if volume == 0 => Some(FileId::Pipe {
+                name_hash: u128::from_le_bytes(identifier),
+            }),
+            3 if volume == 0
+                && identifier[8] <= 1
+                && identifier[9..].iter().all(|&b| b == 0) =>
+            {
+                let object =
+                    u64::from_le_bytes(identifier[..8].try_into().unwrap());
+                Some(FileId::Console { object, input: identifier[8] == 1 })
+            }
+            _ => None,
+        }
+    }
+
+    /// Reopen the file through its volume GUID path and take the identity
+    /// from there.
+    ///
+    /// A file reached through an administrative share such as `\\server\C$`
+    /// may report a different volume serial number than the same file opened
+    /// through its local drive letter. Resolving the handle to a
+    /// `\\?\Volume{GUID}\` path and reopening it yields the serial number
+    /// of the underlying local volume. The path may name another file by
+    /// the time it is opened, so the file id of the reopened file, which
+    /// does not depend on how the volume is reached, must match that of the
+    /// handle. Pipes and consoles are not on a volume, and are left as is.
+    pub fn from_filelike_normalized(f: RawFilelike) -> io::Result<FileId> {
+        let id = FileId::from_filelike(f)?;
+        if let FileId::Pipe { .. } | FileId::Console { .. } = id {
+            return Ok(id);
+        }
+        let path = final_path(HANDLE(f), VOLUME_NAME_GUID)?;
+        let file = open_file(&path)?;
+        let normalized = FileId::from_filelike(file.as_raw_handle())?;
+        if normalized.identifier() != id.identifier() {
+            return Err(Error::Replaced {
+                expected: crate::FileId(id),
+                actual: crate::FileId(normalized),
+                path: Some(path),
+            }
+            .into());
+        }
+        Ok(normalized)
+    }
+}
+
+fn final_path(
+    handle: HANDLE,
+    flags: GETFINALPATHNAMEBYHANDLE_FLAGS,
+) -> io::Result<PathBuf> {
+    let mut buf = vec![0u16; 260];
+    loop {
+        let len =
+            unsafe { GetFinalPathNameByHandleW(handle, &mut buf, flags) };
+        let len = len as usize;
+        if len == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // On success the returned length excludes the terminating NUL. If
+        // the buffer was too small, it is the required size including it.
+        if len < buf.len() {
+            buf.truncate(len);
+            return Ok(PathBuf::from(OsString::from_wide(&buf)));
+        }
+        buf.resize(len, 0);
+    }
+}
+
+pub fn fallbacks(id: &FileId) -> Vec<&'static str> {
+    match id {
+        FileId::Info(_) => Vec::new(),
+        FileId::ByHandle { .. } => vec![
+            "FileIdInfo is unsupported, identities come from \
+             GetFileInformationByHandle",
+        ],
+        FileId::Pipe { .. } => vec![
+            "a single-instance pipe, whose identity is a hash of its name \
+             and is only meaningful while it exists",
+        ],
+        FileId::Console { .. } => vec![
+            "a console, whose identity is only meaningful within this \
+             process, and is not shared by separately opened handles",
+        ],
+    }
+}
+
+/// Character devices are consoles if they have a console mode.
+fn console_mode(handle: HANDLE) -> Option<CONSOLE_MODE> {
+    let mut mode = CONSOLE_MODE::default();
+    // SAFETY: the call only queries the handle, and reports an invalid one
+    // through its result.
+    unsafe { GetConsoleMode(handle, &mut mode) }.ok().map(|()| mode)
+}
+
+/// Whether a console handle is for input. Input modes have flags that
+/// output modes do not, and input handles report their pending events
+/// whatever their mode, while output handles cannot.
+fn is_console_input(handle: HANDLE, mode: CONSOLE_MODE) -> bool {
+    let output = ENABLE_PROCESSED_OUTPUT
+        | ENABLE_WRAP_AT_EOL_OUTPUT
+        | ENABLE_VIRTUAL_TERMINAL_PROCESSING
+        | DISABLE_NEWLINE_AUTO_RETURN
+        | ENABLE_LVB_GRID_WORLDWIDE;
+    if mode.0 & !output.0 != 0 {
+        return true;
+    }
+    let mut events = 0;
+    // SAFETY: the call only queries the handle, and reports an invalid one
+    // through its result.
+    unsafe { GetNumberOfConsoleInputEvents(handle, &mut events) }.is_ok()
+}
+
+/// How many console objects are remembered by [`console_object`].
+const CONSOLE_OBJECTS: usize = 32;
+
+/// The console objects seen most recently, first, with their numbers.
+struct ConsoleObjects {
+    next: u64,
+    seen: VecDeque<(u64, OwnedHandle)>,
+}
+
+/// The number of the console object a handle refers to, from 1.
+///
+/// Handles are matched against a duplicate of the first handle seen for each
+/// object with `CompareObjectHandles`, so duplicates of a handle share a
+/// number, while separately opened handles, even to the same screen buffer,
+/// do not. The duplicates are kept open, so that an object is not freed and
+/// its address reused while it has a number.
+///
+/// Only the `CONSOLE_OBJECTS` most recently seen objects are remembered, so
+/// that the number of open duplicates and the cost of a lookup are bounded.
+/// An object that is forgotten gets a new number if it is seen again, and
+/// numbers are never reused, so identities taken before then only compare
+/// unequal, never equal to those of another object.
+fn console_object(f: RawFilelike) -> io::Result<u64> {
+    static OBJECTS: Mutex<ConsoleObjects> =
+        Mutex::new(ConsoleObjects { next: 1, seen: VecDeque::new() });
+
+    let mut objects = OBJECTS.lock().unwrap_or_else(|err| err.into_inner());
+    let known = objects.seen.iter().position(|(_, other)| {
+        // SAFETY: both handles are open for the duration of the call.
+        unsafe {
+            CompareObjectHandles(HANDLE(other.as_raw_handle()), HANDLE(f))
+        }
+        .as_bool()
+    });
+    if let Some(index) = known {
+        let entry = objects.seen.remove(index).expect("index is in bounds");
+        let number = entry.0;
+        objects.seen.push_front(entry);
+        return Ok(number);
+    }
+    // SAFETY: the handle is only borrowed for the duplication.
+    let handle =
+        unsafe { BorrowedHandle::borrow_raw(f) }.try_clone_to_owned()?;
+    let number = objects.next;
+    objects.next += 1;
+    objects.seen.push_front((number, handle));
+    objects.seen.truncate(CONSOLE_OBJECTS);
+    Ok(number)
+}
+
+/// The name of a pipe within the named pipe filesystem. The pipes of
+/// `std::io::pipe` are named pipes with a generated name, while those of
+/// `CreatePipe` are left unnamed.
+fn pipe_name(handle: HANDLE) -> io::Result<Vec<u16>> {
+    // The layout of `FILE_NAME_INFO`, with room for the name.
+    #[repr(C)]
+    struct NameInfo {
+        len: u32,
+        name: [u16; 260],
+    }
+
+    let mut info = NameInfo { len: 0, name: [0; 260] };
+    unsafe {
+        GetFileInformationByHandleEx(
+            handle,
+            FileNameInfo,
+            &mut info as *mut NameInfo as *mut _,
+            std::mem::size_of::<NameInfo>() as u32,
+        )?
+    };
+    let len = (info.len as usize / 2).min(info.name.len());
+    Ok(info.name[..len].to_vec())
+}
+
+/// Whether the name of a pipe is empty, as that of every pipe created by
+/// `CreatePipe`, so that it cannot tell the pipe apart from the others.
+fn is_unnamed(name: &[u16]) -> bool {
+    matches!(name, [] | [0x5c])
+}
+
+/// Whether a pipe allows a single instance, so that no other pipe shares its
+/// name if it has one. This is the case of anonymous pipes. The query needs
+/// read access or `FILE_READ_ATTRIBUTES`, which the handles of `CreatePipe`
+/// have. When it fails, the pipe is assumed to allow several instances.
+fn is_single_instance(handle: HANDLE) -> bool {
+    let mut max_instances = 0;
+    // SAFETY: the call only queries the handle, and reports an invalid one
+    // through its result.
+    let result = unsafe {
+        GetNamedPipeInfo(
+            handle,
+            None,
+            None,
+            None,
+            Some(&mut max_instances as *mut u32),
+        )
+    };
+    result.is_ok() && max_instances == 1
+}
+
+/// The 128-bit FNV-1a hash, which is stable across releases.
+fn fnv1a_128(units: &[u16]) -> u128 {
+    const OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+    units
+        .iter()
+        .flat_map(|unit| unit.to_le_bytes())
+        .fold(OFFSET, |hash, byte| {
+            (hash ^ u128::from(byte)).wrapping_mul(PRIME)
+        })
+}
+
+/// The errors with which older systems and SMB servers reject `FileIdInfo`.
+fn is_unsupported(err: &windows::core::Error) -> bool {
+    [ERROR_INVALID_PARAMETER, ERROR_NOT_SUPPORTED, ERROR_INVALID_LEVEL]
+        .iter()
+        .any(|code| err.code() == code.to_hresult())
+}
+
+pub fn capabilities() -> crate::Capabilities {
+    crate::Capabilities {
+        identity: true,
+        heuristic: false,
+        pins_while_open: true,
+        stable_across_reboots: true,
+        mount_ids: false,
+        reflink_detection: false,
+        current_path: true,
+        open_no_follow: true,
+        minimal_access: true,
+        mount_watch: cfg!(feature = "mount-watch"),
+    }
+}
+
+/// Returns the current path of the file, as resolved by the object manager.
+///
+/// The final path has `SUBST` drive letters replaced by the directory they
+/// point at, and mapped network drives replaced by their UNC path, so the
+/// same file yields the same path regardless of which letter it was opened
+/// through.
+pub fn current_path(f: RawFilelike) -> io::Result<PathBuf> {
+    final_path(HANDLE(f), VOLUME_NAME_DOS).map(strip_verbatim)
+}
+
+/// Convert a verbatim `\\?\` path into its conventional form, which is
+/// what users and most APIs expect.
+fn strip_verbatim(path: PathBuf) -> PathBuf {
+    let wide: Vec<u16> = path.as_os_str().encode_wide().collect();
+    let prefix = |p: &str| -> Vec<u16> { p.encode_utf16().collect() };
+    let unc = prefix(r"\\?\UNC\");
+    let verbatim = prefix(r"\\?\");
+    let rest = if wide.starts_with(&unc) {
+        let mut rest = prefix(r"\\");
+        rest.extend_from_slice(&wide[unc.len()..]);
+        rest
+    } else if wide.starts_with(&verbatim) {
+        // Only drive paths can be safely converted; anything else (such as
+        // a volume GUID path) has no conventional form.
+        let rest = &wide[verbatim.len()..];
+        if rest.get(1) != Some(&(b':' as u16)) {
+            return path;
+        }
+        rest.to_vec()
+    } else {
+        return path;
+    };
+    PathBuf::from(OsString::from_wide(&rest))
+}
+
+/// The serial numbers of every mounted volume.
+///
+/// The volumes are enumerated by GUID, which includes volumes mounted on a
+/// folder rather than a drive letter, and each root is opened to get the
+/// same serial number that `FileId` reports. Volumes whose root cannot be
+/// opened, such as card readers without media, are skipped.
+#[cfg(feature = "mount-watch")]
+pub fn mounted_volumes() -> io::Result<std::collections::HashSet<u64>> {
+    use windows::Win32::Storage::FileSystem::{
+        FindFirstVolumeW, FindNextVolumeW, FindVolumeClose,
+    };
+
+    let mut volumes = std::collections::HashSet::new();
+    // Volume GUID paths have a fixed length well below this.
+    let mut name = [0u16; 64];
+    let find = unsafe { FindFirstVolumeW(&mut name)? };
+    loop {
+        let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+        let root = PathBuf::from(OsString::from_wide(&name[..len]));
+        if let Ok(file) = open_file(&root)
+            && let Ok(id) = FileId::from_filelike(file.as_raw_handle())
+        {
+            volumes.insert(id.volume());
+        }
+        if unsafe { FindNextVolumeW(find, &mut name) }.is_err() {
+            break;
+        }
+    }
+    let _ = unsafe { FindVolumeClose(find) };
+    Ok(volumes)
+}
+
+/// Null and `INVALID_HANDLE_VALUE` are both used to denote the absence of a
+/// handle, e.g. for the standard streams of a process without a console.
+pub fn is_valid_raw(f: RawFilelike) -> bool {
+    !f.is_null() && HANDLE(f) != INVALID_HANDLE_VALUE
+}
+
+// `FileFullDirectoryInfo` does not report file ids, so no weak identity is
+// available without opening the entry.
+pub fn entry_weak_id(_parent: &FileId, _entry: &DirEntry) -> Option<FileId> {
+    None
+}
+
+/// An entry read from a directory listing.
+pub struct DirEntry {
+    name: OsString,
+}
+
+impl DirEntry {
+    pub fn into_name(self) -> OsString {
+        self.name
+    }
+}
+
+/// A directory listing over a handle of its own, so that its position is
+/// not shared with the handle it was opened from.
+#[derive(Debug)]
+pub struct ReadDir {
+    dir: std::fs::File,
+    /// Aligned for `FILE_FULL_DIR_INFO`.
+    buf: Vec<u64>,
+    /// The offset of the next entry in `buf`, if any is left.
+    next: Option<usize>,
+    done: bool,
+}
+
+/// Read a directory through its handle, with `FileFullDirectoryInfo`.
+///
+/// The directory is opened again relative to the handle, with an empty
+/// name, which reaches the same directory without going through its path.
+pub fn read_dir(dir: RawFilelike, _path: &Path) -> io::Result<ReadDir> {
+    let dir = open_file_at(dir, Path::new(""))?;
+    Ok(ReadDir { dir, buf: vec![0; 8192], next: None, done: false })
+}
+
+impl Iterator for ReadDir {
+    type Item = io::Result<DirEntry>;
 
-        Ok(FileId { file_id_info })
+    fn next(&mut self) -> Option<io::Result<DirEntry>> {
+        loop {
+            let Some(offset) = self.next else {
+                if self.done {
+                    return None;
+                }
+                // SAFETY: the buffer is as large as it is said to be, and
+                // an invalid handle is reported through the result.
+                let result = unsafe {
+                    GetFileInformationByHandleEx(
+                        HANDLE(self.dir.as_raw_handle()),
+                        FileFullDirectoryInfo,
+                        self.buf.as_mut_ptr().cast(),
+                        (self.buf.len() * 8) as u32,
+                    )
+                };
+                if let Err(err) = result {
+                    self.done = true;
+                    if err.code() == ERROR_NO_MORE_FILES.to_hresult() {
+                        return None;
+                    }
+                    return Some(Err(err.into()));
+                }
+                self.next = Some(0);
+                continue;
+            };
+            // SAFETY: the offset is that of an entry the call filled in,
+            // which is aligned, and whose name follows it in the buffer.
+            let name = unsafe {
+                let info = self
+                    .buf
+                    .as_ptr()
+                    .cast::<u8>()
+                    .add(offset)
+                    .cast::<FILE_FULL_DIR_INFO>();
+                self.next = match (*info).NextEntryOffset {
+                    0 => None,
+                    next => Some(offset + next as usize),
+                };
+                std::slice::from_raw_parts(
+                    (*info).FileName.as_ptr(),
+                    (*info).FileNameLength as usize / 2,
+                )
+            };
+            if name == [0x2e] || name == [0x2e, 0x2e] {
+                continue;
+            }
+            let name = OsString::from_wide(name);
+            return Some(Ok(DirEntry { name }));
+        }
     }
 }
 
@@ -129,6 +908,15 @@ where
     }
 }
 
+impl<F> AsHandle for crate::Handle<F>
+where
+    F: AsHandle,
+{
+    fn as_handle(&self) -> BorrowedHandle<'_> {
+        self.handle.as_handle()
+    }
+}
+
 impl<F> IntoRawHandle for crate::Handle<F>
 where
     F: IntoRawHandle,
@@ -138,20 +926,478 @@ where
     }
 }
 
+pub fn set_inheritable(f: RawFilelike, inheritable: bool) -> io::Result<()> {
+    let flags =
+        if inheritable { HANDLE_FLAG_INHERIT } else { HANDLE_FLAGS(0) };
+    unsafe { SetHandleInformation(HANDLE(f), HANDLE_FLAG_INHERIT.0, flags)? };
+    Ok(())
+}
+
 pub fn open_file(path: &Path) -> io::Result<std::fs::File> {
-    let wide_path: Vec<_> =
-        path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
-    let file = unsafe {
-        let handle = CreateFileW(
-            PCWSTR::from_raw(wide_path.as_ptr()),
-            GENERIC_READ.0,
+    open_with_flags(path, FILE_FLAG_BACKUP_SEMANTICS)
+}
+
+/// Open a path relative to an open directory.
+///
+/// Win32 has no relative opens, so this calls `NtCreateFile` with the
+/// directory as the root of the object name. The object manager does not
+/// interpret `.` and `..`, so they are applied to the path beforehand, as
+/// Win32 does for absolute paths.
+pub fn open_file_at(
+    dir: RawFilelike,
+    path: &Path,
+) -> io::Result<std::fs::File> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a path below the directory", path.display()),
+        )
+    };
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part),
+            Component::CurDir => {}
+            Component::ParentDir if parts.pop().is_some() => {}
+            _ => return Err(invalid()),
+        }
+    }
+    let mut units = Vec::new();
+    for part in parts {
+        if !units.is_empty() {
+            units.push(u16::from(b'\\'));
+        }
+        units.extend(part.encode_wide());
+    }
+    nt_open(dir, &mut units, FILE_SYNCHRONOUS_IO_NONALERT)
+}
+
+/// Open a path relative to an open directory without leaving it. Returns
+/// `None` if the path leads outside.
+///
+/// `..` is applied to the path beforehand, as by `open_file_at`, and may
+/// not climb above the directory. Every component is then opened relative
+/// to the previous one without following reparse points, and a symbolic
+/// link or junction on the way, which could lead anywhere, is refused as if
+/// it led outside.
+pub fn open_beneath(
+    dir: RawFilelike,
+    path: &Path,
+) -> io::Result<Option<std::fs::File>> {
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part),
+            Component::CurDir => {}
+            Component::ParentDir if parts.pop().is_some() => {}
+            _ => return Ok(None),
+        }
+    }
+    let mut current: Option<std::fs::File> = None;
+    for part in parts {
+        let at = current.as_ref().map_or(dir, |parent| parent.as_raw_handle());
+        let mut units: Vec<u16> = part.encode_wide().collect();
+        let options = FILE_SYNCHRONOUS_IO_NONALERT | FILE_OPEN_REPARSE_POINT;
+        let file = nt_open(at, &mut units, options)?;
+        if is_name_surrogate(HANDLE(file.as_raw_handle()))? {
+            return Ok(None);
+        }
+        current = Some(file);
+    }
+    match current {
+        Some(file) => Ok(Some(file)),
+        None => open_file_at(dir, Path::new("")).map(Some),
+    }
+}
+
+/// Whether a file is a reparse point that stands for another file, such as
+/// a symbolic link or a junction.
+fn is_name_surrogate(handle: HANDLE) -> io::Result<bool> {
+    /// `IsReparseTagNameSurrogate`.
+    const NAME_SURROGATE: u32 = 0x2000_0000;
+
+    let mut info = FILE_ATTRIBUTE_TAG_INFO::default();
+    // SAFETY: `info` is as large as it is said to be, and an invalid handle
+    // is reported through the result.
+    unsafe {
+        GetFileInformationByHandleEx(
+            handle,
+            FileAttributeTagInfo,
+            &mut info as *mut FILE_ATTRIBUTE_TAG_INFO as *mut _,
+            std::mem::size_of::<FILE_ATTRIBUTE_TAG_INFO>() as u32,
+        )?
+    };
+    Ok(info.FileAttributes & FILE_ATTRIBUTE_REPARSE_POINT.0 != 0
+        && info.ReparseTag & NAME_SURROGATE != 0)
+}
+
+/// Open a name relative to a directory with `NtCreateFile`.
+fn nt_open(
+    dir: RawFilelike,
+    units: &mut [u16],
+    options: NTCREATEFILE_CREATE_OPTIONS,
+) -> io::Result<std::fs::File> {
+    let len = u16::try_from(units.len() * 2).map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidInput, "path is too long")
+    })?;
+    let name = UNICODE_STRING {
+        Length: len,
+        MaximumLength: len,
+        Buffer: PWSTR(units.as_mut_ptr()),
+    };
+    let attributes = OBJECT_ATTRIBUTES {
+        Length: std::mem::size_of::<OBJECT_ATTRIBUTES>() as u32,
+        RootDirectory: HANDLE(dir),
+        ObjectName: &name,
+        Attributes: OBJ_CASE_INSENSITIVE,
+        ..Default::default()
+    };
+    let mut handle = HANDLE::default();
+    let mut status = IO_STATUS_BLOCK::default();
+    // SAFETY: every pointer is to a live local, and an invalid directory is
+    // reported through the status.
+    let result = unsafe {
+        NtCreateFile(
+            &mut handle,
+            FILE_GENERIC_READ,
+            &attributes,
+            &mut status,
+            None,
+            FILE_FLAGS_AND_ATTRIBUTES(0),
             FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+            FILE_OPEN,
+            options,
+            None,
+            0,
+        )
+    };
+    if result.0 < 0 {
+        let code = unsafe { RtlNtStatusToDosError(result) };
+        return Err(io::Error::from_raw_os_error(code as i32));
+    }
+    // SAFETY: the handle was just opened, and is owned by nobody else.
+    Ok(unsafe { std::fs::File::from_raw_handle(handle.0) })
+}
+
+/// Open the path itself, rather than the target of a symbolic link or
+/// junction.
+pub fn open_file_no_follow(path: &Path) -> io::Result<std::fs::File> {
+    open_with_flags(
+        path,
+        FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OPEN_REPARSE_POINT,
+    )
+}
+
+pub fn open_with_options(
+    path: &Path,
+    options: &crate::HandleOptions,
+) -> io::Result<std::fs::File> {
+    let access = if options.minimal_access {
+        FILE_READ_ATTRIBUTES.0
+    } else {
+        GENERIC_READ.0
+    };
+    let mut share = FILE_SHARE_NONE;
+    for (enabled, mode) in [
+        (options.share_read, FILE_SHARE_READ),
+        (options.share_write, FILE_SHARE_WRITE),
+        (options.share_delete, FILE_SHARE_DELETE),
+    ] {
+        if enabled {
+            share |= mode;
+        }
+    }
+    let mut flags = FILE_FLAGS_AND_ATTRIBUTES(0);
+    if options.backup_semantics {
+        flags |= FILE_FLAG_BACKUP_SEMANTICS;
+    }
+    if !options.follow_symlinks {
+        flags |= FILE_FLAG_OPEN_REPARSE_POINT;
+    }
+    open_with(path, access, share, flags)
+}
+
+/// How the Win32 path rules interpret the final component of a path.
+#[derive(Debug, PartialEq)]
+enum Win32Name {
+    /// The path names what it says.
+    Plain,
+    /// The final component ends with dots or spaces, which Win32 strips.
+    /// This is the path that is actually opened.
+    Trimmed(PathBuf),
+    /// The final component is a reserved device name, such as `NUL` or
+    /// `com1`, which opens a device rather than a file.
+    Device(String),
+}
+
+/// Classify a path according to the legacy Win32 rules, which do not apply
+/// to verbatim (`\\?\`) and device (`\\.\`) paths.
+fn classify(path: &Path) -> Win32Name {
+    use std::path::{Component, Prefix};
+
+    if let Some(Component::Prefix(prefix)) = path.components().next()
+        && (prefix.kind().is_verbatim()
+            || matches!(prefix.kind(), Prefix::DeviceNS(_)))
+    {
+        return Win32Name::Plain;
+    }
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return Win32Name::Plain;
+    };
+    let trimmed = name.trim_end_matches(['.', ' ']);
+    if trimmed.is_empty() {
+        return Win32Name::Plain;
+    }
+    // Whether a reserved name followed by an extension, such as `nul.txt`,
+    // opens the device depends on the Windows version: Windows 11 opens a
+    // file for most of them. Only the names that are devices on every
+    // version are refused, and the others are left to `CreateFileW`.
+    if is_reserved_device(trimmed) {
+        return Win32Name::Device(trimmed.to_string());
+    }
+    if trimmed.len() != name.len() {
+        return Win32Name::Trimmed(path.with_file_name(trimmed));
+    }
+    Win32Name::Plain
+}
+
+/// Returns true for the names Win32 maps to devices in every directory.
+fn is_reserved_device(stem: &str) -> bool {
+    const NAMES: [&str; 6] = ["CON", "PRN", "AUX", "NUL", "CONIN$", "CONOUT$"];
+    if NAMES.iter().any(|name| stem.eq_ignore_ascii_case(name)) {
+        return true;
+    }
+    let mut chars = stem.chars();
+    let prefix: String = chars.by_ref().take(3).collect();
+    let digit = chars.next();
+    (prefix.eq_ignore_ascii_case("COM") || prefix.eq_ignore_ascii_case("LPT"))
+        && matches!(digit, Some('1'..='9' | '\u{b9}' | '\u{b2}' | '\u{b3}'))
+        && chars.next().is_none()
+}
+
+fn open_with_flags(
+    path: &Path,
+    flags: FILE_FLAGS_AND_ATTRIBUTES,
+) -> io::Result<std::fs::File> {
+    open_with(
+        path,
+        GENERIC_READ.0,
+        FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+        flags,
+    )
+}
+
+fn open_with(
+    path: &Path,
+    access: u32,
+    share: FILE_SHARE_MODE,
+    flags: FILE_FLAGS_AND_ATTRIBUTES,
+) -> io::Result<std::fs::File> {
+    // Opening a device would fail later, when its identity is taken, with
+    // a less helpful error. Trailing dots and spaces are stripped as Win32
+    // would, but explicitly, so that it shows up in the logs.
+    let trimmed;
+    let path = match classify(path) {
+        Win32Name::Plain => path,
+        Win32Name::Trimmed(path) => {
+            log_debug!("stripped trailing dots or spaces: {}", path.display());
+            trimmed = path;
+            &trimmed
+        }
+        Win32Name::Device(name) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} names the reserved device {}",
+                    path.display(),
+                    name
+                ),
+            ));
+        }
+    };
+    let file = with_wide_path(path, |wide_path| unsafe {
+        let handle = CreateFileW(
+            wide_path,
+            access,
+            share,
             None,
             OPEN_EXISTING,
-            FILE_FLAG_BACKUP_SEMANTICS,
+            flags,
             None,
         )?;
-        std::fs::File::from_raw_filelike(handle.0)
-    };
+        Ok::<_, io::Error>(std::fs::File::from_raw_filelike(handle.0))
+    })?;
     Ok(file)
 }
+
+/// The longest path, in UTF-16 code units, that `with_wide_path` converts
+/// on the stack. This covers every path within the classic `MAX_PATH`.
+const STACK_PATH_LEN: usize = 260;
+
+/// Call `f` with the path as a null-terminated wide string.
+///
+/// Paths of up to `STACK_PATH_LEN` code units are converted into a stack
+/// buffer, so that opening them does not allocate; longer paths fall back
+/// to the heap.
+fn with_wide_path<T>(path: &Path, f: impl FnOnce(PCWSTR) -> T) -> T {
+    let mut buf = [0u16; STACK_PATH_LEN + 1];
+    for (len, unit) in path.as_os_str().encode_wide().enumerate() {
+        if len == STACK_PATH_LEN {
+            let wide: Vec<u16> = path
+                .as_os_str()
+                .encode_wide()
+                .chain(std::iter::once(0))
+                .collect();
+            return f(PCWSTR::from_raw(wide.as_ptr()));
+        }
+        buf[len] = unit;
+    }
+    // The rest of the buffer is zeroed, so the string is terminated.
+    f(PCWSTR::from_raw(buf.as_ptr()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use std::os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle};
+    use windows::Win32::Foundation::HANDLE;
+    use windows::Win32::System::Pipes::CreatePipe;
+
+    use super::{
+        CONSOLE_OBJECTS, FILE_ID_128, FILE_ID_INFO, FileId, Win32Name,
+        classify, fnv1a_128,
+    };
+
+    #[test]
+    fn classify_names() {
+        let class = |path: &str| classify(Path::new(path));
+        assert_eq!(class(r"C:\dir\file.txt"), Win32Name::Plain);
+        assert_eq!(class(r"C:\dir\console"), Win32Name::Plain);
+        assert_eq!(class(r"C:\dir\COM10"), Win32Name::Plain);
+        assert_eq!(class(r"C:\dir\..."), Win32Name::Plain);
+        assert_eq!(
+            class(r"C:\dir\file. . "),
+            Win32Name::Trimmed(PathBuf::from(r"C:\dir\file"))
+        );
+        assert_eq!(class(r"C:\dir\nul.txt"), Win32Name::Plain);
+        assert_eq!(class(r"C:\dir\aux .log"), Win32Name::Plain);
+        for device in ["NUL", "nul. ", "Con ", "com1", "LPT\u{b9}"] {
+            let expected = device.trim_end_matches(['.', ' ']);
+            assert_eq!(
+                class(&format!(r"C:\dir\{}", device)),
+                Win32Name::Device(expected.to_string())
+            );
+        }
+        assert_eq!(class(r"\\?\C:\dir\NUL"), Win32Name::Plain);
+        assert_eq!(class(r"\\?\C:\dir\file."), Win32Name::Plain);
+        assert_eq!(class(r"\\.\NUL"), Win32Name::Plain);
+    }
+
+    #[test]
+    fn by_handle_ids() {
+        let by_handle = FileId::ByHandle { volume: 0x1234, index: 0x5678 };
+        let info = FileId::Info(FILE_ID_INFO {
+            VolumeSerialNumber: 0x1234,
+            FileId: FILE_ID_128 { Identifier: by_handle.identifier() },
+        });
+        assert_ne!(by_handle, info);
+        assert!(info < by_handle);
+        assert!(by_handle < FileId::volume_bound(0x1234, true).unwrap());
+
+        assert_eq!(by_handle.to_string(), "0000000000001234:0000000000005678");
+        for id in [&by_handle, &info] {
+            assert_eq!(FileId::parse(&id.to_string()).as_ref(), Some(id));
+            assert_eq!(FileId::unpack(&id.pack()).as_ref(), Some(id));
+        }
+        assert!(by_handle.pack() > info.pack());
+        assert_eq!(FileId::parse("0000000100000000:0000000000005678"), None);
+    }
+
+    #[test]
+    fn pipe_ids() {
+        assert_eq!(fnv1a_128(&[]), 0x6c62272e07bb014262b821756295c58d);
+        let pipe = FileId::Pipe { name_hash: fnv1a_128(&[0x61]) };
+        assert!(pipe.to_string().starts_with("pipe:"));
+        assert_eq!(FileId::parse(&pipe.to_string()), Some(pipe.clone()));
+        assert_eq!(FileId::unpack(&pipe.pack()), Some(pipe.clone()));
+        assert_eq!(pipe.volume(), 0);
+
+        let (reader, writer) = std::io::pipe().unwrap();
+        let id = FileId::from_filelike(reader.as_raw_handle()).unwrap();
+        assert!(matches!(id, FileId::Pipe { .. }));
+        assert_eq!(FileId::from_filelike(writer.as_raw_handle()).unwrap(), id);
+        let (other, _writer) = std::io::pipe().unwrap();
+        assert_ne!(FileId::from_filelike(other.as_raw_handle()).unwrap(), id);
+
+        // The pipes of `CreatePipe` are unnamed, so they have no identity.
+        let (mut reader, mut writer) = (HANDLE::default(), HANDLE::default());
+        unsafe { CreatePipe(&mut reader, &mut writer, None, 0) }.unwrap();
+        // SAFETY: `CreatePipe` succeeded, so both handles are open and owned
+        // by nobody else.
+        let (reader, _writer) = unsafe {
+            (
+                OwnedHandle::from_raw_handle(reader.0),
+                OwnedHandle::from_raw_handle(writer.0),
+            )
+        };
+        let err = FileId::from_filelike(reader.as_raw_handle()).unwrap_err();
+        assert!(matches!(
+            crate::Error::of(&err),
+            Some(crate::Error::UnsupportedHandleType {
+                class: crate::DeviceClass::Pipe
+            })
+        ));
+    }
+
+    #[test]
+    fn console_ids() {
+        let output = FileId::Console { object: 0x1234, input: false };
+        let input = FileId::Console { object: 0x1234, input: true };
+        assert_ne!(output, input);
+        assert_eq!(output.to_string(), "console:0000000000001234:out");
+        assert_eq!(input.to_string(), "console:0000000000001234:in");
+        for id in [&output, &input] {
+            assert_eq!(FileId::parse(&id.to_string()).as_ref(), Some(id));
+            assert_eq!(FileId::unpack(&id.pack()).as_ref(), Some(id));
+        }
+        assert_eq!(FileId::parse("console:0000000000001234:err"), None);
+
+        // Only when the tests run attached to a console.
+        let open = |path| {
+            std::fs::OpenOptions::new().read(true).write(true).open(path)
+        };
+        if let Ok(output) = open("CONOUT$") {
+            let id = FileId::from_filelike(output.as_raw_handle()).unwrap();
+            assert!(matches!(id, FileId::Console { input: false, .. }));
+            let copy = output.try_clone().unwrap();
+            assert_eq!(
+                FileId::from_filelike(copy.as_raw_handle()).unwrap(),
+                id
+            );
+
+            // Once enough other objects were seen, the first one is
+            // forgotten, and gets a new number.
+            let others: Vec<_> = (0..CONSOLE_OBJECTS)
+                .map(|_| open("CONOUT$").unwrap())
+                .collect();
+            for other in &others {
+                FileId::from_filelike(other.as_raw_handle()).unwrap();
+            }
+            assert_ne!(
+                FileId::from_filelike(output.as_raw_handle()).unwrap(),
+                id
+            );
+        }
+        if let Ok(input) = open("CONIN$") {
+            let id = FileId::from_filelike(input.as_raw_handle()).unwrap();
+            assert!(matches!(id, FileId::Console { input: true, .. }));
+        }
+        assert!(
+            FileId::from_filelike(
+                std::fs::File::open("NUL").unwrap().as_raw_handle()
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,85 @@
+// Run the `cfi` tool against a scratch directory.
+#![cfg(all(feature = "cli", feature = "testing"))]
+
+use std::fs::{self, File};
+use std::path::Path;
+use std::process::{Command, Output};
+
+use cross_file_id::FileId;
+use cross_file_id::testing::TempDir;
+
+fn cfi(args: &[&Path]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_cfi")).args(args).output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn cli() {
+    let tdir = TempDir::new().unwrap();
+    let dir = tdir.path();
+    fs::create_dir(dir.join("sub")).unwrap();
+    File::create(dir.join("a")).unwrap();
+    File::create(dir.join("b")).unwrap();
+    fs::hard_link(dir.join("a"), dir.join("sub").join("alink")).unwrap();
+    let (a, b) = (dir.join("a"), dir.join("b"));
+    let alink = dir.join("sub").join("alink");
+
+    assert_eq!(cfi(&[Path::new("same"), &a, &alink]).status.code(), Some(0));
+    assert_eq!(cfi(&[Path::new("same"), &a, &b]).status.code(), Some(1));
+    assert_eq!(cfi(&[Path::new("same"), &a]).status.code(), Some(2));
+
+    let id = FileId::from_path(&a).unwrap();
+    let output = cfi(&[Path::new("id"), &a]);
+    assert_eq!(stdout(&output).trim().parse::<FileId>().unwrap(), id);
+
+    let output = cfi(&[Path::new("dups"), dir]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        format!("{}\n  {}\n  {}\n", id, a.display(), alink.display())
+    );
+
+    let output = cfi(&[Path::new("hardlinks"), dir, &alink]);
+    assert_eq!(
+        stdout(&output),
+        format!("{}\n{}\n", a.display(), alink.display())
+    );
+
+    let output = cfi(&[Path::new("doctor"), &a]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains(&format!("id: {}", id)));
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_dangling_link() {
+    let tdir = TempDir::new().unwrap();
+    let dir = tdir.path();
+    File::create(dir.join("a")).unwrap();
+    fs::hard_link(dir.join("a"), dir.join("alink")).unwrap();
+    std::os::unix::fs::symlink(dir.join("missing"), dir.join("dangling"))
+        .unwrap();
+    let (a, alink) = (dir.join("a"), dir.join("alink"));
+    let id = FileId::from_path(&a).unwrap();
+
+    // The scan reports the dangling link and goes on.
+    let output = cfi(&[Path::new("dups"), dir]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stdout(&output),
+        format!("{}\n  {}\n  {}\n", id, a.display(), alink.display())
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(&dir.join("dangling").display().to_string()));
+
+    // Without following links, the dangling link has an identity.
+    let output = cfi(&[Path::new("hardlinks"), dir, &a]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        format!("{}\n{}\n", a.display(), alink.display())
+    );
+}
@@ -0,0 +1,13 @@
+// Misuse of guards and verification results must not compile when
+// `unused_must_use` is denied.
+#[cfg(any(unix, feature = "failpoints", feature = "signed"))]
+#[test]
+fn compile_fail() {
+    let t = trybuild::TestCases::new();
+    #[cfg(unix)]
+    t.compile_fail("tests/ui/handoff_dropped.rs");
+    #[cfg(feature = "failpoints")]
+    t.compile_fail("tests/ui/armed_dropped.rs");
+    #[cfg(feature = "signed")]
+    t.compile_fail("tests/ui/verify_ignored.rs");
+}
@@ -0,0 +1,7 @@
+#![deny(unused_must_use)]
+
+use cross_file_id::failpoints::{self, FailPoint};
+
+fn main() {
+    failpoints::arm(FailPoint::Open, 1);
+}
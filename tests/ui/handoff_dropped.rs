@@ -0,0 +1,9 @@
+#![deny(unused_must_use)]
+
+use cross_file_id::Handle;
+
+fn main() -> std::io::Result<()> {
+    let handle = Handle::stdin()?;
+    unsafe { handle.keep_at(10) }?;
+    Ok(())
+}
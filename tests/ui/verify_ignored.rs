@@ -0,0 +1,11 @@
+#![deny(unused_must_use)]
+
+use cross_file_id::FileId;
+use cross_file_id::signed::SignedId;
+
+fn main() -> std::io::Result<()> {
+    let id = FileId::from_path(".")?;
+    let token = SignedId::sign(b"key", &id, b"scope");
+    token.verify_for(b"key", &id);
+    Ok(())
+}